@@ -132,6 +132,7 @@ fn main() {
                 Request::Interest(Interest {
                     name: name.to_string(),
                     granularity: None,
+                    budget: None,
                     sink: Some(Sink::AssocIn(AssocIn {
                         stateful: granularity,
                     })),
@@ -25,7 +25,7 @@ use declarative_dataflow::server;
 use declarative_dataflow::server::{CreateAttribute, Request, Server, TxId};
 use declarative_dataflow::sinks::{Sinkable, SinkingContext};
 use declarative_dataflow::timestamp::{Coarsen, Time};
-use declarative_dataflow::{Output, ResultDiff};
+use declarative_dataflow::{Error, Output, ResultDiff};
 
 mod networking;
 use crate::networking::{DomainEvent, Token, IO, SYSTEM};
@@ -400,7 +400,12 @@ fn main() {
                                 let result = worker.dataflow::<T, _, _>(|scope| {
                                     let sink_context: SinkingContext = (&req).into();
 
-                                    let relation = match server.interest(&req.name, scope) {
+                                    let interest = match req.budget {
+                                        None => server.interest(&req.name, scope),
+                                        Some(ref budget) => server.interest_with_budget(&req.name, scope, budget.clone()),
+                                    };
+
+                                    let relation = match interest {
                                         Err(error) => { return Err(error); }
                                         Ok(relation) => relation,
                                     };
@@ -570,6 +575,15 @@ fn main() {
                 worker.step();
             }
 
+            // Tear down queries that have been lagging behind their
+            // inputs for longer than their budget allows.
+            for (name, clients) in server.enforce_budgets() {
+                for Token(client) in clients {
+                    let error = Error::fault(format!("Query {} exceeded its budget.", name));
+                    io.send.send(Output::Error(client, error, next_tx)).unwrap();
+                }
+            }
+
             // We advance before `step_or_park`, because advancing
             // might take a decent amount of time, in case traces get
             // compacted. If that happens, we can park less before
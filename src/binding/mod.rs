@@ -1,5 +1,6 @@
 //! Binding language, mainly for use in Hector-powered plans.
 
+use std::cmp::Ordering;
 use std::fmt;
 
 use crate::{Aid, Value, Var};
@@ -89,6 +90,21 @@ impl Binding {
             binding: Box::new(binding),
         })
     }
+
+    /// Returns the attribute binding backing this binding, if any,
+    /// looking through negation. Only these can act as the source of
+    /// a delta pipeline, as they are the only ones experiencing
+    /// change.
+    pub fn source(&self) -> Option<&AttributeBinding> {
+        match *self {
+            Binding::Attribute(ref binding) => Some(binding),
+            Binding::Not(ref antijoin) => match *antijoin.binding {
+                Binding::Attribute(ref binding) => Some(binding),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 impl AsBinding for Binding {
@@ -201,7 +217,9 @@ impl fmt::Debug for AttributeBinding {
 }
 
 /// Describes variables whose possible values must not be contained in
-/// the specified attribute.
+/// the specified attribute. All of them must be bound by other,
+/// positive bindings, as the wrapped binding is only ever used to
+/// validate extensions, never to propose them.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Serialize, Deserialize)]
 pub struct AntijoinBinding {
     /// The wrapped binding.
@@ -294,6 +312,60 @@ pub enum BinaryPredicate {
     EQ,
     /// Not equal
     NEQ,
+    /// Equal, ignoring case. Non-string values never match.
+    EqualIgnoreCase,
+    /// Left operand contains the right one, ignoring
+    /// case. Non-string values never match.
+    ContainsIgnoreCase,
+    /// Negation of the wrapped predicate
+    Not(Box<BinaryPredicate>),
+    /// Conjunction of the wrapped predicates
+    And(Vec<BinaryPredicate>),
+    /// Disjunction of the wrapped predicates
+    Or(Vec<BinaryPredicate>),
+}
+
+impl BinaryPredicate {
+    /// Evaluates the predicate on the given pair of operands.
+    /// Combinators apply each of their constituents to the same pair
+    /// of operands.
+    pub fn eval(&self, a: &Value, b: &Value) -> bool {
+        self.eval_by(a, b, &|a: &Value, b: &Value| a.cmp(b))
+    }
+
+    /// Evaluates the predicate as `eval` does, but with comparisons
+    /// following the specified order rather than the natural order of
+    /// values. Operands that compare equal satisfy EQ.
+    pub fn eval_by<F>(&self, a: &Value, b: &Value, compare: &F) -> bool
+    where
+        F: Fn(&Value, &Value) -> Ordering + ?Sized,
+    {
+        match *self {
+            BinaryPredicate::LT => compare(a, b) == Ordering::Less,
+            BinaryPredicate::GT => compare(a, b) == Ordering::Greater,
+            BinaryPredicate::LTE => compare(a, b) != Ordering::Greater,
+            BinaryPredicate::GTE => compare(a, b) != Ordering::Less,
+            BinaryPredicate::EQ => compare(a, b) == Ordering::Equal,
+            BinaryPredicate::NEQ => compare(a, b) != Ordering::Equal,
+            BinaryPredicate::EqualIgnoreCase => match (a, b) {
+                (Value::String(a), Value::String(b)) => a.to_lowercase() == b.to_lowercase(),
+                _ => false,
+            },
+            BinaryPredicate::ContainsIgnoreCase => match (a, b) {
+                (Value::String(a), Value::String(b)) => {
+                    a.to_lowercase().contains(&b.to_lowercase())
+                }
+                _ => false,
+            },
+            BinaryPredicate::Not(ref predicate) => !predicate.eval_by(a, b, compare),
+            BinaryPredicate::And(ref predicates) => {
+                predicates.iter().all(|p| p.eval_by(a, b, compare))
+            }
+            BinaryPredicate::Or(ref predicates) => {
+                predicates.iter().any(|p| p.eval_by(a, b, compare))
+            }
+        }
+    }
 }
 
 /// Describe a binary predicate constraint.
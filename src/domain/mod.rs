@@ -1,21 +1,23 @@
 //! Logic for working with attributes under a shared timestamp
 //! semantics.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use timely::dataflow::operators::{Probe, UnorderedInput};
+use timely::dataflow::operators::{Filter, Probe, UnorderedInput};
 use timely::dataflow::{ProbeHandle, Scope, ScopeParent, Stream};
 use timely::progress::frontier::AntichainRef;
 use timely::progress::Timestamp;
 
+use differential_dataflow::collection::Collection;
 use differential_dataflow::lattice::Lattice;
-use differential_dataflow::operators::arrange::Arrange;
+use differential_dataflow::operators::arrange::{Arrange, ArrangeBySelf};
 use differential_dataflow::operators::Threshold;
 use differential_dataflow::trace::TraceReader;
 use differential_dataflow::AsCollection;
 
 use crate::operators::LastWriteWins;
-use crate::{Aid, Error, Rewind, TxData, Value};
+use crate::plan::prepare::parameters_of;
+use crate::{Aid, Eid, Error, Rewind, TxData, Value};
 use crate::{AttributeConfig, IndexDirection, InputSemantics, QuerySupport};
 use crate::{RelationConfig, RelationHandle};
 use crate::{TraceKeyHandle, TraceValHandle};
@@ -51,12 +53,19 @@ pub struct Domain<T: Timestamp + Lattice> {
     last_advance: Vec<T>,
     /// Input handles to attributes in this domain.
     input_sessions: HashMap<String, UnorderedSession<T, (Value, Value), isize>>,
+    /// Input handles to the parameter sets of prepared rules.
+    parameter_sessions: HashMap<String, UnorderedSession<T, Vec<Value>, isize>>,
+    /// Parameter sets bound so far, per prepared rule.
+    bound_parameters: HashMap<String, HashSet<Vec<Value>>>,
     /// The probe keeping track of source progress in this domain.
     domain_probe: ProbeHandle<T>,
     /// Maintaining the number of probed sources allows us to
     /// distinguish between a domain without sources, and one where
     /// sources have ceased producing inputs.
     probed_source_count: usize,
+    /// Per-attribute probes on the streams feeding sourced
+    /// attributes, allowing us to tell when a source has completed.
+    source_probes: HashMap<Aid, ProbeHandle<T>>,
     /// Configurations for attributes in this domain.
     pub attributes: HashMap<Aid, AttributeConfig>,
     /// Forward count traces.
@@ -75,6 +84,25 @@ pub struct Domain<T: Timestamp + Lattice> {
     pub relations: HashMap<Aid, RelationConfig>,
     /// Relation traces.
     pub arrangements: HashMap<Aid, RelationHandle<T>>,
+    /// Former names of renamed attributes, mapped to the name they
+    /// were renamed to.
+    renamed: HashMap<Aid, Aid>,
+}
+
+/// Checks that the given value is of the same type as the
+/// specified example value, if any. Null is accepted for all types.
+fn check_value_type(aid: &str, value_type: Option<&Value>, v: &Value) -> Result<(), Error> {
+    match value_type {
+        None => Ok(()),
+        Some(_) if *v == Value::Null => Ok(()),
+        Some(expected) => {
+            if std::mem::discriminant(expected) == std::mem::discriminant(v) {
+                Ok(())
+            } else {
+                Err(Error::type_mismatch(aid, expected, v))
+            }
+        }
+    }
 }
 
 impl<T> Domain<T>
@@ -87,8 +115,11 @@ where
             now_at: start_at,
             last_advance: vec![<T as Lattice>::minimum()],
             input_sessions: HashMap::new(),
+            parameter_sessions: HashMap::new(),
+            bound_parameters: HashMap::new(),
             domain_probe: ProbeHandle::new(),
             probed_source_count: 0,
+            source_probes: HashMap::new(),
             attributes: HashMap::new(),
             forward_count: HashMap::new(),
             forward_propose: HashMap::new(),
@@ -98,6 +129,7 @@ where
             reverse_validate: HashMap::new(),
             relations: HashMap::new(),
             arrangements: HashMap::new(),
+            renamed: HashMap::new(),
         }
     }
 
@@ -124,9 +156,6 @@ where
                 InputSemantics::Distinct => pairs.as_collection().distinct(),
             };
 
-            // @TODO should only create this if used later
-            let tuples_reverse = tuples.map(|(e, v)| (v, e));
-
             // Propose traces are used in general, whereas the other
             // indices are only relevant to Hector.
             self.forward_propose.insert(
@@ -134,15 +163,6 @@ where
                 tuples.arrange_named(&format!("->Propose({})", &name)).trace,
             );
 
-            if config.index_direction == IndexDirection::Both {
-                self.reverse_propose.insert(
-                    name.to_string(),
-                    tuples_reverse
-                        .arrange_named(&format!("->_Propose({})", &name))
-                        .trace,
-                );
-            }
-
             // LastWriteWins is a special case, because count,
             // propose, and validate are all essentially the same.
             if config.input_semantics != InputSemantics::LastWriteWins {
@@ -156,16 +176,6 @@ where
                             .arrange_named(&format!("->Count({})", name))
                             .trace,
                     );
-
-                    if config.index_direction == IndexDirection::Both {
-                        self.reverse_count.insert(
-                            name.to_string(),
-                            tuples_reverse
-                                .map(|(k, _v)| (k, ()))
-                                .arrange_named(&format!("->_Count({})", name))
-                                .trace,
-                        );
-                    }
                 }
 
                 if config.query_support >= QuerySupport::Delta {
@@ -176,19 +186,16 @@ where
                             .arrange_named(&format!("->Validate({})", &name))
                             .trace,
                     );
-
-                    if config.index_direction == IndexDirection::Both {
-                        self.reverse_validate.insert(
-                            name.to_string(),
-                            tuples_reverse
-                                .map(|t| (t, ()))
-                                .arrange_named(&format!("->_Validate({})", &name))
-                                .trace,
-                        );
-                    }
                 }
             }
 
+            // Reverse indices can also be installed later on, see
+            // `create_reverse_indices`.
+            if config.index_direction == IndexDirection::Both {
+                let tuples_reverse = tuples.map(|(e, v)| (v, e));
+                self.install_reverse_indices(name, &config, &tuples_reverse);
+            }
+
             // This is crucial. If we forget to install the attribute
             // configuration, its traces will be ignored when
             // advancing the domain.
@@ -200,6 +207,93 @@ where
         }
     }
 
+    /// Arranges (value,entity) pairs into the reverse indices
+    /// required by the given attribute configuration.
+    fn install_reverse_indices<S: Scope + ScopeParent<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        config: &AttributeConfig,
+        tuples_reverse: &Collection<S, (Value, Value), isize>,
+    ) {
+        self.reverse_propose.insert(
+            name.to_string(),
+            tuples_reverse
+                .arrange_named(&format!("->_Propose({})", &name))
+                .trace,
+        );
+
+        if config.input_semantics != InputSemantics::LastWriteWins {
+            if config.query_support == QuerySupport::AdaptiveWCO {
+                self.reverse_count.insert(
+                    name.to_string(),
+                    tuples_reverse
+                        .map(|(k, _v)| (k, ()))
+                        .arrange_named(&format!("->_Count({})", name))
+                        .trace,
+                );
+            }
+
+            if config.query_support >= QuerySupport::Delta {
+                self.reverse_validate.insert(
+                    name.to_string(),
+                    tuples_reverse
+                        .map(|t| (t, ()))
+                        .arrange_named(&format!("->_Validate({})", &name))
+                        .trace,
+                );
+            }
+        }
+    }
+
+    /// Installs reverse indices for an attribute that was created
+    /// with forward indices only, by re-arranging its forward propose
+    /// trace by value within the given scope. Does nothing for
+    /// attributes that are already indexed in both directions.
+    ///
+    /// The forward trace is imported without a shutdown button, s.t.
+    /// the re-arrangement keeps the dataflow it was created in alive,
+    /// even once everything else in there has been shut down.
+    pub fn create_reverse_indices<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        scope: &mut S,
+    ) -> Result<(), Error> {
+        let mut config = match self.attributes.get(name) {
+            None => {
+                return Err(Error::not_found(format!(
+                    "No attribute of name {} exists.",
+                    name
+                )));
+            }
+            Some(config) => config.clone(),
+        };
+
+        if config.index_direction == IndexDirection::Both {
+            return Ok(());
+        }
+
+        let tuples_reverse = match self.forward_propose.get_mut(name) {
+            None => {
+                return Err(Error::fault(format!(
+                    "Attribute {} has no forward propose trace.",
+                    name
+                )));
+            }
+            Some(trace) => trace
+                .import_named(scope, &format!("Reverse({})", name))
+                .as_collection(|e, v| (v.clone(), e.clone())),
+        };
+
+        self.install_reverse_indices(name, &config, &tuples_reverse);
+
+        config.index_direction = IndexDirection::Both;
+        self.attributes.insert(name.to_string(), config);
+
+        info!("Created reverse indices for attribute {}", name);
+
+        Ok(())
+    }
+
     /// Creates an attribute that can be transacted upon by clients.
     pub fn create_transactable_attribute<S: Scope<Timestamp = T>>(
         &mut self,
@@ -223,6 +317,19 @@ where
         Ok(())
     }
 
+    /// Creates an attribute fed by the results of a computation
+    /// within this domain. Derived attributes are not probed, as
+    /// their progress is entirely governed by the attributes they
+    /// are computed from.
+    pub fn create_derived_attribute<S: Scope + ScopeParent<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        config: AttributeConfig,
+        pairs: &Stream<S, ((Value, Value), T, isize)>,
+    ) -> Result<(), Error> {
+        self.create_attribute(name, config, pairs)
+    }
+
     /// Creates an attribute that is controlled by a source and thus
     /// can not be transacted upon by clients.
     pub fn create_sourced_attribute<S: Scope + ScopeParent<Timestamp = T>>(
@@ -231,6 +338,25 @@ where
         config: AttributeConfig,
         pairs: &Stream<S, ((Value, Value), T, isize)>,
     ) -> Result<(), Error> {
+        // Sourced values can't be rejected up front, as transacted
+        // ones are. We therefore drop mistyped values on ingest,
+        // s.t. they never reach any index.
+        let pairs = match config.value_type.clone() {
+            None => pairs.to_owned(),
+            Some(expected) => {
+                let aid = name.to_string();
+                pairs.filter(move |((_e, v), _t, _diff)| {
+                    match check_value_type(&aid, Some(&expected), v) {
+                        Ok(()) => true,
+                        Err(error) => {
+                            warn!("Dropping sourced value: {}", error);
+                            false
+                        }
+                    }
+                })
+            }
+        };
+
         // We need to install a probe on source-fed attributes in
         // order to determine their progress.
 
@@ -247,9 +373,132 @@ where
 
         self.create_attribute(name, config, &source_pairs)?;
 
+        let mut completion_probe = ProbeHandle::new();
+        pairs.probe_with(&mut completion_probe);
+        self.source_probes.insert(name.to_string(), completion_probe);
+
         Ok(())
     }
 
+    /// Returns true iff the source feeding the specified attribute
+    /// has emitted its last datum and dropped all of its
+    /// capabilities. Sources that never complete (e.g. logging
+    /// streams) will always report false.
+    pub fn is_source_complete(&self, name: &str) -> Result<bool, Error> {
+        match self.source_probes.get(name) {
+            None => Err(Error::not_found(format!(
+                "No sourced attribute of name {} exists.",
+                name
+            ))),
+            Some(probe) => Ok(probe.done()),
+        }
+    }
+
+    /// Makes an existing attribute available under a new name,
+    /// re-binding its input, probes, and all of its traces, s.t. no
+    /// data has to be re-introduced. Dataflows that are already
+    /// running are unaffected, new plans must refer to the attribute
+    /// by its new name. Operator names within the dataflow graph
+    /// still reflect the original name.
+    pub fn rename_attribute(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        if !self.attributes.contains_key(from) {
+            return Err(Error::attribute_not_found(from));
+        }
+
+        if self.attributes.contains_key(to) {
+            return Err(Error::conflict(format!(
+                "An attribute of name {} already exists.",
+                to
+            )));
+        }
+
+        fn rebind<V>(map: &mut HashMap<Aid, V>, from: &str, to: &str) {
+            if let Some(x) = map.remove(from) {
+                map.insert(to.to_string(), x);
+            }
+        }
+
+        rebind(&mut self.attributes, from, to);
+        rebind(&mut self.input_sessions, from, to);
+        rebind(&mut self.source_probes, from, to);
+        rebind(&mut self.forward_count, from, to);
+        rebind(&mut self.forward_propose, from, to);
+        rebind(&mut self.forward_validate, from, to);
+        rebind(&mut self.reverse_count, from, to);
+        rebind(&mut self.reverse_propose, from, to);
+        rebind(&mut self.reverse_validate, from, to);
+
+        // Earlier names now refer to the new one, whereas the new
+        // name is no longer a former one.
+        for target in self.renamed.values_mut() {
+            if target == from {
+                *target = to.to_string();
+            }
+        }
+        self.renamed.remove(to);
+        self.renamed.insert(from.to_string(), to.to_string());
+
+        info!("Renamed attribute {} to {}", from, to);
+
+        Ok(())
+    }
+
+    /// Returns the current name of an attribute that was known under
+    /// the specified name before being renamed, if any.
+    pub fn renamed_to(&self, name: &str) -> Option<&Aid> {
+        self.renamed.get(name)
+    }
+
+    /// Creates the input holding the parameter sets of the named
+    /// prepared rule, unless it exists already, and registers it as a
+    /// relation, s.t. the rule can refer to it via `Plan::parameters`.
+    pub fn create_parameter_input<S: Scope<Timestamp = T>>(&mut self, name: &str, scope: &mut S) {
+        if self.parameter_sessions.contains_key(name) {
+            return;
+        }
+
+        let tuples = {
+            let ((handle, cap), tuples) = scope.new_unordered_input::<(Vec<Value>, T, isize)>();
+            let mut session = UnorderedSession::from(handle, cap);
+            session.advance_to(self.now_at.clone());
+
+            self.parameter_sessions.insert(name.to_string(), session);
+
+            tuples.as_collection()
+        };
+
+        self.register_arrangement(
+            parameters_of(name),
+            RelationConfig { trace_slack: None },
+            tuples.arrange_by_self().trace,
+        );
+    }
+
+    /// Adds a parameter set to the input of the named prepared
+    /// rule. Parameter sets bound before are ignored. Like
+    /// transactions, new parameter sets are revealed as the domain
+    /// epoch advances.
+    pub fn bind_parameters(&mut self, name: &str, params: Vec<Value>) -> Result<(), Error> {
+        match self.parameter_sessions.get_mut(name) {
+            None => Err(Error::not_found(format!(
+                "Rule {} has no parameter input.",
+                name
+            ))),
+            Some(session) => {
+                let bound = self
+                    .bound_parameters
+                    .entry(name.to_string())
+                    .or_insert_with(HashSet::new);
+
+                if bound.insert(params.clone()) {
+                    session.update(params, 1);
+                }
+
+                Ok(())
+            }
+        }
+    }
+
     /// Inserts a new named relation.
     pub fn register_arrangement(
         &mut self,
@@ -261,13 +510,30 @@ where
         self.arrangements.insert(name, trace);
     }
 
-    /// Transact data into one or more inputs.
+    /// Checks that the given value matches the type declared for the
+    /// specified attribute, if any.
+    fn check_type(&self, aid: &str, v: &Value) -> Result<(), Error> {
+        let value_type = self
+            .attributes
+            .get(aid)
+            .and_then(|config| config.value_type.as_ref());
+
+        check_value_type(aid, value_type, v)
+    }
+
+    /// Transact data into one or more inputs. Values are checked
+    /// against the declared types of their attributes up front, s.t.
+    /// a mismatch rejects the entire transaction.
     pub fn transact(&mut self, tx_data: Vec<TxData>) -> Result<(), Error> {
+        for TxData(_, _, a, v, _) in tx_data.iter() {
+            self.check_type(a, v)?;
+        }
+
         // @TODO do this smarter, e.g. grouped by handle
         for TxData(op, e, a, v, t) in tx_data {
             match self.input_sessions.get_mut(&a) {
                 None => {
-                    return Err(Error::not_found(format!("Attribute {} does not exist.", a)));
+                    return Err(Error::attribute_not_found(&a));
                 }
                 Some(handle) => match t {
                     None => handle.update((e, v), op),
@@ -279,6 +545,42 @@ where
         Ok(())
     }
 
+    /// Transact a batch of changes, spanning one or more attributes,
+    /// as a single logical transaction at the specified time. The
+    /// domain epoch is advanced to `time` first and all changes are
+    /// introduced at that time, s.t. no reader can observe a partial
+    /// transaction. If any of the attributes does not exist, no
+    /// changes are applied at all.
+    pub fn transact_at(
+        &mut self,
+        changes: Vec<(Aid, Eid, Value, isize)>,
+        time: T,
+    ) -> Result<(), Error> {
+        if let Some((a, _, _, _)) = changes
+            .iter()
+            .find(|(a, _, _, _)| !self.input_sessions.contains_key(a))
+        {
+            return Err(Error::attribute_not_found(&a));
+        }
+
+        for (a, _, v, _) in changes.iter() {
+            self.check_type(a, v)?;
+        }
+
+        self.advance_epoch(time)?;
+
+        for (a, e, v, diff) in changes {
+            let handle = self.input_sessions.get_mut(&a).unwrap();
+            handle.update((Value::Eid(e), v), diff);
+        }
+
+        for handle in self.input_sessions.values_mut() {
+            handle.flush();
+        }
+
+        Ok(())
+    }
+
     /// Closes and drops an existing input.
     pub fn close_input(&mut self, name: String) -> Result<(), Error> {
         match self.input_sessions.remove(&name) {
@@ -340,6 +642,10 @@ where
                 handle.advance_to(next.clone());
                 handle.flush();
             }
+            for handle in self.parameter_sessions.values_mut() {
+                handle.advance_to(next.clone());
+                handle.flush();
+            }
             self.now_at = next;
 
             Ok(())
@@ -423,6 +729,13 @@ where
         &self.domain_probe
     }
 
+    /// Reports the frontier to which traces in this domain have been
+    /// advanced. Times not beyond this frontier can no longer be
+    /// distinguished from one another.
+    pub fn compaction_frontier(&self) -> &[T] {
+        &self.last_advance
+    }
+
     /// Reports the current input epoch.
     pub fn epoch(&self) -> &T {
         &self.now_at
@@ -0,0 +1,183 @@
+//! Errors reported by the fallible parts of the API.
+//!
+//! Errors are categorized along the lines of Cognitect's anomalies,
+//! s.t. clients can decide how to react without parsing messages. The
+//! constructors for specific failures (unknown attributes, unbound
+//! variables, ...) map onto one of these categories and should be
+//! preferred over the general ones, to keep messages consistent.
+//! They also record the specific failure as an `ErrorKind`, for
+//! embedders that want to match on it.
+
+use std::fmt;
+
+use crate::{Aid, Value, Var};
+
+/// The specific failure behind an error. Kinds are not part of the
+/// wire format, errors received from elsewhere are always of kind
+/// `Other` and should be told apart by their category instead.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ErrorKind {
+    /// A reference to an attribute that hasn't been created.
+    AttributeNotFound(Aid),
+    /// A pattern on an attribute lacking the index required to
+    /// implement it.
+    IndexNotBuilt(Aid),
+    /// A reference to a rule or input that hasn't been registered.
+    UnknownName(String),
+    /// A variable that none of the inputs of a plan bind.
+    UnboundVariable(Var),
+    /// A value whose type doesn't match the one declared for its
+    /// attribute.
+    TypeMismatch {
+        /// The attribute the value was meant for.
+        aid: Aid,
+        /// A value of the declared type.
+        expected: Value,
+        /// The offending value.
+        actual: Value,
+    },
+    /// Input that couldn't be parsed.
+    Parse,
+    /// An operation that isn't supported.
+    Unsupported,
+    /// Any other failure, described by category and message only.
+    Other,
+}
+
+impl Default for ErrorKind {
+    fn default() -> Self {
+        ErrorKind::Other
+    }
+}
+
+/// A client-facing, non-exceptional error.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Error {
+    /// Error category.
+    #[serde(rename = "df.error/category")]
+    pub category: String,
+    /// Free-frorm description.
+    #[serde(rename = "df.error/message")]
+    pub message: String,
+    /// The specific failure, if known.
+    #[serde(skip)]
+    pub kind: ErrorKind,
+}
+
+impl Error {
+    /// Attaches the specified kind to this error.
+    fn with_kind(mut self, kind: ErrorKind) -> Error {
+        self.kind = kind;
+        self
+    }
+
+    /// Fix client bug.
+    pub fn incorrect<E: std::string::ToString>(error: E) -> Error {
+        Error {
+            category: "df.error.category/incorrect".to_string(),
+            message: error.to_string(),
+            kind: ErrorKind::Other,
+        }
+    }
+
+    /// Fix client noun.
+    pub fn not_found<E: std::string::ToString>(error: E) -> Error {
+        Error {
+            category: "df.error.category/not-found".to_string(),
+            message: error.to_string(),
+            kind: ErrorKind::Other,
+        }
+    }
+
+    /// Coordinate with worker.
+    pub fn conflict<E: std::string::ToString>(error: E) -> Error {
+        Error {
+            category: "df.error.category/conflict".to_string(),
+            message: error.to_string(),
+            kind: ErrorKind::Other,
+        }
+    }
+
+    /// Fix worker bug.
+    pub fn fault<E: std::string::ToString>(error: E) -> Error {
+        Error {
+            category: "df.error.category/fault".to_string(),
+            message: error.to_string(),
+            kind: ErrorKind::Other,
+        }
+    }
+
+    /// Fix client verb.
+    pub fn unsupported<E: std::string::ToString>(error: E) -> Error {
+        Error {
+            category: "df.error.category/unsupported".to_string(),
+            message: error.to_string(),
+            kind: ErrorKind::Unsupported,
+        }
+    }
+
+    /// Fix client permissions.
+    pub fn forbidden<E: std::string::ToString>(error: E) -> Error {
+        Error {
+            category: "df.error.category/forbidden".to_string(),
+            message: error.to_string(),
+            kind: ErrorKind::Other,
+        }
+    }
+
+    /// Fix client noun, specifically a reference to an attribute
+    /// that hasn't been created.
+    pub fn attribute_not_found(aid: &str) -> Error {
+        Error::not_found(format!("Attribute {} does not exist.", aid))
+            .with_kind(ErrorKind::AttributeNotFound(aid.to_string()))
+    }
+
+    /// Fix client noun, specifically a pattern on an attribute that
+    /// exists, but lacks the index required to implement it.
+    pub fn index_not_built(aid: &str) -> Error {
+        Error::not_found(format!("No propose index built for attribute {}.", aid))
+            .with_kind(ErrorKind::IndexNotBuilt(aid.to_string()))
+    }
+
+    /// Fix client noun, specifically a reference to a rule or input
+    /// that hasn't been registered.
+    pub fn unknown_name(name: &str) -> Error {
+        Error::not_found(format!("Unknown rule {}.", name))
+            .with_kind(ErrorKind::UnknownName(name.to_string()))
+    }
+
+    /// Fix client bug, specifically a plan referring to a variable
+    /// that none of its inputs bind.
+    pub fn unbound_variable(variable: Var) -> Error {
+        Error::incorrect(format!("Variable {} is not bound by any input.", variable))
+            .with_kind(ErrorKind::UnboundVariable(variable))
+    }
+
+    /// Fix client bug, specifically a value whose type doesn't match
+    /// the one declared for its attribute.
+    pub fn type_mismatch(aid: &str, expected: &Value, actual: &Value) -> Error {
+        Error::incorrect(format!(
+            "Type mismatch on attribute {}: expected a value like {:?}, got {:?}.",
+            aid, expected, actual
+        ))
+        .with_kind(ErrorKind::TypeMismatch {
+            aid: aid.to_string(),
+            expected: expected.clone(),
+            actual: actual.clone(),
+        })
+    }
+
+    /// Fix client bug, specifically input that couldn't be parsed.
+    pub fn parse<E: std::string::ToString>(error: E) -> Error {
+        Error::incorrect(format!("Failed to parse input: {}", error.to_string()))
+            .with_kind(ErrorKind::Parse)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.category, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
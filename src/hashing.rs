@@ -0,0 +1,62 @@
+//! Stable hashing.
+//!
+//! The standard library's `DefaultHasher` is free to change its
+//! algorithm between releases. Wherever hashes must agree between
+//! workers (e.g. symbols, canonical plan hashes, or sampling
+//! decisions), we use the 64 bit FNV-1a hash instead, which is fully
+//! specified.
+//!
+//! Structured values are fed to the hasher via their `Hash` impls,
+//! whose encoding (e.g. of enum discriminants, lengths, or string
+//! terminators) isn't guaranteed by the standard library either.
+//! Hashes are therefore deterministic across all processes running
+//! the same build, but must not be persisted or compared across
+//! builds.
+
+use std::hash::{Hash, Hasher};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// A 64 bit FNV-1a hasher. Unlike `DefaultHasher`, the same bytes
+/// always result in the same hash, regardless of the Rust release
+/// or the process computing it.
+#[derive(Clone, Copy, Debug)]
+pub struct StableHasher {
+    state: u64,
+}
+
+impl StableHasher {
+    /// Creates a new hasher.
+    pub fn new() -> Self {
+        StableHasher {
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            self.state ^= u64::from(*byte);
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+/// Hashes the given value with a `StableHasher`.
+pub fn stable_hash<H: Hash + ?Sized>(value: &H) -> u64 {
+    let mut hasher = StableHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
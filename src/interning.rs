@@ -0,0 +1,128 @@
+//! Interning of string values.
+//!
+//! Low-cardinality strings are better represented by compact
+//! symbols, which are cheap to clone, hash, and compare. Sources may
+//! intern strings on ingest, by specifying `Value::Interned` as a
+//! type hint.
+//!
+//! Symbols are derived from the strings they represent via a stable
+//! hash. Workers therefore agree on symbols without having to
+//! coordinate, even if they ingest different records or live in
+//! different processes (running the same build). Resolving a symbol back into its string
+//! requires a table that has seen the string however.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+
+use crate::hashing::stable_hash;
+use crate::{Error, Value};
+
+/// A compact stand-in for an interned string.
+pub type Symbol = u64;
+
+#[derive(Default)]
+struct Symbols {
+    strings: HashMap<Symbol, String>,
+}
+
+lazy_static! {
+    static ref GLOBAL: SymbolTable = SymbolTable::new();
+}
+
+/// A symbol table mapping strings to symbols and back. Clones share
+/// the same underlying table.
+#[derive(Clone, Default)]
+pub struct SymbolTable {
+    inner: Arc<RwLock<Symbols>>,
+}
+
+impl SymbolTable {
+    /// Creates a new, empty symbol table.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the process-wide symbol table, shared by all servers
+    /// in this process and used to resolve symbols on output.
+    pub fn global() -> Self {
+        GLOBAL.clone()
+    }
+
+    /// Returns the symbol for the given string, remembering the
+    /// string for later resolution if it hasn't been seen before.
+    /// Fails if a different string has been interned under the same
+    /// symbol already.
+    pub fn intern(&self, string: &str) -> Result<Symbol, Error> {
+        let symbol = stable_hash(string);
+
+        {
+            let symbols = self.inner.read().expect("symbol table poisoned");
+            if let Some(known) = symbols.strings.get(&symbol) {
+                return check_collision(symbol, known, string);
+            }
+        }
+
+        let mut symbols = self.inner.write().expect("symbol table poisoned");
+        let known = symbols
+            .strings
+            .entry(symbol)
+            .or_insert_with(|| string.to_string());
+
+        check_collision(symbol, known, string)
+    }
+
+    /// Returns the symbol for the given string, if it has been
+    /// interned already.
+    pub fn lookup(&self, string: &str) -> Option<Symbol> {
+        let symbol = stable_hash(string);
+        let symbols = self.inner.read().expect("symbol table poisoned");
+
+        match symbols.strings.get(&symbol) {
+            Some(known) if known == string => Some(symbol),
+            _ => None,
+        }
+    }
+
+    /// Returns the string represented by the given symbol.
+    pub fn resolve(&self, symbol: Symbol) -> Option<String> {
+        let symbols = self.inner.read().expect("symbol table poisoned");
+        symbols.strings.get(&symbol).cloned()
+    }
+
+    /// Replaces interned values by the strings they represent,
+    /// leaving all other values untouched. Symbols unknown to this
+    /// table are left untouched as well.
+    pub fn resolve_value(&self, value: Value) -> Value {
+        match value {
+            Value::Interned(symbol) => match self.resolve(symbol) {
+                None => Value::Interned(symbol),
+                Some(string) => Value::String(string),
+            },
+            other => other,
+        }
+    }
+
+    /// Number of distinct strings interned so far.
+    pub fn len(&self) -> usize {
+        let symbols = self.inner.read().expect("symbol table poisoned");
+        symbols.strings.len()
+    }
+
+    /// Returns true iff no strings have been interned yet.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn check_collision(symbol: Symbol, known: &str, string: &str) -> Result<Symbol, Error> {
+    if known == string {
+        Ok(symbol)
+    } else {
+        Err(Error::conflict(format!(
+            "Strings {:?} and {:?} both hash to symbol {}",
+            known, string, symbol
+        )))
+    }
+}
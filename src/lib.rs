@@ -13,13 +13,20 @@ extern crate serde_derive;
 
 pub mod binding;
 pub mod domain;
+pub mod error;
+pub mod hashing;
+pub mod interning;
 pub mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod operators;
 pub mod plan;
 pub mod scheduling;
 pub mod server;
 pub mod sinks;
 pub mod sources;
+#[cfg(feature = "test-util")]
+pub mod testing;
 pub mod timestamp;
 
 use std::collections::{HashMap, HashSet, VecDeque};
@@ -32,9 +39,8 @@ use timely::order::Product;
 use timely::progress::Timestamp;
 
 use differential_dataflow::lattice::Lattice;
-use differential_dataflow::operators::arrange::{ShutdownButton, TraceAgent};
+use differential_dataflow::operators::arrange::{Arrange, Arranged, ShutdownButton, TraceAgent};
 use differential_dataflow::operators::iterate::Variable;
-#[cfg(not(feature = "set-semantics"))]
 use differential_dataflow::operators::Consolidate;
 #[cfg(feature = "set-semantics")]
 use differential_dataflow::operators::Threshold;
@@ -47,8 +53,9 @@ pub use uuid::Uuid;
 pub use num_rational::Rational32;
 
 pub use binding::{AsBinding, AttributeBinding, Binding};
+pub use error::{Error, ErrorKind};
 pub use plan::{Hector, ImplContext, Implementable, Plan};
-pub use timestamp::{Rewind, Time};
+pub use timestamp::{Rewind, Time, TimestampValue};
 
 /// A unique entity identifier.
 pub type Eid = u64;
@@ -70,12 +77,19 @@ pub enum Value {
     Bool(bool),
     /// A 64 bit signed integer
     Number(i64),
+    /// A 64 bit unsigned integer, e.g. for counts that might exceed
+    /// the range of `Number`
+    UNumber(u64),
     /// A 32 bit rational
     Rational32(Rational32),
     /// An entity identifier
     Eid(Eid),
     /// Milliseconds since midnight, January 1, 1970 UTC
     Instant(u64),
+    /// A string interned into a symbol table.
+    Interned(interning::Symbol),
+    /// The absence of a value, e.g. for optional attributes.
+    Null,
     /// A 16 byte unique identifier.
     #[cfg(feature = "uuid")]
     Uuid(Uuid),
@@ -90,6 +104,26 @@ impl Value {
         Value::Aid(v.to_string())
     }
 
+    /// Hashes the value under the specified seed. Different seeds
+    /// yield independent distributions, which can be used to spread
+    /// clustered keys across workers in custom exchanges. Hashes are
+    /// deterministic within one build, see the `hashing` module.
+    ///
+    /// Note that exchanges feeding into existing arrangements (as in
+    /// Hector's count, propose, and validate stages) must agree with
+    /// the partitioning of those arrangements, which is always
+    /// determined by `Hashable::hashed`. Seeded hashes are therefore
+    /// only safe to use where the receiving side is partitioned by
+    /// the same seed.
+    pub fn hashed_with(&self, seed: u64) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = hashing::StableHasher::new();
+        seed.hash(&mut hasher);
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
     /// Helper to create a UUID value from a string representation.
     #[cfg(feature = "uuid")]
     pub fn uuid_str(v: &str) -> Self {
@@ -114,6 +148,10 @@ impl std::convert::From<f64> for Value {
     }
 }
 
+/// Rationals are represented as `{"num": <numerator>, "den":
+/// <denominator>}` objects, s.t. no precision is lost. Interned
+/// strings are resolved via the global symbol table. Instants are
+/// represented by their milliseconds since the epoch.
 #[cfg(feature = "serde_json")]
 impl std::convert::From<Value> for serde_json::Value {
     fn from(v: Value) -> Self {
@@ -123,7 +161,79 @@ impl std::convert::From<Value> for serde_json::Value {
             Value::String(v) => serde_json::Value::String(v),
             Value::Bool(v) => serde_json::Value::Bool(v),
             Value::Number(v) => serde_json::Value::Number(serde_json::Number::from(v)),
-            _ => unimplemented!(),
+            Value::UNumber(v) => serde_json::Value::Number(serde_json::Number::from(v)),
+            Value::Rational32(v) => {
+                let mut object = serde_json::Map::new();
+                object.insert("num".to_string(), serde_json::Value::from(*v.numer()));
+                object.insert("den".to_string(), serde_json::Value::from(*v.denom()));
+                serde_json::Value::Object(object)
+            }
+            Value::Interned(symbol) => match interning::SymbolTable::global().resolve(symbol) {
+                Some(string) => serde_json::Value::String(string),
+                // Interned by another process, we can't do better.
+                None => serde_json::Value::Number(serde_json::Number::from(symbol)),
+            },
+            Value::Instant(v) => serde_json::Value::Number(serde_json::Number::from(v)),
+            Value::Null => serde_json::Value::Null,
+            #[cfg(feature = "uuid")]
+            Value::Uuid(v) => serde_json::Value::String(v.to_string()),
+            #[cfg(feature = "real")]
+            Value::Real(v) => {
+                // Reals are printed exactly, and all of them fit into
+                // an f64.
+                let float: f64 = v.to_string().parse().expect("failed to convert real");
+                serde_json::Value::from(float)
+            }
+        }
+    }
+}
+
+/// Parses the JSON representation of a value, as produced by
+/// `serde_json::Value::from`. JSON can't tell apart all kinds of
+/// values, therefore strings are read back as `Value::String` and
+/// integers as `Value::Number`, unless they exceed its range. The
+/// only objects recognized are `{"num": <numerator>, "den":
+/// <denominator>}` rationals.
+#[cfg(feature = "serde_json")]
+impl std::convert::TryFrom<serde_json::Value> for Value {
+    type Error = Error;
+
+    fn try_from(json: serde_json::Value) -> Result<Self, Error> {
+        use std::convert::TryFrom;
+
+        match json {
+            serde_json::Value::Null => Ok(Value::Null),
+            serde_json::Value::Bool(v) => Ok(Value::Bool(v)),
+            serde_json::Value::String(v) => Ok(Value::String(v)),
+            serde_json::Value::Number(v) => {
+                if let Some(number) = v.as_i64() {
+                    Ok(Value::Number(number))
+                } else if let Some(number) = v.as_u64() {
+                    Ok(Value::UNumber(number))
+                } else {
+                    Err(Error::parse(format!("{} is not an integer", v)))
+                }
+            }
+            serde_json::Value::Object(ref object) if object.len() == 2 => {
+                let component = |key: &str| {
+                    object
+                        .get(key)
+                        .and_then(|v| v.as_i64())
+                        .and_then(|v| i32::try_from(v).ok())
+                        .ok_or_else(|| {
+                            Error::parse(format!("{} is not a 32 bit integer in {}", key, json))
+                        })
+                };
+
+                let (numer, denom) = (component("num")?, component("den")?);
+
+                if denom == 0 {
+                    Err(Error::parse(format!("zero denominator in {}", json)))
+                } else {
+                    Ok(Value::Rational32(Rational32::new(numer, denom)))
+                }
+            }
+            other => Err(Error::parse(format!("{} can't be read as a value", other))),
         }
     }
 }
@@ -138,59 +248,6 @@ impl std::convert::From<Value> for Eid {
     }
 }
 
-/// A client-facing, non-exceptional error.
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Error {
-    /// Error category.
-    #[serde(rename = "df.error/category")]
-    pub category: String,
-    /// Free-frorm description.
-    #[serde(rename = "df.error/message")]
-    pub message: String,
-}
-
-impl Error {
-    /// Fix client bug.
-    pub fn incorrect<E: std::string::ToString>(error: E) -> Error {
-        Error {
-            category: "df.error.category/incorrect".to_string(),
-            message: error.to_string(),
-        }
-    }
-
-    /// Fix client noun.
-    pub fn not_found<E: std::string::ToString>(error: E) -> Error {
-        Error {
-            category: "df.error.category/not-found".to_string(),
-            message: error.to_string(),
-        }
-    }
-
-    /// Coordinate with worker.
-    pub fn conflict<E: std::string::ToString>(error: E) -> Error {
-        Error {
-            category: "df.error.category/conflict".to_string(),
-            message: error.to_string(),
-        }
-    }
-
-    /// Fix worker bug.
-    pub fn fault<E: std::string::ToString>(error: E) -> Error {
-        Error {
-            category: "df.error.category/fault".to_string(),
-            message: error.to_string(),
-        }
-    }
-
-    /// Fix client verb.
-    pub fn unsupported<E: std::string::ToString>(error: E) -> Error {
-        Error {
-            category: "df.error.category/unsupported".to_string(),
-            message: error.to_string(),
-        }
-    }
-}
-
 /// Transaction data.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub struct TxData(pub isize, pub Value, pub Aid, pub Value, pub Option<Time>);
@@ -266,14 +323,17 @@ impl<T> Shutdownable for ShutdownButton<T> {
 }
 
 /// A wrapper around a vector of ShutdownButton's. Ensures they will
-/// be pressed on dropping the handle.
+/// be pressed on dropping the handle. Buttons can optionally be
+/// tagged (e.g. with the name of the arrangement they control), s.t.
+/// individual parts of a composite handle can be shut down on their
+/// own.
 pub struct ShutdownHandle {
-    shutdown_buttons: Vec<Box<dyn Shutdownable>>,
+    shutdown_buttons: Vec<(Option<String>, Box<dyn Shutdownable>)>,
 }
 
 impl Drop for ShutdownHandle {
     fn drop(&mut self) {
-        for mut button in self.shutdown_buttons.drain(..) {
+        for (_tag, mut button) in self.shutdown_buttons.drain(..) {
             trace!("pressing shutdown button");
             button.press();
         }
@@ -291,7 +351,18 @@ impl ShutdownHandle {
     /// Wraps a single shutdown button into a shutdown handle.
     pub fn from_button<T: Timestamp>(button: ShutdownButton<CapabilitySet<T>>) -> Self {
         ShutdownHandle {
-            shutdown_buttons: vec![Box::new(button)],
+            shutdown_buttons: vec![(None, Box::new(button))],
+        }
+    }
+
+    /// Wraps a single shutdown button into a shutdown handle, tagging
+    /// it with the given name.
+    pub fn from_tagged_button<T: Timestamp>(
+        tag: &str,
+        button: ShutdownButton<CapabilitySet<T>>,
+    ) -> Self {
+        ShutdownHandle {
+            shutdown_buttons: vec![(Some(tag.to_string()), Box::new(button))],
         }
     }
 
@@ -299,7 +370,59 @@ impl ShutdownHandle {
     /// then also be pressed, whenever the handle is shut down or
     /// dropped.
     pub fn add_button<T: Timestamp>(&mut self, button: ShutdownButton<CapabilitySet<T>>) {
-        self.shutdown_buttons.push(Box::new(button));
+        self.shutdown_buttons.push((None, Box::new(button)));
+    }
+
+    /// Adds another shutdown button to this handle, tagging it with
+    /// the given name.
+    pub fn add_tagged_button<T: Timestamp>(
+        &mut self,
+        tag: &str,
+        button: ShutdownButton<CapabilitySet<T>>,
+    ) {
+        self.shutdown_buttons
+            .push((Some(tag.to_string()), Box::new(button)));
+    }
+
+    /// Returns the number of buttons held by this handle.
+    pub fn len(&self) -> usize {
+        self.shutdown_buttons.len()
+    }
+
+    /// Returns true iff this handle holds no buttons.
+    pub fn is_empty(&self) -> bool {
+        self.shutdown_buttons.is_empty()
+    }
+
+    /// Returns the tags of all tagged buttons held by this handle, in
+    /// the order they were added. Tags need not be unique.
+    pub fn tags(&self) -> Vec<&str> {
+        self.shutdown_buttons
+            .iter()
+            .filter_map(|(tag, _button)| tag.as_ref().map(|tag| tag.as_str()))
+            .collect()
+    }
+
+    /// Presses and removes all buttons tagged with the given name,
+    /// leaving all others untouched. Returns the number of buttons
+    /// pressed.
+    pub fn shutdown_tagged(&mut self, tag: &str) -> usize {
+        let mut pressed = 0;
+        let mut remaining = Vec::with_capacity(self.shutdown_buttons.len());
+
+        for (button_tag, mut button) in self.shutdown_buttons.drain(..) {
+            if button_tag.as_ref().map(|x| x.as_str()) == Some(tag) {
+                trace!("pressing shutdown button {}", tag);
+                button.press();
+                pressed += 1;
+            } else {
+                remaining.push((button_tag, button));
+            }
+        }
+
+        self.shutdown_buttons = remaining;
+
+        pressed
     }
 
     /// Combines the buttons of another handle into self.
@@ -385,6 +508,11 @@ pub struct AttributeConfig {
     /// dimension? Timeless attributes do not have an
     /// influence on the overall progress in the system.
     pub timeless: bool,
+    /// Optionally, a type hint (as in the CSV source schema) that all
+    /// values transacted into this attribute must match. The contents
+    /// of the hint are ignored, only its variant matters. Null values
+    /// are always accepted.
+    pub value_type: Option<Value>,
 }
 
 impl Default for AttributeConfig {
@@ -395,6 +523,7 @@ impl Default for AttributeConfig {
             index_direction: IndexDirection::Forward,
             query_support: QuerySupport::Basic,
             timeless: false,
+            value_type: None,
         }
     }
 }
@@ -462,7 +591,7 @@ pub struct Rule {
 /// Relations can be backed by a collection of records of type
 /// `Vec<Value>`, each of a common length (with offsets corresponding
 /// to the variable offsets), or by an existing arrangement.
-trait Relation<'a, G, I>: AsBinding
+pub trait Relation<'a, G, I>: AsBinding
 where
     G: Scope,
     G::Timestamp: Lattice + ExchangeData,
@@ -478,6 +607,25 @@ where
         ShutdownHandle,
     );
 
+    /// A collection containing all tuples, with all updates to the
+    /// same tuple at the same time merged into a single net diff.
+    /// Unlike `distinct`, this preserves multiplicities, it only
+    /// removes churn (e.g. a +1 and a -1 cancelling each other out).
+    fn consolidated(
+        self,
+        nested: &mut Iterative<'a, G, u64>,
+        context: &mut I,
+    ) -> (
+        Collection<Iterative<'a, G, u64>, Vec<Value>, isize>,
+        ShutdownHandle,
+    )
+    where
+        Self: Sized,
+    {
+        let (tuples, shutdown_handle) = self.tuples(nested, context);
+        (tuples.consolidate(), shutdown_handle)
+    }
+
     /// A collection containing all tuples projected onto the
     /// specified variables.
     fn projected(
@@ -505,6 +653,28 @@ where
         Collection<Iterative<'a, G, u64>, (Vec<Value>, Vec<Value>), isize>,
         ShutdownHandle,
     );
+
+    /// An arrangement of all tuples, keyed by `variables`. Keys and
+    /// values are laid out as in `tuples_by_variables`. This allows
+    /// plan stages to make use of `join_core` and friends directly.
+    fn arranged_by_variables(
+        self,
+        nested: &mut Iterative<'a, G, u64>,
+        context: &mut I,
+        variables: &[Var],
+    ) -> (
+        Arranged<
+            Iterative<'a, G, u64>,
+            TraceValHandle<Vec<Value>, Vec<Value>, Product<G::Timestamp, u64>, isize>,
+        >,
+        ShutdownHandle,
+    )
+    where
+        Self: Sized,
+    {
+        let (tuples, shutdown_handle) = self.tuples_by_variables(nested, context, variables);
+        (tuples.arrange(), shutdown_handle)
+    }
 }
 
 /// A collection and variable bindings.
@@ -513,6 +683,19 @@ pub struct CollectionRelation<'a, G: Scope> {
     tuples: Collection<Iterative<'a, G, u64>, Vec<Value>, isize>,
 }
 
+impl<'a, G: Scope> CollectionRelation<'a, G> {
+    /// Wraps an externally constructed collection of tuples, binding
+    /// the i-th element of each tuple to the i-th variable. Collections
+    /// built outside of the iterative scope have to be brought into it
+    /// via `enter` first.
+    pub fn from_collection(
+        variables: Vec<Var>,
+        tuples: Collection<Iterative<'a, G, u64>, Vec<Value>, isize>,
+    ) -> Self {
+        CollectionRelation { variables, tuples }
+    }
+}
+
 impl<'a, G: Scope> AsBinding for CollectionRelation<'a, G>
 where
     G::Timestamp: Lattice + ExchangeData,
@@ -715,6 +898,8 @@ where
                     tuples.as_collection(|e, v| (vec![e.clone()], vec![v.clone()]))
                 } else if variables == [v] {
                     tuples.as_collection(|e, v| (vec![v.clone()], vec![e.clone()]))
+                } else if variables.is_empty() {
+                    tuples.as_collection(|e, v| (vec![], vec![e.clone(), v.clone()]))
                 } else {
                     panic!("invalid projection")
                 };
@@ -849,6 +1034,7 @@ pub fn q(target_variables: Vec<Var>, bindings: Vec<Binding>) -> Plan {
     Plan::Hector(Hector {
         variables: target_variables,
         bindings,
+        order: None,
     })
 }
 
@@ -866,7 +1052,7 @@ where
     for name in names {
         match context.rule(name) {
             None => {
-                return Err(Error::not_found(format!("Unknown rule {}.", name)));
+                return Err(Error::unknown_name(&name));
             }
             Some(rule) => {
                 seen.insert(name.to_string());
@@ -880,8 +1066,13 @@ where
         for dep_name in dependencies.names.iter() {
             if !seen.contains(dep_name) {
                 match context.rule(dep_name) {
+                    None if context.has_relation(dep_name) => {
+                        // Externally registered relations are read
+                        // from their arrangement and have no rule.
+                        seen.insert(dep_name.to_string());
+                    }
                     None => {
-                        return Err(Error::not_found(format!("Unknown rule {}", dep_name)));
+                        return Err(Error::unknown_name(&dep_name));
                     }
                     Some(rule) => {
                         seen.insert(dep_name.to_string());
@@ -891,13 +1082,51 @@ where
             }
         }
 
-        // Ensure all required attributes exist.
+        // Ensure all required attributes exist. Patterns may also
+        // match against binary rules and relations, but attributes
+        // take precedence.
         for aid in dependencies.attributes.iter() {
-            if !context.has_attribute(aid) {
-                return Err(Error::not_found(format!(
-                    "Rule depends on unknown attribute {}",
-                    aid
-                )));
+            if context.has_attribute(aid) {
+                if !context.is_attribute_allowed(aid) {
+                    return Err(Error::forbidden(format!(
+                        "Access to attribute {} is not allowed",
+                        aid
+                    )));
+                }
+
+                continue;
+            } else if seen.contains(aid) {
+                continue;
+            }
+
+            match context.rule(aid) {
+                None if context.has_relation(aid) => {
+                    seen.insert(aid.to_string());
+                }
+                None => {
+                    if let Some(renamed) = context.renamed_attribute(aid) {
+                        return Err(Error::not_found(format!(
+                            "Rule depends on attribute {}, which has been renamed to {}",
+                            aid, renamed
+                        )));
+                    }
+
+                    return Err(Error::not_found(format!(
+                        "Rule depends on unknown attribute {}",
+                        aid
+                    )));
+                }
+                Some(rule) if rule.plan.variables().len() != 2 => {
+                    return Err(Error::incorrect(format!(
+                        "Rule {} is matched as an attribute, but binds {} variables instead of two",
+                        aid,
+                        rule.plan.variables().len()
+                    )));
+                }
+                Some(rule) => {
+                    seen.insert(aid.to_string());
+                    queue.push_back(rule.clone());
+                }
             }
         }
 
@@ -907,6 +1136,96 @@ where
     Ok(rules)
 }
 
+/// Returns the names a plan refers to, including attributes, as
+/// patterns may also match against binary rules.
+fn referenced_names(plan: &Plan) -> HashSet<String> {
+    let dependencies = plan.dependencies();
+
+    dependencies
+        .names
+        .into_iter()
+        .chain(dependencies.attributes.into_iter())
+        .collect()
+}
+
+/// Returns the names a plan refers to from within a negation, i.e.
+/// via `Negate`, on the right-hand side of an `Antijoin`, or via
+/// negated bindings of a `Hector`.
+fn negated_names(plan: &Plan) -> HashSet<String> {
+    match *plan {
+        Plan::Negate(ref plan) => referenced_names(plan),
+        Plan::Antijoin(ref antijoin) => {
+            let mut names = negated_names(&antijoin.left_plan);
+            names.extend(referenced_names(&antijoin.right_plan));
+            names
+        }
+        Plan::Hector(ref hector) => hector
+            .bindings
+            .iter()
+            .filter_map(|binding| match *binding {
+                Binding::Not(_) => binding
+                    .source()
+                    .map(|binding| binding.source_attribute.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => plan.inputs().into_iter().flat_map(negated_names).collect(),
+    }
+}
+
+/// Ensures that negation within the given rules is stratified,
+/// i.e. that no rule depends on any of the names it negates, neither
+/// directly nor via other rules. Negation within a recursive cycle
+/// has no well-defined result and is therefore rejected.
+pub fn check_stratification<'a, R>(rules: R) -> Result<(), Error>
+where
+    R: IntoIterator<Item = &'a Rule>,
+{
+    let rules: Vec<&Rule> = rules.into_iter().collect();
+    let edges: HashMap<&str, HashSet<String>> = rules
+        .iter()
+        .map(|rule| (rule.name.as_str(), referenced_names(&rule.plan)))
+        .collect();
+
+    // Returns true iff `to` is reachable from `from`.
+    let reaches = |from: &str, to: &str| {
+        let mut seen = HashSet::new();
+        let mut stack = vec![from.to_string()];
+
+        while let Some(next) = stack.pop() {
+            if next == to {
+                return true;
+            }
+
+            if let Some(names) = edges.get(next.as_str()) {
+                for name in names.iter() {
+                    if seen.insert(name.clone()) {
+                        stack.push(name.clone());
+                    }
+                }
+            }
+        }
+
+        false
+    };
+
+    for rule in rules.iter() {
+        let mut negated: Vec<String> = negated_names(&rule.plan).into_iter().collect();
+        negated.sort();
+
+        for name in negated.iter() {
+            if reaches(name, &rule.name) {
+                return Err(Error::incorrect(format!(
+                    "Rule {} negates {}, which depends on {} in turn. Negation must not occur within a recursive cycle.",
+                    rule.name, name, rule.name
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Takes a query plan and turns it into a differential dataflow.
 pub fn implement<T, I, S>(
     name: &str,
@@ -920,7 +1239,7 @@ pub fn implement<T, I, S>(
     Error,
 >
 where
-    T: Timestamp + Lattice + Default,
+    T: Timestamp + Lattice + TimestampValue + Default,
     I: ImplContext<T>,
     S: Scope<Timestamp = T>,
 {
@@ -928,6 +1247,10 @@ where
         let publish = vec![name];
         let mut rules = collect_dependencies(&*context, &publish[..])?;
 
+        for rule in rules.iter() {
+            rule.plan.check_patterns(context)?;
+        }
+
         let mut local_arrangements = VariableMap::new();
         let mut result_map = HashMap::new();
 
@@ -1021,7 +1344,7 @@ pub fn implement_neu<T, I, S>(
     Error,
 >
 where
-    T: Timestamp + Lattice + Default,
+    T: Timestamp + Lattice + TimestampValue + Default,
     I: ImplContext<T>,
     S: Scope<Timestamp = T>,
 {
@@ -1029,6 +1352,10 @@ where
         let publish = vec![name];
         let mut rules = collect_dependencies(&*context, &publish[..])?;
 
+        for rule in rules.iter() {
+            rule.plan.check_patterns(context)?;
+        }
+
         let mut local_arrangements = VariableMap::new();
         let mut result_map = HashMap::new();
 
@@ -1086,7 +1413,8 @@ where
         for rule in rules.iter() {
             info!("neu_planning {:?}", rule.name);
 
-            let plan = q(rule.plan.variables(), rule.plan.into_bindings());
+            let bindings = rule.plan.into_bindings_with(&mut || context.gensym());
+            let plan = q(rule.plan.variables(), bindings);
 
             let (relation, shutdown) = plan.implement(nested, &local_arrangements, context);
 
@@ -0,0 +1,165 @@
+//! Throughput metrics for plan stages.
+
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Operator;
+use timely::dataflow::scopes::child::Iterative;
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::{Collection, ExchangeData};
+
+use crate::binding::AttributeBinding;
+use crate::plan::ImplContext;
+use crate::timestamp::TimestampValue;
+use crate::{Implemented, Relation, ShutdownHandle, Value};
+
+/// A receiver of throughput measurements, e.g. a bridge into a
+/// metrics registry. Sinks are shared across all workers and must
+/// therefore be thread-safe.
+pub trait MetricsSink: Send + Sync {
+    /// Records that the plan stage described by `operator` emitted
+    /// `tuples_processed` updates. Called once per batch of updates,
+    /// thus counts should be accumulated by the sink.
+    fn record(&self, operator: &str, tuples_processed: usize);
+
+    /// Records that the plan stage described by `operator` was fed
+    /// `tuples` updates by its inputs. Called once per batch of
+    /// updates, as for `record`.
+    fn record_input(&self, _operator: &str, _tuples: usize) {}
+
+    /// Records that the output frontier of the plan stage described
+    /// by `operator` advanced, i.e. that it won't produce updates at
+    /// times not beyond any of the specified ones anymore. Times are
+    /// encoded as by `TimestampValue::to_value` and an empty frontier
+    /// signals that the stage is complete. Frontiers of timestamps
+    /// without such an encoding are not reported.
+    fn record_frontier(&self, _operator: &str, _frontier: &[Value]) {}
+}
+
+thread_local! {
+    // The stage consuming the relations currently being implemented,
+    // if any. Plans are implemented recursively on each worker, thus
+    // this is maintained per thread.
+    static CONSUMER: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Marks the stage described by `operator` (or none) as the consumer
+/// of all relations implemented until the next call. Returns the
+/// previous consumer, which should be restored afterwards.
+pub(crate) fn consume(operator: Option<String>) -> Option<String> {
+    CONSUMER.with(|consumer| consumer.replace(operator))
+}
+
+/// Counts the updates in a stream, reporting them as either inputs
+/// or outputs of a stage. Outputs also report their frontier.
+fn observe<'a, S>(
+    tuples: &Collection<Iterative<'a, S, u64>, Vec<Value>, isize>,
+    operator: String,
+    sink: Arc<dyn MetricsSink>,
+    is_output: bool,
+) where
+    S: Scope,
+    S::Timestamp: Lattice + ExchangeData + TimestampValue,
+{
+    let mut frontier = None;
+
+    tuples.inner.sink(Pipeline, "Metrics", move |input| {
+        input.for_each(|_time, data| {
+            if is_output {
+                sink.record(&operator, data.len());
+            } else {
+                sink.record_input(&operator, data.len());
+            }
+        });
+
+        if is_output {
+            let current: Option<Vec<Value>> = input
+                .frontier()
+                .frontier()
+                .iter()
+                .map(|t| t.outer.to_value())
+                .collect();
+
+            if let Some(mut current) = current {
+                current.sort();
+                current.dedup();
+
+                if frontier.as_ref() != Some(&current) {
+                    sink.record_frontier(&operator, &current);
+                    frontier = Some(current);
+                }
+            }
+        }
+    });
+}
+
+/// Reports the number of updates produced by an implemented plan
+/// stage, as well as its output frontier, to the specified sink.
+/// Stages implemented directly by an attribute don't do any work of
+/// their own and are passed through.
+pub(crate) fn instrument<'a, S>(
+    operator: String,
+    sink: Arc<dyn MetricsSink>,
+    implemented: (Implemented<'a, S>, ShutdownHandle),
+) -> (Implemented<'a, S>, ShutdownHandle)
+where
+    S: Scope,
+    S::Timestamp: Lattice + ExchangeData + TimestampValue,
+{
+    if let (Implemented::Collection(ref relation), _) = implemented {
+        observe(&relation.tuples, operator, sink, true);
+    }
+
+    implemented
+}
+
+/// Reports the updates of a relation as inputs to the stage
+/// described by `operator`. Relations backed by an attribute are
+/// counted via a separate read of the attribute, leaving the
+/// consuming stage free to use its indices directly.
+pub(crate) fn count_input<'a, T, I, S>(
+    operator: String,
+    sink: Arc<dyn MetricsSink>,
+    implemented: &Implemented<'a, S>,
+    nested: &mut Iterative<'a, S, u64>,
+    context: &mut I,
+) -> ShutdownHandle
+where
+    T: Timestamp + Lattice + TimestampValue,
+    I: ImplContext<T>,
+    S: Scope<Timestamp = T>,
+{
+    match *implemented {
+        Implemented::Collection(ref relation) => {
+            observe(&relation.tuples, operator, sink, false);
+            ShutdownHandle::empty()
+        }
+        Implemented::Attribute(ref binding) => {
+            count_attribute(operator, sink, binding, nested, context)
+        }
+    }
+}
+
+/// Reports the contents of an attribute as inputs to the stage
+/// described by `operator`.
+pub(crate) fn count_attribute<'a, T, I, S>(
+    operator: String,
+    sink: Arc<dyn MetricsSink>,
+    binding: &AttributeBinding,
+    nested: &mut Iterative<'a, S, u64>,
+    context: &mut I,
+) -> ShutdownHandle
+where
+    T: Timestamp + Lattice + TimestampValue,
+    I: ImplContext<T>,
+    S: Scope<Timestamp = T>,
+{
+    let (tuples, shutdown_handle) = binding.clone().tuples(nested, context);
+    observe(&tuples, operator, sink, false);
+
+    shutdown_handle
+}
@@ -2,5 +2,9 @@
 //! declarative-specific operators.
 
 mod last_write_wins;
+mod reclock;
+mod retime;
 
 pub use last_write_wins::LastWriteWins;
+pub use reclock::Reclock;
+pub use retime::Retime;
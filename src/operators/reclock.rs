@@ -0,0 +1,101 @@
+//! Operator restoring timestamp order on streams of updates.
+//!
+//! Plan stages rely on differential's frontier discipline: updates at
+//! a time `t` must be presented while the input frontier still
+//! allows for `t`, and downstream operators act on times as soon as
+//! the frontier passes them. Sources reading from sockets or message
+//! queues might hand out updates with arbitrary timestamps instead,
+//! in which case joins and reductions can report transient results
+//! that are only corrected once the stragglers arrive. `reclock`
+//! holds back updates until their timestamps are complete and then
+//! releases them in timestamp order, s.t. downstream operators only
+//! ever see final inputs for any given time.
+
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::generic::operator::Operator;
+use timely::dataflow::operators::Capability;
+use timely::dataflow::{Scope, Stream};
+use timely::order::PartialOrder;
+use timely::Data;
+
+use differential_dataflow::lattice::Lattice;
+
+/// Provides the `reclock` method.
+pub trait Reclock<S: Scope, D, R> {
+    /// Buffers updates until the input frontier has passed their
+    /// timestamps and releases them in timestamp order, each at its
+    /// own time. Late updates, i.e. those with timestamps the stream
+    /// had already moved past when they arrived, are advanced to the
+    /// time of the batch they arrived in.
+    fn reclock(&self) -> Stream<S, (D, S::Timestamp, R)>;
+}
+
+impl<S, D, R> Reclock<S, D, R> for Stream<S, (D, S::Timestamp, R)>
+where
+    S: Scope,
+    S::Timestamp: Lattice + Ord,
+    D: Data,
+    R: Data,
+{
+    fn reclock(&self) -> Stream<S, (D, S::Timestamp, R)> {
+        let mut pending: Vec<(D, S::Timestamp, R)> = Vec::new();
+        let mut capabilities: Vec<Capability<S::Timestamp>> = Vec::new();
+        let mut buffer = Vec::new();
+
+        self.unary_frontier(Pipeline, "Reclock", move |_capability, _info| {
+            move |input, output| {
+                input.for_each(|capability, data| {
+                    data.swap(&mut buffer);
+
+                    for (datum, time, diff) in buffer.drain(..) {
+                        let time = time.join(capability.time());
+                        pending.push((datum, time, diff));
+                    }
+
+                    let capability = capability.retain();
+                    if !capabilities
+                        .iter()
+                        .any(|held| held.time().less_equal(capability.time()))
+                    {
+                        capabilities.retain(|held| !capability.time().less_equal(held.time()));
+                        capabilities.push(capability);
+                    }
+                });
+
+                // Updates are complete, once the input frontier has
+                // passed their timestamps.
+                let frontier = input.frontier();
+                let (mut ready, remaining): (Vec<_>, Vec<_>) = pending
+                    .drain(..)
+                    .partition(|(_datum, time, _diff)| !frontier.less_equal(time));
+                pending = remaining;
+
+                ready.sort_by(|x, y| x.1.cmp(&y.1));
+
+                let mut ready = ready.into_iter().peekable();
+                while let Some((datum, time, diff)) = ready.next() {
+                    let capability = capabilities
+                        .iter()
+                        .find(|held| held.time().less_equal(&time))
+                        .expect("no capability held for pending update")
+                        .delayed(&time);
+
+                    let mut session = output.session(&capability);
+                    session.give((datum, time.clone(), diff));
+
+                    while ready.peek().map(|x| x.1 == time).unwrap_or(false) {
+                        session.give(ready.next().unwrap());
+                    }
+                }
+
+                // We only hold on to capabilities we might still
+                // need for pending updates.
+                capabilities.retain(|held| {
+                    pending
+                        .iter()
+                        .any(|(_datum, time, _diff)| held.time().less_equal(time))
+                });
+            }
+        })
+    }
+}
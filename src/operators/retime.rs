@@ -0,0 +1,106 @@
+//! Operator moving collections between dataflows with differing
+//! timestamp types.
+//!
+//! Plans are implemented within a single scope and thus require all
+//! of their inputs to share its timestamp type. Sources might come
+//! with their own notion of time however, e.g. plain transaction
+//! counters on the one hand and `Pair<Duration, u64>` event times on
+//! the other. `retime_into` lifts a collection out of the dataflow it
+//! was created in and into another one, translating timestamps via a
+//! user-provided mapping. The result can then be joined with any
+//! other collection of the target scope.
+//!
+//! The mapping must be monotone, i.e. `t1 <= t2` must imply
+//! `mapping(t1) <= mapping(t2)`. This ensures that the mapped input
+//! frontier bounds all mapped timestamps still to come, s.t.
+//! downstream operators never see updates at times they were told
+//! are complete. The mapping need not be injective, distinct source
+//! times mapping to the same target time are simply merged.
+
+use std::rc::Rc;
+
+use timely::dataflow::operators::capture::event::link::EventLink;
+use timely::dataflow::operators::capture::{Capture, Event, EventPusher, Replay};
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+use timely::Data;
+
+use differential_dataflow::{AsCollection, Collection};
+
+/// Provides the `retime_into` method.
+pub trait Retime<S: Scope, D, R> {
+    /// Lifts the collection into the specified scope, translating
+    /// each of its timestamps via `mapping`, which must be
+    /// monotone. Both scopes must live on the same worker.
+    fn retime_into<S2, F>(&self, scope: &mut S2, mapping: F) -> Collection<S2, D, R>
+    where
+        S2: Scope,
+        F: Fn(&S::Timestamp) -> S2::Timestamp + 'static;
+}
+
+impl<S, D, R> Retime<S, D, R> for Collection<S, D, R>
+where
+    S: Scope,
+    D: Data,
+    R: Data,
+{
+    fn retime_into<S2, F>(&self, scope: &mut S2, mapping: F) -> Collection<S2, D, R>
+    where
+        S2: Scope,
+        F: Fn(&S::Timestamp) -> S2::Timestamp + 'static,
+    {
+        let link = Rc::new(EventLink::new());
+        let mut pusher = RetimingPusher {
+            mapping,
+            target: link.clone(),
+        };
+
+        // Replaying starts out holding the minimal target time, while
+        // captured progress is reported relative to the minimal
+        // source time. We therefore move the former to the image of
+        // the latter up front.
+        pusher.target.push(Event::Progress(vec![
+            (S2::Timestamp::minimum(), -1),
+            ((pusher.mapping)(&S::Timestamp::minimum()), 1),
+        ]));
+
+        self.inner.capture_into(pusher);
+
+        Some(link).replay_into(scope).as_collection()
+    }
+}
+
+/// Forwards captured events, translating all timestamps mentioned
+/// by progress updates, messages, and the updates they carry.
+struct RetimingPusher<T2: Timestamp, D, R, F> {
+    mapping: F,
+    target: Rc<EventLink<T2, (D, T2, R)>>,
+}
+
+impl<T1, T2, D, R, F> EventPusher<T1, (D, T1, R)> for RetimingPusher<T2, D, R, F>
+where
+    T1: Timestamp,
+    T2: Timestamp,
+    F: Fn(&T1) -> T2,
+{
+    fn push(&mut self, event: Event<T1, (D, T1, R)>) {
+        let mapping = &self.mapping;
+
+        let event = match event {
+            Event::Progress(changes) => Event::Progress(
+                changes
+                    .into_iter()
+                    .map(|(time, delta)| (mapping(&time), delta))
+                    .collect(),
+            ),
+            Event::Messages(time, data) => Event::Messages(
+                mapping(&time),
+                data.into_iter()
+                    .map(|(datum, time, diff)| (datum, mapping(&time), diff))
+                    .collect(),
+            ),
+        };
+
+        self.target.push(event);
+    }
+}
@@ -8,9 +8,12 @@ use differential_dataflow::difference::DiffPair;
 use differential_dataflow::lattice::Lattice;
 use differential_dataflow::operators::Join as JoinMap;
 use differential_dataflow::operators::{Count, Reduce, Threshold};
+use differential_dataflow::{Collection, Hashable};
 
 use crate::binding::{AsBinding, Binding};
-use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::plan::hyperloglog::{self, Sketch};
+use crate::plan::{ComparatorFn, Dependencies, Function, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
 use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, Value, Var, VariableMap};
 
 use num_rational::{Ratio, Rational32};
@@ -26,16 +29,317 @@ pub enum AggregationFn {
     MEDIAN,
     /// Count
     COUNT,
-    /// Sum
+    /// Sum. Accumulated without overflowing, results beyond the
+    /// range of Number saturate at its bounds.
     SUM,
     /// Average
     AVG,
     /// Variance
     VARIANCE,
+    /// Number of tuples per group, irrespective of their values. The
+    /// corresponding aggregation variable only determines the output
+    /// position and need not be bound by the source plan.
+    CountRows,
+    /// The value of the aggregation variable in the tuple with the
+    /// largest value for the specified variable. Ties are broken by
+    /// the remaining variables, s.t. multiple ArgMax aggregations
+    /// over the same variable always pick the same tuple.
+    ArgMax(Var),
+    /// The value of the aggregation variable in the tuple with the
+    /// smallest value for the specified variable. Ties are broken as
+    /// for ArgMax.
+    ArgMin(Var),
+    /// Estimated number of distinct values per group, computed via
+    /// HyperLogLog sketches with `2^precision` registers. Precision
+    /// must be between 4 and 16, higher precisions trade memory for
+    /// accuracy (the standard error is about `1.04 / 2^(precision/2)`).
+    /// Retractions are supported, see the `hyperloglog` module.
+    ApproxCountDistinct {
+        /// Number of bits used to select a register.
+        precision: u8,
+    },
     // /// Standard deviation
     // STDDEV,
 }
 
+/// Sorts the given tuples and accumulates the multiplicities of equal
+/// tuples, dropping those that cancel out.
+fn consolidated(mut entries: Vec<(Vec<Value>, isize)>) -> Vec<(Vec<Value>, isize)> {
+    entries.sort();
+
+    let mut result: Vec<(Vec<Value>, isize)> = Vec::with_capacity(entries.len());
+    for (tuple, diff) in entries.drain(..) {
+        let is_duplicate = match result.last() {
+            None => false,
+            Some(last) => last.0 == tuple,
+        };
+
+        if is_duplicate {
+            result.last_mut().unwrap().1 += diff;
+        } else {
+            result.push((tuple, diff));
+        }
+    }
+
+    result.retain(|(_tuple, diff)| *diff != 0);
+    result
+}
+
+/// Sorts values by their first element in the specified order, if
+/// any. The sort is stable, s.t. values that compare equal remain in
+/// their natural order.
+fn sort_by_comparator<T: AsRef<[Value]>>(values: &mut [T], comparator: &Option<ComparatorFn>) {
+    if let Some(ref comparator) = *comparator {
+        values.sort_by(|x, y| comparator.compare(&x.as_ref()[0], &y.as_ref()[0]));
+    }
+}
+
+/// Picks the smallest (or largest) of the given values, comparing
+/// their first elements in the specified order, if any. Values are
+/// expected in their natural order, as handed to reductions. Ties
+/// are broken as by `sort_by_comparator`, but in a single pass.
+fn extremum<'a>(
+    values: &[(&'a Vec<Value>, isize)],
+    is_max: bool,
+    comparator: &Option<ComparatorFn>,
+) -> &'a Vec<Value> {
+    match *comparator {
+        None => {
+            if is_max {
+                values[values.len() - 1].0
+            } else {
+                values[0].0
+            }
+        }
+        Some(ref comparator) => {
+            let candidates = values.iter().map(|x| x.0);
+            let compare = |x: &&Vec<Value>, y: &&Vec<Value>| comparator.compare(&x[0], &y[0]);
+
+            // `max_by` returns the last of several maxima and
+            // `min_by` the first of several minima.
+            if is_max {
+                candidates.max_by(compare).unwrap()
+            } else {
+                candidates.min_by(compare).unwrap()
+            }
+        }
+    }
+}
+
+/// Splits a number into its upper and lower 32 bits, s.t. sums of
+/// many numbers can be accumulated as pairs of isize differences
+/// without overflowing.
+fn split_halves(v: i64) -> DiffPair<isize, isize> {
+    DiffPair::new((v >> 32) as isize, (v & 0xFFFF_FFFF) as isize)
+}
+
+/// Narrows a sum to i64, saturating at its bounds.
+fn saturate(sum: i128) -> i64 {
+    if sum > i128::from(std::i64::MAX) {
+        std::i64::MAX
+    } else if sum < i128::from(std::i64::MIN) {
+        std::i64::MIN
+    } else {
+        sum as i64
+    }
+}
+
+/// Reassembles a sum accumulated via `split_halves`, saturating at
+/// the bounds of i64.
+fn saturating_sum(halves: &DiffPair<isize, isize>) -> i64 {
+    saturate(((halves.element1 as i128) << 32) + halves.element2 as i128)
+}
+
+/// Number of bits by which buckets shrink from one level of a
+/// hierarchical aggregation to the next, determining its fan-in.
+const BUCKET_BITS: u64 = 8;
+
+/// Computes the smallest (or largest) value per key in a hierarchy
+/// of reductions. Values are first assigned to buckets by their hash,
+/// the extremum is then computed per bucket, and each level merges
+/// the buckets of the previous one, until a single bucket remains.
+///
+/// Differential re-runs a reduction over all values of a key whenever
+/// any of them changes, which for a single flat reduction means that
+/// retracting the current extremum costs time linear in the size of
+/// the group. Here, an update only touches one bucket per level, each
+/// holding at most `2^BUCKET_BITS` candidates, i.e. the cost per
+/// update is `O(64 / BUCKET_BITS * 2^BUCKET_BITS)`, independently of
+/// the size of the group, at the expense of some additional state.
+fn hierarchical_extremum<G>(
+    values: &Collection<G, (Vec<Value>, Vec<Value>), isize>,
+    is_max: bool,
+    comparator: &Option<ComparatorFn>,
+) -> Collection<G, (Vec<Value>, Vec<Value>), isize>
+where
+    G: Scope,
+    G::Timestamp: Lattice + Ord,
+{
+    let mut buckets = values.map(|(key, value)| {
+        let bucket = value.hashed().as_u64();
+        ((key, bucket), value)
+    });
+
+    for _level in 0..(64 / BUCKET_BITS) {
+        let comparator = comparator.clone();
+        buckets = buckets
+            .map(|((key, bucket), value)| ((key, bucket >> BUCKET_BITS), value))
+            .reduce(move |_key, input, output| {
+                output.push((extremum(input, is_max, &comparator).clone(), 1));
+            });
+    }
+
+    buckets.map(|((key, _bucket), value)| (key, value))
+}
+
+/// Computes all aggregations over the value tuples of a single group
+/// in one pass, in the order in which they are specified. Each
+/// aggregation sees the same inputs as it would in a reduction of its
+/// own, i.e. COUNT, SUM, AVG, and VARIANCE only consider distinct
+/// values. A SUM of zero is reported as such, s.t. the group and its
+/// other aggregates are retained.
+fn aggregate_group(
+    aggregation_fns: &[AggregationFn],
+    value_offsets: &[usize],
+    arg_offsets: &[Option<(usize, usize)>],
+    with_length: usize,
+    comparator: &Option<ComparatorFn>,
+    input: &[(&Vec<Value>, isize)],
+) -> Vec<Value> {
+    let mut output = Vec::with_capacity(aggregation_fns.len());
+
+    for (i, aggregation_fn) in aggregation_fns.iter().enumerate() {
+        let value_offset = value_offsets[i];
+
+        // The distinct aggregation values, each followed by any
+        // with-values, as in `prepare_unary`.
+        let distinct = || -> Vec<Vec<Value>> {
+            consolidated(
+                input
+                    .iter()
+                    .map(|(tuple, diff)| {
+                        let mut v = vec![tuple[value_offset].clone()];
+                        if with_length > 0 {
+                            v.extend(tuple.iter().rev().take(with_length).cloned());
+                        }
+                        (v, *diff)
+                    })
+                    .collect(),
+            )
+            .into_iter()
+            .map(|(v, _diff)| v)
+            .collect()
+        };
+
+        let number = |v: &Vec<Value>| match v[0] {
+            Value::Number(num) => num as isize,
+            _ => panic!("{:?} can only be applied on type Number.", aggregation_fn),
+        };
+
+        let value = match aggregation_fn {
+            AggregationFn::MIN | AggregationFn::MAX | AggregationFn::MEDIAN => {
+                let entries = distinct();
+                let mut ordered: Vec<&Vec<Value>> = entries.iter().collect();
+                sort_by_comparator(&mut ordered, comparator);
+
+                let index = match aggregation_fn {
+                    AggregationFn::MIN => 0,
+                    AggregationFn::MAX => ordered.len() - 1,
+                    _ => ordered.len() / 2,
+                };
+
+                ordered[index][0].clone()
+            }
+            AggregationFn::COUNT => Value::Number(distinct().len() as i64),
+            AggregationFn::CountRows => {
+                let count: isize = input.iter().map(|(_tuple, diff)| *diff).sum();
+                Value::Number(count as i64)
+            }
+            AggregationFn::ArgMax(_) | AggregationFn::ArgMin(_) => {
+                let (by_offset, payload_offset) = arg_offsets[i].unwrap();
+                let entries = consolidated(
+                    input
+                        .iter()
+                        .map(|(tuple, diff)| {
+                            let mut v = Vec::with_capacity(tuple.len() + 1);
+                            v.push(tuple[by_offset].clone());
+                            v.extend(tuple.iter().cloned());
+                            (v, *diff)
+                        })
+                        .collect(),
+                );
+                let mut ordered: Vec<&Vec<Value>> = entries.iter().map(|x| &x.0).collect();
+                sort_by_comparator(&mut ordered, comparator);
+
+                let row = match aggregation_fn {
+                    AggregationFn::ArgMax(_) => ordered[ordered.len() - 1],
+                    _ => ordered[0],
+                };
+
+                row[payload_offset + 1].clone()
+            }
+            AggregationFn::ApproxCountDistinct { precision } => {
+                // The shared reduction sees all values anyway, so we
+                // sketch them directly.
+                let mut sketch = Sketch::new(*precision);
+                for (tuple, _diff) in input.iter() {
+                    let (register, rank) = hyperloglog::observe(&tuple[value_offset], *precision);
+                    sketch.insert(register, rank);
+                }
+
+                Value::Number(sketch.estimate())
+            }
+            AggregationFn::SUM => {
+                let sum: i128 = distinct().iter().map(|v| number(v) as i128).sum();
+
+                Value::Number(saturate(sum))
+            }
+            AggregationFn::AVG => {
+                let (sum, count) = distinct()
+                    .iter()
+                    .fold((0, 0), |(sum, count), v| (sum + number(v), count + 1));
+
+                Value::Rational32(Ratio::new(sum as i32, count as i32))
+            }
+            AggregationFn::VARIANCE => {
+                let (sum_square, sum, c) =
+                    distinct()
+                        .iter()
+                        .fold((0, 0, 0), |(sum_square, sum, c), v| {
+                            let v = number(v);
+                            (sum_square + v * v, sum + v, c + 1)
+                        });
+                let (sum_square, sum, c) = (sum_square as i32, sum as i32, c as i32);
+
+                Value::Rational32(Rational32::new(sum_square, c) - Rational32::new(sum, c).pow(2))
+            }
+        };
+
+        output.push(value);
+    }
+
+    output
+}
+
+/// Position of an output value, either within the group key or
+/// amongst the aggregates.
+#[derive(Clone, Copy, Debug)]
+enum Slot {
+    Key(usize),
+    Aggregate(usize),
+}
+
+/// Assembles an output tuple from a group key and its aggregates.
+fn assemble(layout: &[Slot], key: &[Value], aggregates: &[Value]) -> Vec<Value> {
+    layout
+        .iter()
+        .map(|slot| match *slot {
+            Slot::Key(i) => key[i].clone(),
+            Slot::Aggregate(i) => aggregates[i].clone(),
+        })
+        .collect()
+}
+
 /// [WIP] A plan stage applying the specified aggregation functions to
 /// bindings for the specified variables. Given multiple aggregations
 /// we iterate and n-1 joins are applied to the results.
@@ -47,12 +351,103 @@ pub struct Aggregate<P: Implementable> {
     pub plan: Box<P>,
     /// Logical predicate to apply.
     pub aggregation_fns: Vec<AggregationFn>,
-    /// Relation variables that determine the grouping.
+    /// Relation variables that determine the grouping. If empty,
+    /// all tuples fall into a single group and the stage reports
+    /// exactly one tuple holding the global aggregates, as long as
+    /// its source is non-empty.
     pub key_variables: Vec<Var>,
     /// Aggregation variables
     pub aggregation_variables: Vec<Var>,
     /// With variables
     pub with_variables: Vec<Var>,
+    /// Optionally, a function (and its constant inputs, as in
+    /// `Transform`) deriving the group key. If specified, bindings
+    /// for `key_variables` are passed as arguments to the function
+    /// and the group key is its result, which takes the place of the
+    /// key variables in the output. Otherwise tuples are grouped by
+    /// `key_variables` directly. SPLIT and NOW can't serve as group
+    /// functions.
+    #[serde(default)]
+    pub group_fn: Option<(Function, Vec<Option<Value>>)>,
+    /// Should groups that exist (i.e. have at least one tuple) but
+    /// whose aggregate has vanished be reported with the identity of
+    /// the aggregation function instead of disappearing? This only
+    /// affects SUM, whose result cancels out to nothing for groups
+    /// whose values sum up to zero, and which then reports 0. Counts
+    /// never vanish for existing groups, and groups without any
+    /// tuples are unknown to the aggregation and thus never reported,
+    /// s.t. COUNT and CountRows are unaffected. MIN, MAX, MEDIAN,
+    /// AVG, VARIANCE, ArgMax, and ArgMin have no identity and remain
+    /// absent for empty groups. When computed alongside other
+    /// aggregations, a SUM of zero is always reported, s.t. the other
+    /// aggregates of its group aren't lost.
+    pub default_on_empty: bool,
+}
+
+impl<P: Implementable> Aggregate<P> {
+    /// Creates an aggregation grouped by `key_variables` directly,
+    /// with all optional behaviour disabled.
+    pub fn new(
+        variables: Vec<Var>,
+        plan: P,
+        aggregation_fns: Vec<AggregationFn>,
+        key_variables: Vec<Var>,
+        aggregation_variables: Vec<Var>,
+        with_variables: Vec<Var>,
+    ) -> Self {
+        Aggregate {
+            variables,
+            plan: Box::new(plan),
+            aggregation_fns,
+            key_variables,
+            aggregation_variables,
+            with_variables,
+            group_fn: None,
+            default_on_empty: false,
+        }
+    }
+
+    /// Determines where each output variable is taken from. The i-th
+    /// aggregate is bound to the i-th aggregation variable, all
+    /// remaining variables are bound from the group key. If the key
+    /// is derived via `group_fn`, it consists of the function's
+    /// result alone, which must therefore be bound to a single
+    /// variable.
+    fn output_layout(&self) -> Vec<Slot> {
+        let mut layout: Vec<Option<Slot>> = vec![None; self.variables.len()];
+
+        for (i, variable) in self.aggregation_variables.iter().enumerate() {
+            let position = (0..layout.len())
+                .find(|pos| self.variables[*pos] == *variable && layout[*pos].is_none())
+                .expect("Aggregation variable must be bound by the aggregation.");
+
+            layout[position] = Some(Slot::Aggregate(i));
+        }
+
+        let key_positions: Vec<usize> = (0..layout.len())
+            .filter(|pos| layout[*pos].is_none())
+            .collect();
+
+        if self.group_fn.is_some() && key_positions.len() != 1 {
+            panic!(
+                "Aggregations grouped by a function bind a single group variable, not {}.",
+                key_positions.len()
+            );
+        }
+
+        for position in key_positions {
+            let key_index = if self.group_fn.is_some() {
+                0
+            } else {
+                AsBinding::binds(&self.key_variables, self.variables[position])
+                    .expect("Variables must either be key or aggregation variables.")
+            };
+
+            layout[position] = Some(Slot::Key(key_index));
+        }
+
+        layout.into_iter().map(|slot| slot.unwrap()).collect()
+    }
 }
 
 impl<P: Implementable> Implementable for Aggregate<P> {
@@ -60,8 +455,8 @@ impl<P: Implementable> Implementable for Aggregate<P> {
         self.plan.dependencies()
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
-        self.plan.into_bindings()
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        self.plan.into_bindings_with(gensym)
     }
 
     fn implement<'b, T, I, S>(
@@ -71,19 +466,56 @@ impl<P: Implementable> Implementable for Aggregate<P> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        self.implement_by(nested, local_arrangements, context, None)
+    }
+}
+
+impl<P: Implementable> Aggregate<P> {
+    /// Implements the aggregation, comparing values in the specified
+    /// order, if any.
+    fn implement_by<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+        comparator: Option<ComparatorFn>,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
         let (relation, mut shutdown_handle) =
             self.plan.implement(nested, local_arrangements, context);
 
+        // Variables in the value part of each tuple, in order.
+        let value_variables: Vec<Var> = relation
+            .variables()
+            .drain(..)
+            .filter(|x| !self.key_variables.contains(x))
+            .collect();
+
         // We split the incoming tuples into their (key, value) parts.
-        let tuples = {
+        {
             let (tuples, shutdown) =
                 relation.tuples_by_variables(nested, context, &self.key_variables);
             shutdown_handle.merge_with(shutdown);
-            tuples
+
+            match self.group_fn.clone() {
+                None => tuples,
+                Some((function, constants)) => tuples.map(move |(key, tuple)| {
+                    let group = {
+                        let args: Vec<&Value> = key.iter().collect();
+                        function.apply(&args, &constants)
+                    };
+
+                    (vec![group], tuple)
+                }),
+            }
         };
 
         // For each aggregation function that is to be applied, we
@@ -93,8 +525,16 @@ impl<P: Implementable> Implementable for Aggregate<P> {
         let mut value_offsets = Vec::new();
         let mut seen = Vec::new();
 
-        for variable in self.aggregation_variables.iter() {
-            if !seen.contains(variable) {
+        for (variable, aggregation_fn) in self
+            .aggregation_variables
+            .iter()
+            .zip(self.aggregation_fns.iter())
+        {
+            if *aggregation_fn == AggregationFn::CountRows {
+                // CountRows doesn't look at any values, its variable
+                // is bound by the aggregation itself.
+                value_offsets.push(0);
+            } else if !seen.contains(variable) {
                 seen.push(*variable);
                 value_offsets.push(seen.len() - 1);
             } else {
@@ -104,184 +544,299 @@ impl<P: Implementable> Implementable for Aggregate<P> {
 
         // Users can specify weird find clauses like [:find ?key1 (min ?v1) ?key2]
         // and we would like to avoid an extra projection. Thus, we pre-compute
-        // the correct output offset for each aggregation.
+        // where each output value is to be taken from.
+        let layout = self.output_layout();
 
-        let mut variables = self.variables.clone();
-        let mut output_offsets = Vec::new();
-
-        for variable in self.aggregation_variables.iter() {
-            let output_index = AsBinding::binds(&variables, *variable).unwrap();
-            output_offsets.push(output_index);
-
-            variables[output_index] = 0;
+        for aggregation_fn in self.aggregation_fns.iter() {
+            if let AggregationFn::ApproxCountDistinct { precision } = *aggregation_fn {
+                hyperloglog::check_precision(precision);
+            }
         }
 
-        let mut collections = Vec::new();
+        if self.aggregation_fns.len() > 1 {
+            // Offsets of the ordering and payload values for
+            // ArgMax/ArgMin aggregations, as in their individual
+            // reductions below.
+            let arg_offsets: Vec<Option<(usize, usize)>> = self
+                .aggregation_fns
+                .iter()
+                .zip(self.aggregation_variables.iter())
+                .map(|(aggregation_fn, variable)| match aggregation_fn {
+                    AggregationFn::ArgMax(by) | AggregationFn::ArgMin(by) => {
+                        let by_offset = AsBinding::binds(&value_variables, *by)
+                            .expect("ArgMax/ArgMin variable must be bound by the source plan.");
+                        let payload_offset = AsBinding::binds(&value_variables, *variable)
+                            .expect("Aggregation variable must be bound by the source plan.");
+
+                        Some((by_offset, payload_offset))
+                    }
+                    _ => None,
+                })
+                .collect();
 
-        // We iterate over all aggregations and keep track of the
-        // resulting collections, s.t. they can be joined afterwards.
-        for (i, aggregation_fn) in self.aggregation_fns.iter().enumerate() {
-            let value_offset = value_offsets[i];
+            let aggregation_fns = self.aggregation_fns.clone();
             let with_length = self.with_variables.len();
+            let comparator = comparator.clone();
 
-            // Access the right value for the given iteration loop and extend possible with-values.
-            let prepare_unary = move |(key, tuple): (Vec<Value>, Vec<Value>)| {
-                let value = &tuple[value_offset];
-                let mut v = vec![value.clone()];
+            let tuples = tuples
+                .reduce(move |_key, input, output| {
+                    let aggregates = aggregate_group(
+                        &aggregation_fns,
+                        &value_offsets,
+                        &arg_offsets,
+                        with_length,
+                        &comparator,
+                        input,
+                    );
 
-                // With-variables are always the last elements in the value part of each tuple, given they are specified.
-                // We append these, s.t. we consolidate correctly.
-                if with_length > 0 {
-                    v.extend(tuple.iter().rev().take(with_length).cloned());
-                }
+                    output.push((aggregates, 1));
+                })
+                .map(move |(key, vals)| assemble(&layout, &key, &vals));
 
-                (key, v)
+            let relation = CollectionRelation {
+                variables: self.variables.to_vec(),
+                tuples,
             };
 
-            match aggregation_fn {
-                AggregationFn::MIN => {
-                    let tuples = tuples
-                        .map(prepare_unary)
-                        .reduce(|_key, vals, output| {
-                            let min = &vals[0].0[0];
-                            output.push((min.clone(), 1));
-                        })
-                        .map(move |(key, min)| (key, vec![min]));
-                    collections.push(tuples);
-                }
-                AggregationFn::MAX => {
-                    let tuples = tuples
-                        .map(prepare_unary)
-                        .reduce(|_key, vals, output| {
-                            let max = &vals[vals.len() - 1].0[0];
-                            output.push((max.clone(), 1));
-                        })
-                        .map(move |(key, max)| (key, vec![max]));
-                    collections.push(tuples);
-                }
-                AggregationFn::MEDIAN => {
-                    let tuples = tuples
-                        .map(prepare_unary)
-                        .reduce(|_key, vals, output| {
-                            let median = &vals[vals.len() / 2].0[0];
-                            output.push((median.clone(), 1));
-                        })
-                        .map(move |(key, med)| (key, vec![med]));
-                    collections.push(tuples);
-                }
-                AggregationFn::COUNT => {
-                    let tuples = tuples
-                        .map(prepare_unary)
-                        .reduce(|_key, input, output| output.push((input.len(), 1)))
-                        .map(move |(key, count)| (key, vec![Value::Number(count as i64)]));
-                    collections.push(tuples);
-                }
-                AggregationFn::SUM => {
-                    let tuples = tuples
-                        .map(prepare_unary)
-                        .distinct()
-                        .explode(|(key, val)| {
-                            let v = match val[0] {
-                                Value::Number(num) => num,
-                                _ => panic!("SUM can only be applied on type Number."),
-                            };
-                            Some((key, v as isize))
-                        })
-                        .count()
-                        .map(move |(key, count)| (key, vec![Value::Number(count as i64)]));
-                    collections.push(tuples);
-                }
-                AggregationFn::AVG => {
-                    let tuples = tuples
-                        .map(prepare_unary)
-                        .distinct()
-                        .explode(move |(key, val)| {
-                            let v = match val[0] {
-                                Value::Number(num) => num,
-                                _ => panic!("AVG can only be applied on type Number."),
-                            };
-                            Some((key, DiffPair::new(v as isize, 1)))
-                        })
-                        .count()
-                        .map(move |(key, diff_pair)| {
-                            (
-                                key,
-                                vec![Value::Rational32(Ratio::new(
-                                    diff_pair.element1 as i32,
-                                    diff_pair.element2 as i32,
-                                ))],
-                            )
-                        });
-                    collections.push(tuples);
-                }
-                AggregationFn::VARIANCE => {
-                    let tuples = tuples
-                        .map(prepare_unary)
-                        .distinct()
-                        .explode(move |(key, val)| {
-                            let v = match val[0] {
-                                Value::Number(num) => num,
-                                _ => panic!("VARIANCE can only be applied on type Number."),
-                            };
-                            Some((
-                                key,
-                                DiffPair::new(
-                                    DiffPair::new(v as isize * v as isize, v as isize),
-                                    1,
-                                ),
-                            ))
-                        })
-                        .count()
-                        .map(move |(key, diff_pair)| {
-                            let sum_square = diff_pair.element1.element1 as i32;
-                            let sum = diff_pair.element1.element2 as i32;
-                            let c = diff_pair.element2 as i32;
-                            (
-                                key,
-                                vec![Value::Rational32(
-                                    Rational32::new(sum_square, c) - Rational32::new(sum, c).pow(2),
-                                )],
-                            )
-                        });
-                    collections.push(tuples);
-                }
-            };
+            return (Implemented::Collection(relation), shutdown_handle);
         }
 
-        let aggregated = if collections.len() == 1 {
-            let output_index = output_offsets[0];
-            CollectionRelation {
-                variables: self.variables.to_vec(),
-                tuples: collections[0].map(move |(key, val)| {
-                    let mut k = key.clone();
-                    let v = val[0].clone();
-                    k.insert(output_index, v);
-                    k
-                }),
+        // A single aggregation is computed by a dedicated dataflow.
+        let aggregation_fn = &self.aggregation_fns[0];
+        let value_offset = value_offsets[0];
+        let with_length = self.with_variables.len();
+
+        // Access the aggregated value and extend possible with-values.
+        let prepare_unary = move |(key, tuple): (Vec<Value>, Vec<Value>)| {
+            let value = &tuple[value_offset];
+            let mut v = vec![value.clone()];
+
+            // With-variables are always the last elements in the value part of each tuple, given they are specified.
+            // We append these, s.t. we consolidate correctly.
+            if with_length > 0 {
+                v.extend(tuple.iter().rev().take(with_length).cloned());
             }
-        } else {
-            // @TODO replace this with a join application
-            let left = collections.remove(0);
-            let tuples = collections.iter().fold(left, |coll, next| {
-                coll.join_map(&next, |key, v1, v2| {
-                    let mut val = v1.clone();
-                    val.append(&mut v2.clone());
-                    (key.clone(), val)
-                })
-            });
 
-            CollectionRelation {
-                variables: self.variables.to_vec(),
-                tuples: tuples.map(move |(key, vals)| {
-                    let mut v = key.clone();
-                    for (i, val) in vals.iter().enumerate() {
-                        v.insert(output_offsets[i], val.clone())
-                    }
-                    v
-                }),
+            (key, v)
+        };
+
+        let aggregated = match aggregation_fn {
+            AggregationFn::MIN | AggregationFn::MAX => {
+                let is_max = *aggregation_fn == AggregationFn::MAX;
+                hierarchical_extremum(&tuples.map(prepare_unary), is_max, &comparator)
+                    .map(|(key, value)| (key, vec![value[0].clone()]))
             }
+            AggregationFn::MEDIAN => {
+                let comparator = comparator.clone();
+                tuples
+                    .map(prepare_unary)
+                    .reduce(move |_key, vals, output| {
+                        let mut ordered: Vec<&Vec<Value>> = vals.iter().map(|x| x.0).collect();
+                        sort_by_comparator(&mut ordered, &comparator);
+
+                        let median = &ordered[ordered.len() / 2][0];
+                        output.push((median.clone(), 1));
+                    })
+                    .map(move |(key, med)| (key, vec![med]))
+            }
+            AggregationFn::COUNT => tuples
+                .map(prepare_unary)
+                .reduce(|_key, input, output| output.push((input.len(), 1)))
+                .map(move |(key, count)| (key, vec![Value::Number(count as i64)])),
+            AggregationFn::CountRows => tuples
+                .map(|(key, _tuple)| key)
+                .count()
+                .map(|(key, count)| (key, vec![Value::Number(count as i64)])),
+            AggregationFn::ArgMax(by) | AggregationFn::ArgMin(by) => {
+                let by_offset = AsBinding::binds(&value_variables, *by)
+                    .expect("ArgMax/ArgMin variable must be bound by the source plan.");
+                let payload_offset =
+                    AsBinding::binds(&value_variables, self.aggregation_variables[0])
+                        .expect("Aggregation variable must be bound by the source plan.");
+                let is_max = match aggregation_fn {
+                    AggregationFn::ArgMax(_) => true,
+                    _ => false,
+                };
+                let comparator = comparator.clone();
+
+                // We prepend the ordering value to the full
+                // tuple, s.t. the reduction sorts by it first and
+                // by the remaining values second.
+                tuples
+                    .map(move |(key, tuple)| {
+                        let mut v = Vec::with_capacity(tuple.len() + 1);
+                        v.push(tuple[by_offset].clone());
+                        v.extend(tuple.into_iter());
+                        (key, v)
+                    })
+                    .reduce(move |_key, vals, output| {
+                        let mut ordered: Vec<&Vec<Value>> = vals.iter().map(|x| x.0).collect();
+                        sort_by_comparator(&mut ordered, &comparator);
+
+                        let row = if is_max {
+                            ordered[ordered.len() - 1]
+                        } else {
+                            ordered[0]
+                        };
+                        output.push((row[payload_offset + 1].clone(), 1));
+                    })
+                    .map(move |(key, arg)| (key, vec![arg]))
+            }
+            AggregationFn::ApproxCountDistinct { precision } => {
+                let precision = *precision;
+
+                // Only (register, rank) pairs are retained per
+                // group, rather than the values themselves.
+                tuples
+                    .map(move |(key, tuple)| {
+                        let (register, rank) =
+                            hyperloglog::observe(&tuple[value_offset], precision);
+                        let observation =
+                            vec![Value::Number(register as i64), Value::Number(rank.into())];
+
+                        (key, observation)
+                    })
+                    .reduce(move |_key, input, output| {
+                        let mut sketch = Sketch::new(precision);
+                        for (observation, _count) in input.iter() {
+                            if let (Value::Number(register), Value::Number(rank)) =
+                                (&observation[0], &observation[1])
+                            {
+                                sketch.insert(*register as usize, *rank as u8);
+                            }
+                        }
+
+                        output.push((Value::Number(sketch.estimate()), 1));
+                    })
+                    .map(move |(key, estimate)| (key, vec![estimate]))
+            }
+            AggregationFn::SUM => {
+                let sums = tuples
+                    .map(prepare_unary)
+                    .distinct()
+                    .explode(|(key, val)| {
+                        let v = match val[0] {
+                            Value::Number(num) => num,
+                            _ => panic!("SUM can only be applied on type Number."),
+                        };
+                        Some((key, split_halves(v)))
+                    })
+                    .count()
+                    .flat_map(move |(key, halves)| match saturating_sum(&halves) {
+                        // Halves may cancel out without being zero
+                        // individually, such groups vanish, too.
+                        0 => None,
+                        sum => Some((key, vec![Value::Number(sum)])),
+                    });
+
+                if self.default_on_empty {
+                    // Groups whose values sum up to zero vanish
+                    // from the count, we re-introduce them here.
+                    let zeros = tuples
+                        .map(|(key, _tuple)| (key, ()))
+                        .distinct()
+                        .antijoin(&sums.map(|(key, _sum)| key))
+                        .map(|(key, ())| (key, vec![Value::Number(0)]));
+
+                    sums.concat(&zeros)
+                } else {
+                    sums
+                }
+            }
+            AggregationFn::AVG => tuples
+                .map(prepare_unary)
+                .distinct()
+                .explode(move |(key, val)| {
+                    let v = match val[0] {
+                        Value::Number(num) => num,
+                        _ => panic!("AVG can only be applied on type Number."),
+                    };
+                    Some((key, DiffPair::new(v as isize, 1)))
+                })
+                .count()
+                .map(move |(key, diff_pair)| {
+                    (
+                        key,
+                        vec![Value::Rational32(Ratio::new(
+                            diff_pair.element1 as i32,
+                            diff_pair.element2 as i32,
+                        ))],
+                    )
+                }),
+            AggregationFn::VARIANCE => tuples
+                .map(prepare_unary)
+                .distinct()
+                .explode(move |(key, val)| {
+                    let v = match val[0] {
+                        Value::Number(num) => num,
+                        _ => panic!("VARIANCE can only be applied on type Number."),
+                    };
+                    Some((
+                        key,
+                        DiffPair::new(DiffPair::new(v as isize * v as isize, v as isize), 1),
+                    ))
+                })
+                .count()
+                .map(move |(key, diff_pair)| {
+                    let sum_square = diff_pair.element1.element1 as i32;
+                    let sum = diff_pair.element1.element2 as i32;
+                    let c = diff_pair.element2 as i32;
+                    (
+                        key,
+                        vec![Value::Rational32(
+                            Rational32::new(sum_square, c) - Rational32::new(sum, c).pow(2),
+                        )],
+                    )
+                }),
+        };
+
+        let relation = CollectionRelation {
+            variables: self.variables.to_vec(),
+            tuples: aggregated.map(move |(key, val)| assemble(&layout, &key, &val)),
         };
 
-        (Implemented::Collection(aggregated), shutdown_handle)
+        (Implemented::Collection(relation), shutdown_handle)
+    }
+}
+
+/// A plan stage applying an `Aggregate` whose MIN, MAX, MEDIAN,
+/// ArgMax, and ArgMin aggregations compare values in a user-supplied
+/// order, rather than the natural order of values. As with
+/// `FilterFn`, plans containing this stage can't be sent over the
+/// wire.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub struct AggregateBy<P: Implementable> {
+    /// The aggregation to apply.
+    pub aggregate: Aggregate<P>,
+    /// Order in which to compare values.
+    pub comparator: ComparatorFn,
+}
+
+impl<P: Implementable> Implementable for AggregateBy<P> {
+    fn dependencies(&self) -> Dependencies {
+        self.aggregate.dependencies()
+    }
+
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        self.aggregate.into_bindings_with(gensym)
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        self.aggregate.implement_by(
+            nested,
+            local_arrangements,
+            context,
+            Some(self.comparator.clone()),
+        )
     }
 }
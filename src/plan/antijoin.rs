@@ -5,18 +5,22 @@ use timely::dataflow::Scope;
 use timely::progress::Timestamp;
 
 use differential_dataflow::lattice::Lattice;
-use differential_dataflow::operators::{Join, Threshold};
+use differential_dataflow::operators::{Join, Reduce, Threshold};
 
 use crate::binding::{AsBinding, Binding};
 use crate::plan::{Dependencies, ImplContext, Implementable};
-use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, Var, VariableMap};
+use crate::timestamp::TimestampValue;
+use crate::VariableMap;
+use crate::{AttributeBinding, CollectionRelation, Implemented, Relation, ShutdownHandle, Var};
 
 /// A plan stage anti-joining both its sources on the specified
 /// variables. Throws if the sources are not union-compatible, i.e. bind
 /// all of the same variables in the same order.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub struct Antijoin<P1: Implementable, P2: Implementable> {
-    /// TODO
+    /// Variables on which to antijoin. Tuples are keyed on all of
+    /// them, s.t. a left tuple is removed iff the right source
+    /// contains a matching binding for every variable.
     pub variables: Vec<Var>,
     /// Plan for the left input.
     pub left_plan: Box<P1>,
@@ -24,6 +28,89 @@ pub struct Antijoin<P1: Implementable, P2: Implementable> {
     pub right_plan: Box<P2>,
 }
 
+/// Antijoins a relation against the keys of an attribute's count
+/// index, or its propose index if it has no count index. This avoids
+/// materializing the attribute as a collection of tuples and
+/// re-arranging it, as would be required in general.
+fn collection_attribute<'b, T, I, S>(
+    nested: &mut Iterative<'b, S, u64>,
+    context: &mut I,
+    target: Var,
+    left: Implemented<'b, S>,
+    right: &AttributeBinding,
+) -> (Implemented<'b, S>, ShutdownHandle)
+where
+    T: Timestamp + Lattice + TimestampValue,
+    I: ImplContext<T>,
+    S: Scope<Timestamp = T>,
+{
+    let variables = std::iter::once(target)
+        .chain(left.variables().into_iter().filter(|x| *x != target))
+        .collect();
+
+    let (left_keyed, mut shutdown_handle) = left.tuples_by_variables(nested, context, &[target]);
+
+    let forward = if target == right.variables.0 {
+        true
+    } else if target == right.variables.1 {
+        false
+    } else {
+        panic!("Unbound target variable in Collection<->Attribute antijoin.");
+    };
+
+    let name = &right.source_attribute;
+
+    // Each key of the index exactly once, regardless of how many
+    // values it maps to. Count indices hold a single entry per key
+    // already, other attributes have to fall back to their propose
+    // index.
+    let count = if forward {
+        context.forward_count(name)
+    } else {
+        context.reverse_count(name)
+    };
+
+    let keys = match count {
+        Some(count_trace) => {
+            let (counts, shutdown_count) =
+                count_trace.import_frontier(&nested.parent, &format!("Count({})", name));
+            shutdown_handle.add_button(shutdown_count);
+
+            counts
+                .enter(nested)
+                .reduce(|_key, _input, output| output.push(((), 1)))
+                .map(|(key, ())| key)
+        }
+        None => {
+            let propose = if forward {
+                context.forward_propose(name)
+            } else {
+                context.reverse_propose(name)
+            };
+
+            let (propose, shutdown_propose) = propose
+                .expect("propose trace does not exist")
+                .import_frontier(&nested.parent, name);
+            shutdown_handle.add_button(shutdown_propose);
+
+            propose
+                .enter(nested)
+                .reduce(|_key, _input, output| output.push(((), 1)))
+                .map(|(key, ())| key)
+        }
+    };
+
+    let tuples = left_keyed
+        .distinct()
+        .map(|(mut key, tuple)| (key.pop().expect("missing key"), tuple))
+        .antijoin(&keys)
+        .map(|(key, tuple)| std::iter::once(key).chain(tuple.into_iter()).collect());
+
+    let relation = CollectionRelation { variables, tuples };
+
+    (Implemented::Collection(relation), shutdown_handle)
+}
+
 impl<P1: Implementable, P2: Implementable> Implementable for Antijoin<P1, P2> {
     fn dependencies(&self) -> Dependencies {
         Dependencies::merge(
@@ -32,16 +119,32 @@ impl<P1: Implementable, P2: Implementable> Implementable for Antijoin<P1, P2> {
         )
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
-        unimplemented!();
-        // let mut left_bindings = self.left_plan.into_bindings();
-        // let mut right_bindings = self.right_plan.into_bindings();
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        let mut bindings = self.left_plan.into_bindings_with(gensym);
+        let mut right_bindings = self.right_plan.into_bindings_with(gensym);
+
+        // Hector negates individual bindings, which must not bind
+        // anything beyond what the rest of the query binds already.
+        if right_bindings.len() != 1 {
+            panic!("Antijoins against more than a single binding can't be implemented via Hector.");
+        }
 
-        // let mut bindings = Vec::with_capacity(left_bindings.len() + right_bindings.len());
-        // bindings.append(&mut left_bindings);
-        // bindings.append(&mut right_bindings);
+        let right = right_bindings.pop().unwrap();
 
-        // bindings
+        if let Some(variable) = right
+            .variables()
+            .into_iter()
+            .find(|x| !self.variables.contains(x))
+        {
+            panic!(
+                "Antijoins not on all variables of {:?} can't be implemented via Hector, {} is missing.",
+                right, variable
+            );
+        }
+
+        bindings.push(Binding::not(right));
+
+        bindings
     }
 
     fn implement<'b, T, I, S>(
@@ -51,7 +154,7 @@ impl<P1: Implementable, P2: Implementable> Implementable for Antijoin<P1, P2> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -71,6 +174,16 @@ impl<P1: Implementable, P2: Implementable> Implementable for Antijoin<P1, P2> {
             right
         };
 
+        if let Implemented::Attribute(ref binding) = right {
+            if self.variables.len() == 1 {
+                let (implemented, shutdown) =
+                    collection_attribute(nested, context, self.variables[0], left, binding);
+                shutdown_handle.merge_with(shutdown);
+
+                return (implemented, shutdown_handle);
+            }
+        }
+
         let variables = self
             .variables
             .iter()
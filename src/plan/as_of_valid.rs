@@ -0,0 +1,128 @@
+//! Valid-time plan stage, for bitemporal data.
+
+use timely::dataflow::scopes::child::Iterative;
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::operators::{Join, Threshold};
+
+use crate::binding::Binding;
+use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
+use crate::{Aid, Value, Var};
+use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, VariableMap};
+
+/// A plan stage retaining only those source tuples, whose entity was
+/// valid at the specified instant. The dataflow timestamp keeps
+/// tracking transaction-time, while valid-time is modeled in the data
+/// itself, as an interval given by two attributes. An entity is valid
+/// at an instant, if one of its `valid_from` values is at or before
+/// it, and none of its `valid_to` values are. Entities without a
+/// `valid_to` value are thus valid indefinitely. Values are compared
+/// by their natural order, s.t. the instant should be of the same
+/// type as the values of both attributes.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct AsOfValid<P: Implementable> {
+    /// Variable bound to the entities whose validity is checked.
+    pub variable: Var,
+    /// Plan for the data source.
+    pub plan: Box<P>,
+    /// Attribute holding the (inclusive) start of an entity's
+    /// valid-time interval.
+    pub valid_from: Aid,
+    /// Attribute holding the (exclusive) end of an entity's
+    /// valid-time interval.
+    pub valid_to: Aid,
+    /// Instant at which entities must be valid.
+    pub valid_time: Value,
+}
+
+impl<P: Implementable> AsOfValid<P> {
+    /// Returns the variables bound by this stage, i.e. the entity
+    /// variable followed by the remaining variables of the source.
+    pub fn variables(&self, source: Vec<Var>) -> Vec<Var> {
+        std::iter::once(self.variable)
+            .chain(source.into_iter().filter(|x| *x != self.variable))
+            .collect()
+    }
+}
+
+impl<P: Implementable> Implementable for AsOfValid<P> {
+    fn dependencies(&self) -> Dependencies {
+        let mut dependencies = self.plan.dependencies();
+
+        dependencies.attributes.insert(self.valid_from.to_string());
+        dependencies.attributes.insert(self.valid_to.to_string());
+
+        dependencies
+    }
+
+    fn into_bindings_with(&self, _gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        panic!("AsOfValid can't be implemented via Hector.");
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        let (relation, mut shutdown_handle) =
+            self.plan.implement(nested, local_arrangements, context);
+        let variables = self.variables(relation.variables());
+
+        let (keyed, shutdown) = relation.tuples_by_variables(nested, context, &[self.variable]);
+        shutdown_handle.merge_with(shutdown);
+
+        // Entities whose interval started at or before the instant.
+        let started = {
+            let instant = self.valid_time.clone();
+            let (propose, shutdown_propose) = context
+                .forward_propose(&self.valid_from)
+                .expect("forward propose trace does not exist")
+                .import_frontier(&nested.parent, &self.valid_from);
+
+            shutdown_handle.add_button(shutdown_propose);
+
+            propose
+                .enter(nested)
+                .filter(move |_e, v| *v <= instant)
+                .as_collection(|e, _v| e.clone())
+                .distinct()
+        };
+
+        // Entities whose interval ended at or before the instant.
+        let ended = {
+            let instant = self.valid_time.clone();
+            let (propose, shutdown_propose) = context
+                .forward_propose(&self.valid_to)
+                .expect("forward propose trace does not exist")
+                .import_frontier(&nested.parent, &self.valid_to);
+
+            shutdown_handle.add_button(shutdown_propose);
+
+            propose
+                .enter(nested)
+                .filter(move |_e, v| *v <= instant)
+                .as_collection(|e, _v| e.clone())
+                .distinct()
+        };
+
+        let valid = started.map(|e| (e, ())).antijoin(&ended).map(|(e, ())| e);
+
+        let tuples = keyed
+            .map(|(mut key, tuple)| (key.pop().expect("missing key"), tuple))
+            .semijoin(&valid)
+            .map(|(key, tuple)| std::iter::once(key).chain(tuple.into_iter()).collect());
+
+        let relation = CollectionRelation { variables, tuples };
+
+        (Implemented::Collection(relation), shutdown_handle)
+    }
+}
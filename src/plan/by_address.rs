@@ -0,0 +1,51 @@
+//! Identity semantics for user closures embedded in plans.
+//!
+//! Plans must be comparable, hashable, and printable, closures are
+//! none of these. Wrappers around shared closures therefore compare
+//! by the address of the closure they point to, s.t. a wrapper is
+//! equal to its clones and to nothing else.
+
+/// Implements `Debug`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`, and
+/// `Hash` for a newtype around an `Arc` of the given closure type,
+/// all in terms of the address of the closure.
+macro_rules! by_address {
+    ($name:ident, $closure:ty) => {
+        impl $name {
+            fn address(&self) -> usize {
+                &*self.0 as *const $closure as *const u8 as usize
+            }
+        }
+
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}({:#x})", stringify!($name), self.address())
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.address() == other.address()
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.address().cmp(&other.address())
+            }
+        }
+
+        impl std::hash::Hash for $name {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                std::hash::Hash::hash(&self.address(), state);
+            }
+        }
+    };
+}
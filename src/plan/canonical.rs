@@ -0,0 +1,351 @@
+//! Canonical forms of query plans, s.t. equivalent plans can be
+//! recognized, e.g. to reuse dataflows that are already installed.
+
+use std::collections::HashMap;
+
+use crate::binding::{AntijoinBinding, AttributeBinding, Binding};
+use crate::binding::{BinaryPredicateBinding, ConstantBinding};
+use crate::hashing::stable_hash;
+use crate::plan::optimize::output_variables;
+use crate::plan::{Aggregate, AggregateBy, AggregationFn, Filter, FilterBy, Join, Plan, Project};
+use crate::Var;
+
+/// Assigns canonical numbers to variables, in the order in which
+/// they are first encountered.
+#[derive(Clone, Default)]
+struct Numbering {
+    numbers: HashMap<Var, Var>,
+}
+
+impl Numbering {
+    fn var(&mut self, variable: Var) -> Var {
+        let next = self.numbers.len() as Var;
+        *self.numbers.entry(variable).or_insert(next)
+    }
+
+    fn vars(&mut self, variables: &[Var]) -> Vec<Var> {
+        variables.iter().map(|x| self.var(*x)).collect()
+    }
+}
+
+fn canonical_binding(binding: &Binding, numbering: &mut Numbering) -> Binding {
+    match *binding {
+        Binding::Attribute(ref binding) => Binding::Attribute(AttributeBinding {
+            variables: (
+                numbering.var(binding.variables.0),
+                numbering.var(binding.variables.1),
+            ),
+            source_attribute: binding.source_attribute.clone(),
+        }),
+        Binding::Not(ref antijoin) => Binding::Not(AntijoinBinding {
+            binding: Box::new(canonical_binding(&antijoin.binding, numbering)),
+        }),
+        Binding::Constant(ref constant) => Binding::Constant(ConstantBinding {
+            variable: numbering.var(constant.variable),
+            value: constant.value.clone(),
+        }),
+        Binding::BinaryPredicate(ref predicate) => {
+            Binding::BinaryPredicate(BinaryPredicateBinding {
+                variables: (
+                    numbering.var(predicate.variables.0),
+                    numbering.var(predicate.variables.1),
+                ),
+                predicate: predicate.predicate.clone(),
+            })
+        }
+    }
+}
+
+fn canonical_aggregation_fn(
+    aggregation_fn: &AggregationFn,
+    numbering: &mut Numbering,
+) -> AggregationFn {
+    match *aggregation_fn {
+        AggregationFn::ArgMax(by) => AggregationFn::ArgMax(numbering.var(by)),
+        AggregationFn::ArgMin(by) => AggregationFn::ArgMin(numbering.var(by)),
+        ref other => other.clone(),
+    }
+}
+
+fn canonical_aggregate(aggregate: &Aggregate<Plan>, numbering: &mut Numbering) -> Aggregate<Plan> {
+    let mut aggregate = aggregate.clone();
+    aggregate.variables = numbering.vars(&aggregate.variables);
+    aggregate.key_variables = numbering.vars(&aggregate.key_variables);
+    aggregate.aggregation_variables = numbering.vars(&aggregate.aggregation_variables);
+    aggregate.with_variables = numbering.vars(&aggregate.with_variables);
+    aggregate.aggregation_fns = aggregate
+        .aggregation_fns
+        .iter()
+        .map(|x| canonical_aggregation_fn(x, numbering))
+        .collect();
+    aggregate.plan = Box::new(aggregate.plan.canonicalize(numbering));
+    aggregate
+}
+
+fn canonical_filter(filter: &Filter<Plan>, numbering: &mut Numbering) -> Filter<Plan> {
+    let mut filter = filter.clone();
+    filter.variables = numbering.vars(&filter.variables);
+    filter.plan = Box::new(filter.plan.canonicalize(numbering));
+    filter
+}
+
+/// Brings operands whose order doesn't affect the result into the
+/// order of their canonical forms. The canonical form of each operand
+/// is derived independently, based on the variables numbered so far,
+/// s.t. the resulting order doesn't depend on the original one.
+/// Operands with equal canonical forms are interchangeable.
+fn sorted<T, F>(operands: &[T], numbering: &Numbering, canonical: F) -> Vec<T>
+where
+    T: Clone + Ord,
+    F: Fn(&T, &mut Numbering) -> T,
+{
+    let mut keyed: Vec<(T, &T)> = operands
+        .iter()
+        .map(|operand| (canonical(operand, &mut numbering.clone()), operand))
+        .collect();
+
+    keyed.sort_by(|x, y| x.0.cmp(&y.0));
+    keyed
+        .drain(..)
+        .map(|(_key, operand)| operand.clone())
+        .collect()
+}
+
+impl Plan {
+    /// Returns a hash identifying the plan up to equivalence, for use
+    /// as a key when caching compiled dataflows. Before hashing, the
+    /// plan is brought into a canonical form:
+    ///
+    /// * Variables are renumbered in the order in which they are
+    ///   first encountered, s.t. plans differing only in their choice
+    ///   of variables hash equally.
+    /// * The plans of a Union and the bindings of a Hector stage are
+    ///   ordered by their canonical forms.
+    /// * The operands of a Join are ordered by their canonical forms.
+    ///   Where swapping them would change the layout of the output
+    ///   tuples, the join is wrapped in a projection restoring it.
+    ///
+    /// Plans with equal hashes thus produce the same tuples, barring
+    /// hash collisions. The converse doesn't hold in general, as not
+    /// all equivalences are recognized. Variables introduced while
+    /// implementing (e.g. for [e a ?v] patterns) are not part of the
+    /// plan and therefore don't affect the hash.
+    ///
+    /// Hashes agree across processes running the same build (see the
+    /// `hashing` module), unless the plan contains user closures
+    /// (e.g. FilterFn, TransformFn, or the comparators of FilterBy
+    /// and AggregateBy), which are identified by their address.
+    pub fn canonical_hash(&self) -> u64 {
+        stable_hash(&self.canonicalize(&mut Numbering::default()))
+    }
+
+    fn canonicalize(&self, numbering: &mut Numbering) -> Plan {
+        match *self {
+            Plan::Project(ref projection) => {
+                let mut projection = projection.clone();
+                projection.variables = numbering.vars(&projection.variables);
+                projection.plan = Box::new(projection.plan.canonicalize(numbering));
+                Plan::Project(projection)
+            }
+            Plan::Aggregate(ref aggregate) => {
+                Plan::Aggregate(canonical_aggregate(aggregate, numbering))
+            }
+            Plan::AggregateBy(ref aggregate) => Plan::AggregateBy(AggregateBy {
+                aggregate: canonical_aggregate(&aggregate.aggregate, numbering),
+                comparator: aggregate.comparator.clone(),
+            }),
+            Plan::Union(ref union) => {
+                let mut union = union.clone();
+                union.variables = numbering.vars(&union.variables);
+                union.plans = sorted(&union.plans, numbering, |plan, numbering| {
+                    plan.canonicalize(numbering)
+                })
+                .iter()
+                .map(|plan| plan.canonicalize(numbering))
+                .collect();
+                Plan::Union(union)
+            }
+            Plan::UnionTagged(ref union) => {
+                // Tags are assigned by position, the order of the
+                // plans is thus significant.
+                let mut union = union.clone();
+                union.tag = numbering.var(union.tag);
+                union.variables = numbering.vars(&union.variables);
+                union.plans = union
+                    .plans
+                    .iter()
+                    .map(|plan| plan.canonicalize(numbering))
+                    .collect();
+                Plan::UnionTagged(union)
+            }
+            Plan::Join(ref join) => {
+                let variables = numbering.vars(&join.variables);
+                let left = join.left_plan.canonicalize(&mut numbering.clone());
+                let right = join.right_plan.canonicalize(&mut numbering.clone());
+
+                if right < left {
+                    let swapped = Plan::Join(Join {
+                        variables: join.variables.clone(),
+                        left_plan: join.right_plan.clone(),
+                        right_plan: join.left_plan.clone(),
+                    });
+                    let layout = output_variables(self);
+
+                    if output_variables(&swapped) == layout {
+                        swapped.canonicalize(numbering)
+                    } else {
+                        Plan::Project(Project {
+                            variables: layout,
+                            plan: Box::new(swapped),
+                        })
+                        .canonicalize(numbering)
+                    }
+                } else {
+                    Plan::Join(Join {
+                        variables,
+                        left_plan: Box::new(join.left_plan.canonicalize(numbering)),
+                        right_plan: Box::new(join.right_plan.canonicalize(numbering)),
+                    })
+                }
+            }
+            Plan::Hector(ref hector) => {
+                let mut hector = hector.clone();
+                hector.variables = numbering.vars(&hector.variables);
+                hector.bindings = sorted(&hector.bindings, numbering, canonical_binding)
+                    .iter()
+                    .map(|binding| canonical_binding(binding, numbering))
+                    .collect();
+                hector.order = hector.order.map(|order| numbering.vars(&order));
+                Plan::Hector(hector)
+            }
+            Plan::Antijoin(ref antijoin) => {
+                let mut antijoin = antijoin.clone();
+                antijoin.variables = numbering.vars(&antijoin.variables);
+                antijoin.left_plan = Box::new(antijoin.left_plan.canonicalize(numbering));
+                antijoin.right_plan = Box::new(antijoin.right_plan.canonicalize(numbering));
+                Plan::Antijoin(antijoin)
+            }
+            Plan::Negate(ref plan) => Plan::Negate(Box::new(plan.canonicalize(numbering))),
+            Plan::Consolidate(ref plan) => {
+                Plan::Consolidate(Box::new(plan.canonicalize(numbering)))
+            }
+            Plan::Filter(ref filter) => Plan::Filter(canonical_filter(filter, numbering)),
+            Plan::FilterBy(ref filter) => Plan::FilterBy(FilterBy {
+                filter: canonical_filter(&filter.filter, numbering),
+                comparator: filter.comparator.clone(),
+            }),
+            Plan::FilterFn(ref filter) => {
+                let mut filter = filter.clone();
+                filter.variables = numbering.vars(&filter.variables);
+                filter.plan = Box::new(filter.plan.canonicalize(numbering));
+                Plan::FilterFn(filter)
+            }
+            Plan::Transform(ref transform) => {
+                let mut transform = transform.clone();
+                transform.variables = numbering.vars(&transform.variables);
+                transform.result_variable = numbering.var(transform.result_variable);
+                transform.plan = Box::new(transform.plan.canonicalize(numbering));
+                Plan::Transform(transform)
+            }
+            Plan::TransformFn(ref transform) => {
+                let mut transform = transform.clone();
+                transform.variables = numbering.vars(&transform.variables);
+                transform.result_variable = numbering.var(transform.result_variable);
+                transform.plan = Box::new(transform.plan.canonicalize(numbering));
+                Plan::TransformFn(transform)
+            }
+            Plan::Rename(ref rename) => {
+                let mut rename = rename.clone();
+                rename.mapping = rename
+                    .mapping
+                    .iter()
+                    .map(|(from, to)| (numbering.var(*from), numbering.var(*to)))
+                    .collect();
+                rename.plan = Box::new(rename.plan.canonicalize(numbering));
+                Plan::Rename(rename)
+            }
+            Plan::AsOfValid(ref as_of) => {
+                let mut as_of = as_of.clone();
+                as_of.variable = numbering.var(as_of.variable);
+                as_of.plan = Box::new(as_of.plan.canonicalize(numbering));
+                Plan::AsOfValid(as_of)
+            }
+            Plan::Missing(ref missing) => {
+                let mut missing = missing.clone();
+                missing.variable = numbering.var(missing.variable);
+                missing.plan = Box::new(missing.plan.canonicalize(numbering));
+                Plan::Missing(missing)
+            }
+            Plan::Sample(ref sample) => {
+                let mut sample = sample.clone();
+                sample.plan = Box::new(sample.plan.canonicalize(numbering));
+                Plan::Sample(sample)
+            }
+            Plan::JoinAny(ref join) => {
+                let mut join = join.clone();
+                join.conditions = join
+                    .conditions
+                    .iter()
+                    .map(|condition| {
+                        condition
+                            .iter()
+                            .map(|(left, right)| (numbering.var(*left), numbering.var(*right)))
+                            .collect()
+                    })
+                    .collect();
+                join.left_plan = Box::new(join.left_plan.canonicalize(numbering));
+                join.right_plan = Box::new(join.right_plan.canonicalize(numbering));
+                Plan::JoinAny(join)
+            }
+            Plan::MatchA(e, ref a, v) => {
+                Plan::MatchA(numbering.var(e), a.clone(), numbering.var(v))
+            }
+            Plan::MatchEA(e, ref a, v) => Plan::MatchEA(e, a.clone(), numbering.var(v)),
+            Plan::MatchAV(e, ref a, ref v) => Plan::MatchAV(numbering.var(e), a.clone(), v.clone()),
+            Plan::CountIndex(ref count) => {
+                let mut count = count.clone();
+                count.key = numbering.var(count.key);
+                count.count = numbering.var(count.count);
+                Plan::CountIndex(count)
+            }
+            Plan::NameExpr(ref variables, ref name) => {
+                Plan::NameExpr(numbering.vars(variables), name.clone())
+            }
+            Plan::Pull(ref pull) => {
+                let mut pull = pull.clone();
+                pull.variables = numbering.vars(&pull.variables);
+                pull.paths = pull
+                    .paths
+                    .iter()
+                    .map(|path| path.canonicalize(numbering))
+                    .collect();
+                Plan::Pull(pull)
+            }
+            Plan::PullLevel(ref path) => {
+                let mut path = path.clone();
+                path.variables = numbering.vars(&path.variables);
+                path.pull_variable = numbering.var(path.pull_variable);
+                path.plan = Box::new(path.plan.canonicalize(numbering));
+                Plan::PullLevel(path)
+            }
+            Plan::PullAll(ref path) => {
+                let mut path = path.clone();
+                path.variables = numbering.vars(&path.variables);
+                Plan::PullAll(path)
+            }
+            Plan::PullEntities(ref path) => {
+                let mut path = path.clone();
+                path.variables = numbering.vars(&path.variables);
+                Plan::PullEntities(path)
+            }
+            Plan::PullPath(ref path) => {
+                let mut path = path.clone();
+                path.entity = numbering.var(path.entity);
+                path.value = numbering.var(path.value);
+                Plan::PullPath(path)
+            }
+            #[cfg(feature = "graphql")]
+            Plan::GraphQl(ref q) => Plan::GraphQl(q.clone()),
+        }
+    }
+}
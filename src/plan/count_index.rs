@@ -0,0 +1,89 @@
+//! Plan stage exposing the count indices maintained for attributes.
+
+use timely::dataflow::scopes::child::Iterative;
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::operators::Reduce;
+
+use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
+use crate::{Aid, Value, Var};
+use crate::{CollectionRelation, Implemented, ShutdownHandle, VariableMap};
+
+/// A plan stage binding each entity (or, in reverse, each value) of
+/// an attribute to the number of values (or entities) associated
+/// with it. Counts are read from the count indices maintained for
+/// worst-case optimal joins, i.e. the forward count for entities and
+/// the reverse count for values, and thus require the attribute to
+/// support `QuerySupport::AdaptiveWCO` (and `IndexDirection::Both`
+/// in reverse). Counts are distinct only for attributes with
+/// distinct input semantics.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct CountIndex {
+    /// Variable bound to the entity (or value).
+    pub key: Var,
+    /// Attribute to count.
+    pub attribute: Aid,
+    /// Variable bound to the count.
+    pub count: Var,
+    /// Count entities per value, rather than values per entity?
+    pub reverse: bool,
+}
+
+impl Implementable for CountIndex {
+    fn dependencies(&self) -> Dependencies {
+        if self.reverse {
+            Dependencies::reverse_attribute(&self.attribute)
+        } else {
+            Dependencies::attribute(&self.attribute)
+        }
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        _local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        let trace = if self.reverse {
+            context.reverse_count(&self.attribute)
+        } else {
+            context.forward_count(&self.attribute)
+        };
+
+        match trace {
+            None => panic!("no count index built for attribute {:?}", &self.attribute),
+            Some(count_trace) => {
+                let name = format!("Count({})", &self.attribute);
+                let (arranged, shutdown_count) =
+                    count_trace.import_frontier(&nested.parent, &name);
+
+                // The count is the accumulated multiplicity of each
+                // key, which the reduction hands us directly.
+                let tuples = arranged
+                    .enter(nested)
+                    .reduce(|_key, input, output| {
+                        output.push((Value::Number(input[0].1 as i64), 1));
+                    })
+                    .map(|(key, count)| vec![key, count]);
+
+                let relation = CollectionRelation {
+                    variables: vec![self.key, self.count],
+                    tuples,
+                };
+
+                (
+                    Implemented::Collection(relation),
+                    ShutdownHandle::from_button(shutdown_count),
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Coarse cost and cardinality estimates for query plans.
+
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::trace::{BatchReader, TraceReader};
+
+use crate::binding::Binding;
+use crate::plan::{AggregateBy, FilterBy, ImplContext, Plan};
+
+/// Cardinality assumed for relations that we know nothing about.
+const DEFAULT_CARDINALITY: usize = 1000;
+
+/// Divisor applied to a relation's cardinality for each predicate,
+/// i.e. an estimated 1/3 of its tuples pass.
+const PREDICATE_SELECTIVITY: usize = 3;
+
+/// Divisor applied to a relation's cardinality to estimate its
+/// number of groups (or distinct keys), i.e. each group is estimated
+/// to hold 10 tuples.
+const GROUP_SELECTIVITY: usize = 10;
+
+/// A rough estimate of the output size of a plan and of the relative
+/// amount of work required to compute it. Estimates are only
+/// meaningful in comparison to one another.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct CostEstimate {
+    /// Estimated number of output tuples.
+    pub cardinality: usize,
+    /// Estimated relative cost, roughly the number of tuples touched.
+    pub cost: usize,
+}
+
+impl CostEstimate {
+    /// An estimate for reading `cardinality` tuples.
+    fn scan(cardinality: usize) -> Self {
+        CostEstimate {
+            cardinality,
+            cost: cardinality,
+        }
+    }
+
+    /// An estimate producing `cardinality` tuples from the given
+    /// inputs, paying for all of their work and tuples.
+    fn derived(cardinality: usize, inputs: &[CostEstimate]) -> Self {
+        let cost = inputs.iter().fold(cardinality, |cost, input| {
+            cost.saturating_add(input.cost).saturating_add(input.cardinality)
+        });
+
+        CostEstimate { cardinality, cost }
+    }
+}
+
+/// Estimated number of tuples matching a single key, given the size
+/// of an index and the number of distinct keys in it.
+fn lookup(size: usize, keys: Option<usize>) -> usize {
+    match keys {
+        None => std::cmp::max(1, size / GROUP_SELECTIVITY),
+        Some(keys) => std::cmp::max(1, size / std::cmp::max(1, keys)),
+    }
+}
+
+/// Number of distinct keys in a count trace, as an upper bound.
+fn key_count<Tr: TraceReader>(trace: &mut Tr) -> usize
+where
+    Tr::Batch: BatchReader<Tr::Key, Tr::Val, Tr::Time, Tr::R>,
+{
+    let mut size = 0;
+    trace.map_batches(|batch| size += batch.len());
+    size
+}
+
+impl Plan {
+    /// Walks the plan and produces a coarse estimate of its output
+    /// cardinality and relative cost, based on the sizes of the
+    /// attributes and relations it depends on. Nothing is
+    /// implemented. Joins are assumed to be on keys of the larger
+    /// input, predicates to pass a third of their inputs.
+    pub fn estimate<T, I>(&self, context: &mut I) -> CostEstimate
+    where
+        T: Timestamp + Lattice,
+        I: ImplContext<T>,
+    {
+        match *self {
+            Plan::Project(ref projection) => {
+                let input = projection.plan.estimate(context);
+                CostEstimate::derived(input.cardinality, &[input])
+            }
+            Plan::Aggregate(ref aggregate)
+            | Plan::AggregateBy(AggregateBy { ref aggregate, .. }) => {
+                let input = aggregate.plan.estimate(context);
+                let cardinality = if aggregate.key_variables.is_empty() {
+                    1
+                } else {
+                    std::cmp::max(1, input.cardinality / GROUP_SELECTIVITY)
+                };
+                CostEstimate::derived(cardinality, &[input])
+            }
+            Plan::Union(ref union) => {
+                let inputs: Vec<CostEstimate> =
+                    union.plans.iter().map(|plan| plan.estimate(context)).collect();
+                let cardinality = inputs
+                    .iter()
+                    .fold(0, |sum: usize, input| sum.saturating_add(input.cardinality));
+                CostEstimate::derived(cardinality, &inputs)
+            }
+            Plan::UnionTagged(ref union) => {
+                let inputs: Vec<CostEstimate> =
+                    union.plans.iter().map(|plan| plan.estimate(context)).collect();
+                let cardinality = inputs
+                    .iter()
+                    .fold(0, |sum: usize, input| sum.saturating_add(input.cardinality));
+                CostEstimate::derived(cardinality, &inputs)
+            }
+            Plan::Join(ref join) => {
+                let left = join.left_plan.estimate(context);
+                let right = join.right_plan.estimate(context);
+                let cardinality = if join.variables.is_empty() {
+                    left.cardinality.saturating_mul(right.cardinality)
+                } else {
+                    std::cmp::min(left.cardinality, right.cardinality)
+                };
+                CostEstimate::derived(cardinality, &[left, right])
+            }
+            Plan::Hector(ref hector) => {
+                let mut inputs = Vec::new();
+                let mut predicates = 0;
+
+                for binding in hector.bindings.iter() {
+                    match *binding {
+                        Binding::Attribute(ref binding) => {
+                            let size = context
+                                .attribute_size(&binding.source_attribute)
+                                .unwrap_or(DEFAULT_CARDINALITY);
+                            inputs.push(CostEstimate::scan(size));
+                        }
+                        Binding::Constant(_) | Binding::BinaryPredicate(_) => predicates += 1,
+                        Binding::Not(_) => {}
+                    }
+                }
+
+                // Worst-case optimal joins never produce more than
+                // their smallest input per proposing attribute.
+                let mut cardinality = inputs
+                    .iter()
+                    .map(|input| input.cardinality)
+                    .min()
+                    .unwrap_or(1);
+
+                for _ in 0..predicates {
+                    cardinality = std::cmp::max(1, cardinality / PREDICATE_SELECTIVITY);
+                }
+
+                CostEstimate::derived(cardinality, &inputs)
+            }
+            Plan::Antijoin(ref antijoin) => {
+                let left = antijoin.left_plan.estimate(context);
+                let right = antijoin.right_plan.estimate(context);
+                CostEstimate::derived(left.cardinality, &[left, right])
+            }
+            Plan::Negate(ref plan) | Plan::Consolidate(ref plan) => {
+                let input = plan.estimate(context);
+                CostEstimate::derived(input.cardinality, &[input])
+            }
+            Plan::Filter(ref filter) | Plan::FilterBy(FilterBy { ref filter, .. }) => {
+                let input = filter.plan.estimate(context);
+                let cardinality = std::cmp::max(1, input.cardinality / PREDICATE_SELECTIVITY);
+                CostEstimate::derived(cardinality, &[input])
+            }
+            Plan::FilterFn(ref filter) => {
+                let input = filter.plan.estimate(context);
+                let cardinality = std::cmp::max(1, input.cardinality / PREDICATE_SELECTIVITY);
+                CostEstimate::derived(cardinality, &[input])
+            }
+            Plan::Transform(ref transform) => {
+                let input = transform.plan.estimate(context);
+                CostEstimate::derived(input.cardinality, &[input])
+            }
+            Plan::TransformFn(ref transform) => {
+                let input = transform.plan.estimate(context);
+                CostEstimate::derived(input.cardinality, &[input])
+            }
+            Plan::Rename(ref rename) => rename.plan.estimate(context),
+            Plan::Sample(ref sample) => {
+                let input = sample.plan.estimate(context);
+                let cardinality = input.cardinality as i128 * i128::from(*sample.rate.numer())
+                    / i128::from(*sample.rate.denom());
+                CostEstimate::derived(std::cmp::max(1, cardinality) as usize, &[input])
+            }
+            Plan::AsOfValid(ref as_of) => {
+                let input = as_of.plan.estimate(context);
+                let cardinality = std::cmp::max(1, input.cardinality / PREDICATE_SELECTIVITY);
+                CostEstimate::derived(cardinality, &[input])
+            }
+            Plan::JoinAny(ref join) => {
+                let left = join.left_plan.estimate(context);
+                let right = join.right_plan.estimate(context);
+                let cardinality = std::cmp::min(left.cardinality, right.cardinality)
+                    .saturating_mul(join.conditions.len());
+                CostEstimate::derived(std::cmp::max(1, cardinality), &[left, right])
+            }
+            Plan::Missing(ref missing) => {
+                let input = missing.plan.estimate(context);
+                let cardinality = std::cmp::max(1, input.cardinality / PREDICATE_SELECTIVITY);
+                CostEstimate::derived(cardinality, &[input])
+            }
+            Plan::MatchA(_, ref a, _) => {
+                CostEstimate::scan(context.attribute_size(a).unwrap_or(DEFAULT_CARDINALITY))
+            }
+            Plan::MatchEA(_, ref a, _) => {
+                let size = context.attribute_size(a).unwrap_or(DEFAULT_CARDINALITY);
+                let keys = context.forward_count(a).map(key_count);
+                CostEstimate::scan(lookup(size, keys))
+            }
+            Plan::MatchAV(_, ref a, _) => {
+                let size = context.attribute_size(a).unwrap_or(DEFAULT_CARDINALITY);
+                let keys = context.reverse_count(a).map(key_count);
+                CostEstimate::scan(lookup(size, keys))
+            }
+            Plan::CountIndex(ref count) => {
+                let size = context
+                    .attribute_size(&count.attribute)
+                    .unwrap_or(DEFAULT_CARDINALITY);
+                CostEstimate::scan(std::cmp::max(1, size / GROUP_SELECTIVITY))
+            }
+            Plan::NameExpr(_, ref name) => {
+                CostEstimate::scan(context.relation_size(name).unwrap_or(DEFAULT_CARDINALITY))
+            }
+            Plan::Pull(ref pull) => {
+                let inputs: Vec<CostEstimate> =
+                    pull.paths.iter().map(|path| path.estimate(context)).collect();
+                let cardinality = inputs
+                    .iter()
+                    .fold(0, |sum: usize, input| sum.saturating_add(input.cardinality));
+                CostEstimate::derived(cardinality, &inputs)
+            }
+            Plan::PullLevel(ref path) => {
+                let mut inputs = vec![path.plan.estimate(context)];
+                for a in path.pull_attributes.iter() {
+                    let size = context.attribute_size(a).unwrap_or(DEFAULT_CARDINALITY);
+                    inputs.push(CostEstimate::scan(size));
+                }
+                let cardinality = inputs[0]
+                    .cardinality
+                    .saturating_mul(std::cmp::max(1, path.pull_attributes.len()));
+                CostEstimate::derived(cardinality, &inputs)
+            }
+            Plan::PullAll(ref path) => {
+                let inputs: Vec<CostEstimate> = path
+                    .pull_attributes
+                    .iter()
+                    .map(|a| {
+                        let size = context.attribute_size(a).unwrap_or(DEFAULT_CARDINALITY);
+                        CostEstimate::scan(size)
+                    })
+                    .collect();
+                let cardinality = inputs
+                    .iter()
+                    .fold(0, |sum: usize, input| sum.saturating_add(input.cardinality));
+                CostEstimate::derived(cardinality, &inputs)
+            }
+            Plan::PullEntities(ref path) => {
+                let inputs: Vec<CostEstimate> = path
+                    .pull_attributes
+                    .iter()
+                    .map(|a| {
+                        let size = context.attribute_size(a).unwrap_or(DEFAULT_CARDINALITY);
+                        let keys = context.forward_count(a).map(key_count);
+                        CostEstimate::scan(lookup(size, keys).saturating_mul(path.entities.len()))
+                    })
+                    .collect();
+                let cardinality = inputs
+                    .iter()
+                    .fold(0, |sum: usize, input| sum.saturating_add(input.cardinality));
+                CostEstimate::derived(cardinality, &inputs)
+            }
+            Plan::PullPath(ref path) => {
+                let inputs: Vec<CostEstimate> = path
+                    .path
+                    .iter()
+                    .map(|a| {
+                        let size = context.attribute_size(a).unwrap_or(DEFAULT_CARDINALITY);
+                        CostEstimate::scan(size)
+                    })
+                    .collect();
+                let cardinality = inputs
+                    .first()
+                    .map(|input| input.cardinality)
+                    .unwrap_or(0);
+                CostEstimate::derived(cardinality, &inputs)
+            }
+            #[cfg(feature = "graphql")]
+            Plan::GraphQl(_) => CostEstimate::scan(DEFAULT_CARDINALITY),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Human-readable explanations of query plans.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::plan::{Dependencies, Implementable, Plan};
+
+/// Number of spaces by which each level of the tree is indented.
+const INDENT: usize = 2;
+
+/// Renders a set of names in a deterministic order.
+fn sorted(names: &HashSet<String>) -> String {
+    let mut names: Vec<&String> = names.iter().collect();
+    names.sort();
+
+    names
+        .iter()
+        .map(|name| name.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+impl Plan {
+    /// Renders the plan as an indented tree, one stage per line,
+    /// similar to SQL `EXPLAIN`. Each line shows the stage, the
+    /// variables it binds, and any attributes or relations the stage
+    /// itself (rather than one of its inputs) depends on, e.g.
+    ///
+    /// ```text
+    /// Join on [0] binds [0]
+    ///   MatchA [0 :name 1] binds [0, 1] (attributes: :name)
+    ///   MatchA [0 :age 2] binds [0, 2] (attributes: :age)
+    /// ```
+    pub fn explain(&self) -> String {
+        let mut explanation = String::new();
+        self.explain_into(0, &mut explanation);
+
+        explanation
+    }
+
+    fn explain_into(&self, depth: usize, explanation: &mut String) {
+        let indent = " ".repeat(depth * INDENT);
+        let children = self.inputs();
+
+        write!(explanation, "{}{}", indent, self.label()).unwrap();
+
+        // GraphQl plans don't report their variables.
+        #[cfg(feature = "graphql")]
+        let binds = if let Plan::GraphQl(_) = *self {
+            false
+        } else {
+            true
+        };
+        #[cfg(not(feature = "graphql"))]
+        let binds = true;
+
+        if binds {
+            write!(explanation, " binds {:?}", self.variables()).unwrap();
+        }
+
+        // Only dependencies introduced by this stage are shown,
+        // everything else is explained further down the tree.
+        let mut inherited = Dependencies::none();
+        for child in children.iter() {
+            inherited = Dependencies::merge(inherited, child.dependencies());
+        }
+
+        let dependencies = self.dependencies();
+        let mut annotations = Vec::new();
+
+        let names = &dependencies.names - &inherited.names;
+        if !names.is_empty() {
+            annotations.push(format!("names: {}", sorted(&names)));
+        }
+
+        let attributes = &dependencies.attributes - &inherited.attributes;
+        if !attributes.is_empty() {
+            annotations.push(format!("attributes: {}", sorted(&attributes)));
+        }
+
+        let reverse = &dependencies.reverse_attributes - &inherited.reverse_attributes;
+        if !reverse.is_empty() {
+            annotations.push(format!("reverse indices: {}", sorted(&reverse)));
+        }
+
+        if !annotations.is_empty() {
+            write!(explanation, " ({})", annotations.join("; ")).unwrap();
+        }
+
+        explanation.push('\n');
+
+        if let Plan::Hector(ref hector) = *self {
+            let indent = " ".repeat((depth + 1) * INDENT);
+            for binding in hector.bindings.iter() {
+                writeln!(explanation, "{}{:?}", indent, binding).unwrap();
+            }
+        }
+
+        for child in children {
+            child.explain_into(depth + 1, explanation);
+        }
+    }
+
+    /// A short description of this stage, without its inputs.
+    pub(crate) fn label(&self) -> String {
+        match *self {
+            Plan::Project(_) => "Project".to_string(),
+            Plan::Aggregate(ref aggregate) => format!(
+                "Aggregate {:?} by {:?}",
+                aggregate.aggregation_fns, aggregate.key_variables
+            ),
+            Plan::AggregateBy(ref aggregate) => format!(
+                "AggregateBy {:?} by {:?}",
+                aggregate.aggregate.aggregation_fns, aggregate.aggregate.key_variables
+            ),
+            Plan::Union(_) => "Union".to_string(),
+            Plan::UnionTagged(ref union) => format!("UnionTagged by {}", union.tag),
+            Plan::Join(ref join) => format!("Join on {:?}", join.variables),
+            Plan::JoinAny(ref join) => format!("JoinAny on {:?}", join.conditions),
+            Plan::Hector(_) => "Hector".to_string(),
+            Plan::Antijoin(ref antijoin) => format!("Antijoin on {:?}", antijoin.variables),
+            Plan::Negate(_) => "Negate".to_string(),
+            Plan::Consolidate(_) => "Consolidate".to_string(),
+            Plan::Filter(ref filter) => {
+                format!("Filter {:?} on {:?}", filter.predicate, filter.variables)
+            }
+            Plan::FilterFn(ref filter) => format!("FilterFn on {:?}", filter.variables),
+            Plan::FilterBy(ref filter) => format!(
+                "FilterBy {:?} on {:?}",
+                filter.filter.predicate, filter.filter.variables
+            ),
+            Plan::Transform(ref transform) => format!(
+                "Transform {:?} of {:?} into {}",
+                transform.function, transform.variables, transform.result_variable
+            ),
+            Plan::TransformFn(ref transform) => format!(
+                "TransformFn of {:?} into {}",
+                transform.variables, transform.result_variable
+            ),
+            Plan::Rename(ref rename) => format!("Rename {:?}", rename.mapping),
+            Plan::Sample(ref sample) => {
+                format!("Sample at rate {} (seed {})", sample.rate, sample.seed)
+            }
+            Plan::AsOfValid(ref as_of) => format!(
+                "AsOfValid {} in [{}, {}) at {:?}",
+                as_of.variable, as_of.valid_from, as_of.valid_to, as_of.valid_time
+            ),
+            Plan::Missing(ref missing) => {
+                format!("Missing {} without {}", missing.variable, missing.attribute)
+            }
+            Plan::MatchA(e, ref a, v) => format!("MatchA [{} {} {}]", e, a, v),
+            Plan::MatchEA(e, ref a, v) => format!("MatchEA [{} {} {}]", e, a, v),
+            Plan::MatchAV(e, ref a, ref v) => format!("MatchAV [{} {} {:?}]", e, a, v),
+            Plan::CountIndex(ref count) if count.reverse => {
+                format!("CountIndex {} per value", count.attribute)
+            }
+            Plan::CountIndex(ref count) => format!("CountIndex {} per entity", count.attribute),
+            Plan::NameExpr(_, ref name) => format!("NameExpr {}", name),
+            Plan::Pull(_) => "Pull".to_string(),
+            Plan::PullLevel(ref path) => format!(
+                "PullLevel {:?} of {}",
+                path.pull_attributes, path.pull_variable
+            ),
+            Plan::PullAll(ref path) => format!("PullAll {:?}", path.pull_attributes),
+            Plan::PullEntities(ref path) => format!(
+                "PullEntities {:?} of {:?}",
+                path.pull_attributes, path.entities
+            ),
+            Plan::PullPath(ref path) => format!("PullPath {:?}", path.path),
+            #[cfg(feature = "graphql")]
+            Plan::GraphQl(ref q) => format!("GraphQl {:?}", q.query),
+        }
+    }
+}
@@ -1,5 +1,8 @@
 //! Predicate expression plan.
 
+use std::cmp::Ordering;
+use std::sync::Arc;
+
 use timely::dataflow::scopes::child::Iterative;
 use timely::dataflow::Scope;
 use timely::progress::Timestamp;
@@ -10,36 +13,14 @@ pub use crate::binding::{
     AsBinding, BinaryPredicate as Predicate, BinaryPredicateBinding, Binding,
 };
 use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
 use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, Value, Var, VariableMap};
 
-#[inline(always)]
-fn lt(a: &Value, b: &Value) -> bool {
-    a < b
-}
-#[inline(always)]
-fn lte(a: &Value, b: &Value) -> bool {
-    a <= b
-}
-#[inline(always)]
-fn gt(a: &Value, b: &Value) -> bool {
-    a > b
-}
-#[inline(always)]
-fn gte(a: &Value, b: &Value) -> bool {
-    a >= b
-}
-#[inline(always)]
-fn eq(a: &Value, b: &Value) -> bool {
-    a == b
-}
-#[inline(always)]
-fn neq(a: &Value, b: &Value) -> bool {
-    a != b
-}
-
 /// A plan stage filtering source tuples by the specified
 /// predicate. Frontends are responsible for ensuring that the source
-/// binds the argument variables.
+/// binds the argument variables. The predicate is applied either to
+/// a variable and a constant, or to two variables (if no constants
+/// are provided).
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub struct Filter<P: Implementable> {
     /// TODO
@@ -52,22 +33,71 @@ pub struct Filter<P: Implementable> {
     pub constants: Vec<Option<Value>>,
 }
 
+impl<P: Implementable> Filter<P> {
+    /// Creates a filter retaining only those tuples in which the
+    /// specified variable is bound to `Value::Null`.
+    ///
+    /// Note that every variable in a relation is bound in every
+    /// tuple. An optional attribute that is absent must therefore be
+    /// represented by an explicit `Value::Null` binding (e.g. as
+    /// introduced by an outer join or a default value). Filtering on
+    /// a variable the source doesn't bind at all is an error.
+    pub fn is_null(variable: Var, plan: P) -> Self {
+        Filter {
+            variables: vec![variable],
+            predicate: Predicate::EQ,
+            plan: Box::new(plan),
+            constants: vec![None, Some(Value::Null)],
+        }
+    }
+
+    /// Creates a filter retaining only those tuples in which the
+    /// specified variable is bound to anything other than
+    /// `Value::Null`. See `is_null`.
+    pub fn is_not_null(variable: Var, plan: P) -> Self {
+        Filter {
+            variables: vec![variable],
+            predicate: Predicate::NEQ,
+            plan: Box::new(plan),
+            constants: vec![None, Some(Value::Null)],
+        }
+    }
+}
+
 impl<P: Implementable> Implementable for Filter<P> {
     fn dependencies(&self) -> Dependencies {
         self.plan.dependencies()
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
-        // let mut bindings = self.plan.into_bindings();
-        // let variables = self.variables.clone();
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        // Comparisons against constants are expressed by binding
+        // each constant to a fresh variable, s.t. Hector can prune
+        // tuples as soon as the compared variable is bound.
+        let mut bindings = self.plan.into_bindings_with(gensym);
+        let mut variables = self.variables.iter();
+        let mut operands = Vec::with_capacity(2);
 
-        unimplemented!();
-        // bindings.push(Binding::BinaryPredicate(BinaryPredicateBinding {
-        //     variables: (variables[0], variables[1]),
-        //     predicate: self.predicate.clone(),
-        // }));
+        for position in 0..2 {
+            match self.constants.get(position) {
+                Some(Some(constant)) => {
+                    let sym = gensym();
+                    bindings.push(Binding::constant(sym, constant.clone()));
+                    operands.push(sym);
+                }
+                _ => operands.push(
+                    *variables
+                        .next()
+                        .expect("Filter doesn't bind enough variables."),
+                ),
+            }
+        }
+
+        bindings.push(Binding::BinaryPredicate(BinaryPredicateBinding {
+            variables: (operands[0], operands[1]),
+            predicate: self.predicate.clone(),
+        }));
 
-        // bindings
+        bindings
     }
 
     fn implement<'b, T, I, S>(
@@ -77,7 +107,26 @@ impl<P: Implementable> Implementable for Filter<P> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        self.implement_by(nested, local_arrangements, context, None)
+    }
+}
+
+impl<P: Implementable> Filter<P> {
+    /// Implements the filter, comparing operands in the specified
+    /// order, if any.
+    fn implement_by<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+        comparator: Option<ComparatorFn>,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -90,13 +139,10 @@ impl<P: Implementable> Implementable for Filter<P> {
             .map(|variable| relation.binds(*variable).expect("variable not found"))
             .collect();
 
-        let binary_predicate = match self.predicate {
-            Predicate::LT => lt,
-            Predicate::LTE => lte,
-            Predicate::GT => gt,
-            Predicate::GTE => gte,
-            Predicate::EQ => eq,
-            Predicate::NEQ => neq,
+        let predicate = self.predicate.clone();
+        let eval = move |a: &Value, b: &Value| match comparator {
+            None => predicate.eval(a, b),
+            Some(ref comparator) => predicate.eval_by(a, b, &*comparator.0),
         };
 
         let variables = relation.variables();
@@ -106,27 +152,168 @@ impl<P: Implementable> Implementable for Filter<P> {
             projected
         };
 
-        let filtered = if let Some(constant) = self.constants[0].clone() {
+        // Without any constants, the predicate compares the bindings
+        // of the two specified variables.
+        let constant_at = |idx: usize| self.constants.get(idx).cloned().unwrap_or(None);
+
+        let filtered = if let Some(constant) = constant_at(0) {
             CollectionRelation {
                 variables,
-                tuples: projected
-                    .filter(move |tuple| binary_predicate(&constant, &tuple[key_offsets[0]])),
+                tuples: projected.filter(move |tuple| eval(&constant, &tuple[key_offsets[0]])),
             }
-        } else if let Some(constant) = self.constants[1].clone() {
+        } else if let Some(constant) = constant_at(1) {
             CollectionRelation {
                 variables,
-                tuples: projected
-                    .filter(move |tuple| binary_predicate(&tuple[key_offsets[0]], &constant)),
+                tuples: projected.filter(move |tuple| eval(&tuple[key_offsets[0]], &constant)),
             }
         } else {
             CollectionRelation {
                 variables,
-                tuples: projected.filter(move |tuple| {
-                    binary_predicate(&tuple[key_offsets[0]], &tuple[key_offsets[1]])
-                }),
+                tuples: projected
+                    .filter(move |tuple| eval(&tuple[key_offsets[0]], &tuple[key_offsets[1]])),
             }
         };
 
         (Implemented::Collection(filtered), shutdown_handle)
     }
 }
+
+/// A user-supplied predicate over the bindings of a tuple. As
+/// closures can't be serialized, plans containing them can only be
+/// constructed (and implemented) in-process. Predicates are compared
+/// and hashed by identity, i.e. two predicates are equal iff they
+/// share the same closure.
+#[derive(Clone)]
+pub struct PredicateFn(pub Arc<dyn Fn(&[Value]) -> bool + Send + Sync>);
+
+impl PredicateFn {
+    /// Wraps the given closure.
+    pub fn new<F: Fn(&[Value]) -> bool + Send + Sync + 'static>(predicate: F) -> Self {
+        PredicateFn(Arc::new(predicate))
+    }
+}
+
+by_address!(PredicateFn, dyn Fn(&[Value]) -> bool + Send + Sync);
+
+/// A user-supplied order of values, for domains in which the natural
+/// order of values is meaningless (e.g. semantic versions or numbers
+/// held as strings). Comparators must be total orders. As with
+/// `PredicateFn`, plans containing them can only be constructed
+/// in-process, and comparators are compared and hashed by identity.
+#[derive(Clone)]
+pub struct ComparatorFn(pub Arc<dyn Fn(&Value, &Value) -> Ordering + Send + Sync>);
+
+impl ComparatorFn {
+    /// Wraps the given closure.
+    pub fn new<F: Fn(&Value, &Value) -> Ordering + Send + Sync + 'static>(comparator: F) -> Self {
+        ComparatorFn(Arc::new(comparator))
+    }
+
+    /// Compares the given pair of values.
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        (self.0)(a, b)
+    }
+}
+
+by_address!(
+    ComparatorFn,
+    dyn Fn(&Value, &Value) -> Ordering + Send + Sync
+);
+
+/// A plan stage filtering source tuples by an arbitrary user
+/// closure, for cases not covered by the built-in predicates. The
+/// closure is passed the bindings of the specified variables, in
+/// order. Plans containing this stage can't be sent over the wire.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub struct FilterFn<P: Implementable> {
+    /// Variables whose bindings are passed to the predicate.
+    pub variables: Vec<Var>,
+    /// Predicate to apply.
+    pub predicate: PredicateFn,
+    /// Plan for the data source.
+    pub plan: Box<P>,
+}
+
+impl<P: Implementable> Implementable for FilterFn<P> {
+    fn dependencies(&self) -> Dependencies {
+        self.plan.dependencies()
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        let (relation, mut shutdown_handle) =
+            self.plan.implement(nested, local_arrangements, context);
+
+        let key_offsets: Vec<usize> = self
+            .variables
+            .iter()
+            .map(|variable| relation.binds(*variable).expect("variable not found"))
+            .collect();
+
+        let predicate = self.predicate.clone();
+
+        let variables = relation.variables();
+        let projected = {
+            let (projected, shutdown) = relation.projected(nested, context, &variables);
+            shutdown_handle.merge_with(shutdown);
+            projected
+        };
+
+        let filtered = CollectionRelation {
+            variables,
+            tuples: projected.filter(move |tuple| {
+                let arguments: Vec<Value> =
+                    key_offsets.iter().map(|idx| tuple[*idx].clone()).collect();
+                (predicate.0)(&arguments)
+            }),
+        };
+
+        (Implemented::Collection(filtered), shutdown_handle)
+    }
+}
+
+/// A plan stage applying a `Filter` whose operands are compared in a
+/// user-supplied order, rather than the natural order of values. As
+/// with `FilterFn`, plans containing this stage can't be sent over
+/// the wire.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub struct FilterBy<P: Implementable> {
+    /// The filter to apply.
+    pub filter: Filter<P>,
+    /// Order in which to compare operands.
+    pub comparator: ComparatorFn,
+}
+
+impl<P: Implementable> Implementable for FilterBy<P> {
+    fn dependencies(&self) -> Dependencies {
+        self.filter.dependencies()
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        self.filter.implement_by(
+            nested,
+            local_arrangements,
+            context,
+            Some(self.comparator.clone()),
+        )
+    }
+}
@@ -13,6 +13,7 @@ use graphql_parser::query::{Name, Value};
 use crate::binding::Binding;
 use crate::plan::{gensym, Dependencies, ImplContext, Implementable};
 use crate::plan::{Hector, Plan, Pull, PullAll, PullLevel};
+use crate::timestamp::TimestampValue;
 use crate::{Aid, Var};
 use crate::{Implemented, ShutdownHandle, VariableMap};
 
@@ -33,6 +34,7 @@ impl GraphQl {
         let empty_plan = Hector {
             variables: vec![0],
             bindings: vec![],
+            order: None,
         };
 
         GraphQl {
@@ -46,7 +48,10 @@ impl GraphQl {
         let ast = parse_query(&query).expect("graphQL ast parsing failed");
         let paths = ast.into_paths(Hector {
             variables: root_plan.variables(),
-            bindings: root_plan.into_bindings(),
+            // Paths draw their symbols from the per-thread counter
+            // as well, as there is no context to draw from yet.
+            bindings: root_plan.into_bindings_with(&mut gensym),
+            order: None,
         });
 
         GraphQl { query, paths }
@@ -221,6 +226,7 @@ fn selection_set_to_paths(
                 variables: vec![],
                 plan: Box::new(Plan::Hector(plan)),
                 cardinality_many: false,
+                condition: None,
             }));
         }
     }
@@ -246,7 +252,7 @@ impl Implementable for GraphQl {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -26,6 +26,7 @@ use crate::plan::pull_v2::{PathId, Pull, PullAll, PullLevel};
 use crate::plan::{gensym, Dependencies, ImplContext, Implementable};
 use crate::plan::{Hector, Plan};
 use crate::timestamp;
+use crate::timestamp::TimestampValue;
 use crate::ShutdownHandle;
 use crate::{Aid, Output, Value, Var};
 
@@ -48,6 +49,7 @@ impl GraphQl {
         let empty_plan = Hector {
             variables: vec![0],
             bindings: vec![],
+            order: None,
         };
 
         GraphQl {
@@ -62,7 +64,10 @@ impl GraphQl {
         let ast = parse_query(&query).expect("graphQL ast parsing failed");
         let paths = ast.into_paths(Hector {
             variables: root_plan.variables(),
-            bindings: root_plan.into_bindings(),
+            // Paths draw their symbols from the per-thread counter
+            // as well, as there is no context to draw from yet.
+            bindings: root_plan.into_bindings_with(&mut gensym),
+            order: None,
         });
 
         GraphQl {
@@ -281,7 +286,7 @@ impl GraphQl {
         context: &mut I,
     ) -> (Stream<S, Output>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
         S::Timestamp: std::convert::Into<timestamp::Time>,
@@ -32,6 +32,7 @@ use crate::binding::{BinaryPredicateBinding, ConstantBinding};
 use crate::logging::DeclarativeEvent;
 use crate::plan::{Dependencies, ImplContext, Implementable};
 use crate::timestamp::altneu::AltNeu;
+use crate::timestamp::TimestampValue;
 use crate::{Aid, Value, Var};
 use crate::{CollectionRelation, Implemented, ShutdownHandle, VariableMap};
 
@@ -92,16 +93,15 @@ where
     }
 }
 
-impl<'a, S, V> IntoExtender<'a, S, V> for BinaryPredicateBinding
+impl<'a, S> IntoExtender<'a, S, Value> for BinaryPredicateBinding
 where
     S: Scope,
     S::Timestamp: Timestamp + Lattice,
-    V: ExchangeData + Hash,
 {
-    fn into_extender<P: ExchangeData + IndexNode<V>, B: AsBinding + std::fmt::Debug>(
+    fn into_extender<P: ExchangeData + IndexNode<Value>, B: AsBinding + std::fmt::Debug>(
         &self,
         prefix: &B,
-    ) -> Vec<Extender<'a, S, P, V>> {
+    ) -> Vec<Extender<'a, S, P, Value>> {
         match direction(prefix, self.variables) {
             Err(_msg) => {
                 // We won't panic here, this just means the predicate's variables
@@ -130,6 +130,10 @@ pub struct Hector {
     pub variables: Vec<Var>,
     /// Bindings to join.
     pub bindings: Vec<Binding>,
+    /// Preferred order in which to bind variables, overriding the
+    /// order chosen by `plan_order` (see `order_variables`).
+    #[serde(default)]
+    pub order: Option<Vec<Var>>,
 }
 
 enum Direction {
@@ -170,8 +174,8 @@ where
 /// special cases, because we always have to start from prefixes of
 /// size two.
 pub fn source_conflicts(source_index: usize, bindings: &[Binding]) -> Vec<&Binding> {
-    match bindings[source_index] {
-        Binding::Attribute(ref source) => {
+    match bindings[source_index].source() {
+        Some(source) => {
             let prefix_0 = vec![source.variables.0];
             let prefix_1 = vec![source.variables.1];
 
@@ -191,7 +195,7 @@ pub fn source_conflicts(source_index: usize, bindings: &[Binding]) -> Vec<&Bindi
                 })
                 .collect()
         }
-        _ => panic!("Source must be an AttributeBinding."),
+        None => panic!("Source must be an AttributeBinding."),
     }
 }
 
@@ -218,12 +222,12 @@ pub fn plan_order(source_index: usize, bindings: &[Binding]) -> (Vec<Var>, Vec<B
     // first. Presently, we just pick attributes arbitrarily.
 
     let mut prefix: Vec<Var> = Vec::with_capacity(variables.len());
-    match bindings[source_index] {
-        Binding::Attribute(ref source) => {
+    match bindings[source_index].source() {
+        Some(source) => {
             prefix.push(source.variables.0);
             prefix.push(source.variables.1);
         }
-        _ => panic!("Source binding must be an attribute."),
+        None => panic!("Source binding must be an attribute."),
     }
 
     let candidates_for = |bindings: &[Binding], target: Var| {
@@ -301,6 +305,49 @@ pub fn plan_order(source_index: usize, bindings: &[Binding]) -> (Vec<Var>, Vec<B
     (prefix, ordered_bindings)
 }
 
+/// Orders the variables according to the given preference, as far
+/// as the bindings permit. The prefix always starts out with the
+/// variables of the source binding. Afterwards, the first preferred
+/// variable that can be extended from the current prefix is bound
+/// next. Variables the preference doesn't mention are bound last, in
+/// the order chosen by `plan_order`.
+pub fn order_variables(source_index: usize, bindings: &[Binding], preference: &[Var]) -> Vec<Var> {
+    let (default_order, _) = plan_order(source_index, bindings);
+
+    let mut prefix: Vec<Var> = default_order[..2].to_vec();
+    let mut remaining: Vec<Var> = Vec::with_capacity(default_order.len());
+
+    for variable in preference.iter().chain(default_order.iter()) {
+        if default_order.contains(variable)
+            && !prefix.contains(variable)
+            && !remaining.contains(variable)
+        {
+            remaining.push(*variable);
+        }
+    }
+
+    while !remaining.is_empty() {
+        let next = remaining.iter().position(|target| {
+            bindings.iter().enumerate().any(|(index, binding)| {
+                index != source_index
+                    && binding.binds(*target).is_some()
+                    && binding.can_extend(&prefix, *target)
+            })
+        });
+
+        match next {
+            Some(position) => prefix.push(remaining.remove(position)),
+            None => {
+                // Nothing can be extended anymore, which would also
+                // be the case for the default order.
+                prefix.append(&mut remaining);
+            }
+        }
+    }
+
+    prefix
+}
+
 trait IndexNode<V> {
     fn index(&self, index: usize) -> V;
 }
@@ -337,6 +384,15 @@ impl IndexNode<Value> for Vec<Value> {
 }
 
 impl Hector {
+    /// Returns the order in which variables are bound by the delta
+    /// pipeline sourcing its prefixes from the specified binding.
+    pub fn variable_order(&self, source_index: usize) -> Vec<Var> {
+        match self.order {
+            None => plan_order(source_index, &self.bindings).0,
+            Some(ref preference) => order_variables(source_index, &self.bindings, preference),
+        }
+    }
+
     // @TODO pass single binding as argument?
     // @TODO make these static and take variables as well?
 
@@ -347,7 +403,7 @@ impl Hector {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -466,7 +522,7 @@ impl Implementable for Hector {
             .bindings
             .iter()
             .flat_map(|binding| {
-                if let Binding::Attribute(binding) = binding {
+                if let Some(binding) = binding.source() {
                     Some(binding.source_attribute.clone())
                 } else {
                     None
@@ -474,13 +530,41 @@ impl Implementable for Hector {
             })
             .collect::<HashSet<Aid>>();
 
+        // Bindings whose value variable is bound elsewhere might be
+        // extended from the value side, which requires the reverse
+        // index.
+        let reverse_attributes = self
+            .bindings
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, binding)| {
+                if let Some(binding) = binding.source() {
+                    let value_variable = binding.variables.1;
+                    let bound_elsewhere = self
+                        .bindings
+                        .iter()
+                        .enumerate()
+                        .any(|(other, b)| other != idx && b.binds(value_variable).is_some());
+
+                    if bound_elsewhere {
+                        Some(binding.source_attribute.clone())
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+            })
+            .collect::<HashSet<Aid>>();
+
         Dependencies {
             names: HashSet::new(),
             attributes,
+            reverse_attributes,
         }
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
+    fn into_bindings_with(&self, _gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
         self.bindings.clone()
     }
 
@@ -491,7 +575,7 @@ impl Implementable for Hector {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -533,19 +617,22 @@ impl Implementable for Hector {
 
                 // For each AttributeBinding (only AttributeBindings
                 // actually experience change), we construct a delta query
-                // driven by changes to that binding.
+                // driven by changes to that binding. This includes
+                // negated ones, whose changes retract (or restore)
+                // the tuples they match.
 
                 let changes = self.bindings.iter().enumerate()
-                    .flat_map(|(idx, delta_binding)| match delta_binding {
-                        Binding::Attribute(delta_binding) => {
+                    .flat_map(|(idx, delta)| match delta.source() {
+                        Some(delta_binding) => {
 
                             // We need to determine an order on the attributes
                             // that ensures that each is bound by preceeding
-                            // attributes. For now, we will take the requested order.
+                            // attributes. Unless an order was requested
+                            // explicitly, we take the one found by `plan_order`.
 
                             // @TODO use binding order returned here?
                             // might be problematic to ensure ordering is maintained?
-                            let (variables, _) = plan_order(idx, &self.bindings);
+                            let variables = self.variable_order(idx);
 
                             let mut prefix = Vec::with_capacity(variables.len());
 
@@ -572,7 +659,13 @@ impl Implementable for Hector {
                                     arranged
                                 });
 
-                            let mut source_conflicts = source_conflicts(idx, &self.bindings);
+                            // Attribute bindings over both variables of the
+                            // source merely restrict the pairs it produces, we
+                            // validate those right away.
+                            let (mut pair_conflicts, mut source_conflicts): (Vec<&Binding>, Vec<&Binding>) =
+                                source_conflicts(idx, &self.bindings)
+                                    .into_iter()
+                                    .partition(|conflict| conflict.source().is_some());
 
                             let mut source = if !source_conflicts.is_empty() {
                                 // @TODO there can be more than one conflict
@@ -622,6 +715,100 @@ impl Implementable for Hector {
                                     .as_collection(|e,v| vec![e.clone(), v.clone()])
                             };
 
+                            // Changes to a negated attribute affect the
+                            // tuples they match with the opposite sign.
+                            if let Binding::Not(_) = delta {
+                                source = source.negate();
+                            }
+
+                            for conflict in pair_conflicts.drain(..) {
+                                let other = conflict.source().unwrap();
+                                let other_idx = self.bindings.iter().position(|b| std::ptr::eq(b, conflict)).unwrap();
+                                let neu = other_idx >= idx;
+                                let forward = other.variables.0 == prefix[0];
+                                let name = other.source_attribute.to_string();
+
+                                debug!("\t...validating against {:?}", conflict);
+
+                                let (counts, proposes, validates) = if forward {
+                                    (&mut forward_counts, &mut forward_proposes, &mut forward_validates)
+                                } else {
+                                    (&mut reverse_counts, &mut reverse_proposes, &mut reverse_validates)
+                                };
+
+                                let count = counts
+                                    .entry(name.clone())
+                                    .or_insert_with(|| {
+                                        let trace = if forward { context.forward_count(&name) } else { context.reverse_count(&name) };
+                                        let (arranged, shutdown) = trace
+                                            .expect("count doesn't exist")
+                                            .import_frontier(&scope.parent.parent, &format!("Counts({})", &name));
+
+                                        shutdown_handle.add_button(shutdown);
+
+                                        arranged
+                                    })
+                                    .enter(&scope.parent)
+                                    .enter_at(&scope, move |_,_,t| AltNeu { time: t.clone(), neu });
+
+                                let propose = proposes
+                                    .entry(name.clone())
+                                    .or_insert_with(|| {
+                                        let trace = if forward { context.forward_propose(&name) } else { context.reverse_propose(&name) };
+                                        let (arranged, shutdown) = trace
+                                            .expect("propose doesn't exist")
+                                            .import_frontier(&scope.parent.parent, &format!("Propose({})", &name));
+
+                                        shutdown_handle.add_button(shutdown);
+
+                                        arranged
+                                    })
+                                    .enter(&scope.parent)
+                                    .enter_at(&scope, move |_,_,t| AltNeu { time: t.clone(), neu });
+
+                                let validate = validates
+                                    .entry(name.clone())
+                                    .or_insert_with(|| {
+                                        let trace = if forward { context.forward_validate(&name) } else { context.reverse_validate(&name) };
+                                        let (arranged, shutdown) = trace
+                                            .expect("validate doesn't exist")
+                                            .import_frontier(&scope.parent.parent, &format!("Validate({})", &name));
+
+                                        shutdown_handle.add_button(shutdown);
+
+                                        arranged
+                                    })
+                                    .enter(&scope.parent)
+                                    .enter_at(&scope, move |_,_,t| AltNeu { time: t.clone(), neu });
+
+                                // Either way, the first variable of the prefix
+                                // is the key of the index we validate against.
+                                let mut extender: Extender<'_, _, Vec<Value>, _> =
+                                    Box::new(CollectionExtender {
+                                        phantom: std::marker::PhantomData,
+                                        count,
+                                        propose,
+                                        validate,
+                                        key_selector: Rc::new(|prefix: &Vec<Value>| prefix.index(0)),
+                                    });
+
+                                if let Binding::Not(_) = conflict {
+                                    extender = Box::new(AntijoinExtender {
+                                        phantom: std::marker::PhantomData,
+                                        extender,
+                                    });
+                                }
+
+                                let pairs = source.map(|tuple| (vec![tuple[0].clone()], tuple[1].clone()));
+
+                                source = extender
+                                    .validate(&pairs)
+                                    .map(|(mut tuple, v)| {
+                                        tuple.push(v);
+                                        tuple
+                                    });
+                            }
+
                             for target in variables.iter() {
                                 match AsBinding::binds(&prefix, *target) {
                                     Some(_) => { /* already bound */ continue },
@@ -902,7 +1089,7 @@ impl Implementable for Hector {
                                      .inner)
                             }
                         }
-                        _ => None
+                        None => None
                     });
 
                 (inner.concatenate(changes).as_collection().leave(), shutdown_handle)
@@ -1020,15 +1207,14 @@ where
     direction: Direction,
 }
 
-impl<'a, S, V, P> PrefixExtender<S> for BinaryPredicateExtender<P, V>
+impl<'a, S, P> PrefixExtender<S> for BinaryPredicateExtender<P, Value>
 where
     S: Scope,
     S::Timestamp: Lattice + ExchangeData,
-    V: ExchangeData + Hash,
-    P: ExchangeData + IndexNode<V>,
+    P: ExchangeData + IndexNode<Value>,
 {
     type Prefix = P;
-    type Extension = V;
+    type Extension = Value;
 
     fn count(
         &mut self,
@@ -1038,45 +1224,19 @@ where
         None
     }
 
-    fn propose(&mut self, prefixes: &Collection<S, P>) -> Collection<S, (P, V)> {
+    fn propose(&mut self, prefixes: &Collection<S, P>) -> Collection<S, (P, Value)> {
         prefixes.map(|_prefix| panic!("BinaryPredicateExtender should never be asked to propose."))
     }
 
-    fn validate(&mut self, extensions: &Collection<S, (P, V)>) -> Collection<S, (P, V)> {
-        use self::BinaryPredicate::{EQ, GT, GTE, LT, LTE, NEQ};
+    fn validate(&mut self, extensions: &Collection<S, (P, Value)>) -> Collection<S, (P, Value)> {
+        let predicate = self.predicate.clone();
         match self.direction {
-            Direction::Reverse(offset) => {
-                match self.predicate {
-                    LT => extensions
-                        .filter(move |(prefix, extension)| *extension > prefix.index(offset)),
-                    LTE => extensions
-                        .filter(move |(prefix, extension)| *extension >= prefix.index(offset)),
-                    GT => extensions
-                        .filter(move |(prefix, extension)| *extension < prefix.index(offset)),
-                    GTE => extensions
-                        .filter(move |(prefix, extension)| *extension <= prefix.index(offset)),
-                    EQ => extensions
-                        .filter(move |(prefix, extension)| *extension == prefix.index(offset)),
-                    NEQ => extensions
-                        .filter(move |(prefix, extension)| *extension != prefix.index(offset)),
-                }
-            }
-            Direction::Forward(offset) => {
-                match self.predicate {
-                    LT => extensions
-                        .filter(move |(prefix, extension)| *extension < prefix.index(offset)),
-                    LTE => extensions
-                        .filter(move |(prefix, extension)| *extension <= prefix.index(offset)),
-                    GT => extensions
-                        .filter(move |(prefix, extension)| *extension > prefix.index(offset)),
-                    GTE => extensions
-                        .filter(move |(prefix, extension)| *extension >= prefix.index(offset)),
-                    EQ => extensions
-                        .filter(move |(prefix, extension)| *extension == prefix.index(offset)),
-                    NEQ => extensions
-                        .filter(move |(prefix, extension)| *extension != prefix.index(offset)),
-                }
-            }
+            Direction::Reverse(offset) => extensions.filter(move |(prefix, extension)| {
+                predicate.eval(&prefix.index(offset), extension)
+            }),
+            Direction::Forward(offset) => extensions.filter(move |(prefix, extension)| {
+                predicate.eval(extension, &prefix.index(offset))
+            }),
         }
     }
 }
@@ -1156,6 +1316,10 @@ where
         let logic1 = self.key_selector.clone();
         let logic2 = self.key_selector.clone();
 
+        // Prefixes must be routed to the worker holding the
+        // corresponding keys in the count trace, hence we have to
+        // use the same hash function the arrangement was partitioned
+        // by (see `Value::hashed_with` for why this can't be seeded).
         let exchange = Exchange::new(move |update: &((P, usize, usize), S::Timestamp, isize)| {
             logic1(&(update.0).0).hashed().as_u64()
         });
@@ -0,0 +1,99 @@
+//! HyperLogLog sketches, backing the approximate distinct count
+//! aggregation.
+//!
+//! HyperLogLog doesn't support deletes, as each register only retains
+//! the maximum rank observed. Instead of maintaining sketches
+//! directly, we therefore map each value to the (register, rank) pair
+//! it would update and let differential maintain the multiplicities
+//! of these pairs per group. A sketch is then rebuilt from the pairs
+//! that are present. Retractions simply decrement the corresponding
+//! pair, and the state kept per group is bounded by the number of
+//! registers times the number of possible ranks, independently of the
+//! number of distinct values.
+
+use crate::hashing::stable_hash;
+use crate::Value;
+
+/// Smallest supported precision.
+pub const MIN_PRECISION: u8 = 4;
+
+/// Largest supported precision.
+pub const MAX_PRECISION: u8 = 16;
+
+/// Panics unless the given precision is supported.
+pub fn check_precision(precision: u8) {
+    if precision < MIN_PRECISION || precision > MAX_PRECISION {
+        panic!(
+            "HyperLogLog precision must be between {} and {}, got {}.",
+            MIN_PRECISION, MAX_PRECISION, precision
+        );
+    }
+}
+
+/// Returns the register a value falls into, and the rank it would
+/// record there, for a sketch with `2^precision` registers. Hashing is
+/// deterministic, s.t. all workers agree on the result.
+pub fn observe(value: &Value, precision: u8) -> (usize, u8) {
+    let hash = stable_hash(value);
+
+    let register = (hash >> (64 - precision)) as usize;
+    let remainder = hash << precision;
+
+    // Position of the leftmost one bit in the remaining bits,
+    // capped for remainders that are all zeros.
+    let rank = std::cmp::min(remainder.leading_zeros() as u8, 64 - precision) + 1;
+
+    (register, rank)
+}
+
+/// A HyperLogLog sketch with `2^precision` registers.
+pub struct Sketch {
+    registers: Vec<u8>,
+}
+
+impl Sketch {
+    /// Creates an empty sketch.
+    pub fn new(precision: u8) -> Self {
+        check_precision(precision);
+
+        Sketch {
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    /// Records an observation, as returned by `observe`.
+    pub fn insert(&mut self, register: usize, rank: u8) {
+        if self.registers[register] < rank {
+            self.registers[register] = rank;
+        }
+    }
+
+    /// Estimates the number of distinct values observed, applying the
+    /// usual linear counting correction for small cardinalities.
+    pub fn estimate(&self) -> i64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|rank| 2f64.powi(-i32::from(*rank)))
+            .sum();
+
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|rank| **rank == 0).count();
+
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+
+        estimate.round() as i64
+    }
+}
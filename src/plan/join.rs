@@ -2,19 +2,17 @@
 
 use timely::dataflow::scopes::child::Iterative;
 use timely::dataflow::Scope;
-use timely::order::Product;
 use timely::progress::Timestamp;
 
 use differential_dataflow::lattice::Lattice;
-use differential_dataflow::operators::arrange::{Arrange, Arranged};
 use differential_dataflow::operators::JoinCore;
 
 use crate::binding::{AsBinding, Binding};
 use crate::plan::{next_id, Dependencies, ImplContext, Implementable};
-use crate::{Aid, Eid, Value, Var};
+use crate::timestamp::TimestampValue;
+use crate::{Aid, Eid, Plan, Value, Var};
 use crate::{
-    AttributeBinding, CollectionRelation, Implemented, Relation, ShutdownHandle, TraceValHandle,
-    VariableMap,
+    AttributeBinding, CollectionRelation, Implemented, Relation, ShutdownHandle, VariableMap,
 };
 
 /// A plan stage joining two source relations on the specified
@@ -30,6 +28,27 @@ pub struct Join<P1: Implementable, P2: Implementable> {
     pub right_plan: Box<P2>,
 }
 
+impl Join<Plan, Plan> {
+    /// Creates a natural join, i.e. one on all variables bound by
+    /// both inputs, in the order in which the left input binds
+    /// them. Joins on a subset of the shared variables must still be
+    /// constructed explicitly.
+    pub fn on(left: Plan, right: Plan) -> Self {
+        let right_variables = right.variables();
+        let variables = left
+            .variables()
+            .into_iter()
+            .filter(|variable| right_variables.contains(variable))
+            .collect();
+
+        Join {
+            variables,
+            left_plan: Box::new(left),
+            right_plan: Box::new(right),
+        }
+    }
+}
+
 fn attribute_attribute<'b, T, I, S>(
     nested: &mut Iterative<'b, S, u64>,
     context: &mut I,
@@ -38,7 +57,7 @@ fn attribute_attribute<'b, T, I, S>(
     right: AttributeBinding,
 ) -> (Implemented<'b, S>, ShutdownHandle)
 where
-    T: Timestamp + Lattice,
+    T: Timestamp + Lattice + TimestampValue,
     I: ImplContext<T>,
     S: Scope<Timestamp = T>,
 {
@@ -110,7 +129,7 @@ fn collection_collection<'b, T, S, I>(
     right: CollectionRelation<'b, S>,
 ) -> (Implemented<'b, S>, ShutdownHandle)
 where
-    T: Timestamp + Lattice,
+    T: Timestamp + Lattice + TimestampValue,
     I: ImplContext<T>,
     S: Scope<Timestamp = T>,
 {
@@ -132,22 +151,16 @@ where
         )
         .collect();
 
-    let left_arranged: Arranged<
-        Iterative<'b, S, u64>,
-        TraceValHandle<Vec<Value>, Vec<Value>, Product<S::Timestamp, u64>, isize>,
-    > = {
-        let (arranged, shutdown) = left.tuples_by_variables(nested, context, &target_variables);
+    let left_arranged = {
+        let (arranged, shutdown) = left.arranged_by_variables(nested, context, &target_variables);
         shutdown_handle.merge_with(shutdown);
-        arranged.arrange()
+        arranged
     };
 
-    let right_arranged: Arranged<
-        Iterative<'b, S, u64>,
-        TraceValHandle<Vec<Value>, Vec<Value>, Product<S::Timestamp, u64>, isize>,
-    > = {
-        let (arranged, shutdown) = right.tuples_by_variables(nested, context, &target_variables);
+    let right_arranged = {
+        let (arranged, shutdown) = right.arranged_by_variables(nested, context, &target_variables);
         shutdown_handle.merge_with(shutdown);
-        arranged.arrange()
+        arranged
     };
 
     let tuples = left_arranged.join_core(&right_arranged, |key: &Vec<Value>, v1, v2| {
@@ -173,7 +186,7 @@ fn collection_attribute<'b, T, S, I>(
     right: AttributeBinding,
 ) -> (Implemented<'b, S>, ShutdownHandle)
 where
-    T: Timestamp + Lattice,
+    T: Timestamp + Lattice + TimestampValue,
     I: ImplContext<T>,
     S: Scope<Timestamp = T>,
 {
@@ -250,9 +263,9 @@ impl<P1: Implementable, P2: Implementable> Implementable for Join<P1, P2> {
         )
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
-        let mut left_bindings = self.left_plan.into_bindings();
-        let mut right_bindings = self.right_plan.into_bindings();
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        let mut left_bindings = self.left_plan.into_bindings_with(gensym);
+        let mut right_bindings = self.right_plan.into_bindings_with(gensym);
 
         let mut bindings = Vec::with_capacity(left_bindings.len() + right_bindings.len());
         bindings.append(&mut left_bindings);
@@ -295,7 +308,7 @@ impl<P1: Implementable, P2: Implementable> Implementable for Join<P1, P2> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
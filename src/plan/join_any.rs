@@ -0,0 +1,151 @@
+//! Disjunctive equijoin expression plan.
+
+use timely::dataflow::operators::Concatenate;
+use timely::dataflow::scopes::child::Iterative;
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::operators::{Join, Threshold};
+use differential_dataflow::AsCollection;
+
+use crate::binding::{AsBinding, Binding};
+use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
+use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, Value, Var, VariableMap};
+
+/// A plan stage joining two source relations on any of several
+/// conditions, e.g. `left.x = right.a OR left.x = right.b`. Each
+/// condition is a conjunction of equalities between a variable bound
+/// by the left input and one bound by the right input. Output tuples
+/// consist of the bindings of the left input, followed by those of
+/// the right input. The inputs must therefore not share any
+/// variables.
+///
+/// This is equivalent to the union over one join per condition and
+/// is implemented as such. As with `Union`, duplicates are removed,
+/// s.t. a pair of tuples satisfying several of the conditions is
+/// reported only once.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct JoinAny<P1: Implementable, P2: Implementable> {
+    /// Alternative join conditions, each given as pairs of a left
+    /// and a right variable that must be bound to the same value.
+    pub conditions: Vec<Vec<(Var, Var)>>,
+    /// Plan for the left input.
+    pub left_plan: Box<P1>,
+    /// Plan for the right input.
+    pub right_plan: Box<P2>,
+}
+
+impl<P1: Implementable, P2: Implementable> JoinAny<P1, P2> {
+    /// Returns the variables bound by this stage, i.e. those of the
+    /// left source followed by those of the right source.
+    pub fn variables(&self, left: Vec<Var>, right: Vec<Var>) -> Vec<Var> {
+        left.into_iter().chain(right.into_iter()).collect()
+    }
+}
+
+/// Returns the offsets of the specified variables amongst all bound
+/// variables.
+fn offsets(bound: &[Var], variables: &[Var]) -> Vec<usize> {
+    variables
+        .iter()
+        .map(|variable| {
+            bound
+                .iter()
+                .position(|x| x == variable)
+                .expect("variable not found")
+        })
+        .collect()
+}
+
+impl<P1: Implementable, P2: Implementable> Implementable for JoinAny<P1, P2> {
+    fn dependencies(&self) -> Dependencies {
+        Dependencies::merge(
+            self.left_plan.dependencies(),
+            self.right_plan.dependencies(),
+        )
+    }
+
+    fn into_bindings_with(&self, _gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        panic!("JoinAny can't be implemented via Hector.");
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        let (left, mut shutdown_handle) =
+            self.left_plan
+                .implement(nested, local_arrangements, context);
+        let (right, shutdown_right) =
+            self.right_plan
+                .implement(nested, local_arrangements, context);
+        shutdown_handle.merge_with(shutdown_right);
+
+        let left_variables = left.variables();
+        let right_variables = right.variables();
+
+        if let Some(shared) = left_variables.iter().find(|x| right_variables.contains(x)) {
+            panic!(
+                "Inputs of JoinAny must not share variables, but both bind {}.",
+                shared
+            );
+        }
+
+        let left_tuples = {
+            let (tuples, shutdown) = left.projected(nested, context, &left_variables);
+            shutdown_handle.merge_with(shutdown);
+            tuples
+        };
+
+        let right_tuples = {
+            let (tuples, shutdown) = right.projected(nested, context, &right_variables);
+            shutdown_handle.merge_with(shutdown);
+            tuples
+        };
+
+        let streams = self.conditions.iter().map(|condition| {
+            let left_keys: Vec<Var> = condition.iter().map(|pair| pair.0).collect();
+            let right_keys: Vec<Var> = condition.iter().map(|pair| pair.1).collect();
+
+            let left_offsets = offsets(&left_variables, &left_keys);
+            let right_offsets = offsets(&right_variables, &right_keys);
+
+            let left_keyed = left_tuples.map(move |tuple| {
+                let key: Vec<Value> = left_offsets.iter().map(|i| tuple[*i].clone()).collect();
+                (key, tuple)
+            });
+
+            let right_keyed = right_tuples.map(move |tuple| {
+                let key: Vec<Value> = right_offsets.iter().map(|i| tuple[*i].clone()).collect();
+                (key, tuple)
+            });
+
+            left_keyed
+                .join_map(&right_keyed, |_key, left, right| {
+                    left.iter()
+                        .chain(right.iter())
+                        .cloned()
+                        .collect::<Vec<Value>>()
+                })
+                .inner
+        });
+
+        let tuples = nested.concatenate(streams).as_collection().distinct();
+
+        let relation = CollectionRelation {
+            variables: self.variables(left_variables, right_variables),
+            tuples,
+        };
+
+        (Implemented::Collection(relation), shutdown_handle)
+    }
+}
@@ -0,0 +1,97 @@
+//! Plan stage retaining entities lacking an attribute.
+
+use timely::dataflow::scopes::child::Iterative;
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::operators::{Join, Threshold};
+
+use crate::binding::Binding;
+use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
+use crate::{Aid, Var};
+use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, VariableMap};
+
+/// A plan stage retaining only those source tuples, whose entity
+/// doesn't have any value for the specified attribute, i.e. the
+/// equivalent of `(not [?e a _])`. Absence can only be established
+/// relative to a set of candidates, the source plan therefore acts
+/// as the domain of entities under consideration (e.g. all entities
+/// with a `:name`). Entities unknown to the source never appear in
+/// the output, no matter which attributes they lack.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct Missing<P: Implementable> {
+    /// Variable bound to the entities that must lack the attribute.
+    pub variable: Var,
+    /// Plan for the domain of candidate entities.
+    pub plan: Box<P>,
+    /// Attribute the entities must not have.
+    pub attribute: Aid,
+}
+
+impl<P: Implementable> Missing<P> {
+    /// Returns the variables bound by this stage, i.e. the entity
+    /// variable followed by the remaining variables of the source.
+    pub fn variables(&self, source: Vec<Var>) -> Vec<Var> {
+        std::iter::once(self.variable)
+            .chain(source.into_iter().filter(|x| *x != self.variable))
+            .collect()
+    }
+}
+
+impl<P: Implementable> Implementable for Missing<P> {
+    fn dependencies(&self) -> Dependencies {
+        let mut dependencies = self.plan.dependencies();
+        dependencies.attributes.insert(self.attribute.to_string());
+
+        dependencies
+    }
+
+    fn into_bindings_with(&self, _gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        panic!("Missing can't be implemented via Hector.");
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        let (relation, mut shutdown_handle) =
+            self.plan.implement(nested, local_arrangements, context);
+        let variables = self.variables(relation.variables());
+
+        let (keyed, shutdown) = relation.tuples_by_variables(nested, context, &[self.variable]);
+        shutdown_handle.merge_with(shutdown);
+
+        // Entities with at least one value for the attribute.
+        let present = {
+            let (propose, shutdown_propose) = context
+                .forward_propose(&self.attribute)
+                .expect("forward propose trace does not exist")
+                .import_frontier(&nested.parent, &self.attribute);
+
+            shutdown_handle.add_button(shutdown_propose);
+
+            propose
+                .enter(nested)
+                .as_collection(|e, _v| e.clone())
+                .distinct()
+        };
+
+        let tuples = keyed
+            .map(|(mut key, tuple)| (key.pop().expect("missing key"), tuple))
+            .antijoin(&present)
+            .map(|(key, tuple)| std::iter::once(key).chain(tuple.into_iter()).collect());
+
+        let relation = CollectionRelation { variables, tuples };
+
+        (Implemented::Collection(relation), shutdown_handle)
+    }
+}
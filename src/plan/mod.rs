@@ -1,67 +1,121 @@
 //! Types and traits for implementing query plans.
 
+use std::cell::Cell;
 use std::collections::HashSet;
 use std::ops::Deref;
-use std::sync::atomic::{self, AtomicUsize};
 
 use timely::dataflow::scopes::child::Iterative;
 use timely::dataflow::Scope;
 use timely::progress::Timestamp;
+use timely::PartialOrder;
 
 use differential_dataflow::lattice::Lattice;
+use differential_dataflow::operators::{Consolidate, Threshold};
+use differential_dataflow::trace::{BatchReader, Cursor, TraceReader};
 
 use crate::binding::{AsBinding, AttributeBinding, Binding};
-use crate::Rule;
+use crate::timestamp::TimestampValue;
+use crate::{Error, Rule};
 use crate::{Aid, Eid, Value, Var};
 use crate::{
     CollectionRelation, Implemented, Relation, RelationHandle, ShutdownHandle, VariableMap,
 };
 use crate::{TraceKeyHandle, TraceValHandle};
 
+#[macro_use]
+mod by_address;
+
 #[cfg(feature = "set-semantics")]
 pub mod aggregate;
 #[cfg(not(feature = "set-semantics"))]
 pub mod aggregate_neu;
 pub mod antijoin;
+pub mod as_of_valid;
+pub mod canonical;
+pub mod count_index;
+pub mod estimate;
+pub mod explain;
 pub mod filter;
 #[cfg(feature = "graphql")]
 pub mod graphql;
 #[cfg(feature = "graphql")]
 pub mod graphql_v2;
 pub mod hector;
+pub mod hyperloglog;
 pub mod join;
+pub mod join_any;
+pub mod missing;
+pub mod optimize;
+pub mod prepare;
 pub mod project;
 pub mod pull;
 pub mod pull_v2;
+mod range;
+pub mod rename;
+pub mod sample;
 pub mod transform;
 pub mod union;
 
 #[cfg(feature = "set-semantics")]
-pub use self::aggregate::{Aggregate, AggregationFn};
+pub use self::aggregate::{Aggregate, AggregateBy, AggregationFn};
 #[cfg(not(feature = "set-semantics"))]
-pub use self::aggregate_neu::{Aggregate, AggregationFn};
+pub use self::aggregate_neu::{Aggregate, AggregateBy, AggregationFn};
 pub use self::antijoin::Antijoin;
-pub use self::filter::{Filter, Predicate};
+pub use self::as_of_valid::AsOfValid;
+pub use self::count_index::CountIndex;
+pub use self::estimate::CostEstimate;
+pub use self::filter::{ComparatorFn, Filter, FilterBy, FilterFn, Predicate, PredicateFn};
 #[cfg(feature = "graphql")]
 pub use self::graphql::GraphQl;
 pub use self::hector::Hector;
 pub use self::join::Join;
+pub use self::join_any::JoinAny;
+pub use self::missing::Missing;
 pub use self::project::Project;
-pub use self::pull::{Pull, PullAll, PullLevel};
-pub use self::transform::{Function, Transform};
-pub use self::union::Union;
+pub use self::pull::{
+    sort_pulled, Pull, PullAll, PullCondition, PullEntities, PullLevel, PullPath,
+};
+#[cfg(feature = "serde_json")]
+pub use self::pull::pull_to_json;
+pub use self::rename::Rename;
+pub use self::sample::Sample;
+pub use self::transform::{DatePart, Function, FunctionFn, Transform, TransformFn};
+pub use self::union::{Union, UnionTagged};
 
-static ID: AtomicUsize = AtomicUsize::new(0);
-static SYM: AtomicUsize = AtomicUsize::new(std::usize::MAX);
+thread_local! {
+    static ID: Cell<usize> = Cell::new(0);
+    static SYM: Cell<usize> = Cell::new(std::usize::MAX);
+}
 
-/// @FIXME
+/// Returns a fresh id, e.g. for use as an entity id when datafying
+/// plans. Counters are maintained per thread, s.t. compiling the
+/// same plans on each worker produces the same ids, independently of
+/// what other workers are doing.
 pub fn next_id() -> Eid {
-    ID.fetch_add(1, atomic::Ordering::SeqCst) as Eid
+    ID.with(|id| {
+        let next = id.get();
+        id.set(next + 1);
+        next as Eid
+    })
 }
 
-/// @FIXME
+/// Returns a fresh variable symbol. Symbols are handed out counting
+/// down from the largest possible variable, in order to avoid
+/// clashing with user-specified variables. Counters are maintained
+/// per thread, as for `next_id`.
 pub fn gensym() -> Var {
-    SYM.fetch_sub(1, atomic::Ordering::SeqCst) as Var
+    SYM.with(|sym| {
+        let next = sym.get();
+        sym.set(next - 1);
+        next as Var
+    })
+}
+
+/// Resets the id and symbol counters of the calling thread, s.t.
+/// subsequent compilations are reproducible.
+pub fn reset_counters() {
+    ID.with(|id| id.set(0));
+    SYM.with(|sym| sym.set(std::usize::MAX));
 }
 
 /// A thing that can provide global state required during the
@@ -73,6 +127,13 @@ where
     /// Returns the definition for the rule of the given name.
     fn rule(&self, name: &str) -> Option<&Rule>;
 
+    /// Returns the definitions of all registered rules, sorted by
+    /// name.
+    fn rules(&self) -> Vec<&Rule>;
+
+    /// Returns the names of all attributes, sorted.
+    fn attributes(&self) -> Vec<Aid>;
+
     /// Returns a mutable reference to a (non-base) relation, if one
     /// is registered under the given name.
     fn global_arrangement(&mut self, name: &str) -> Option<&mut RelationHandle<T>>;
@@ -80,6 +141,32 @@ where
     /// Checks whether an attribute of that name exists.
     fn has_attribute(&self, name: &str) -> bool;
 
+    /// Checks whether plans may access the attribute of that
+    /// name. Allows everything by default.
+    fn is_attribute_allowed(&self, _name: &str) -> bool {
+        true
+    }
+
+    /// Makes the attribute of the given name available under a new
+    /// name, re-using its existing traces. Contexts that don't manage
+    /// attributes themselves don't support this.
+    fn rename_attribute(&mut self, from: &str, _to: &str) -> Result<(), Error> {
+        Err(Error::unsupported(format!(
+            "Attribute {} can't be renamed in this context.",
+            from
+        )))
+    }
+
+    /// Returns the current name of an attribute that was formerly
+    /// known under the given name, if it has been renamed.
+    fn renamed_attribute(&self, _name: &str) -> Option<Aid> {
+        None
+    }
+
+    /// Checks whether a (non-base) relation is registered under the
+    /// given name.
+    fn has_relation(&self, name: &str) -> bool;
+
     /// Retrieves the forward count trace for the specified aid.
     fn forward_count(&mut self, name: &str) -> Option<&mut TraceKeyHandle<Value, T, isize>>;
 
@@ -115,6 +202,152 @@ where
     /// materialized and re-used on their own (i.e. without more
     /// specific constraints).
     fn is_underconstrained(&self, name: &str) -> bool;
+
+    /// Returns a fresh variable symbol, unique within this
+    /// context. Implementations should maintain their own counter,
+    /// s.t. compilations against different contexts don't share a
+    /// namespace. The default falls back to the per-thread counter.
+    fn gensym(&mut self) -> Var {
+        gensym()
+    }
+
+    /// Returns the sink to report the throughput of joins,
+    /// aggregates, and filters to, if any.
+    #[cfg(feature = "metrics")]
+    fn metrics_sink(&self) -> Option<std::sync::Arc<dyn crate::metrics::MetricsSink>> {
+        None
+    }
+
+    /// Returns an estimate of the number of tuples held by the
+    /// relation of the given name, derived from the batch metadata
+    /// of its arrangement. The estimate counts updates, not distinct
+    /// tuples, and is thus an upper bound until the trace has been
+    /// compacted.
+    fn relation_size(&mut self, name: &str) -> Option<usize> {
+        self.global_arrangement(name).map(|trace| {
+            let mut size = 0;
+            trace.map_batches(|batch| size += batch.len());
+            size
+        })
+    }
+
+    /// Returns the frontier of the relation of the given name, i.e.
+    /// the upper bound of the most recent batch in its trace. The
+    /// relation reflects all updates at times not in advance of the
+    /// frontier, an empty frontier indicates that it is complete.
+    fn relation_frontier(&mut self, name: &str) -> Option<Vec<T>> {
+        self.global_arrangement(name).map(|trace| {
+            let mut frontier = vec![T::minimum()];
+            trace.map_batches(|batch| frontier = batch.upper().to_vec());
+            frontier
+        })
+    }
+
+    /// Checks whether the relation of the given name has been fully
+    /// computed up to and including `time`, s.t. reading it now
+    /// yields a consistent answer as of `time`. Relations that don't
+    /// exist are never stable.
+    fn is_stable(&mut self, name: &str, time: &T) -> bool {
+        match self.relation_frontier(name) {
+            None => false,
+            Some(frontier) => !frontier.iter().any(|t| t.less_equal(time)),
+        }
+    }
+
+    /// Reads the current contents of the relation of the given name
+    /// from its trace. Tuples are reported at their (possibly
+    /// compacted) times and are not consolidated, s.t. this is
+    /// mostly useful for tests and debugging.
+    fn dump_relation(&mut self, name: &str) -> Option<Vec<(Vec<Value>, T, isize)>> {
+        self.global_arrangement(name).map(|trace| {
+            let mut tuples = Vec::new();
+            let (mut cursor, storage) = trace.cursor();
+
+            while cursor.key_valid(&storage) {
+                while cursor.val_valid(&storage) {
+                    let tuple = cursor.key(&storage);
+                    cursor.map_times(&storage, |time, diff| {
+                        tuples.push((tuple.clone(), time.clone(), *diff));
+                    });
+                    cursor.step_val(&storage);
+                }
+                cursor.step_key(&storage);
+            }
+
+            tuples
+        })
+    }
+
+    /// Reads the updates to the relation of the given name that
+    /// happened in the interval `(from, to]` from its trace, without
+    /// re-computing anything. Updates are consolidated per tuple and
+    /// time. Once a trace has been compacted beyond `from`, earlier
+    /// updates can no longer be told apart from those in the
+    /// interval. Rather than returning a best-effort answer, this is
+    /// reported as an error.
+    fn changes_between(
+        &mut self,
+        name: &str,
+        from: T,
+        to: T,
+    ) -> Result<Vec<(Vec<Value>, T, isize)>, Error> {
+        let trace = match self.global_arrangement(name) {
+            None => return Err(Error::unknown_name(name)),
+            Some(trace) => trace,
+        };
+
+        if !trace
+            .advance_frontier()
+            .iter()
+            .any(|frontier| frontier.less_equal(&from))
+        {
+            return Err(Error::incorrect(format!(
+                "Relation {} has been compacted beyond {:?}",
+                name, from
+            )));
+        }
+
+        let mut changes = Vec::new();
+        let (mut cursor, storage) = trace.cursor();
+
+        while cursor.key_valid(&storage) {
+            while cursor.val_valid(&storage) {
+                let tuple = cursor.key(&storage);
+                cursor.map_times(&storage, |time, diff| {
+                    if !time.less_equal(&from) && time.less_equal(&to) {
+                        changes.push((tuple.clone(), time.clone(), *diff));
+                    }
+                });
+                cursor.step_val(&storage);
+            }
+            cursor.step_key(&storage);
+        }
+
+        changes.sort();
+
+        let mut consolidated: Vec<(Vec<Value>, T, isize)> = Vec::with_capacity(changes.len());
+        for (tuple, time, diff) in changes.drain(..) {
+            match consolidated.last_mut() {
+                Some(last) if last.0 == tuple && last.1 == time => last.2 += diff,
+                _ => consolidated.push((tuple, time, diff)),
+            }
+        }
+        consolidated.retain(|(_, _, diff)| *diff != 0);
+
+        Ok(consolidated)
+    }
+
+    /// Returns an estimate of the number of (e,v) pairs held by the
+    /// attribute of the given name, derived from the batch metadata
+    /// of its forward propose trace. As for `relation_size`, the
+    /// estimate is an upper bound until the trace has been compacted.
+    fn attribute_size(&mut self, name: &str) -> Option<usize> {
+        self.forward_propose(name).map(|trace| {
+            let mut size = 0;
+            trace.map_batches(|batch| size += batch.len());
+            size
+        })
+    }
 }
 
 /// Description of everything a plan needs prior to synthesis.
@@ -123,6 +356,9 @@ pub struct Dependencies {
     pub names: HashSet<String>,
     /// Attributes queries in Match* expressions.
     pub attributes: HashSet<Aid>,
+    /// Attributes that must (also) be indexed by value. A subset of
+    /// `attributes`.
+    pub reverse_attributes: HashSet<Aid>,
 }
 
 impl Dependencies {
@@ -131,6 +367,7 @@ impl Dependencies {
         Dependencies {
             names: HashSet::new(),
             attributes: HashSet::new(),
+            reverse_attributes: HashSet::new(),
         }
     }
 
@@ -142,6 +379,7 @@ impl Dependencies {
         Dependencies {
             names,
             attributes: HashSet::new(),
+            reverse_attributes: HashSet::new(),
         }
     }
 
@@ -153,15 +391,30 @@ impl Dependencies {
         Dependencies {
             names: HashSet::new(),
             attributes,
+            reverse_attributes: HashSet::new(),
         }
     }
 
+    /// A description representing a dependency on a single
+    /// attribute, which must be indexed by value.
+    pub fn reverse_attribute(aid: &str) -> Dependencies {
+        let mut dependencies = Dependencies::attribute(aid);
+        dependencies.reverse_attributes.insert(aid.to_string());
+
+        dependencies
+    }
+
     /// Merges two dependency descriptions into one, representing
     /// their union.
     pub fn merge(left: Dependencies, right: Dependencies) -> Dependencies {
         Dependencies {
             names: left.names.union(&right.names).cloned().collect(),
             attributes: left.attributes.union(&right.attributes).cloned().collect(),
+            reverse_attributes: left
+                .reverse_attributes
+                .union(&right.reverse_attributes)
+                .cloned()
+                .collect(),
         }
     }
 }
@@ -174,11 +427,35 @@ pub trait Implementable {
     fn dependencies(&self) -> Dependencies;
 
     /// Transforms an implementable into an equivalent set of bindings
-    /// that can be unified by Hector.
-    fn into_bindings(&self) -> Vec<Binding> {
+    /// that can be unified by Hector. Any variables introduced along
+    /// the way are drawn from `gensym`, usually backed by
+    /// `ImplContext::gensym`, s.t. all workers compiling the same
+    /// plan arrive at the same bindings.
+    fn into_bindings_with(&self, _gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
         panic!("This plan can't be implemented via Hector.");
     }
 
+    /// Transforms an implementable into an equivalent set of bindings,
+    /// drawing variables from a counter local to this call. This is
+    /// meant for inspecting bindings, the result must not be combined
+    /// with variables generated elsewhere.
+    fn into_bindings(&self) -> Vec<Binding> {
+        let mut next_sym = std::u32::MAX;
+        self.into_bindings_with(&mut || {
+            let sym = next_sym;
+            next_sym -= 1;
+            sym
+        })
+    }
+
+    /// Returns the variables this plan references without binding
+    /// them itself or via its inputs. A well-formed plan has no free
+    /// variables, so this can be used to detect unbound variables
+    /// before implementing anything.
+    fn free_variables(&self) -> Vec<Var> {
+        Vec::new()
+    }
+
     /// @TODO
     fn datafy(&self) -> Vec<(Eid, Aid, Value)> {
         Vec::new()
@@ -192,11 +469,81 @@ pub trait Implementable {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>;
 }
 
+/// Returns the attribute and value variable of plans implemented
+/// directly by an attribute, i.e. of (possibly renamed) patterns of
+/// the form [?e a ?v].
+fn matched_attribute(plan: &Plan) -> Option<(&Aid, Var)> {
+    match *plan {
+        Plan::MatchA(_, ref a, v) => Some((a, v)),
+        Plan::Rename(ref rename) => {
+            matched_attribute(&rename.plan).map(|(a, v)| (a, rename.rename(v)))
+        }
+        _ => None,
+    }
+}
+
+/// Returns all variables bound by the given plan. Unlike
+/// `Plan::variables`, this includes the non-key variables passed
+/// through by joins.
+fn bound_variables(plan: &Plan) -> Vec<Var> {
+    match *plan {
+        Plan::Join(ref join) => {
+            let mut variables = join.variables.clone();
+            for variable in bound_variables(&join.left_plan)
+                .into_iter()
+                .chain(bound_variables(&join.right_plan).into_iter())
+            {
+                if !variables.contains(&variable) {
+                    variables.push(variable);
+                }
+            }
+            variables
+        }
+        Plan::Antijoin(ref antijoin) => {
+            let mut variables = antijoin.variables.clone();
+            for variable in bound_variables(&antijoin.left_plan) {
+                if !variables.contains(&variable) {
+                    variables.push(variable);
+                }
+            }
+            variables
+        }
+        _ => plan.variables(),
+    }
+}
+
+/// Returns the free variables of the given inputs, followed by those
+/// of the referenced variables that aren't bound by every one of
+/// them.
+fn free_in(referenced: &[Var], inputs: &[&Plan]) -> Vec<Var> {
+    let mut free = Vec::new();
+
+    for input in inputs.iter() {
+        for variable in input.free_variables() {
+            if !free.contains(&variable) {
+                free.push(variable);
+            }
+        }
+    }
+
+    for variable in referenced.iter() {
+        let bound = inputs
+            .iter()
+            .all(|input| bound_variables(input).contains(variable));
+
+        if !bound && !free.contains(variable) {
+            free.push(*variable);
+        }
+    }
+
+    free
+}
+
 /// Possible query plan types.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub enum Plan {
@@ -204,26 +551,66 @@ pub enum Plan {
     Project(Project<Plan>),
     /// Aggregation
     Aggregate(Aggregate<Plan>),
+    /// Aggregation comparing values in a user-supplied order. Only
+    /// available in-process, plans containing it can't be serialized.
+    #[serde(skip)]
+    AggregateBy(AggregateBy<Plan>),
     /// Union
     Union(Union<Plan>),
+    /// Union, tagging each tuple with the index of its source
+    UnionTagged(UnionTagged<Plan>),
     /// Equijoin
     Join(Join<Plan, Plan>),
+    /// Joins two bindings on any of several conditions
+    JoinAny(JoinAny<Plan, Plan>),
     /// WCO
     Hector(Hector),
     /// Antijoin
     Antijoin(Antijoin<Plan, Plan>),
-    /// Negation
+    /// Negation. The source is thresholded before negating, s.t.
+    /// each tuple is retracted exactly once, regardless of its
+    /// multiplicity in the source.
     Negate(Box<Plan>),
+    /// Consolidation. Updates to the same tuple at the same time are
+    /// merged into a single net diff, s.t. consumers only see actual
+    /// changes in multiplicity.
+    Consolidate(Box<Plan>),
     /// Filters bindings by one of the built-in predicates
     Filter(Filter<Plan>),
+    /// Filters bindings by a user closure. Only available
+    /// in-process, plans containing it can't be serialized.
+    #[serde(skip)]
+    FilterFn(FilterFn<Plan>),
+    /// Filters bindings by one of the built-in predicates, comparing
+    /// operands in a user-supplied order. Only available in-process,
+    /// plans containing it can't be serialized.
+    #[serde(skip)]
+    FilterBy(FilterBy<Plan>),
     /// Transforms a binding by a function expression
     Transform(Transform<Plan>),
-    /// Data pattern of the form [?e a ?v]
+    /// Transforms a binding by a user closure. Only available
+    /// in-process, plans containing it can't be serialized.
+    #[serde(skip)]
+    TransformFn(TransformFn<Plan>),
+    /// Relabels the variables bound by a binding
+    Rename(Rename<Plan>),
+    /// Restricts bindings to entities valid at a given instant
+    AsOfValid(AsOfValid<Plan>),
+    /// Restricts bindings to entities lacking an attribute
+    Missing(Missing<Plan>),
+    /// Retains a deterministic, pseudo-random sample of bindings
+    Sample(Sample<Plan>),
+    /// Data pattern of the form [?e a ?v]. Patterns may also refer
+    /// to a binary rule or relation instead of an attribute. Should
+    /// an attribute of the same name exist, it takes precedence.
     MatchA(Var, Aid, Var),
     /// Data pattern of the form [e a ?v]
     MatchEA(Eid, Aid, Var),
     /// Data pattern of the form [?e a v]
     MatchAV(Var, Aid, Value),
+    /// Number of values per entity (or entities per value) of an
+    /// attribute, read from its count index
+    CountIndex(CountIndex),
     /// Sources data from another relation.
     NameExpr(Vec<Var>, String),
     /// Pull expression
@@ -232,35 +619,133 @@ pub enum Plan {
     PullLevel(PullLevel<Plan>),
     /// Single-level pull expression
     PullAll(PullAll),
+    /// Single-level pull expression for a known set of entities
+    PullEntities(PullEntities),
+    /// Values at the end of a path of reference attributes
+    PullPath(PullPath),
     /// GraphQl pull expression
     #[cfg(feature = "graphql")]
     GraphQl(GraphQl),
 }
 
 impl Plan {
+    /// Checks that all [e a ?v] and [?e a v] patterns within this
+    /// plan can be implemented, i.e. that each attribute they match
+    /// has a propose index in either direction. Patterns against
+    /// rules or relations of the same name are not affected. As
+    /// implementing such a pattern would fail irrecoverably, this
+    /// must be checked before implementing.
+    pub fn check_patterns<T, I>(&self, context: &mut I) -> Result<(), Error>
+    where
+        T: Timestamp + Lattice,
+        I: ImplContext<T>,
+    {
+        match *self {
+            Plan::MatchEA(_, ref a, _) | Plan::MatchAV(_, ref a, _) => {
+                if !context.has_attribute(a) {
+                    if context.rule(a).is_none() && !context.has_relation(a) {
+                        return Err(Error::attribute_not_found(a));
+                    }
+                } else if context.forward_propose(a).is_none()
+                    && context.reverse_propose(a).is_none()
+                {
+                    return Err(Error::index_not_built(a));
+                }
+
+                Ok(())
+            }
+            _ => {
+                for input in self.inputs() {
+                    input.check_patterns(context)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
     /// Returns the variables bound by this plan.
     pub fn variables(&self) -> Vec<Var> {
         match *self {
             Plan::Project(ref projection) => projection.variables.clone(),
-            Plan::Aggregate(ref aggregate) => aggregate.variables.clone(),
+            Plan::Aggregate(ref aggregate)
+            | Plan::AggregateBy(AggregateBy { ref aggregate, .. }) => aggregate.variables.clone(),
             Plan::Union(ref union) => union.variables.clone(),
+            Plan::UnionTagged(ref union) => std::iter::once(union.tag)
+                .chain(union.variables.iter().cloned())
+                .collect(),
             Plan::Join(ref join) => join.variables.clone(),
+            Plan::JoinAny(ref join) => {
+                join.variables(join.left_plan.variables(), join.right_plan.variables())
+            }
             Plan::Hector(ref hector) => hector.variables.clone(),
             Plan::Antijoin(ref antijoin) => antijoin.variables.clone(),
             Plan::Negate(ref plan) => plan.variables(),
-            Plan::Filter(ref filter) => filter.variables.clone(),
+            Plan::Consolidate(ref plan) => plan.variables(),
+            Plan::Filter(ref filter) | Plan::FilterBy(FilterBy { ref filter, .. }) => {
+                filter.variables.clone()
+            }
+            Plan::FilterFn(ref filter) => filter.plan.variables(),
             Plan::Transform(ref transform) => transform.variables.clone(),
+            Plan::TransformFn(ref transform) => {
+                let mut variables = transform.plan.variables();
+                variables.push(transform.result_variable);
+                variables
+            }
+            Plan::Rename(ref rename) => rename
+                .plan
+                .variables()
+                .into_iter()
+                .map(|x| rename.rename(x))
+                .collect(),
+            Plan::AsOfValid(ref as_of) => as_of.variables(as_of.plan.variables()),
+            Plan::Missing(ref missing) => missing.variables(missing.plan.variables()),
+            Plan::Sample(ref sample) => sample.plan.variables(),
             Plan::MatchA(e, _, v) => vec![e, v],
             Plan::MatchEA(_, _, v) => vec![v],
             Plan::MatchAV(e, _, _) => vec![e],
+            Plan::CountIndex(ref count) => vec![count.key, count.count],
             Plan::NameExpr(ref variables, ref _name) => variables.clone(),
             Plan::Pull(ref pull) => pull.variables.clone(),
             Plan::PullLevel(ref path) => path.variables.clone(),
             Plan::PullAll(ref path) => path.variables.clone(),
+            Plan::PullEntities(ref path) => path.variables.clone(),
+            Plan::PullPath(ref path) => vec![path.entity, path.value],
             #[cfg(feature = "graphql")]
             Plan::GraphQl(_) => unimplemented!(),
         }
     }
+
+    /// Returns the plans this plan reads from directly.
+    pub fn inputs(&self) -> Vec<&Plan> {
+        match *self {
+            Plan::Project(ref projection) => vec![projection.plan.as_ref()],
+            Plan::Aggregate(ref aggregate)
+            | Plan::AggregateBy(AggregateBy { ref aggregate, .. }) => vec![aggregate.plan.as_ref()],
+            Plan::Union(ref union) => union.plans.iter().collect(),
+            Plan::UnionTagged(ref union) => union.plans.iter().collect(),
+            Plan::Join(ref join) => vec![join.left_plan.as_ref(), join.right_plan.as_ref()],
+            Plan::JoinAny(ref join) => vec![join.left_plan.as_ref(), join.right_plan.as_ref()],
+            Plan::Antijoin(ref antijoin) => {
+                vec![antijoin.left_plan.as_ref(), antijoin.right_plan.as_ref()]
+            }
+            Plan::Negate(ref plan) => vec![plan.as_ref()],
+            Plan::Consolidate(ref plan) => vec![plan.as_ref()],
+            Plan::Filter(ref filter) | Plan::FilterBy(FilterBy { ref filter, .. }) => {
+                vec![filter.plan.as_ref()]
+            }
+            Plan::FilterFn(ref filter) => vec![filter.plan.as_ref()],
+            Plan::Transform(ref transform) => vec![transform.plan.as_ref()],
+            Plan::TransformFn(ref transform) => vec![transform.plan.as_ref()],
+            Plan::Pull(ref pull) => pull.paths.iter().collect(),
+            Plan::Rename(ref rename) => vec![rename.plan.as_ref()],
+            Plan::AsOfValid(ref as_of) => vec![as_of.plan.as_ref()],
+            Plan::Missing(ref missing) => vec![missing.plan.as_ref()],
+            Plan::Sample(ref sample) => vec![sample.plan.as_ref()],
+            Plan::PullLevel(ref path) => vec![path.plan.as_ref()],
+            _ => Vec::new(),
+        }
+    }
 }
 
 impl Implementable for Plan {
@@ -269,37 +754,93 @@ impl Implementable for Plan {
         match *self {
             Plan::Project(ref projection) => projection.dependencies(),
             Plan::Aggregate(ref aggregate) => aggregate.dependencies(),
+            Plan::AggregateBy(ref aggregate) => aggregate.dependencies(),
             Plan::Union(ref union) => union.dependencies(),
-            Plan::Join(ref join) => join.dependencies(),
+            Plan::UnionTagged(ref union) => union.dependencies(),
+            Plan::Join(ref join) => {
+                let mut dependencies = join.dependencies();
+
+                // Attribute<->Attribute joins on the value variable
+                // are served from the reverse index.
+                if let (Some((left, left_v)), Some((right, right_v))) = (
+                    matched_attribute(&join.left_plan),
+                    matched_attribute(&join.right_plan),
+                ) {
+                    if join.variables == [left_v] {
+                        dependencies.reverse_attributes.insert(left.to_string());
+                    }
+
+                    if join.variables == [right_v] {
+                        dependencies.reverse_attributes.insert(right.to_string());
+                    }
+                }
+
+                dependencies
+            }
+            Plan::JoinAny(ref join) => join.dependencies(),
             Plan::Hector(ref hector) => hector.dependencies(),
-            Plan::Antijoin(ref antijoin) => antijoin.dependencies(),
+            Plan::Antijoin(ref antijoin) => {
+                let mut dependencies = antijoin.dependencies();
+
+                // Antijoins against an attribute on its value
+                // variable are served from the reverse index.
+                if let Some((right, right_v)) = matched_attribute(&antijoin.right_plan) {
+                    if antijoin.variables == [right_v] {
+                        dependencies.reverse_attributes.insert(right.to_string());
+                    }
+                }
+
+                dependencies
+            }
             Plan::Negate(ref plan) => plan.dependencies(),
+            Plan::Consolidate(ref plan) => plan.dependencies(),
             Plan::Filter(ref filter) => filter.dependencies(),
+            Plan::FilterFn(ref filter) => filter.dependencies(),
+            Plan::FilterBy(ref filter) => filter.dependencies(),
             Plan::Transform(ref transform) => transform.dependencies(),
+            Plan::TransformFn(ref transform) => transform.dependencies(),
+            Plan::Rename(ref rename) => rename.dependencies(),
+            Plan::AsOfValid(ref as_of) => as_of.dependencies(),
+            Plan::Missing(ref missing) => missing.dependencies(),
+            Plan::Sample(ref sample) => sample.dependencies(),
             Plan::MatchA(_, ref a, _) => Dependencies::attribute(a),
             Plan::MatchEA(_, ref a, _) => Dependencies::attribute(a),
             Plan::MatchAV(_, ref a, _) => Dependencies::attribute(a),
+            Plan::CountIndex(ref count) => count.dependencies(),
             Plan::NameExpr(_, ref name) => Dependencies::name(name),
             Plan::Pull(ref pull) => pull.dependencies(),
             Plan::PullLevel(ref path) => path.dependencies(),
             Plan::PullAll(ref path) => path.dependencies(),
+            Plan::PullEntities(ref path) => path.dependencies(),
+            Plan::PullPath(ref path) => path.dependencies(),
             #[cfg(feature = "graphql")]
             Plan::GraphQl(ref q) => q.dependencies(),
         }
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
         // @TODO provide a general fold for plans
         match *self {
-            Plan::Project(ref projection) => projection.into_bindings(),
-            Plan::Aggregate(ref aggregate) => aggregate.into_bindings(),
-            Plan::Union(ref union) => union.into_bindings(),
-            Plan::Join(ref join) => join.into_bindings(),
-            Plan::Hector(ref hector) => hector.into_bindings(),
-            Plan::Antijoin(ref antijoin) => antijoin.into_bindings(),
-            Plan::Negate(ref plan) => plan.into_bindings(),
-            Plan::Filter(ref filter) => filter.into_bindings(),
-            Plan::Transform(ref transform) => transform.into_bindings(),
+            Plan::Project(ref projection) => projection.into_bindings_with(gensym),
+            Plan::Aggregate(ref aggregate) => aggregate.into_bindings_with(gensym),
+            Plan::AggregateBy(ref aggregate) => aggregate.into_bindings_with(gensym),
+            Plan::Union(ref union) => union.into_bindings_with(gensym),
+            Plan::UnionTagged(ref union) => union.into_bindings_with(gensym),
+            Plan::Join(ref join) => join.into_bindings_with(gensym),
+            Plan::JoinAny(ref join) => join.into_bindings_with(gensym),
+            Plan::Hector(ref hector) => hector.into_bindings_with(gensym),
+            Plan::Antijoin(ref antijoin) => antijoin.into_bindings_with(gensym),
+            Plan::Negate(ref plan) => plan.into_bindings_with(gensym),
+            Plan::Consolidate(ref plan) => plan.into_bindings_with(gensym),
+            Plan::Filter(ref filter) => filter.into_bindings_with(gensym),
+            Plan::FilterFn(ref filter) => filter.into_bindings_with(gensym),
+            Plan::FilterBy(ref filter) => filter.into_bindings_with(gensym),
+            Plan::Transform(ref transform) => transform.into_bindings_with(gensym),
+            Plan::TransformFn(ref transform) => transform.into_bindings_with(gensym),
+            Plan::Rename(ref rename) => rename.into_bindings_with(gensym),
+            Plan::AsOfValid(ref as_of) => as_of.into_bindings_with(gensym),
+            Plan::Missing(ref missing) => missing.into_bindings_with(gensym),
+            Plan::Sample(ref sample) => sample.into_bindings_with(gensym),
             Plan::MatchA(e, ref a, v) => vec![Binding::attribute(e, a, v)],
             Plan::MatchEA(match_e, ref a, v) => {
                 let e = gensym();
@@ -315,12 +856,129 @@ impl Implementable for Plan {
                     Binding::constant(v, match_v.clone()),
                 ]
             }
+            Plan::CountIndex(ref count) => count.into_bindings_with(gensym),
             Plan::NameExpr(_, ref _name) => unimplemented!(), // @TODO hmm...
-            Plan::Pull(ref pull) => pull.into_bindings(),
-            Plan::PullLevel(ref path) => path.into_bindings(),
-            Plan::PullAll(ref path) => path.into_bindings(),
+            Plan::Pull(ref pull) => pull.into_bindings_with(gensym),
+            Plan::PullLevel(ref path) => path.into_bindings_with(gensym),
+            Plan::PullAll(ref path) => path.into_bindings_with(gensym),
+            Plan::PullEntities(ref path) => path.into_bindings_with(gensym),
+            Plan::PullPath(ref path) => path.into_bindings_with(gensym),
+            #[cfg(feature = "graphql")]
+            Plan::GraphQl(ref q) => q.into_bindings_with(gensym),
+        }
+    }
+
+    fn free_variables(&self) -> Vec<Var> {
+        // @TODO provide a general fold for plans
+        match *self {
+            Plan::Project(ref projection) => {
+                free_in(&projection.variables, &[projection.plan.as_ref()])
+            }
+            Plan::Aggregate(ref aggregate)
+            | Plan::AggregateBy(AggregateBy { ref aggregate, .. }) => {
+                let referenced: Vec<Var> = aggregate
+                    .key_variables
+                    .iter()
+                    .chain(
+                        aggregate
+                            .aggregation_variables
+                            .iter()
+                            .zip(aggregate.aggregation_fns.iter())
+                            .filter(|(_, aggregation_fn)| {
+                                **aggregation_fn != AggregationFn::CountRows
+                            })
+                            .map(|(variable, _)| variable),
+                    )
+                    .chain(aggregate.with_variables.iter())
+                    .cloned()
+                    .collect();
+
+                free_in(&referenced, &[aggregate.plan.as_ref()])
+            }
+            Plan::Union(ref union) => {
+                let plans: Vec<&Plan> = union.plans.iter().collect();
+                free_in(&union.variables, &plans)
+            }
+            Plan::UnionTagged(ref union) => {
+                let plans: Vec<&Plan> = union.plans.iter().collect();
+                free_in(&union.variables, &plans)
+            }
+            Plan::Join(ref join) => free_in(
+                &join.variables,
+                &[join.left_plan.as_ref(), join.right_plan.as_ref()],
+            ),
+            Plan::JoinAny(ref join) => {
+                let (left, right): (Vec<Var>, Vec<Var>) =
+                    join.conditions.iter().flatten().cloned().unzip();
+
+                let mut free = free_in(&left, &[join.left_plan.as_ref()]);
+                for variable in free_in(&right, &[join.right_plan.as_ref()]) {
+                    if !free.contains(&variable) {
+                        free.push(variable);
+                    }
+                }
+                free
+            }
+            Plan::Hector(ref hector) => {
+                let bound: Vec<Var> = hector
+                    .bindings
+                    .iter()
+                    .flat_map(|binding| match *binding {
+                        Binding::Attribute(ref binding) => binding.variables(),
+                        _ => Vec::new(),
+                    })
+                    .collect();
+
+                let mut free = Vec::new();
+                let referenced = hector
+                    .variables
+                    .iter()
+                    .cloned()
+                    .chain(hector.bindings.iter().flat_map(AsBinding::variables));
+
+                for variable in referenced {
+                    if !bound.contains(&variable) && !free.contains(&variable) {
+                        free.push(variable);
+                    }
+                }
+
+                free
+            }
+            Plan::Antijoin(ref antijoin) => free_in(
+                &antijoin.variables,
+                &[antijoin.left_plan.as_ref(), antijoin.right_plan.as_ref()],
+            ),
+            Plan::Negate(ref plan) => plan.free_variables(),
+            Plan::Consolidate(ref plan) => plan.free_variables(),
+            Plan::Filter(ref filter) | Plan::FilterBy(FilterBy { ref filter, .. }) => {
+                free_in(&filter.variables, &[filter.plan.as_ref()])
+            }
+            Plan::FilterFn(ref filter) => free_in(&filter.variables, &[filter.plan.as_ref()]),
+            Plan::Transform(ref transform) => {
+                free_in(&transform.variables, &[transform.plan.as_ref()])
+            }
+            Plan::TransformFn(ref transform) => {
+                free_in(&transform.variables, &[transform.plan.as_ref()])
+            }
+            Plan::Rename(ref rename) => free_in(&rename.sources(), &[rename.plan.as_ref()]),
+            Plan::AsOfValid(ref as_of) => free_in(&[as_of.variable], &[as_of.plan.as_ref()]),
+            Plan::Missing(ref missing) => free_in(&[missing.variable], &[missing.plan.as_ref()]),
+            Plan::Sample(ref sample) => sample.plan.free_variables(),
+            Plan::MatchA(_, _, _) => Vec::new(),
+            Plan::MatchEA(_, _, _) => Vec::new(),
+            Plan::MatchAV(_, _, _) => Vec::new(),
+            Plan::CountIndex(_) => Vec::new(),
+            Plan::NameExpr(_, _) => Vec::new(),
+            Plan::Pull(ref pull) => {
+                let plans: Vec<&Plan> = pull.paths.iter().collect();
+                free_in(&[], &plans)
+            }
+            Plan::PullLevel(ref path) => free_in(&[path.pull_variable], &[path.plan.as_ref()]),
+            Plan::PullAll(_) => Vec::new(),
+            Plan::PullEntities(_) => Vec::new(),
+            Plan::PullPath(_) => Vec::new(),
             #[cfg(feature = "graphql")]
-            Plan::GraphQl(ref q) => q.into_bindings(),
+            Plan::GraphQl(_) => Vec::new(),
         }
     }
 
@@ -329,13 +987,24 @@ impl Implementable for Plan {
         match *self {
             Plan::Project(ref projection) => projection.datafy(),
             Plan::Aggregate(ref aggregate) => aggregate.datafy(),
+            Plan::AggregateBy(ref aggregate) => aggregate.datafy(),
             Plan::Union(ref union) => union.datafy(),
+            Plan::UnionTagged(ref union) => union.datafy(),
             Plan::Join(ref join) => join.datafy(),
+            Plan::JoinAny(ref join) => join.datafy(),
             Plan::Hector(ref hector) => hector.datafy(),
             Plan::Antijoin(ref antijoin) => antijoin.datafy(),
             Plan::Negate(ref plan) => plan.datafy(),
+            Plan::Consolidate(ref plan) => plan.datafy(),
             Plan::Filter(ref filter) => filter.datafy(),
+            Plan::FilterFn(ref filter) => filter.datafy(),
+            Plan::FilterBy(ref filter) => filter.datafy(),
             Plan::Transform(ref transform) => transform.datafy(),
+            Plan::TransformFn(ref transform) => transform.datafy(),
+            Plan::Rename(ref rename) => rename.datafy(),
+            Plan::AsOfValid(ref as_of) => as_of.datafy(),
+            Plan::Missing(ref missing) => missing.datafy(),
+            Plan::Sample(ref sample) => sample.datafy(),
             Plan::MatchA(_e, ref a, _v) => vec![(
                 next_id(),
                 "df.pattern/a".to_string(),
@@ -357,10 +1026,13 @@ impl Implementable for Plan {
                 ),
                 (next_id(), "df.pattern/v".to_string(), v.clone()),
             ],
+            Plan::CountIndex(ref count) => count.datafy(),
             Plan::NameExpr(_, ref _name) => Vec::new(),
             Plan::Pull(ref pull) => pull.datafy(),
             Plan::PullLevel(ref path) => path.datafy(),
             Plan::PullAll(ref path) => path.datafy(),
+            Plan::PullEntities(ref path) => path.datafy(),
+            Plan::PullPath(ref path) => path.datafy(),
             #[cfg(feature = "graphql")]
             Plan::GraphQl(ref q) => q.datafy(),
         }
@@ -373,19 +1045,62 @@ impl Implementable for Plan {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
-        match *self {
+        let consumer = consume(self, context);
+
+        let implemented = match *self {
             Plan::Project(ref projection) => {
                 projection.implement(nested, local_arrangements, context)
             }
             Plan::Aggregate(ref aggregate) => {
                 aggregate.implement(nested, local_arrangements, context)
             }
-            Plan::Union(ref union) => union.implement(nested, local_arrangements, context),
+            Plan::AggregateBy(ref aggregate) => {
+                aggregate.implement(nested, local_arrangements, context)
+            }
+            Plan::Union(ref union) => {
+                // Disjunctions often match the same attribute against
+                // several values. Rather than importing the attribute
+                // once per branch, we implement all such branches
+                // off of a single import.
+                // Branches are identified by the index of the first
+                // plan they implement.
+                let mut shared: Vec<(usize, Var, &Aid, Vec<Value>)> = Vec::new();
+                let mut others = Vec::new();
+
+                for (index, plan) in union.plans.iter().enumerate() {
+                    match *plan {
+                        Plan::MatchAV(sym1, ref a, ref match_v) if context.has_attribute(a) => {
+                            match shared.iter_mut().find(|(_, x, y, _)| *x == sym1 && *y == a) {
+                                None => shared.push((index, sym1, a, vec![match_v.clone()])),
+                                Some((_, _, _, values)) => values.push(match_v.clone()),
+                            }
+                        }
+                        _ => others.push((index, plan)),
+                    }
+                }
+
+                let mut scope = nested.clone();
+                let mut relations = Vec::with_capacity(shared.len() + others.len());
+
+                for (index, sym1, a, values) in shared.drain(..) {
+                    let relation = implement_match_av(&mut scope, context, sym1, a, values);
+                    relations.push((index, relation));
+                }
+
+                for (index, plan) in others.drain(..) {
+                    let relation = plan.implement(&mut scope, local_arrangements, context);
+                    relations.push((index, relation));
+                }
+
+                union::concatenate(nested, context, &union.variables, relations)
+            }
+            Plan::UnionTagged(ref union) => union.implement(nested, local_arrangements, context),
             Plan::Join(ref join) => join.implement(nested, local_arrangements, context),
+            Plan::JoinAny(ref join) => join.implement(nested, local_arrangements, context),
             Plan::Hector(ref hector) => hector.implement(nested, local_arrangements, context),
             Plan::Antijoin(ref antijoin) => antijoin.implement(nested, local_arrangements, context),
             Plan::Negate(ref plan) => {
@@ -397,7 +1112,12 @@ impl Implementable for Plan {
                     let (projected, shutdown) = relation.projected(nested, context, &variables);
                     shutdown_handle.merge_with(shutdown);
 
-                    projected.negate()
+                    // Plans may produce duplicate tuples, which would
+                    // otherwise be negated at their full
+                    // multiplicity. Inside of iterative scopes this
+                    // also produces spurious intermediate diffs, so
+                    // we ensure multiplicities of at most one first.
+                    projected.distinct().negate()
                 };
 
                 (
@@ -405,10 +1125,60 @@ impl Implementable for Plan {
                     shutdown_handle,
                 )
             }
-            Plan::Filter(ref filter) => filter.implement(nested, local_arrangements, context),
+            Plan::Consolidate(ref plan) => {
+                let (relation, mut shutdown_handle) =
+                    plan.implement(nested, local_arrangements, context);
+                let variables = relation.variables();
+
+                let tuples = {
+                    let (projected, shutdown) = relation.projected(nested, context, &variables);
+                    shutdown_handle.merge_with(shutdown);
+
+                    projected.consolidate()
+                };
+
+                (
+                    Implemented::Collection(CollectionRelation { variables, tuples }),
+                    shutdown_handle,
+                )
+            }
+            Plan::Filter(ref filter) => {
+                // Comparisons of an attribute's value against
+                // constants only need to visit the matching range of
+                // its reverse index.
+                let scan = range::as_range_scan(filter).filter(|(_e, a, _v, _range)| {
+                    context.has_attribute(a) && context.reverse_propose(a).is_some()
+                });
+
+                match scan {
+                    Some((e, a, v, range)) => {
+                        range::implement_range_scan(nested, context, e, &a, v, range)
+                    }
+                    None => filter.implement(nested, local_arrangements, context),
+                }
+            }
+            Plan::FilterFn(ref filter) => filter.implement(nested, local_arrangements, context),
+            Plan::FilterBy(ref filter) => filter.implement(nested, local_arrangements, context),
             Plan::Transform(ref transform) => {
                 transform.implement(nested, local_arrangements, context)
             }
+            Plan::TransformFn(ref transform) => {
+                transform.implement(nested, local_arrangements, context)
+            }
+            Plan::Rename(ref rename) => rename.implement(nested, local_arrangements, context),
+            Plan::AsOfValid(ref as_of) => as_of.implement(nested, local_arrangements, context),
+            Plan::Missing(ref missing) => missing.implement(nested, local_arrangements, context),
+            Plan::Sample(ref sample) => sample.implement(nested, local_arrangements, context),
+            Plan::MatchA(_, ref a, _) | Plan::MatchEA(_, ref a, _) | Plan::MatchAV(_, ref a, _)
+                if !context.is_attribute_allowed(a) =>
+            {
+                panic!("access to attribute {:?} is not allowed", a)
+            }
+            Plan::MatchA(_, ref a, _) | Plan::MatchEA(_, ref a, _) | Plan::MatchAV(_, ref a, _)
+                if !context.has_attribute(a) =>
+            {
+                match_name(self, context).implement(nested, local_arrangements, context)
+            }
             Plan::MatchA(e, ref a, v) => {
                 let binding = AttributeBinding {
                     variables: (e, v),
@@ -418,46 +1188,33 @@ impl Implementable for Plan {
                 (Implemented::Attribute(binding), ShutdownHandle::empty())
             }
             Plan::MatchEA(match_e, ref a, sym1) => {
-                let (tuples, shutdown_propose) = match context.forward_propose(a) {
-                    None => panic!("attribute {:?} does not exist", a),
-                    Some(propose_trace) => {
-                        let (propose, shutdown_propose) =
-                            propose_trace.import_frontier(&nested.parent, a);
-
-                        let tuples = propose
-                            .enter(nested)
-                            .filter(move |e, _v| *e == Value::Eid(match_e))
-                            .as_collection(|_e, v| vec![v.clone()]);
-
-                        (tuples, shutdown_propose)
-                    }
-                };
+                // We prefer the forward index, which is keyed by
+                // entity, but fall back to the reverse index.
+                let (tuples, shutdown_propose) = if let Some(propose_trace) =
+                    context.forward_propose(a)
+                {
+                    let (propose, shutdown_propose) =
+                        propose_trace.import_frontier(&nested.parent, a);
 
-                let relation = CollectionRelation {
-                    variables: vec![sym1],
-                    tuples,
-                };
+                    let tuples = propose
+                        .enter(nested)
+                        .filter(move |e, _v| *e == Value::Eid(match_e))
+                        .as_collection(|_e, v| vec![v.clone()]);
 
-                (
-                    Implemented::Collection(relation),
-                    ShutdownHandle::from_button(shutdown_propose),
-                )
-            }
-            Plan::MatchAV(sym1, ref a, ref match_v) => {
-                let (tuples, shutdown_propose) = match context.forward_propose(a) {
-                    None => panic!("attribute {:?} does not exist", a),
-                    Some(propose_trace) => {
-                        let match_v = match_v.clone();
-                        let (propose, shutdown_propose) =
-                            propose_trace.import_core(&nested.parent, a);
-
-                        let tuples = propose
-                            .enter(nested)
-                            .filter(move |_e, v| *v == match_v)
-                            .as_collection(|e, _v| vec![e.clone()]);
-
-                        (tuples, shutdown_propose)
-                    }
+                    (tuples, shutdown_propose)
+                } else if let Some(propose_trace) = context.reverse_propose(a) {
+                    let (propose, shutdown_propose) =
+                        propose_trace.import_frontier(&nested.parent, a);
+
+                    let tuples = propose
+                        .enter(nested)
+                        .filter(move |_v, e| *e == Value::Eid(match_e))
+                        .as_collection(|v, _e| vec![v.clone()]);
+
+                    (tuples, shutdown_propose)
+                } else {
+                    // Ruled out by `check_patterns`.
+                    panic!("{}", Error::index_not_built(a))
                 };
 
                 let relation = CollectionRelation {
@@ -470,44 +1227,61 @@ impl Implementable for Plan {
                     ShutdownHandle::from_button(shutdown_propose),
                 )
             }
+            Plan::MatchAV(sym1, ref a, ref match_v) => {
+                implement_match_av(nested, context, sym1, a, vec![match_v.clone()])
+            }
+            Plan::CountIndex(ref count) => count.implement(nested, local_arrangements, context),
             Plan::NameExpr(ref syms, ref name) => {
-                if context.is_underconstrained(name) {
-                    match local_arrangements.get(name) {
-                        None => panic!("{:?} not in relation map", name),
-                        Some(named) => {
-                            let relation = CollectionRelation {
-                                variables: syms.clone(),
-                                tuples: named.deref().clone(), // @TODO re-use variable directly?
-                            };
-
-                            (Implemented::Collection(relation), ShutdownHandle::empty())
-                        }
+                match local_arrangements.get(name) {
+                    Some(named) if context.is_underconstrained(name) => {
+                        let relation = CollectionRelation {
+                            variables: syms.clone(),
+                            tuples: named.deref().clone(), // @TODO re-use variable directly?
+                        };
+
+                        (Implemented::Collection(relation), ShutdownHandle::empty())
                     }
-                } else {
-                    // If a rule is not underconstrained, we can
-                    // safely re-use it. @TODO it's debatable whether
-                    // we should then immediately assume that it is
-                    // available as a global arrangement, but we'll do
-                    // so for now.
-
-                    match context.global_arrangement(name) {
-                        None => panic!("{:?} not in query map", name),
-                        Some(named) => {
-                            let (arranged, shutdown_button) =
-                                named.import_frontier(&nested.parent, name);
-
-                            let relation = CollectionRelation {
-                                variables: syms.clone(),
-                                tuples: arranged
-                                    .enter(nested)
-                                    // @TODO this destroys all the arrangement re-use
-                                    .as_collection(|tuple, _| tuple.clone()),
-                            };
-
-                            (
-                                Implemented::Collection(relation),
-                                ShutdownHandle::from_button(shutdown_button),
-                            )
+                    _ => {
+                        // If a rule is not underconstrained, we can
+                        // safely re-use its global arrangement. The
+                        // same holds for relations registered from
+                        // outside, which have no rule at all.
+                        //
+                        // No additional barrier is needed for
+                        // arrangements that are still catching up on
+                        // some workers. Imported traces only hand out
+                        // sealed batches and their frontier follows
+                        // the arrangement's input frontier, which is
+                        // tracked across all workers. Downstream
+                        // operators therefore wait for lagging
+                        // workers, rather than seeing partial times.
+                        // A name missing entirely on this worker
+                        // means that workers disagree about what has
+                        // been registered, which we can't recover
+                        // from.
+
+                        match context.global_arrangement(name) {
+                            None => panic!(
+                                "relation {:?} is not registered on this worker, relations must be registered on all workers before queries refer to them",
+                                name
+                            ),
+                            Some(named) => {
+                                let (arranged, shutdown_button) =
+                                    named.import_frontier(&nested.parent, name);
+
+                                let relation = CollectionRelation {
+                                    variables: syms.clone(),
+                                    tuples: arranged
+                                        .enter(nested)
+                                        // @TODO this destroys all the arrangement re-use
+                                        .as_collection(|tuple, _| tuple.clone()),
+                                };
+
+                                (
+                                    Implemented::Collection(relation),
+                                    ShutdownHandle::from_tagged_button(name, shutdown_button),
+                                )
+                            }
                         }
                     }
                 }
@@ -515,8 +1289,237 @@ impl Implementable for Plan {
             Plan::Pull(ref pull) => pull.implement(nested, local_arrangements, context),
             Plan::PullLevel(ref path) => path.implement(nested, local_arrangements, context),
             Plan::PullAll(ref path) => path.implement(nested, local_arrangements, context),
+            Plan::PullEntities(ref path) => path.implement(nested, local_arrangements, context),
+            Plan::PullPath(ref path) => path.implement(nested, local_arrangements, context),
             #[cfg(feature = "graphql")]
             Plan::GraphQl(ref query) => query.implement(nested, local_arrangements, context),
+        };
+
+        instrumented(self, consumer, nested, context, implemented)
+    }
+}
+
+/// Checks whether the throughput of a stage is reported, which is
+/// the case for stages doing actual work (joins, aggregates, and
+/// filters).
+#[cfg(feature = "metrics")]
+fn is_instrumented(plan: &Plan) -> bool {
+    match *plan {
+        Plan::Aggregate(_)
+        | Plan::AggregateBy(_)
+        | Plan::Join(_)
+        | Plan::JoinAny(_)
+        | Plan::Hector(_)
+        | Plan::Antijoin(_)
+        | Plan::Filter(_)
+        | Plan::FilterFn(_)
+        | Plan::FilterBy(_) => true,
+        _ => false,
+    }
+}
+
+/// Marks the stage as the consumer of the relations implemented for
+/// its inputs, if its throughput is reported. Returns the consumer
+/// of the stage itself.
+#[cfg(feature = "metrics")]
+fn consume<T, I>(plan: &Plan, context: &I) -> Option<String>
+where
+    T: Timestamp + Lattice,
+    I: ImplContext<T>,
+{
+    if context.metrics_sink().is_some() && is_instrumented(plan) {
+        crate::metrics::consume(Some(plan.label()))
+    } else {
+        crate::metrics::consume(None)
+    }
+}
+
+/// Reports the inputs and the throughput of stages doing actual work
+/// to the context's metrics sink, if any, and restores the consumer
+/// of the stage.
+#[cfg(feature = "metrics")]
+fn instrumented<'b, T, I, S>(
+    plan: &Plan,
+    consumer: Option<String>,
+    nested: &mut Iterative<'b, S, u64>,
+    context: &mut I,
+    implemented: (Implemented<'b, S>, ShutdownHandle),
+) -> (Implemented<'b, S>, ShutdownHandle)
+where
+    T: Timestamp + Lattice + TimestampValue,
+    I: ImplContext<T>,
+    S: Scope<Timestamp = T>,
+{
+    crate::metrics::consume(consumer.clone());
+
+    let sink = match context.metrics_sink() {
+        None => return implemented,
+        Some(sink) => sink,
+    };
+
+    let (implemented, mut shutdown_handle) = implemented;
+
+    if let Some(consumer) = consumer {
+        shutdown_handle.merge_with(crate::metrics::count_input(
+            consumer,
+            sink.clone(),
+            &implemented,
+            nested,
+            context,
+        ));
+    }
+
+    if !is_instrumented(plan) {
+        return (implemented, shutdown_handle);
+    }
+
+    // Worst-case optimal joins read attributes directly, rather than
+    // implementing any inputs.
+    if let Plan::Hector(ref hector) = *plan {
+        for binding in hector.bindings.iter() {
+            if let Binding::Attribute(ref binding) = *binding {
+                shutdown_handle.merge_with(crate::metrics::count_attribute(
+                    plan.label(),
+                    sink.clone(),
+                    binding,
+                    nested,
+                    context,
+                ));
+            }
         }
     }
+
+    crate::metrics::instrument(plan.label(), sink, (implemented, shutdown_handle))
+}
+
+/// Without metrics, no stage consumes anything.
+#[cfg(not(feature = "metrics"))]
+fn consume<T, I>(_plan: &Plan, _context: &I) -> Option<String>
+where
+    T: Timestamp + Lattice,
+    I: ImplContext<T>,
+{
+    None
+}
+
+/// Without metrics, stages are left as they are.
+#[cfg(not(feature = "metrics"))]
+fn instrumented<'b, T, I, S>(
+    _plan: &Plan,
+    _consumer: Option<String>,
+    _nested: &mut Iterative<'b, S, u64>,
+    _context: &mut I,
+    implemented: (Implemented<'b, S>, ShutdownHandle),
+) -> (Implemented<'b, S>, ShutdownHandle)
+where
+    T: Timestamp + Lattice + TimestampValue,
+    I: ImplContext<T>,
+    S: Scope<Timestamp = T>,
+{
+    implemented
+}
+
+/// Rewrites a pattern against a named relation, rather than an
+/// attribute, into the equivalent plan on top of a `NameExpr`. Named
+/// relations are keyed by their first variable, which thus plays the
+/// role of the entity.
+fn match_name<T, I>(plan: &Plan, context: &mut I) -> Plan
+where
+    T: Timestamp + Lattice,
+    I: ImplContext<T>,
+{
+    match *plan {
+        Plan::MatchA(e, ref a, v) => Plan::NameExpr(vec![e, v], a.to_string()),
+        Plan::MatchEA(match_e, ref a, v) => {
+            let e = context.gensym();
+
+            Plan::Project(Project {
+                variables: vec![v],
+                plan: Box::new(Plan::Filter(Filter {
+                    variables: vec![e],
+                    predicate: Predicate::EQ,
+                    plan: Box::new(Plan::NameExpr(vec![e, v], a.to_string())),
+                    constants: vec![None, Some(Value::Eid(match_e))],
+                })),
+            })
+        }
+        Plan::MatchAV(e, ref a, ref match_v) => {
+            let v = context.gensym();
+
+            Plan::Project(Project {
+                variables: vec![e],
+                plan: Box::new(Plan::Filter(Filter {
+                    variables: vec![v],
+                    predicate: Predicate::EQ,
+                    plan: Box::new(Plan::NameExpr(vec![e, v], a.to_string())),
+                    constants: vec![None, Some(match_v.clone())],
+                })),
+            })
+        }
+        _ => panic!("{:?} is not a data pattern", plan),
+    }
+}
+
+/// Implements patterns of the form [?e a v] for each of the given
+/// values, off of a single import of the attribute.
+fn implement_match_av<'b, T, I, S>(
+    nested: &mut Iterative<'b, S, u64>,
+    context: &mut I,
+    sym1: Var,
+    a: &str,
+    mut values: Vec<Value>,
+) -> (Implemented<'b, S>, ShutdownHandle)
+where
+    T: Timestamp + Lattice + TimestampValue,
+    I: ImplContext<T>,
+    S: Scope<Timestamp = T>,
+{
+    // Values may repeat across patterns, each of which contributes
+    // its own matches, just as if it were implemented separately.
+    values.sort();
+    let mut counts: Vec<(Value, usize)> = Vec::new();
+    for value in values.drain(..) {
+        match counts.last_mut() {
+            Some((last, count)) if *last == value => *count += 1,
+            _ => counts.push((value, 1)),
+        }
+    }
+
+    let matches = move |v: &Value| match counts.binary_search_by(|(x, _)| x.cmp(v)) {
+        Ok(index) => counts[index].1,
+        Err(_) => 0,
+    };
+
+    // We prefer the reverse index, which is keyed by value, but fall
+    // back to the forward index.
+    let (tuples, shutdown_propose) = if let Some(propose_trace) = context.reverse_propose(a) {
+        let (propose, shutdown_propose) = propose_trace.import_core(&nested.parent, a);
+
+        let tuples = propose
+            .enter(nested)
+            .flat_map_ref(move |v, e| std::iter::repeat(vec![e.clone()]).take(matches(v)));
+
+        (tuples, shutdown_propose)
+    } else if let Some(propose_trace) = context.forward_propose(a) {
+        let (propose, shutdown_propose) = propose_trace.import_core(&nested.parent, a);
+
+        let tuples = propose
+            .enter(nested)
+            .flat_map_ref(move |e, v| std::iter::repeat(vec![e.clone()]).take(matches(v)));
+
+        (tuples, shutdown_propose)
+    } else {
+        // Ruled out by `check_patterns`.
+        panic!("{}", Error::index_not_built(a))
+    };
+
+    let relation = CollectionRelation {
+        variables: vec![sym1],
+        tuples,
+    };
+
+    (
+        Implemented::Collection(relation),
+        ShutdownHandle::from_button(shutdown_propose),
+    )
 }
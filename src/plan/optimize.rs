@@ -0,0 +1,213 @@
+//! Plan rewrites choosing between join strategies.
+
+use std::collections::HashMap;
+
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+
+use crate::binding::Binding;
+use crate::plan::{Hector, ImplContext, Implementable, Plan};
+use crate::{Aid, Var};
+
+/// Returns true iff the plan is a tree of binary joins over data
+/// patterns, i.e. something that can be expressed as a single set of
+/// Hector bindings without changing its meaning.
+fn is_join_tree(plan: &Plan) -> bool {
+    match *plan {
+        Plan::Join(ref join) => is_join_tree(&join.left_plan) && is_join_tree(&join.right_plan),
+        Plan::MatchA(_, _, _) | Plan::MatchEA(_, _, _) | Plan::MatchAV(_, _, _) => true,
+        _ => false,
+    }
+}
+
+/// Returns the variables produced by a join tree, in the order in
+/// which the binary join implementation would produce them, s.t. a
+/// rewrite doesn't change the shape of the output tuples.
+pub(crate) fn output_variables(plan: &Plan) -> Vec<Var> {
+    match *plan {
+        Plan::Join(ref join) => {
+            let targets = &join.variables;
+            let left = output_variables(&join.left_plan);
+            let right = output_variables(&join.right_plan);
+
+            targets
+                .iter()
+                .cloned()
+                .chain(left.into_iter().filter(|x| !targets.contains(x)))
+                .chain(right.into_iter().filter(|x| !targets.contains(x)))
+                .collect()
+        }
+        _ => plan.variables(),
+    }
+}
+
+/// Returns true iff the attribute bindings, viewed as edges between
+/// variables, contain a cycle.
+fn is_cyclic(bindings: &[Binding]) -> bool {
+    let mut parents: HashMap<Var, Var> = HashMap::new();
+
+    fn root(parents: &mut HashMap<Var, Var>, x: Var) -> Var {
+        let parent = *parents.entry(x).or_insert(x);
+        if parent == x {
+            x
+        } else {
+            let root = root(parents, parent);
+            parents.insert(x, root);
+            root
+        }
+    }
+
+    for binding in bindings.iter() {
+        if let Binding::Attribute(ref binding) = *binding {
+            let (e, v) = binding.variables;
+            let (root_e, root_v) = (root(&mut parents, e), root(&mut parents, v));
+
+            if root_e == root_v {
+                return true;
+            }
+
+            parents.insert(root_e, root_v);
+        }
+    }
+
+    false
+}
+
+/// Returns true iff all indices required by Hector are maintained
+/// for the specified attribute.
+fn supports_wco<T, I>(context: &mut I, aid: &Aid) -> bool
+where
+    T: Timestamp + Lattice,
+    I: ImplContext<T>,
+{
+    context.forward_propose(aid).is_some()
+        && context.forward_validate(aid).is_some()
+        && context.reverse_propose(aid).is_some()
+        && context.reverse_validate(aid).is_some()
+}
+
+/// Optimizes each of the given plans, e.g. the inputs of a union.
+fn optimize_all<T, I>(plans: Vec<Plan>, context: &mut I) -> Vec<Plan>
+where
+    T: Timestamp + Lattice,
+    I: ImplContext<T>,
+{
+    plans
+        .into_iter()
+        .map(|plan| plan.optimize(context))
+        .collect()
+}
+
+impl Plan {
+    /// Rewrites the plan, choosing a join strategy for each tree of
+    /// binary joins over data patterns. For now this is
+    /// conservative: only cyclic patterns (e.g. triangles) are turned
+    /// into a single Hector stage, and only if all attributes
+    /// involved maintain the indices required for worst-case optimal
+    /// joins. All other plans are left as they are, apart from
+    /// optimizing their inputs.
+    pub fn optimize<T, I>(self, context: &mut I) -> Plan
+    where
+        T: Timestamp + Lattice,
+        I: ImplContext<T>,
+    {
+        match self {
+            Plan::Join(mut join) => {
+                if is_join_tree(&join.left_plan) && is_join_tree(&join.right_plan) {
+                    let plan = Plan::Join(join);
+                    let bindings = plan.into_bindings_with(&mut || context.gensym());
+                    let supported = bindings.iter().all(|binding| match *binding {
+                        Binding::Attribute(ref binding) => {
+                            supports_wco(context, &binding.source_attribute)
+                        }
+                        _ => true,
+                    });
+
+                    if supported && is_cyclic(&bindings) {
+                        Plan::Hector(Hector {
+                            variables: output_variables(&plan),
+                            bindings,
+                            order: None,
+                        })
+                    } else {
+                        plan
+                    }
+                } else {
+                    join.left_plan = Box::new(join.left_plan.optimize(context));
+                    join.right_plan = Box::new(join.right_plan.optimize(context));
+                    Plan::Join(join)
+                }
+            }
+            Plan::Project(mut projection) => {
+                projection.plan = Box::new(projection.plan.optimize(context));
+                Plan::Project(projection)
+            }
+            Plan::Aggregate(mut aggregate) => {
+                aggregate.plan = Box::new(aggregate.plan.optimize(context));
+                Plan::Aggregate(aggregate)
+            }
+            Plan::AggregateBy(mut aggregate) => {
+                aggregate.aggregate.plan = Box::new(aggregate.aggregate.plan.optimize(context));
+                Plan::AggregateBy(aggregate)
+            }
+            Plan::Union(mut union) => {
+                union.plans = optimize_all(union.plans, context);
+                Plan::Union(union)
+            }
+            Plan::UnionTagged(mut union) => {
+                union.plans = optimize_all(union.plans, context);
+                Plan::UnionTagged(union)
+            }
+            Plan::Antijoin(mut antijoin) => {
+                antijoin.left_plan = Box::new(antijoin.left_plan.optimize(context));
+                antijoin.right_plan = Box::new(antijoin.right_plan.optimize(context));
+                Plan::Antijoin(antijoin)
+            }
+            Plan::Negate(plan) => Plan::Negate(Box::new(plan.optimize(context))),
+            Plan::Consolidate(plan) => Plan::Consolidate(Box::new(plan.optimize(context))),
+            Plan::Filter(mut filter) => {
+                filter.plan = Box::new(filter.plan.optimize(context));
+                Plan::Filter(filter)
+            }
+            Plan::FilterFn(mut filter) => {
+                filter.plan = Box::new(filter.plan.optimize(context));
+                Plan::FilterFn(filter)
+            }
+            Plan::FilterBy(mut filter) => {
+                filter.filter.plan = Box::new(filter.filter.plan.optimize(context));
+                Plan::FilterBy(filter)
+            }
+            Plan::Transform(mut transform) => {
+                transform.plan = Box::new(transform.plan.optimize(context));
+                Plan::Transform(transform)
+            }
+            Plan::TransformFn(mut transform) => {
+                transform.plan = Box::new(transform.plan.optimize(context));
+                Plan::TransformFn(transform)
+            }
+            Plan::Rename(mut rename) => {
+                rename.plan = Box::new(rename.plan.optimize(context));
+                Plan::Rename(rename)
+            }
+            Plan::AsOfValid(mut as_of) => {
+                as_of.plan = Box::new(as_of.plan.optimize(context));
+                Plan::AsOfValid(as_of)
+            }
+            Plan::Missing(mut missing) => {
+                missing.plan = Box::new(missing.plan.optimize(context));
+                Plan::Missing(missing)
+            }
+            Plan::Sample(mut sample) => {
+                sample.plan = Box::new(sample.plan.optimize(context));
+                Plan::Sample(sample)
+            }
+            Plan::JoinAny(mut join) => {
+                join.left_plan = Box::new(join.left_plan.optimize(context));
+                join.right_plan = Box::new(join.right_plan.optimize(context));
+                Plan::JoinAny(join)
+            }
+            plan => plan,
+        }
+    }
+}
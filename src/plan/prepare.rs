@@ -0,0 +1,43 @@
+//! Prepared plans, i.e. plans that are implemented once and then
+//! serve many sets of parameters.
+//!
+//! Rather than having constants substituted, a prepared rule binds
+//! its parameters like any other variables, via `Plan::parameters`,
+//! and joins or compares them against its other bindings. Parameter
+//! sets are fed into a dedicated input collection (see
+//! `Server::interest_prepared`), s.t. a single dataflow computes the
+//! results for all of them, each result carrying the parameters it
+//! was derived from.
+
+use crate::plan::Plan;
+use crate::Var;
+
+/// Returns the name of the relation holding the parameter sets of
+/// the named prepared rule.
+pub fn parameters_of(name: &str) -> String {
+    format!("{}/parameters", name)
+}
+
+impl Plan {
+    /// Binds the given variables to the parameters of the named
+    /// prepared rule, i.e. one tuple per parameter set of interest,
+    /// with the i-th variable bound to the i-th parameter.
+    pub fn parameters(name: &str, variables: Vec<Var>) -> Plan {
+        Plan::NameExpr(variables, parameters_of(name))
+    }
+
+    /// Returns the variables this plan binds to the parameters of
+    /// the named prepared rule, if it refers to them at all.
+    pub fn parameter_variables(&self, name: &str) -> Option<Vec<Var>> {
+        match *self {
+            Plan::NameExpr(ref variables, ref relation) if *relation == parameters_of(name) => {
+                Some(variables.clone())
+            }
+            _ => self
+                .inputs()
+                .iter()
+                .filter_map(|input| input.parameter_variables(name))
+                .next(),
+        }
+    }
+}
@@ -8,6 +8,7 @@ use differential_dataflow::lattice::Lattice;
 
 use crate::binding::Binding;
 use crate::plan::{next_id, Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
 use crate::{Aid, Eid, Value, Var};
 use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, VariableMap};
 
@@ -27,8 +28,8 @@ impl<P: Implementable> Implementable for Project<P> {
         self.plan.dependencies()
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
-        self.plan.into_bindings()
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        self.plan.into_bindings_with(gensym)
     }
 
     fn datafy(&self) -> Vec<(Eid, Aid, Value)> {
@@ -53,7 +54,7 @@ impl<P: Implementable> Implementable for Project<P> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
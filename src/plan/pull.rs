@@ -1,5 +1,8 @@
 //! Pull expression plan, but without nesting.
 
+#[cfg(feature = "serde_json")]
+use std::collections::BTreeMap;
+
 use timely::dataflow::operators::{Concat, Concatenate};
 use timely::dataflow::scopes::child::Iterative;
 use timely::dataflow::Scope;
@@ -7,10 +10,12 @@ use timely::order::Product;
 use timely::progress::Timestamp;
 
 use differential_dataflow::lattice::Lattice;
-use differential_dataflow::AsCollection;
+use differential_dataflow::operators::{Join, Threshold};
+use differential_dataflow::{AsCollection, Collection};
 
 use crate::binding::AsBinding;
-use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::plan::{Dependencies, ImplContext, Implementable, Predicate};
+use crate::timestamp::TimestampValue;
 use crate::{Aid, Value, Var};
 use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, VariableMap};
 
@@ -31,6 +36,23 @@ pub struct PullLevel<P: Implementable> {
     pub path_attributes: Vec<Aid>,
     /// @TODO
     pub cardinality_many: bool,
+    /// Condition the input entities must satisfy in order for this
+    /// level to be pulled for them.
+    #[serde(default)]
+    pub condition: Option<PullCondition>,
+}
+
+/// A condition on the entities of a pull level. It is satisfied by
+/// all entities with at least one value for `attribute`, for which
+/// `predicate` holds with respect to `value`.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct PullCondition {
+    /// Attribute to check.
+    pub attribute: Aid,
+    /// Predicate to apply to the attribute's values.
+    pub predicate: Predicate,
+    /// Value to compare against.
+    pub value: Value,
 }
 
 /// A plan stage for pull queries split into individual paths. So
@@ -47,6 +69,24 @@ pub struct Pull<P: Implementable> {
     pub paths: Vec<P>,
 }
 
+/// A plan stage following a path of reference attributes, binding
+/// each starting entity to the value(s) found at the end of the
+/// path. So the dotted path `:person/company.:company/address` would
+/// be represented as `[:person/company :company/address]`. Entities
+/// for which the path breaks off along the way are not reported, and
+/// multi-valued attributes along the path produce one result per
+/// value.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct PullPath {
+    /// Variable bound to the starting entity.
+    pub entity: Var,
+    /// Attributes to follow, in order. All but the last one must
+    /// hold entity ids.
+    pub path: Vec<Aid>,
+    /// Variable bound to the value at the end of the path.
+    pub value: Var,
+}
+
 fn interleave(values: &[Value], constants: &[Aid]) -> Vec<Value> {
     if values.is_empty() || constants.is_empty() {
         values.to_owned()
@@ -84,6 +124,12 @@ impl<P: Implementable> Implementable for PullLevel<P> {
             dependencies = Dependencies::merge(dependencies, attribute_dependencies);
         }
 
+        if let Some(ref condition) = self.condition {
+            dependencies
+                .attributes
+                .insert(condition.attribute.to_string());
+        }
+
         dependencies
     }
 
@@ -94,7 +140,7 @@ impl<P: Implementable> Implementable for PullLevel<P> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -105,6 +151,49 @@ impl<P: Implementable> Implementable for PullLevel<P> {
 
         let (input, mut shutdown_handle) = self.plan.implement(nested, local_arrangements, context);
 
+        // Restrict the input to entities satisfying the condition, if
+        // any, before anything is pulled for them.
+        let input = match self.condition {
+            None => input,
+            Some(ref condition) => {
+                let e_offset = input
+                    .binds(self.pull_variable)
+                    .expect("input relation doesn't bind pull_variable");
+                let variables = input.variables();
+
+                let tuples = {
+                    let (tuples, shutdown) = input.tuples(nested, context);
+                    shutdown_handle.merge_with(shutdown);
+                    tuples
+                };
+
+                let satisfying = {
+                    let (propose, shutdown_propose) = context
+                        .forward_propose(&condition.attribute)
+                        .expect("forward propose trace does not exist")
+                        .import_frontier(&nested.parent, &condition.attribute);
+
+                    shutdown_handle.add_button(shutdown_propose);
+
+                    let predicate = condition.predicate.clone();
+                    let value = condition.value.clone();
+
+                    propose
+                        .enter(nested)
+                        .filter(move |_e, v| predicate.eval(v, &value))
+                        .as_collection(|e, _v| e.clone())
+                        .distinct()
+                };
+
+                let tuples = tuples
+                    .map(move |tuple| (tuple[e_offset].clone(), tuple))
+                    .semijoin(&satisfying)
+                    .map(|(_e, tuple)| tuple);
+
+                Implemented::Collection(CollectionRelation { variables, tuples })
+            }
+        };
+
         if self.pull_attributes.is_empty() {
             if self.path_attributes.is_empty() {
                 // nothing to pull
@@ -233,6 +322,71 @@ impl<P: Implementable> Implementable for PullLevel<P> {
     }
 }
 
+impl Implementable for PullPath {
+    fn dependencies(&self) -> Dependencies {
+        self.path
+            .iter()
+            .fold(Dependencies::none(), |dependencies, a| {
+                Dependencies::merge(dependencies, Dependencies::attribute(a))
+            })
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        _local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        use differential_dataflow::operators::arrange::{Arrange, Arranged, TraceAgent};
+        use differential_dataflow::operators::JoinCore;
+        use differential_dataflow::trace::implementations::ord::OrdValSpine;
+
+        let mut shutdown_handle = ShutdownHandle::empty();
+
+        // We keep track of (current value, starting entity) pairs,
+        // s.t. each step can join on the current value.
+        let mut tuples: Option<Collection<Iterative<'b, S, u64>, (Value, Value), isize>> = None;
+
+        for a in self.path.iter() {
+            let (propose, shutdown_propose) = match context.forward_propose(a) {
+                None => panic!("attribute {:?} does not exist", a),
+                Some(propose_trace) => propose_trace.import_frontier(&nested.parent, a),
+            };
+            shutdown_handle.add_button(shutdown_propose);
+
+            let e_v = propose.enter(nested);
+
+            tuples = Some(match tuples {
+                None => e_v.as_collection(|e, v| (v.clone(), e.clone())),
+                Some(tuples) => {
+                    let by_value: Arranged<
+                        Iterative<S, u64>,
+                        TraceAgent<OrdValSpine<Value, Value, Product<T, u64>, isize>>,
+                    > = tuples.arrange();
+
+                    by_value.join_core(&e_v, |_e, start, v| Some((v.clone(), start.clone())))
+                }
+            });
+        }
+
+        let tuples = tuples
+            .expect("PullPath requires at least one attribute")
+            .map(|(v, start)| vec![start, v]);
+
+        let relation = CollectionRelation {
+            variables: vec![self.entity, self.value],
+            tuples,
+        };
+
+        (Implemented::Collection(relation), shutdown_handle)
+    }
+}
+
 impl<P: Implementable> Implementable for Pull<P> {
     fn dependencies(&self) -> Dependencies {
         let mut dependencies = Dependencies::none();
@@ -250,7 +404,7 @@ impl<P: Implementable> Implementable for Pull<P> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -284,6 +438,128 @@ impl<P: Implementable> Implementable for Pull<P> {
     }
 }
 
+/// Intermediate representation of a single pulled entity.
+#[cfg(feature = "serde_json")]
+#[derive(Default)]
+struct PulledEntity {
+    /// Values for each attribute pulled on this level.
+    values: BTreeMap<Aid, Vec<Value>>,
+    /// Nested entities for each reference attribute, by eid. Paths
+    /// of cardinality one don't carry child eids.
+    children: BTreeMap<Aid, BTreeMap<Option<Value>, PulledEntity>>,
+}
+
+#[cfg(feature = "serde_json")]
+impl PulledEntity {
+    fn into_json(self) -> serde_json::Value {
+        let mut object = serde_json::Map::new();
+
+        for (aid, mut values) in self.values.into_iter() {
+            // Results arrive in no particular order.
+            values.sort();
+
+            let value = if values.len() == 1 {
+                serde_json::Value::from(values.pop().unwrap())
+            } else {
+                serde_json::Value::Array(values.into_iter().map(serde_json::Value::from).collect())
+            };
+
+            object.insert(aid, value);
+        }
+
+        for (aid, mut children) in self.children.into_iter() {
+            let value = if children.len() == 1 && children.contains_key(&None) {
+                children.remove(&None).unwrap().into_json()
+            } else {
+                serde_json::Value::Array(
+                    children
+                        .into_iter()
+                        .map(|(_eid, child)| child.into_json())
+                        .collect(),
+                )
+            };
+
+            object.insert(aid, value);
+        }
+
+        serde_json::Value::Object(object)
+    }
+}
+
+/// Nests the (consolidated) results of a pull query into documents,
+/// as GraphQL or REST clients would expect them. Each result is
+/// expected to be a path as produced by `Pull` and `PullLevel`,
+/// i.e. `[e a v]` or `[e ref-a child-e ... a v]`. The result is an
+/// object mapping root eids to entity objects. Nested entities are
+/// represented as arrays of objects, unless they were pulled with
+/// cardinality one. Attributes with multiple values are represented
+/// as arrays, otherwise values are inlined.
+#[cfg(feature = "serde_json")]
+pub fn pull_to_json(results: &[Vec<Value>]) -> serde_json::Value {
+    let mut roots: BTreeMap<Value, PulledEntity> = BTreeMap::new();
+
+    for path in results.iter() {
+        assert!(path.len() >= 3, "malformed path {:?}", path);
+
+        let (a, v) = match (&path[path.len() - 2], &path[path.len() - 1]) {
+            (Value::Aid(a), v) => (a.clone(), v.clone()),
+            _ => panic!("malformed path {:?}", path),
+        };
+
+        let mut entity = roots.entry(path[0].clone()).or_insert_with(Default::default);
+
+        // Walk the (ref-a child-e) segments between root and leaf.
+        let mut idx = 1;
+        while idx < path.len() - 2 {
+            let aid = match path[idx] {
+                Value::Aid(ref aid) => aid.clone(),
+                _ => panic!("malformed path {:?}", path),
+            };
+            let child = if idx + 1 < path.len() - 2 {
+                Some(path[idx + 1].clone())
+            } else {
+                None
+            };
+
+            entity = entity
+                .children
+                .entry(aid)
+                .or_insert_with(Default::default)
+                .entry(child)
+                .or_insert_with(Default::default);
+
+            idx += 2;
+        }
+
+        entity.values.entry(a).or_insert_with(Vec::new).push(v);
+    }
+
+    let mut object = serde_json::Map::new();
+    for (eid, entity) in roots.into_iter() {
+        let key = match serde_json::Value::from(eid) {
+            serde_json::Value::String(key) => key,
+            other => other.to_string(),
+        };
+
+        object.insert(key, entity.into_json());
+    }
+
+    serde_json::Value::Object(object)
+}
+
+/// Sorts the results of a pull query by attribute, then by root
+/// entity, and finally by the full path. Dataflow outputs carry no
+/// order, so results collected from `Pull`, `PullLevel`, or `PullAll`
+/// should be sorted this way before being compared or displayed.
+pub fn sort_pulled(results: &mut [Vec<Value>]) {
+    fn key(path: &[Value]) -> (Option<&Value>, Option<&Value>, &[Value]) {
+        let attribute = path.len().checked_sub(2).and_then(|idx| path.get(idx));
+        (attribute, path.first(), path)
+    }
+
+    results.sort_by(|x, y| key(x).cmp(&key(y)));
+}
+
 /// A plan stage for extracting all tuples for a given set of
 /// attributes.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
@@ -313,7 +589,7 @@ impl Implementable for PullAll {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -358,3 +634,125 @@ impl Implementable for PullAll {
         (Implemented::Collection(relation), shutdown_handle)
     }
 }
+
+/// A plan stage for extracting all [e a v] tuples for a given set of
+/// attributes and a small set of known entities, e.g. to populate an
+/// entity-detail view. Unlike `PullLevel`, which joins its input
+/// against the attributes in full, this seeks directly to each of
+/// the entities within the attributes' forward indices. The work
+/// done is therefore proportional to the size of the result, rather
+/// than to the size of the attributes.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct PullEntities {
+    /// Variables bound by this stage.
+    pub variables: Vec<Var>,
+    /// Entities to pull, usually eids.
+    pub entities: Vec<Value>,
+    /// Attributes to pull for the entities.
+    pub pull_attributes: Vec<Aid>,
+}
+
+impl Implementable for PullEntities {
+    fn dependencies(&self) -> Dependencies {
+        let mut dependencies = Dependencies::none();
+
+        for attribute in &self.pull_attributes {
+            let attribute_dependencies = Dependencies::attribute(&attribute);
+            dependencies = Dependencies::merge(dependencies, attribute_dependencies);
+        }
+
+        dependencies
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        _local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        use timely::dataflow::channels::pact::Pipeline;
+        use timely::dataflow::operators::generic::operator::Operator;
+
+        use differential_dataflow::trace::{cursor::Cursor, BatchReader, TraceReader};
+
+        assert!(!self.pull_attributes.is_empty());
+
+        let mut shutdown_handle = ShutdownHandle::empty();
+
+        // Seeking in ascending order lets each batch cursor move
+        // forward only.
+        let mut entities = self.entities.clone();
+        entities.sort();
+        entities.dedup();
+
+        let streams = self.pull_attributes.iter().map(|a| {
+            let (propose, frontier) = match context.forward_propose(a) {
+                None => panic!("attribute {:?} does not exist", a),
+                Some(propose_trace) => {
+                    let frontier: Vec<T> = propose_trace.advance_frontier().to_vec();
+                    let (arranged, shutdown_propose) = propose_trace.import_core(&nested.parent, a);
+
+                    shutdown_handle.add_button(shutdown_propose);
+
+                    (arranged, frontier)
+                }
+            };
+
+            let attribute = Value::Aid(a.clone());
+            let entities = entities.clone();
+
+            propose
+                .stream
+                .unary(Pipeline, "PullEntities", move |_, _| {
+                    move |input, output| {
+                        input.for_each(|time, data| {
+                            let mut session = output.session(&time);
+
+                            for batch in data.iter() {
+                                let mut cursor = batch.cursor();
+
+                                for e in entities.iter() {
+                                    cursor.seek_key(batch, e);
+
+                                    if cursor.get_key(batch) != Some(e) {
+                                        continue;
+                                    }
+
+                                    while let Some(v) = cursor.get_val(batch) {
+                                        cursor.map_times(batch, |t, diff| {
+                                            let mut forwarded = t.clone();
+                                            forwarded.advance_by(&frontier);
+
+                                            session.give((
+                                                vec![e.clone(), attribute.clone(), v.clone()],
+                                                forwarded,
+                                                *diff,
+                                            ));
+                                        });
+                                        cursor.step_val(batch);
+                                    }
+                                }
+                            }
+                        });
+                    }
+                })
+                .as_collection()
+                .enter(nested)
+                .inner
+        });
+
+        let tuples = nested.concatenate(streams).as_collection();
+
+        let relation = CollectionRelation {
+            variables: self.variables.to_vec(),
+            tuples,
+        };
+
+        (Implemented::Collection(relation), shutdown_handle)
+    }
+}
@@ -11,6 +11,7 @@ use differential_dataflow::lattice::Lattice;
 
 use crate::binding::AsBinding;
 use crate::plan::{Dependencies, ImplContext, Implementable, Plan};
+use crate::timestamp::TimestampValue;
 use crate::{Aid, Value, Var};
 use crate::{Relation, ShutdownHandle, VariableMap};
 
@@ -61,7 +62,7 @@ impl<P: Implementable> PullLevel<P> {
         ShutdownHandle,
     )
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -174,7 +175,7 @@ impl PullAll {
         ShutdownHandle,
     )
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -251,7 +252,7 @@ impl Pull {
         ShutdownHandle,
     )
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
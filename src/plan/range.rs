@@ -0,0 +1,211 @@
+//! Range scans over the values of an attribute.
+
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::generic::operator::Operator;
+use timely::dataflow::scopes::child::Iterative;
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+use differential_dataflow::trace::{cursor::Cursor, BatchReader};
+use differential_dataflow::AsCollection;
+
+use crate::plan::{Filter, ImplContext, Plan, Predicate};
+use crate::timestamp::TimestampValue;
+use crate::{Aid, CollectionRelation, Implemented, ShutdownHandle, Value, Var};
+
+/// Bounds on the values of an attribute, each of them either
+/// inclusive or exclusive. Values are compared by their natural
+/// order, which is also the order of the keys in an attribute's
+/// reverse indices.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ValueRange {
+    lower: Option<(Value, bool)>,
+    upper: Option<(Value, bool)>,
+}
+
+impl ValueRange {
+    /// Restricts the range by the predicate `v op constant`. Returns
+    /// false for predicates not describing a range.
+    fn restrict(&mut self, predicate: &Predicate, constant: &Value) -> bool {
+        match *predicate {
+            Predicate::LT => self.restrict_upper(constant, false),
+            Predicate::LTE => self.restrict_upper(constant, true),
+            Predicate::GT => self.restrict_lower(constant, false),
+            Predicate::GTE => self.restrict_lower(constant, true),
+            Predicate::EQ => {
+                self.restrict_lower(constant, true);
+                self.restrict_upper(constant, true);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn restrict_lower(&mut self, value: &Value, inclusive: bool) {
+        let tighter = match self.lower {
+            None => true,
+            Some((ref bound, bound_inclusive)) => {
+                value > bound || (value == bound && bound_inclusive && !inclusive)
+            }
+        };
+
+        if tighter {
+            self.lower = Some((value.clone(), inclusive));
+        }
+    }
+
+    fn restrict_upper(&mut self, value: &Value, inclusive: bool) {
+        let tighter = match self.upper {
+            None => true,
+            Some((ref bound, bound_inclusive)) => {
+                value < bound || (value == bound && bound_inclusive && !inclusive)
+            }
+        };
+
+        if tighter {
+            self.upper = Some((value.clone(), inclusive));
+        }
+    }
+
+    fn above_lower(&self, value: &Value) -> bool {
+        match self.lower {
+            None => true,
+            Some((ref bound, true)) => value >= bound,
+            Some((ref bound, false)) => value > bound,
+        }
+    }
+
+    fn below_upper(&self, value: &Value) -> bool {
+        match self.upper {
+            None => true,
+            Some((ref bound, true)) => value <= bound,
+            Some((ref bound, false)) => value < bound,
+        }
+    }
+}
+
+/// Returns the predicate `b op' a` equivalent to `a op b`.
+fn flipped(predicate: &Predicate) -> Predicate {
+    match *predicate {
+        Predicate::LT => Predicate::GT,
+        Predicate::GT => Predicate::LT,
+        Predicate::LTE => Predicate::GTE,
+        Predicate::GTE => Predicate::LTE,
+        ref other => other.clone(),
+    }
+}
+
+fn collect_range(plan: &Plan, range: &mut ValueRange) -> Option<(Var, Aid, Var)> {
+    match *plan {
+        Plan::MatchA(e, ref a, v) if e != v => Some((e, a.to_string(), v)),
+        Plan::Filter(ref filter) => {
+            let pattern = collect_range(&filter.plan, range)?;
+
+            if filter.variables.get(0) != Some(&pattern.2) {
+                return None;
+            }
+
+            let restricted = match (filter.constants.get(0), filter.constants.get(1)) {
+                (Some(Some(_)), Some(Some(_))) => false,
+                (Some(Some(constant)), _) => range.restrict(&flipped(&filter.predicate), constant),
+                (_, Some(Some(constant))) => range.restrict(&filter.predicate, constant),
+                _ => false,
+            };
+
+            if restricted {
+                Some(pattern)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Recognizes filters (possibly nested) comparing the value of a
+/// single attribute pattern [?e a ?v] against constants, e.g. to
+/// select values between two bounds. Returns the pattern and the
+/// range of values it is restricted to.
+pub(crate) fn as_range_scan(filter: &Filter<Plan>) -> Option<(Var, Aid, Var, ValueRange)> {
+    let mut range = ValueRange::default();
+    let (e, a, v) = collect_range(&Plan::Filter(filter.clone()), &mut range)?;
+
+    Some((e, a, v, range))
+}
+
+/// Implements the pattern [?e a ?v] restricted to the given range of
+/// values, by seeking to the lower bound within each batch of the
+/// attribute's reverse propose trace and stepping through its keys
+/// until the upper bound is passed, rather than importing the
+/// attribute in full.
+pub(crate) fn implement_range_scan<'b, T, I, S>(
+    nested: &mut Iterative<'b, S, u64>,
+    context: &mut I,
+    e: Var,
+    a: &str,
+    v: Var,
+    range: ValueRange,
+) -> (Implemented<'b, S>, ShutdownHandle)
+where
+    T: Timestamp + Lattice + TimestampValue,
+    I: ImplContext<T>,
+    S: Scope<Timestamp = T>,
+{
+    let (propose, shutdown_propose) = context
+        .reverse_propose(a)
+        .expect("reverse propose trace does not exist")
+        .import_core(&nested.parent, a);
+
+    let tuples = propose
+        .stream
+        .unary(Pipeline, "RangeScan", move |_, _| {
+            move |input, output| {
+                input.for_each(|time, data| {
+                    let mut session = output.session(&time);
+
+                    for batch in data.iter() {
+                        let mut cursor = batch.cursor();
+
+                        if let Some((ref lower, _)) = range.lower {
+                            cursor.seek_key(batch, lower);
+                        }
+
+                        while let Some(value) = cursor.get_key(batch) {
+                            if !range.below_upper(value) {
+                                break;
+                            }
+
+                            if range.above_lower(value) {
+                                while let Some(entity) = cursor.get_val(batch) {
+                                    cursor.map_times(batch, |t, diff| {
+                                        session.give((
+                                            vec![entity.clone(), value.clone()],
+                                            t.clone(),
+                                            *diff,
+                                        ));
+                                    });
+                                    cursor.step_val(batch);
+                                }
+                            }
+
+                            cursor.step_key(batch);
+                        }
+                    }
+                });
+            }
+        })
+        .as_collection()
+        .enter(nested);
+
+    let relation = CollectionRelation {
+        variables: vec![e, v],
+        tuples,
+    };
+
+    (
+        Implemented::Collection(relation),
+        ShutdownHandle::from_button(shutdown_propose),
+    )
+}
@@ -0,0 +1,113 @@
+//! Variable renaming plan.
+
+use timely::dataflow::scopes::child::Iterative;
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+
+use crate::plan::{next_id, Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
+use crate::{Aid, Eid, Value, Var};
+use crate::{
+    AttributeBinding, CollectionRelation, Implemented, Relation, ShutdownHandle, VariableMap,
+};
+
+/// A plan stage relabeling the variables bound by its source, e.g. to
+/// align two sources prior to joining them. Tuples are passed through
+/// untouched. All renames happen simultaneously, so swapping two
+/// variables is fine. Throws if a variable is renamed onto another
+/// one that remains bound by the source, as the resulting relation
+/// would bind the same variable twice.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct Rename<P: Implementable> {
+    /// Plan for the data source.
+    pub plan: Box<P>,
+    /// Pairs of source and target variables. Variables not
+    /// mentioned keep their name.
+    pub mapping: Vec<(Var, Var)>,
+}
+
+impl<P: Implementable> Rename<P> {
+    /// Returns the new name of the given variable.
+    pub fn rename(&self, variable: Var) -> Var {
+        self.mapping
+            .iter()
+            .find(|(source, _target)| *source == variable)
+            .map(|(_source, target)| *target)
+            .unwrap_or(variable)
+    }
+
+    /// Returns the variables renamed by this stage.
+    pub fn sources(&self) -> Vec<Var> {
+        self.mapping
+            .iter()
+            .map(|(source, _target)| *source)
+            .collect()
+    }
+
+    /// Renames the given variables, throwing on collisions.
+    fn renamed(&self, variables: &[Var]) -> Vec<Var> {
+        let renamed: Vec<Var> = variables.iter().map(|x| self.rename(*x)).collect();
+
+        for (i, variable) in renamed.iter().enumerate() {
+            if renamed[..i].contains(variable) {
+                panic!(
+                    "Renaming {:?} by {:?} binds variable {} more than once.",
+                    variables, self.mapping, variable
+                );
+            }
+        }
+
+        renamed
+    }
+}
+
+impl<P: Implementable> Implementable for Rename<P> {
+    fn dependencies(&self) -> Dependencies {
+        self.plan.dependencies()
+    }
+
+    fn datafy(&self) -> Vec<(Eid, Aid, Value)> {
+        let eid = next_id();
+        let mut data = self.plan.datafy();
+
+        if data.is_empty() {
+            Vec::new()
+        } else {
+            let child_eid = data[0].0;
+
+            data.push((eid, "df.rename/binding".to_string(), Value::Eid(child_eid)));
+
+            data
+        }
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        let (relation, shutdown_handle) = self.plan.implement(nested, local_arrangements, context);
+        let variables = self.renamed(&relation.variables());
+
+        let renamed = match relation {
+            Implemented::Attribute(binding) => Implemented::Attribute(AttributeBinding {
+                variables: (variables[0], variables[1]),
+                source_attribute: binding.source_attribute,
+            }),
+            Implemented::Collection(relation) => Implemented::Collection(CollectionRelation {
+                variables,
+                tuples: relation.tuples,
+            }),
+        };
+
+        (renamed, shutdown_handle)
+    }
+}
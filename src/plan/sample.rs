@@ -0,0 +1,91 @@
+//! Sampling plan stage, for approximate query results.
+
+use std::hash::{Hash, Hasher};
+
+use timely::dataflow::scopes::child::Iterative;
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+
+use crate::hashing::StableHasher;
+use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
+use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, Value, VariableMap};
+use crate::{Rational32, Var};
+
+/// A plan stage retaining a pseudo-random sample of its source
+/// tuples, each with probability `rate`. Whether a tuple is kept is
+/// decided by hashing it together with the seed, rather than by
+/// rolling a die, s.t. retractions always match their earlier
+/// additions and results are reproducible across runs and workers.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct Sample<P: Implementable> {
+    /// Plan for the data source.
+    pub plan: Box<P>,
+    /// Probability of retaining a tuple, between zero and one.
+    pub rate: Rational32,
+    /// Seed for the hash deciding which tuples are retained.
+    pub seed: u64,
+}
+
+impl<P: Implementable> Sample<P> {
+    /// Returns true iff the given tuple is part of the sample.
+    pub fn retains(&self, tuple: &[Value]) -> bool {
+        retains(*self.rate.numer(), *self.rate.denom(), self.seed, tuple)
+    }
+}
+
+/// Decides whether a tuple falls into the first `numer / denom` of
+/// the hash space.
+fn retains(numer: i32, denom: i32, seed: u64, tuple: &[Value]) -> bool {
+    let mut hasher = StableHasher::new();
+    seed.hash(&mut hasher);
+    tuple.hash(&mut hasher);
+
+    // hash / 2^64 < numer / denom
+    u128::from(hasher.finish()) * (denom as u128) < (numer as u128) << 64
+}
+
+impl<P: Implementable> Implementable for Sample<P> {
+    fn dependencies(&self) -> Dependencies {
+        self.plan.dependencies()
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        if self.rate < Rational32::from_integer(0) || self.rate > Rational32::from_integer(1) {
+            panic!(
+                "Sample rate must be between zero and one, got {}.",
+                self.rate
+            );
+        }
+
+        let (relation, mut shutdown_handle) =
+            self.plan.implement(nested, local_arrangements, context);
+        let variables: Vec<Var> = relation.variables();
+
+        let tuples = {
+            let (tuples, shutdown) = relation.tuples(nested, context);
+            shutdown_handle.merge_with(shutdown);
+            tuples
+        };
+
+        let (numer, denom, seed) = (*self.rate.numer(), *self.rate.denom(), self.seed);
+        let sampled = CollectionRelation {
+            variables,
+            tuples: tuples.filter(move |tuple| retains(numer, denom, seed, tuple)),
+        };
+
+        (Implemented::Collection(sampled), shutdown_handle)
+    }
+}
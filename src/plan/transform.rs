@@ -1,27 +1,285 @@
 //! Function expression plan.
 
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use timely::dataflow::operators::Map;
 use timely::dataflow::scopes::child::Iterative;
 use timely::dataflow::Scope;
 use timely::progress::Timestamp;
 
 use differential_dataflow::lattice::Lattice;
+use differential_dataflow::AsCollection;
 
 use crate::binding::{AsBinding, Binding};
 use crate::plan::{Dependencies, ImplContext, Implementable};
+use crate::timestamp::TimestampValue;
 use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, Value, Var, VariableMap};
 
+/// Components that can be extracted from a timestamp.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum DatePart {
+    /// Year, e.g. 2018
+    Year,
+    /// Month of the year, from 1 to 12
+    Month,
+    /// Day of the month, from 1 to 31
+    Day,
+    /// Hour of the day, from 0 to 23
+    Hour,
+    /// Minute of the hour, from 0 to 59
+    Minute,
+    /// Day of the week, from 1 (Monday) to 7 (Sunday)
+    Weekday,
+}
+
+impl DatePart {
+    /// Extracts the component from a unix timestamp in milliseconds,
+    /// interpreted as UTC.
+    pub fn extract(self, t: u64) -> i64 {
+        let seconds = (t / 1000) as i64;
+        let days = seconds / 86_400;
+
+        match self {
+            DatePart::Hour => (seconds % 86_400) / 3600,
+            DatePart::Minute => (seconds % 3600) / 60,
+            // 1970-01-01 was a Thursday.
+            DatePart::Weekday => (days + 3) % 7 + 1,
+            DatePart::Year | DatePart::Month | DatePart::Day => {
+                // (adapted from howardhinnant.github.io/date_algorithms.html)
+                let z = days + 719_468;
+                let era = z / 146_097;
+                let doe = z - era * 146_097;
+                let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+                let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+                let mp = (5 * doy + 2) / 153;
+                let day = doy - (153 * mp + 2) / 5 + 1;
+                let month = if mp < 10 { mp + 3 } else { mp - 9 };
+                let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+                match self {
+                    DatePart::Year => year,
+                    DatePart::Month => month,
+                    _ => day,
+                }
+            }
+        }
+    }
+}
+
+/// Folds operands into the initial value via the respective checked
+/// operation, returning `None` on overflow. All operands must be
+/// numbers of the same signedness.
+fn fold_checked<'a, I>(
+    name: &str,
+    init: &Value,
+    operands: I,
+    signed: fn(i64, i64) -> Option<i64>,
+    unsigned: fn(u64, u64) -> Option<u64>,
+) -> Option<Value>
+where
+    I: Iterator<Item = &'a Value>,
+{
+    let mut result = init.clone();
+
+    for operand in operands {
+        result = match (result, operand) {
+            (Value::Number(x), Value::Number(y)) => Value::Number(signed(x, *y)?),
+            (Value::UNumber(x), Value::UNumber(y)) => Value::UNumber(unsigned(x, *y)?),
+            _ => panic!("{} can only be applied to numbers of the same kind", name),
+        };
+    }
+
+    match result {
+        Value::Number(_) | Value::UNumber(_) => Some(result),
+        _ => panic!("{} can only be applied to numbers", name),
+    }
+}
+
 /// Permitted functions.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub enum Function {
     /// Truncates a unix timestamp into an hourly interval
     TRUNCATE,
-    /// Adds one or more numbers to the first provided
+    /// Adds one or more numbers to the first provided. Overflows
+    /// yield no result, i.e. `Value::Null` when applied on its own,
+    /// while `Transform` stages drop the tuple.
     ADD,
-    /// Subtracts one or more numbers from the first provided
+    /// Subtracts one or more numbers from the first provided.
+    /// Overflows are handled as for `ADD`.
     SUBTRACT,
+    /// Extracts a component (in UTC) from a unix timestamp
+    DatePart(DatePart),
+    /// Returns the first of its arguments that isn't `Value::Null`,
+    /// falling back to the constant inputs (in order) if all of them
+    /// are. The result is `Value::Null` if there is no such value,
+    /// s.t. tuples are never dropped.
+    COALESCE,
+    /// Splits a string into its non-empty elements, delimited by the
+    /// constant input (defaulting to ","). Unlike all other
+    /// functions, this produces any number of results, each of which
+    /// is bound in a tuple of its own. Tuples whose string has no
+    /// elements are thus dropped.
+    SPLIT,
+    /// Binds the logical time at which a tuple was produced, encoded
+    /// as by `TimestampValue::to_value`. Takes no arguments. Unlike
+    /// all other functions, this depends on the dataflow rather than
+    /// on the tuple, and is therefore only available in `Transform`
+    /// stages. NOW is meant for append-only inputs: a retraction
+    /// happens later than the corresponding addition and thus binds
+    /// a different result, s.t. the two never cancel out. Tuples read
+    /// from compacted traces bind the compacted times, so results
+    /// are only reproducible for queries registered before their
+    /// inputs are compacted.
+    NOW,
+    /// Converts an integer into the type of the constant input, which
+    /// serves as an exemplary value of that type (as for
+    /// `AttributeConfig::value_type`). Supports conversions between
+    /// `Eid`, `Number`, and `UNumber`, e.g. to join attributes that
+    /// disagree on the representation of the same ids. Note that
+    /// casting makes values of different types match, that were
+    /// never meant to (e.g. an entity id and an unrelated count), so
+    /// it should be limited to values known to be ids. Integers
+    /// outside of the range of the target type are handled as
+    /// overflows of `ADD`.
+    CAST,
 }
 
-/// A plan stage applying a built-in function to source tuples.
+impl Function {
+    /// Applies the function to the given arguments and constant
+    /// inputs.
+    pub fn apply(&self, args: &[&Value], constants: &[Option<Value>]) -> Value {
+        match *self {
+            Function::TRUNCATE => {
+                let mut t = match args[0] {
+                    Value::Instant(inst) => *inst as u64,
+                    _ => panic!("TRUNCATE can only be applied to timestamps"),
+                };
+                let default_interval = String::from(":hour");
+                let interval_param = match constants.get(1).cloned().unwrap_or(None) {
+                    Some(Value::String(interval)) => interval,
+                    None => default_interval,
+                    _ => panic!("Parameter for TRUNCATE must be a string"),
+                };
+
+                let mod_val = match interval_param.as_ref() {
+                    ":minute" => 60000,
+                    ":hour" => 3_600_000,
+                    ":day" => 86_400_000,
+                    ":week" => 604_800_000,
+                    _ => panic!("Unknown interval for TRUNCATE"),
+                };
+
+                t = t - (t % mod_val);
+                Value::Instant(t)
+            }
+            Function::ADD | Function::SUBTRACT | Function::CAST => {
+                self.checked(args, constants).unwrap_or(Value::Null)
+            }
+            Function::DatePart(part) => match args[0] {
+                Value::Instant(t) => Value::Number(part.extract(*t)),
+                _ => panic!("DatePart can only be applied to timestamps"),
+            },
+            Function::COALESCE => args
+                .iter()
+                .cloned()
+                .chain(constants.iter().filter_map(Option::as_ref))
+                .find(|value| **value != Value::Null)
+                .cloned()
+                .unwrap_or(Value::Null),
+            Function::SPLIT => panic!("SPLIT produces multiple values, use apply_many"),
+            Function::NOW => panic!("NOW depends on the dataflow time, use it in a Transform"),
+        }
+    }
+
+    /// Applies an arithmetic function or CAST, returning `None` if it
+    /// overflowed.
+    fn checked(&self, args: &[&Value], constants: &[Option<Value>]) -> Option<Value> {
+        match *self {
+            Function::ADD => {
+                let mut operands = args
+                    .iter()
+                    .cloned()
+                    .chain(constants.iter().filter_map(Option::as_ref));
+
+                match operands.next() {
+                    None => Some(Value::Number(0)),
+                    Some(first) => {
+                        fold_checked("ADD", first, operands, i64::checked_add, u64::checked_add)
+                    }
+                }
+            }
+            Function::SUBTRACT => {
+                let mut operands: Vec<&Value> = args
+                    .iter()
+                    .cloned()
+                    .chain(constants.iter().filter_map(Option::as_ref))
+                    .collect();
+
+                // minuend is either constant or variable, depending on
+                // position in transform
+                let minuend = match constants.get(0) {
+                    Some(Some(_)) => operands.remove(args.len()),
+                    _ => operands.remove(0),
+                };
+
+                fold_checked(
+                    "SUBTRACT",
+                    minuend,
+                    operands.into_iter(),
+                    i64::checked_sub,
+                    u64::checked_sub,
+                )
+            }
+            Function::CAST => {
+                let n = match args[0] {
+                    Value::Eid(x) | Value::UNumber(x) => i128::from(*x),
+                    Value::Number(x) => i128::from(*x),
+                    _ => panic!("CAST can only be applied to integers"),
+                };
+
+                match constants.get(1).cloned().unwrap_or(None) {
+                    Some(Value::Eid(_)) => u64::try_from(n).ok().map(Value::Eid),
+                    Some(Value::UNumber(_)) => u64::try_from(n).ok().map(Value::UNumber),
+                    Some(Value::Number(_)) => i64::try_from(n).ok().map(Value::Number),
+                    _ => panic!("Parameter for CAST must be an Eid, Number, or UNumber"),
+                }
+            }
+            _ => panic!("{:?} is not an arithmetic function", self),
+        }
+    }
+
+    /// Applies the function to the given arguments and constant
+    /// inputs, returning all of its results. Functions other than
+    /// SPLIT produce exactly one, unless arithmetic overflowed.
+    pub fn apply_many(&self, args: &[&Value], constants: &[Option<Value>]) -> Vec<Value> {
+        match *self {
+            Function::ADD | Function::SUBTRACT | Function::CAST => {
+                self.checked(args, constants).into_iter().collect()
+            }
+            Function::SPLIT => {
+                let delimiter = match constants.get(1).cloned().unwrap_or(None) {
+                    Some(Value::String(delimiter)) => delimiter,
+                    None => String::from(","),
+                    _ => panic!("Parameter for SPLIT must be a string"),
+                };
+
+                match args[0] {
+                    Value::String(s) => s
+                        .split(delimiter.as_str())
+                        .filter(|element| !element.is_empty())
+                        .map(|element| Value::String(element.to_string()))
+                        .collect(),
+                    _ => panic!("SPLIT can only be applied to strings"),
+                }
+            }
+            _ => vec![self.apply(args, constants)],
+        }
+    }
+}
+
+/// A plan stage applying a built-in function to source tuples,
+/// extending each of them by the result(s) of the function.
 /// Frontends are responsible for ensuring that the source
 /// binds the argument variables and that the result is projected onto
 /// the right variable.
@@ -44,8 +302,8 @@ impl<P: Implementable> Implementable for Transform<P> {
         self.plan.dependencies()
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
-        self.plan.into_bindings()
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
+        self.plan.into_bindings_with(gensym)
     }
 
     fn implement<'b, T, I, S>(
@@ -55,7 +313,7 @@ impl<P: Implementable> Implementable for Transform<P> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
@@ -79,114 +337,131 @@ impl<P: Implementable> Implementable for Transform<P> {
             tuples
         };
 
-        let transformed = match self.function {
-            Function::TRUNCATE => CollectionRelation {
-                variables,
-                tuples: tuples.map(move |tuple| {
-                    let mut t = match tuple[key_offsets[0]] {
-                        Value::Instant(inst) => inst as u64,
-                        _ => panic!("TRUNCATE can only be applied to timestamps"),
-                    };
-                    let default_interval = String::from(":hour");
-                    let interval_param = match constants_local[1].clone() {
-                        Some(Value::String(interval)) => interval,
-                        None => default_interval,
-                        _ => panic!("Parameter for TRUNCATE must be a string"),
-                    };
-
-                    let mod_val = match interval_param.as_ref() {
-                        ":minute" => 60000,
-                        ":hour" => 3_600_000,
-                        ":day" => 86_400_000,
-                        ":week" => 604_800_000,
-                        _ => panic!("Unknown interval for TRUNCATE"),
-                    };
-
-                    t = t - (t % mod_val);
-                    let mut v = tuple.clone();
-                    v.push(Value::Instant(t));
-                    v
-                }),
-            },
-            Function::ADD => CollectionRelation {
+        if self.function == Function::NOW {
+            if <T as Lattice>::minimum().to_value().is_none() {
+                panic!("NOW can't encode timestamps of this type");
+            }
+
+            let stamped = CollectionRelation {
                 variables,
-                tuples: tuples.map(move |tuple| {
-                    let mut result = 0;
+                tuples: tuples
+                    .inner
+                    .map(|(mut tuple, t, diff)| {
+                        tuple.push(t.outer.to_value().expect("unsupported timestamp"));
+                        (tuple, t, diff)
+                    })
+                    .as_collection(),
+            };
 
-                    // summands (vars)
-                    for offset in &key_offsets {
-                        let summand = match tuple[*offset] {
-                            Value::Number(s) => s as i64,
-                            _ => panic!("ADD can only be applied to numbers"),
-                        };
+            return (Implemented::Collection(stamped), shutdown_handle);
+        }
 
-                        result += summand;
-                    }
+        let function = self.function.clone();
+        let transformed = CollectionRelation {
+            variables,
+            tuples: tuples.flat_map(move |tuple| {
+                let results = {
+                    let args: Vec<&Value> = key_offsets.iter().map(|i| &tuple[*i]).collect();
+                    function.apply_many(&args, &constants_local)
+                };
 
-                    // summands (constants)
-                    for arg in &constants_local {
-                        if let Some(constant) = arg {
-                            let summand = match constant {
-                                Value::Number(s) => *s as i64,
-                                _ => panic!("ADD can only be applied to numbers"),
-                            };
+                results
+                    .into_iter()
+                    .map(|result| {
+                        let mut v = tuple.clone();
+                        v.push(result);
+                        v
+                    })
+                    .collect::<Vec<Vec<Value>>>()
+            }),
+        };
 
-                            result += summand;
-                        }
-                    }
+        (Implemented::Collection(transformed), shutdown_handle)
+    }
+}
 
-                    let mut v = tuple.clone();
-                    v.push(Value::Number(result));
-                    v
-                }),
-            },
-            Function::SUBTRACT => CollectionRelation {
-                variables,
-                tuples: tuples.map(move |tuple| {
-                    // minuend is either variable or variable, depending on
-                    // position in transform
-
-                    let mut result = match constants_local[0].clone() {
-                        Some(constant) => match constant {
-                            Value::Number(minuend) => minuend as i64,
-                            _ => panic!("SUBTRACT can only be applied to numbers"),
-                        },
-                        None => match tuple[key_offsets[0]] {
-                            Value::Number(minuend) => minuend as i64,
-                            _ => panic!("SUBTRACT can only be applied to numbers"),
-                        },
-                    };
-
-                    // avoid filtering out the minuend by doubling it
-                    result = result + result;
-
-                    // subtrahends (vars)
-                    for offset in &key_offsets {
-                        let subtrahend = match tuple[*offset] {
-                            Value::Number(s) => s as i64,
-                            _ => panic!("SUBTRACT can only be applied to numbers"),
-                        };
-
-                        result -= subtrahend;
-                    }
+/// An arbitrary user closure deriving a new value from the bindings
+/// of a tuple. Returning `None` drops the tuple. Closures are
+/// compared by identity.
+#[derive(Clone)]
+pub struct FunctionFn(pub Arc<dyn Fn(&[Value]) -> Option<Value> + Send + Sync>);
 
-                    // subtrahends (constants)
-                    for arg in &constants_local {
-                        if let Some(constant) = arg {
-                            let subtrahend = match constant {
-                                Value::Number(s) => *s as i64,
-                                _ => panic!("SUBTRACT can only be applied to numbers"),
-                            };
+impl FunctionFn {
+    /// Wraps the given closure.
+    pub fn new<F: Fn(&[Value]) -> Option<Value> + Send + Sync + 'static>(function: F) -> Self {
+        FunctionFn(Arc::new(function))
+    }
+}
 
-                            result -= subtrahend;
-                        }
-                    }
+by_address!(FunctionFn, dyn Fn(&[Value]) -> Option<Value> + Send + Sync);
 
-                    let mut v = tuple.clone();
-                    v.push(Value::Number(result));
-                    v
-                }),
-            },
+/// A plan stage applying an arbitrary user closure to source
+/// tuples, for cases not covered by the built-in functions. The
+/// closure is passed the bindings of the specified variables, in
+/// order, and its result is bound to the result variable. Tuples for
+/// which it returns `None` are dropped. Plans containing this stage
+/// can't be sent over the wire.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub struct TransformFn<P: Implementable> {
+    /// Variables whose bindings are passed to the function.
+    pub variables: Vec<Var>,
+    /// Variable to which the result of the function is bound.
+    pub result_variable: Var,
+    /// Plan for the data source.
+    pub plan: Box<P>,
+    /// Function to apply.
+    pub function: FunctionFn,
+}
+
+impl<P: Implementable> Implementable for TransformFn<P> {
+    fn dependencies(&self) -> Dependencies {
+        self.plan.dependencies()
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        let (relation, mut shutdown_handle) =
+            self.plan.implement(nested, local_arrangements, context);
+
+        let key_offsets: Vec<usize> = self
+            .variables
+            .iter()
+            .map(|variable| relation.binds(*variable).expect("variable not found"))
+            .collect();
+
+        let mut variables = relation.variables();
+        variables.push(self.result_variable);
+
+        let tuples = {
+            let (tuples, shutdown) = relation.tuples(nested, context);
+            shutdown_handle.merge_with(shutdown);
+            tuples
+        };
+
+        let function = self.function.clone();
+        let transformed = CollectionRelation {
+            variables,
+            tuples: tuples.flat_map(move |mut tuple| {
+                let result = {
+                    let arguments: Vec<Value> =
+                        key_offsets.iter().map(|i| tuple[*i].clone()).collect();
+                    (function.0)(&arguments)
+                };
+
+                result.map(|value| {
+                    tuple.push(value);
+                    tuple
+                })
+            }),
         };
 
         (Implemented::Collection(transformed), shutdown_handle)
@@ -7,13 +7,16 @@ use timely::progress::Timestamp;
 use differential_dataflow::lattice::Lattice;
 use differential_dataflow::operators::Threshold;
 
-use crate::binding::Binding;
+use crate::binding::{AsBinding, Binding};
 use crate::plan::{Dependencies, ImplContext, Implementable};
-use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, Var, VariableMap};
-
-/// A plan stage taking the union over its sources. Frontends are
-/// responsible to ensure that the sources are union-compatible
-/// (i.e. bind all of the same variables in the same order).
+use crate::timestamp::TimestampValue;
+use crate::{CollectionRelation, Implemented, Relation, ShutdownHandle, Value, Var, VariableMap};
+
+/// A plan stage taking the union over its sources. Sources must be
+/// union-compatible, i.e. each of them must bind (at least) all of
+/// the specified variables, onto which they are projected. This is
+/// checked when implementing the union, with a panic identifying the
+/// offending source and variable.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub struct Union<P: Implementable> {
     /// TODO
@@ -33,10 +36,10 @@ impl<P: Implementable> Implementable for Union<P> {
         dependencies
     }
 
-    fn into_bindings(&self) -> Vec<Binding> {
+    fn into_bindings_with(&self, gensym: &mut dyn FnMut() -> Var) -> Vec<Binding> {
         self.plans
             .iter()
-            .flat_map(Implementable::into_bindings)
+            .flat_map(|plan| plan.into_bindings_with(gensym))
             .collect()
     }
 
@@ -47,23 +50,81 @@ impl<P: Implementable> Implementable for Union<P> {
         context: &mut I,
     ) -> (Implemented<'b, S>, ShutdownHandle)
     where
-        T: Timestamp + Lattice,
+        T: Timestamp + Lattice + TimestampValue,
+        I: ImplContext<T>,
+        S: Scope<Timestamp = T>,
+    {
+        let mut scope = nested.clone();
+
+        let relations = self
+            .plans
+            .iter()
+            .map(|plan| plan.implement(&mut scope, local_arrangements, context))
+            .enumerate()
+            .collect();
+
+        concatenate(nested, context, &self.variables, relations)
+    }
+}
+
+/// A plan stage taking the union over its sources, like `Union`, but
+/// additionally binding the `tag` variable to the index of the source
+/// each tuple came from (as a `Value::Number`). Output tuples are of
+/// the form `[tag, ...variables]`. Duplicates are removed per source,
+/// s.t. a tuple produced by several sources is reported once for each
+/// of them. The tag variable must be fresh, i.e. not be bound by any
+/// of the sources. Sources must be union-compatible, as for `Union`.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct UnionTagged<P: Implementable> {
+    /// Variable bound to the source index.
+    pub tag: Var,
+    /// Variables bound by every source.
+    pub variables: Vec<Var>,
+    /// Plans for the data sources.
+    pub plans: Vec<P>,
+}
+
+impl<P: Implementable> Implementable for UnionTagged<P> {
+    fn dependencies(&self) -> Dependencies {
+        let mut dependencies = Dependencies::none();
+
+        for plan in self.plans.iter() {
+            dependencies = Dependencies::merge(dependencies, plan.dependencies());
+        }
+
+        dependencies
+    }
+
+    fn implement<'b, T, I, S>(
+        &self,
+        nested: &mut Iterative<'b, S, u64>,
+        local_arrangements: &VariableMap<Iterative<'b, S, u64>>,
+        context: &mut I,
+    ) -> (Implemented<'b, S>, ShutdownHandle)
+    where
+        T: Timestamp + Lattice + TimestampValue,
         I: ImplContext<T>,
         S: Scope<Timestamp = T>,
     {
         use differential_dataflow::AsCollection;
         use timely::dataflow::operators::Concatenate;
 
+        if self.variables.contains(&self.tag) {
+            panic!("Tag variable {} must not be bound by any source.", self.tag);
+        }
+
         let mut scope = nested.clone();
         let mut shutdown_handle = ShutdownHandle::empty();
 
-        let streams = self.plans.iter().map(|plan| {
+        let streams = self.plans.iter().enumerate().map(|(index, plan)| {
             let relation = {
                 let (relation, shutdown) = plan.implement(&mut scope, local_arrangements, context);
                 shutdown_handle.merge_with(shutdown);
                 relation
             };
 
+            check_compatible(index, &relation, &self.variables);
+
             let projected = {
                 let (projected, shutdown) =
                     relation.projected(&mut scope, context, &self.variables);
@@ -71,16 +132,85 @@ impl<P: Implementable> Implementable for Union<P> {
                 projected
             };
 
-            projected.inner
+            let tag = Value::Number(index as i64);
+
+            projected
+                .map(move |tuple| std::iter::once(tag.clone()).chain(tuple).collect())
+                .inner
         });
 
         let concat = nested.concatenate(streams).as_collection();
 
+        let variables = std::iter::once(self.tag)
+            .chain(self.variables.iter().cloned())
+            .collect();
+
         let concatenated = CollectionRelation {
-            variables: self.variables.to_vec(),
+            variables,
             tuples: concat.distinct(),
         };
 
         (Implemented::Collection(concatenated), shutdown_handle)
     }
 }
+
+/// Ensures that the relation implementing the source at the given
+/// index binds all of the specified variables.
+fn check_compatible<'b, T, S>(index: usize, relation: &Implemented<'b, S>, variables: &[Var])
+where
+    T: Timestamp + Lattice,
+    S: Scope<Timestamp = T>,
+{
+    let bound = relation.variables();
+
+    if let Some(missing) = variables.iter().find(|x| !bound.contains(x)) {
+        panic!(
+            "Source {} of the union over {:?} doesn't bind variable {}, only {:?}.",
+            index, variables, missing, bound
+        );
+    }
+}
+
+/// Concatenates the given relations, after projecting each of them
+/// onto the specified variables, and removes duplicates. Relations
+/// are tagged with the index of the source they implement, for
+/// reporting sources that aren't union-compatible.
+pub(crate) fn concatenate<'b, T, I, S>(
+    nested: &mut Iterative<'b, S, u64>,
+    context: &mut I,
+    variables: &[Var],
+    relations: Vec<(usize, (Implemented<'b, S>, ShutdownHandle))>,
+) -> (Implemented<'b, S>, ShutdownHandle)
+where
+    T: Timestamp + Lattice + TimestampValue,
+    I: ImplContext<T>,
+    S: Scope<Timestamp = T>,
+{
+    use differential_dataflow::AsCollection;
+    use timely::dataflow::operators::Concatenate;
+
+    let mut scope = nested.clone();
+    let mut shutdown_handle = ShutdownHandle::empty();
+
+    let streams = relations.into_iter().map(|(index, (relation, shutdown))| {
+        shutdown_handle.merge_with(shutdown);
+        check_compatible(index, &relation, variables);
+
+        let projected = {
+            let (projected, shutdown) = relation.projected(&mut scope, context, variables);
+            shutdown_handle.merge_with(shutdown);
+            projected
+        };
+
+        projected.inner
+    });
+
+    let concat = nested.concatenate(streams).as_collection();
+
+    let concatenated = CollectionRelation {
+        variables: variables.to_vec(),
+        tuples: concat.distinct(),
+    };
+
+    (Implemented::Collection(concatenated), shutdown_handle)
+}
@@ -4,30 +4,49 @@ use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver};
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use timely::communication::Allocate;
+use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::capture::event::link::EventLink;
+use timely::dataflow::operators::{Filter, Operator};
 use timely::dataflow::{ProbeHandle, Scope};
 use timely::logging::{BatchLogger, TimelyEvent};
 use timely::progress::Timestamp;
 use timely::worker::Worker;
+use timely::PartialOrder;
 
 use differential_dataflow::collection::Collection;
 use differential_dataflow::lattice::Lattice;
 use differential_dataflow::logging::DifferentialEvent;
+use differential_dataflow::operators::arrange::{Arrange, ArrangeBySelf, Arranged};
+use differential_dataflow::operators::Consolidate;
+use differential_dataflow::AsCollection;
 
 use crate::domain::Domain;
+use crate::interning::SymbolTable;
 use crate::logging::DeclarativeEvent;
-use crate::plan::ImplContext;
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsSink;
+use crate::plan::{ImplContext, Implementable, Project};
 use crate::scheduling::Scheduler;
 use crate::sinks::Sink;
 use crate::sources::{Source, Sourceable, SourcingContext};
 use crate::Rule;
-use crate::{implement, implement_neu, AttributeConfig, RelationHandle, ShutdownHandle};
-use crate::{Aid, Error, Rewind, Time, TxData, Value};
+use crate::{check_stratification, collect_dependencies, implement, implement_neu, q};
+use crate::{Aid, Eid, Error, Plan, Rewind, Time, TimestampValue, TxData, Value, Var};
+use crate::{AttributeConfig, ShutdownHandle};
+use crate::{CollectionRelation, Relation};
+use crate::{RelationConfig, RelationHandle};
 use crate::{TraceKeyHandle, TraceValHandle};
 
+pub mod schema;
+
+pub use self::schema::Schema;
+
 /// Server configuration.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Configuration {
@@ -39,6 +58,12 @@ pub struct Configuration {
     pub enable_logging: bool,
     /// Should queries use the optimizer during implementation?
     pub enable_optimizer: bool,
+    /// Should query results be consolidated before they are handed
+    /// out? This merges all updates to the same tuple at the same
+    /// time, s.t. consumers don't see changes cancelling each other
+    /// out. Multiplicities are preserved.
+    #[serde(default)]
+    pub consolidate_results: bool,
 }
 
 impl Default for Configuration {
@@ -48,6 +73,7 @@ impl Default for Configuration {
             manual_advance: false,
             enable_logging: false,
             enable_optimizer: false,
+            consolidate_results: false,
         }
     }
 }
@@ -72,6 +98,11 @@ impl Configuration {
         );
         opts.optflag("", "enable-logging", "enable log event sources");
         opts.optflag("", "enable-optimizer", "enable WCO queries");
+        opts.optflag(
+            "",
+            "consolidate-results",
+            "consolidate query results before handing them out",
+        );
         opts.optflag("", "enable-meta", "enable queries on the query graph");
 
         opts
@@ -93,6 +124,7 @@ impl Configuration {
             manual_advance: matches.opt_present("manual-advance"),
             enable_logging: matches.opt_present("enable-logging"),
             enable_optimizer: matches.opt_present("enable-optimizer"),
+            consolidate_results: matches.opt_present("consolidate-results"),
         }
     }
 }
@@ -112,6 +144,30 @@ pub struct Interest {
     pub sink: Option<Sink>,
     /// Whether or not to log events from this dataflow.
     pub disable_logging: Option<bool>,
+    /// An optional limit on how long the dataflow may lag behind its
+    /// inputs, before it is torn down.
+    pub budget: Option<Budget>,
+}
+
+/// Limits on the resources a query may consume while lagging behind
+/// its inputs. Budgets are reset whenever the query catches up, s.t.
+/// they only affect queries that are stuck (e.g. pathological plans
+/// running the worker hot indefinitely).
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub enum Budget {
+    /// Maximum wall-clock time spent lagging behind.
+    Deadline(Duration),
+    /// Maximum number of consecutive budget checks (usually one per
+    /// iteration of the step loop) spent lagging behind.
+    Steps(usize),
+}
+
+/// Bookkeeping for a query subject to a budget.
+struct Budgeted<T: Timestamp> {
+    budget: Budget,
+    since: Instant,
+    steps: usize,
+    probe: ProbeHandle<T>,
 }
 
 impl std::convert::From<&Interest> for crate::sinks::SinkingContext {
@@ -181,6 +237,17 @@ pub enum Request {
     Shutdown,
 }
 
+/// Events delivered to subscribers, see `Server::subscribe`.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum SubscriptionEvent<T> {
+    /// Changes to the results of the subscribed rule.
+    Batch(Vec<(Vec<Value>, T, isize)>),
+    /// The results are complete for all times not greater or equal
+    /// to one of the times in the new frontier. An empty frontier
+    /// indicates that no further changes will arrive.
+    FrontierAdvanced(Vec<T>),
+}
+
 /// Server context maintaining globally registered arrangements and
 /// input handles.
 pub struct Server<T, Token>
@@ -199,6 +266,11 @@ where
     pub interests: HashMap<String, HashSet<Token>>,
     // Mapping from query names to their shutdown handles.
     shutdown_handles: HashMap<String, ShutdownHandle>,
+    // Mapping from query names to the budgets they are subject to.
+    budgets: HashMap<String, Budgeted<T>>,
+    // Mapping from prepared rules to their results, arranged by the
+    // parameters they were derived from.
+    prepared: HashMap<String, TraceValHandle<Vec<Value>, Vec<Value>, T, isize>>,
     /// Probe keeping track of overall dataflow progress.
     pub probe: ProbeHandle<T>,
     /// Scheduler managing deferred operator activations.
@@ -207,6 +279,11 @@ where
     timely_events: Option<Rc<EventLink<Duration, (Duration, usize, TimelyEvent)>>>,
     // Link to replayable Differential logging events.
     differential_events: Option<Rc<EventLink<Duration, (Duration, usize, DifferentialEvent)>>>,
+    /// Symbol table used by sources to intern strings. Symbols are
+    /// derived from the strings themselves, s.t. all workers agree on
+    /// them. Defaults to the process-wide table, which is also used
+    /// to resolve symbols on output.
+    pub symbols: SymbolTable,
 }
 
 /// Implementation context.
@@ -220,6 +297,15 @@ where
     pub underconstrained: HashSet<Aid>,
     /// Internal domain of command sequence numbers.
     pub internal: Domain<T>,
+    /// Attributes plans may access. All attributes are accessible
+    /// if none are specified.
+    pub allowed_attributes: Option<HashSet<Aid>>,
+    /// Sink to report the throughput of plan stages to.
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<Arc<dyn MetricsSink>>,
+    // Next symbol to hand out, counting down s.t. generated
+    // variables don't clash with user-specified ones.
+    next_sym: Var,
 }
 
 impl<T> ImplContext<T> for Context<T>
@@ -230,6 +316,20 @@ where
         self.rules.get(name)
     }
 
+    fn rules(&self) -> Vec<&Rule> {
+        let mut rules: Vec<&Rule> = self.rules.values().collect();
+        rules.sort_by(|x, y| x.name.cmp(&y.name));
+
+        rules
+    }
+
+    fn attributes(&self) -> Vec<Aid> {
+        let mut attributes: Vec<Aid> = self.internal.attributes.keys().cloned().collect();
+        attributes.sort();
+
+        attributes
+    }
+
     fn global_arrangement(&mut self, name: &str) -> Option<&mut RelationHandle<T>> {
         self.internal.arrangements.get_mut(name)
     }
@@ -238,6 +338,32 @@ where
         self.internal.attributes.contains_key(name)
     }
 
+    fn is_attribute_allowed(&self, name: &str) -> bool {
+        match self.allowed_attributes {
+            None => true,
+            Some(ref allowed) => allowed.contains(name),
+        }
+    }
+
+    fn has_relation(&self, name: &str) -> bool {
+        self.internal.arrangements.contains_key(name)
+    }
+
+    fn rename_attribute(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        if self.rules.contains_key(to) {
+            return Err(Error::conflict(format!(
+                "A rule of name {} already exists.",
+                to
+            )));
+        }
+
+        self.internal.rename_attribute(from, to)
+    }
+
+    fn renamed_attribute(&self, name: &str) -> Option<Aid> {
+        self.internal.renamed_to(name).cloned()
+    }
+
     fn forward_count(&mut self, name: &str) -> Option<&mut TraceKeyHandle<Value, T, isize>> {
         self.internal.forward_count.get_mut(name)
     }
@@ -278,11 +404,22 @@ where
         // self.underconstrained.contains(name)
         true
     }
+
+    fn gensym(&mut self) -> Var {
+        let sym = self.next_sym;
+        self.next_sym -= 1;
+        sym
+    }
+
+    #[cfg(feature = "metrics")]
+    fn metrics_sink(&self) -> Option<Arc<dyn MetricsSink>> {
+        self.metrics.clone()
+    }
 }
 
 impl<T, Token> Server<T, Token>
 where
-    T: Timestamp + Lattice + Default + Rewind,
+    T: Timestamp + Lattice + Default + Rewind + TimestampValue,
     Token: Hash + Eq + Copy,
 {
     /// Creates a new server state from a configuration.
@@ -306,13 +443,20 @@ where
                 rules: HashMap::new(),
                 internal: Domain::new(Default::default()),
                 underconstrained: HashSet::new(),
+                allowed_attributes: None,
+                #[cfg(feature = "metrics")]
+                metrics: None,
+                next_sym: std::u32::MAX,
             },
             interests: HashMap::new(),
             shutdown_handles: HashMap::new(),
+            budgets: HashMap::new(),
+            prepared: HashMap::new(),
             scheduler: Rc::new(RefCell::new(Scheduler::from(probe.clone()))),
             probe,
             timely_events,
             differential_events,
+            symbols: SymbolTable::global(),
         }
     }
 
@@ -339,6 +483,7 @@ where
     fn shutdown_query(&mut self, name: &str) {
         info!("Shutting down {}", name);
         self.shutdown_handles.remove(name);
+        self.budgets.remove(name);
     }
 
     /// Handles a Transact request.
@@ -356,11 +501,45 @@ where
         }
     }
 
-    /// Handles an Interest request.
+    /// Transacts a batch of changes across multiple attributes
+    /// atomically at the specified time. See `Domain::transact_at`.
+    pub fn transact_at(
+        &mut self,
+        changes: Vec<(Aid, Eid, Value, isize)>,
+        time: T,
+        owner: usize,
+        worker_index: usize,
+    ) -> Result<(), Error> {
+        // only the owner should actually introduce new inputs
+        if owner == worker_index {
+            self.context.internal.transact_at(changes, time)
+        } else {
+            self.context.internal.advance_epoch(time)
+        }
+    }
+
+    /// Handles an Interest request. Results are consolidated if the
+    /// server is configured to do so.
     pub fn interest<S: Scope<Timestamp = T>>(
         &mut self,
         name: &str,
         scope: &mut S,
+    ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
+        let relation = self.interest_raw(name, scope)?;
+
+        if self.config.consolidate_results {
+            Ok(relation.consolidate())
+        } else {
+            Ok(relation)
+        }
+    }
+
+    /// Implements the named relation, if necessary, and imports it
+    /// into the given scope.
+    fn interest_raw<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        scope: &mut S,
     ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
         // We need to do a `contains_key` here to avoid taking
         // a mut ref on context.
@@ -375,6 +554,8 @@ where
 
             Ok(relation)
         } else {
+            self.create_reverse_indices(name, scope)?;
+
             let (mut rel_map, shutdown_handle) = if self.config.enable_optimizer {
                 implement_neu(name, scope, &mut self.context)?
             } else {
@@ -402,10 +583,318 @@ where
         }
     }
 
+    /// Installs reverse indices for all attributes that the named
+    /// rule and its dependencies need indexed by value, but were
+    /// created with forward indices only. Reverse indices are thus
+    /// only paid for once a query actually requires them.
+    fn create_reverse_indices<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        scope: &mut S,
+    ) -> Result<(), Error> {
+        let mut reverse_attributes = HashSet::new();
+
+        for rule in collect_dependencies(&self.context, &[name])?.iter() {
+            // With the optimizer enabled, all rules are implemented
+            // via Hector, which might need different indices.
+            let dependencies = if self.config.enable_optimizer {
+                let bindings = rule.plan.into_bindings_with(&mut || self.context.gensym());
+                q(rule.plan.variables(), bindings).dependencies()
+            } else {
+                rule.plan.dependencies()
+            };
+
+            reverse_attributes.extend(dependencies.reverse_attributes);
+        }
+
+        // Patterns against named relations don't need any indices.
+        for aid in reverse_attributes.iter() {
+            if !self.context.has_attribute(aid) {
+                continue;
+            }
+
+            self.context.internal.create_reverse_indices(aid, scope)?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles an Interest request for a prepared rule, i.e. one
+    /// binding parameters via `Plan::parameters`. All parameter sets
+    /// are served by a single dataflow, implemented on the first
+    /// request and arranged by the parameters each result was derived
+    /// from. The specified parameters are added to the rule's
+    /// parameter input, and the returned collection is restricted to
+    /// the results derived from them, with the parameter bindings
+    /// themselves projected away.
+    ///
+    /// Parameter sets are only introduced by the first worker, s.t.
+    /// each is bound exactly once, regardless of how many workers
+    /// handle the request.
+    pub fn interest_prepared<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        params: &[Value],
+        scope: &mut S,
+    ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
+        let parameters = match self.context.rules.get(name) {
+            None => return Err(Error::unknown_name(name)),
+            Some(rule) => match rule.plan.parameter_variables(name) {
+                None => {
+                    return Err(Error::incorrect(format!(
+                        "Rule {} doesn't bind any parameters.",
+                        name
+                    )));
+                }
+                Some(parameters) => parameters,
+            },
+        };
+
+        if parameters.len() != params.len() {
+            return Err(Error::incorrect(format!(
+                "Rule {} expects {} parameters, got {}.",
+                name,
+                parameters.len(),
+                params.len()
+            )));
+        }
+
+        if !self.prepared.contains_key(name) {
+            self.context.internal.create_parameter_input(name, scope);
+
+            let trace = self.interest_arranged(name, &parameters, scope)?;
+            self.prepared.insert(name.to_string(), trace);
+        }
+
+        if scope.index() == 0 {
+            self.context
+                .internal
+                .bind_parameters(name, params.to_vec())?;
+        }
+
+        let params = params.to_vec();
+        let relation = self
+            .prepared
+            .get_mut(name)
+            .unwrap()
+            .import_named(scope, name)
+            .filter(move |key, _tuple| *key == params)
+            .as_collection(|_key, tuple| tuple.clone());
+
+        Ok(relation)
+    }
+
+    /// Handles an Interest request, subjecting the resulting dataflow
+    /// to the specified budget (see `enforce_budgets`). Relations
+    /// that are already arranged are not implemented anew and are
+    /// thus not subject to any budget. Fails if the query is already
+    /// subject to a different budget.
+    pub fn interest_with_budget<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        scope: &mut S,
+        budget: Budget,
+    ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
+        if let Some(budgeted) = self.budgets.get(name) {
+            if budgeted.budget != budget {
+                return Err(Error::conflict(format!(
+                    "Query {} is already subject to budget {:?}.",
+                    name, budgeted.budget
+                )));
+            }
+
+            return self.interest(name, scope);
+        }
+
+        let relation = self.interest(name, scope)?;
+
+        if self.shutdown_handles.contains_key(name) {
+            let mut probe = ProbeHandle::new();
+            let relation = relation.probe_with(&mut probe);
+
+            self.budgets.insert(
+                name.to_string(),
+                Budgeted {
+                    budget,
+                    since: Instant::now(),
+                    steps: 0,
+                    probe,
+                },
+            );
+
+            Ok(relation)
+        } else {
+            Ok(relation)
+        }
+    }
+
+    /// Handles an Interest request, but arranges the resulting
+    /// tuples by the specified variables rather than returning them
+    /// as an unkeyed collection. The returned trace can be used for
+    /// direct lookups by key. Keys and values are laid out as
+    /// described for `Relation::tuples_by_variables`, with variables
+    /// referring to those bound by the rule's plan.
+    pub fn interest_arranged<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        arrange_by: &[Var],
+        scope: &mut S,
+    ) -> Result<TraceValHandle<Vec<Value>, Vec<Value>, T, isize>, Error> {
+        let variables = match self.context.rules.get(name) {
+            None => return Err(Error::unknown_name(name)),
+            Some(rule) => rule.plan.variables(),
+        };
+
+        if let Some(variable) = arrange_by.iter().find(|x| !variables.contains(x)) {
+            return Err(Error::incorrect(format!(
+                "Rule {} doesn't bind variable {}.",
+                name, variable
+            )));
+        }
+
+        let relation = self.interest(name, scope)?;
+        let context = &mut self.context;
+
+        let tuples = scope.iterative::<u64, _, _>(|nested| {
+            let relation = CollectionRelation::from_collection(variables, relation.enter(nested));
+            // Collections don't import anything, so there is nothing
+            // to shut down here.
+            let (tuples, _shutdown) = relation.tuples_by_variables(nested, context, arrange_by);
+
+            tuples.leave()
+        });
+
+        // Lookups are only meaningful once the arrangement has caught
+        // up, which we track like for any other result.
+        let arranged: Arranged<S, TraceValHandle<Vec<Value>, Vec<Value>, T, isize>> = tuples
+            .probe_with(&mut self.probe)
+            .arrange_named(&format!("ArrangedBy({})", name));
+
+        Ok(arranged.trace)
+    }
+
+    /// Checks all budgeted queries against their budgets, shutting
+    /// down those that have been lagging behind the domain for too
+    /// long. Meant to be called once per iteration of the step
+    /// loop. Returns the names of all queries that were shut down,
+    /// together with the clients that were interested in them.
+    pub fn enforce_budgets(&mut self) -> Vec<(String, HashSet<Token>)> {
+        let now = Instant::now();
+        let mut exhausted = Vec::new();
+
+        for (name, budgeted) in self.budgets.iter_mut() {
+            let domain = &self.context.internal;
+            let lagging = budgeted
+                .probe
+                .with_frontier(|frontier| domain.dominates(frontier));
+
+            if lagging {
+                budgeted.steps += 1;
+
+                let is_exhausted = match budgeted.budget {
+                    Budget::Deadline(deadline) => now.duration_since(budgeted.since) > deadline,
+                    Budget::Steps(steps) => budgeted.steps > steps,
+                };
+
+                if is_exhausted {
+                    exhausted.push(name.clone());
+                }
+            } else {
+                budgeted.since = now;
+                budgeted.steps = 0;
+            }
+        }
+
+        exhausted
+            .into_iter()
+            .map(|name| {
+                warn!("Query {} exceeded its budget", name);
+
+                self.shutdown_query(&name);
+                let clients = self.interests.remove(&name).unwrap_or_default();
+
+                (name, clients)
+            })
+            .collect()
+    }
+
+    /// Indicates interest in the specified rule as of the specified
+    /// logical time, i.e. only changes at times less than or equal to
+    /// `time` are revealed. Fails if traces have already been
+    /// compacted beyond `time`, as the corresponding history is no
+    /// longer available.
+    pub fn interest_as_of<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        scope: &mut S,
+        time: T,
+    ) -> Result<Collection<S, Vec<Value>, isize>, Error> {
+        let compacted = !self
+            .context
+            .internal
+            .compaction_frontier()
+            .iter()
+            .any(|t| t.less_equal(&time));
+
+        if compacted {
+            return Err(Error::not_found(format!(
+                "History of {} as of {:?} has already been compacted.",
+                name, time
+            )));
+        }
+
+        let relation = self
+            .interest(name, scope)?
+            .inner
+            .filter(move |(_tuple, t, _diff)| t.less_equal(&time))
+            .as_collection();
+
+        Ok(relation)
+    }
+
+    /// Registers an externally constructed collection of tuples as a
+    /// named relation, s.t. rules can refer to it via `NameExpr`
+    /// like to any other rule.
+    ///
+    /// In multi-worker settings this must happen on all workers,
+    /// before any of them implements a query referring to the name.
+    pub fn register_collection<S: Scope<Timestamp = T>>(
+        &mut self,
+        name: &str,
+        collection: &Collection<S, Vec<Value>, isize>,
+    ) -> Result<(), Error> {
+        if self.context.rules.contains_key(name) || self.context.has_relation(name) {
+            return Err(Error::conflict(format!(
+                "A relation of name {} already exists.",
+                name
+            )));
+        }
+
+        let arranged = collection.arrange_by_self();
+
+        self.context.internal.register_arrangement(
+            name.to_string(),
+            RelationConfig { trace_slack: None },
+            arranged.trace,
+        );
+
+        Ok(())
+    }
+
     /// Handles a Register request.
     pub fn register(&mut self, req: Register) -> Result<(), Error> {
         let Register { rules, .. } = req;
 
+        // Rules are only accepted if negation remains stratified
+        // across all rules known so far.
+        check_stratification(
+            self.context.rules.values().chain(
+                rules
+                    .iter()
+                    .filter(|rule| !self.context.rules.contains_key(&rule.name)),
+            ),
+        )?;
+
         for rule in rules.into_iter() {
             if self.context.rules.contains_key(&rule.name) {
                 // @TODO panic if hashes don't match
@@ -437,6 +926,54 @@ where
         self.context.internal.create_transactable_attribute(name, config, scope)
     }
 
+    /// Implements the specified plan and installs its results as a
+    /// new attribute, s.t. other rules can match against it. The
+    /// plan's bindings for `e_var` and `v_var` become the entity and
+    /// value of each datom. Which indices are maintained is governed
+    /// by the provided configuration, as for any other attribute.
+    pub fn materialize_as_attribute<S: Scope<Timestamp = T>>(
+        &mut self,
+        scope: &mut S,
+        plan: &Plan,
+        aid: &str,
+        e_var: Var,
+        v_var: Var,
+        config: AttributeConfig,
+    ) -> Result<(), Error> {
+        if self.context.has_attribute(aid) {
+            return Err(Error::conflict(format!(
+                "An attribute of name {} already exists.",
+                aid
+            )));
+        }
+
+        if self.context.rules.contains_key(aid) {
+            return Err(Error::conflict(format!(
+                "A rule of name {} already exists.",
+                aid
+            )));
+        }
+
+        self.register(Register {
+            rules: vec![Rule {
+                name: aid.to_string(),
+                plan: Plan::Project(Project {
+                    variables: vec![e_var, v_var],
+                    plan: Box::new(plan.clone()),
+                }),
+            }],
+            publish: vec![],
+        })?;
+
+        let pairs = self
+            .interest(aid, scope)?
+            .map(|tuple| (tuple[0].clone(), tuple[1].clone()));
+
+        self.context
+            .internal
+            .create_derived_attribute(aid, config, &pairs.inner)
+    }
+
     /// Returns a fresh sourcing context, useful for installing 3DF
     /// compatible sources manually.
     pub fn make_sourcing_context(&self) -> SourcingContext<T> {
@@ -446,6 +983,7 @@ where
             domain_probe: self.context.internal.domain_probe().clone(),
             timely_events: self.timely_events.clone().unwrap(),
             differential_events: self.differential_events.clone().unwrap(),
+            symbols: self.symbols.clone(),
         }
     }
 
@@ -536,6 +1074,53 @@ where
             .with_frontier(|out_frontier| self.context.internal.dominates(out_frontier))
     }
 
+    /// Registers the specified rule, indicates interest in it, and
+    /// forwards all changes to its results over a channel, one batch
+    /// at a time, as the dataflow makes progress. Whenever the
+    /// frontier of the results advances, this is forwarded as well,
+    /// after any batches at the times it closes, s.t. subscribers
+    /// can tell complete times from partial ones.
+    pub fn subscribe<S: Scope<Timestamp = T>>(
+        &mut self,
+        scope: &mut S,
+        rule: Rule,
+    ) -> Result<Receiver<SubscriptionEvent<T>>, Error> {
+        let name = rule.name.clone();
+
+        self.register(Register {
+            rules: vec![rule],
+            publish: vec![name.clone()],
+        })?;
+
+        let (send_results, results) = channel();
+        let mut frontier = vec![T::minimum()];
+
+        self.interest(&name, scope)?
+            .probe_with(&mut self.probe)
+            .inner
+            .sink(Pipeline, "Subscription", move |input| {
+                // The subscriber having gone away is not an error,
+                // we just stop forwarding.
+
+                input.for_each(|_time, data| {
+                    let mut batch = Vec::new();
+                    data.swap(&mut batch);
+
+                    send_results.send(SubscriptionEvent::Batch(batch)).ok();
+                });
+
+                let current = input.frontier().frontier().to_vec();
+                if current != frontier {
+                    frontier = current;
+                    send_results
+                        .send(SubscriptionEvent::FrontierAdvanced(frontier.clone()))
+                        .ok();
+                }
+            });
+
+        Ok(results)
+    }
+
     /// Helper for registering, publishing, and indicating interest in
     /// a single, named query. Used for testing.
     pub fn test_single<S: Scope<Timestamp = T>>(
@@ -0,0 +1,64 @@
+//! Declarative descriptions of the attributes and sources a server
+//! should provide, s.t. deployments can be bootstrapped from a single
+//! file rather than a sequence of requests.
+
+#[cfg(feature = "serde_json")]
+use std::io::Read;
+
+use timely::dataflow::Scope;
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+
+use crate::server::{CreateAttribute, Server};
+use crate::sources::{Source, Sourceable};
+use crate::{Error, Rewind, TimestampValue};
+
+/// Attributes and sources to install on a server. Attributes are
+/// described exactly as in `CreateAttribute` requests, including
+/// their input semantics (e.g. `LastWriteWins` for attributes of
+/// cardinality one) and value type hints.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Schema {
+    /// Attributes to create, in order.
+    #[serde(default)]
+    pub attributes: Vec<CreateAttribute>,
+    /// Sources to register, in order, after all attributes have been
+    /// created.
+    #[serde(default)]
+    pub sources: Vec<Source>,
+}
+
+impl Schema {
+    /// Parses a schema from its JSON representation.
+    #[cfg(feature = "serde_json")]
+    pub fn from_reader<R: Read>(reader: R) -> Result<Schema, Error> {
+        serde_json::from_reader(reader).map_err(Error::parse)
+    }
+
+    /// Creates all attributes and registers all sources described
+    /// by this schema. Stops at the first failure, e.g. if an
+    /// attribute of the same name already exists. Must be called
+    /// with the same schema on all workers.
+    pub fn install<T, Token, S>(
+        &self,
+        server: &mut Server<T, Token>,
+        scope: &mut S,
+    ) -> Result<(), Error>
+    where
+        T: Timestamp + Lattice + Default + Rewind + TimestampValue,
+        Token: std::hash::Hash + Eq + Copy,
+        S: Scope<Timestamp = T>,
+        Source: Sourceable<S>,
+    {
+        for attribute in self.attributes.iter() {
+            server.create_attribute(scope, &attribute.name, attribute.config.clone())?;
+        }
+
+        for source in self.sources.iter() {
+            server.register_source(Box::new(source.clone()), scope)?;
+        }
+
+        Ok(())
+    }
+}
@@ -167,9 +167,12 @@ where
     };
 
     // It's important to treat retractions first, otherwise we might
-    // dissoc the new value.
-    paths.sort_by_key(|(_path, _t, diff)| *diff);
-    paths.sort_by_key(|(_path, t, _diff)| t.clone());
+    // dissoc the new value. Paths are ordered within each time and
+    // diff as well, s.t. the result doesn't depend on the order in
+    // which they arrived.
+    paths.sort_by(|(x_path, x_t, x_diff), (y_path, y_t, y_diff)| {
+        (x_t, x_diff, x_path).cmp(&(y_t, y_diff, y_path))
+    });
 
     let mut changes: Vec<Vec<String>> = Vec::new();
 
@@ -0,0 +1,304 @@
+//! Operators to export the settled contents of a relation into
+//! local files.
+//!
+//! Unlike the other sinks, which forward result diffs as they come
+//! in, exports maintain the net state of a relation and update the
+//! target file whenever the input frontier has passed new updates.
+//! The file therefore always reflects the relation as of a complete
+//! time, with any retractions reconciled. As long as tuples are only
+//! added, new ones are appended to the file. Retractions require the
+//! file to be rewritten.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::generic::{Operator, OutputHandle};
+use timely::dataflow::operators::probe::Probe;
+use timely::dataflow::{ProbeHandle, Scope, Stream};
+
+use differential_dataflow::collection::Collection;
+use differential_dataflow::lattice::Lattice;
+
+use crate::{Error, Value};
+
+/// Net multiplicities of the tuples of a relation.
+type State = HashMap<Vec<Value>, isize>;
+
+/// Rows to be written to an export, in a deterministic order.
+enum Update<'a> {
+    /// Tuples were only added since the last update, these rows can
+    /// be appended to the file.
+    Append(Vec<&'a Vec<Value>>),
+    /// Tuples were retracted, the file has to be rewritten with these
+    /// rows, i.e. with the current contents of the relation.
+    Rewrite(Vec<&'a Vec<Value>>),
+}
+
+/// Applies an update to the file at `path`. `write` is called with
+/// the rows to write and a flag indicating whether the file starts
+/// out empty (e.g. s.t. headers can be written).
+fn export<F>(path: &str, update: Update, mut write: F) -> std::io::Result<()>
+where
+    F: FnMut(&mut BufWriter<File>, &[&Vec<Value>], bool) -> std::io::Result<()>,
+{
+    match update {
+        Update::Append(rows) => {
+            let mut writer = BufWriter::new(OpenOptions::new().append(true).open(path)?);
+            write(&mut writer, &rows, false)?;
+            writer.flush()
+        }
+        Update::Rewrite(rows) => replace(path, |file| write(file, &rows, true)),
+    }
+}
+
+/// Initializes the target file as an empty export, s.t.
+/// configuration errors surface when attaching rather than on the
+/// first update.
+fn initialize<F>(path: &str, write: F) -> Result<(), Error>
+where
+    F: FnMut(&mut BufWriter<File>, &[&Vec<Value>], bool) -> std::io::Result<()>,
+{
+    export(path, Update::Rewrite(Vec::new()), write)
+        .map_err(|error| Error::fault(format!("Failed to create {}: {}", path, error)))
+}
+
+/// Replaces the contents of the file at `path` with whatever `write`
+/// produces. Contents are written to a temporary file first, s.t.
+/// readers never observe a partially written export.
+fn replace<F>(path: &str, write: F) -> std::io::Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+{
+    let temporary = format!("{}.tmp", path);
+
+    {
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        write(&mut writer)?;
+        writer.flush()?;
+    }
+
+    fs::rename(&temporary, path)
+}
+
+/// Tuples with positive multiplicity, in a deterministic order, each
+/// repeated according to its multiplicity.
+fn rows(state: &State) -> Vec<&Vec<Value>> {
+    let mut rows: Vec<&Vec<Value>> = state
+        .iter()
+        .filter(|(_tuple, count)| **count > 0)
+        .flat_map(|(tuple, count)| std::iter::repeat(tuple).take(*count as usize))
+        .collect();
+
+    rows.sort();
+    rows
+}
+
+/// Gathers all updates on a single worker and folds them into the
+/// net state of the relation, once the input frontier has passed
+/// them. `flush` is called once per frontier advance that changed the
+/// state, with the rows to append or rewrite.
+fn settle<S, F>(
+    collection: &Collection<S, Vec<Value>, isize>,
+    name: &str,
+    mut flush: F,
+) -> ProbeHandle<S::Timestamp>
+where
+    S: Scope,
+    S::Timestamp: Lattice,
+    F: FnMut(Update) + 'static,
+{
+    let mut pending: Vec<(Vec<Value>, S::Timestamp, isize)> = Vec::new();
+    let mut state = State::new();
+    let mut buffer = Vec::new();
+
+    let is_exporter = collection.scope().index() == 0;
+
+    let settled: Stream<S, ()> =
+        collection
+            .inner
+            .unary_frontier(Exchange::new(|_| 0), name, move |_cap, _info| {
+                move |input, _output: &mut OutputHandle<_, (), _>| {
+                    input.for_each(|_time, data| {
+                        data.swap(&mut buffer);
+                        pending.extend(buffer.drain(..));
+                    });
+
+                    let frontier = input.frontier();
+                    let (ready, remaining): (Vec<_>, Vec<_>) = pending
+                        .drain(..)
+                        .partition(|(_tuple, time, _diff)| !frontier.less_equal(time));
+                    pending = remaining;
+
+                    let mut delta = State::new();
+                    for (tuple, _time, diff) in ready.into_iter() {
+                        *delta.entry(tuple).or_insert(0) += diff;
+                    }
+                    delta.retain(|_tuple, diff| *diff != 0);
+
+                    let is_growing = delta.values().all(|diff| *diff > 0);
+                    for (tuple, diff) in delta.iter() {
+                        *state.entry(tuple.clone()).or_insert(0) += diff;
+                    }
+                    state.retain(|_tuple, count| *count != 0);
+
+                    if is_exporter && !delta.is_empty() {
+                        if is_growing {
+                            flush(Update::Append(rows(&delta)));
+                        } else {
+                            flush(Update::Rewrite(rows(&state)));
+                        }
+                    }
+                }
+            });
+
+    settled.probe()
+}
+
+/// Exports a relation to a CSV file.
+#[cfg(feature = "csv-source")]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct CsvSink {
+    /// Path to a file on the local filesystem of the first worker.
+    pub path: String,
+    /// Should a header with the column names be written?
+    pub has_headers: bool,
+    /// Column delimiter to use.
+    pub delimiter: u8,
+    /// Column names and the offsets of the corresponding variables
+    /// in the relation's tuples, in the order they should be written.
+    pub columns: Vec<(String, usize)>,
+}
+
+#[cfg(feature = "csv-source")]
+impl CsvSink {
+    /// Attaches the sink to a collection of tuples. The returned
+    /// probe can be used to wait for the export to catch up.
+    pub fn attach<S>(
+        &self,
+        collection: &Collection<S, Vec<Value>, isize>,
+    ) -> Result<ProbeHandle<S::Timestamp>, Error>
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+    {
+        initialize(&self.path, |file, rows, is_empty| {
+            self.write(file, rows, is_empty)
+        })?;
+
+        let config = self.clone();
+
+        Ok(settle(collection, "CsvSink", move |update| {
+            let result = export(&config.path, update, |file, rows, is_empty| {
+                config.write(file, rows, is_empty)
+            });
+
+            if let Err(error) = result {
+                panic!("failed to export to {}: {}", config.path, error);
+            }
+        }))
+    }
+
+    /// Writes the specified rows, preceded by a header if requested
+    /// and the file starts out empty.
+    fn write(
+        &self,
+        file: &mut BufWriter<File>,
+        rows: &[&Vec<Value>],
+        is_empty: bool,
+    ) -> std::io::Result<()> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .from_writer(file);
+
+        if self.has_headers && is_empty {
+            writer.write_record(self.columns.iter().map(|(name, _offset)| name))?;
+        }
+
+        for tuple in rows.iter() {
+            writer.write_record(
+                self.columns
+                    .iter()
+                    .map(|(_name, offset)| field(&tuple[*offset])),
+            )?;
+        }
+
+        writer.flush()
+    }
+}
+
+/// Renders a value as a CSV field.
+#[cfg(feature = "csv-source")]
+fn field(value: &Value) -> String {
+    match *value {
+        Value::Aid(ref v) => v.to_string(),
+        Value::String(ref v) => v.to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::Number(v) => v.to_string(),
+        Value::UNumber(v) => v.to_string(),
+        Value::Rational32(ref v) => v.to_string(),
+        Value::Eid(v) => v.to_string(),
+        Value::Instant(v) => v.to_string(),
+        Value::Null => String::new(),
+        ref other => format!("{:?}", other),
+    }
+}
+
+/// Exports a relation to a newline-delimited JSON file, one object
+/// per tuple.
+#[cfg(feature = "serde_json")]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct JsonSink {
+    /// Path to a file on the local filesystem of the first worker.
+    pub path: String,
+    /// Keys and the offsets of the corresponding variables in the
+    /// relation's tuples.
+    pub columns: Vec<(String, usize)>,
+}
+
+#[cfg(feature = "serde_json")]
+impl JsonSink {
+    /// Attaches the sink to a collection of tuples. The returned
+    /// probe can be used to wait for the export to catch up.
+    pub fn attach<S>(
+        &self,
+        collection: &Collection<S, Vec<Value>, isize>,
+    ) -> Result<ProbeHandle<S::Timestamp>, Error>
+    where
+        S: Scope,
+        S::Timestamp: Lattice,
+    {
+        initialize(&self.path, |file, rows, _is_empty| self.write(file, rows))?;
+
+        let config = self.clone();
+
+        Ok(settle(collection, "JsonSink", move |update| {
+            let result = export(&config.path, update, |file, rows, _is_empty| {
+                config.write(file, rows)
+            });
+
+            if let Err(error) = result {
+                panic!("failed to export to {}: {}", config.path, error);
+            }
+        }))
+    }
+
+    /// Writes the specified rows, one object per line.
+    fn write(&self, file: &mut BufWriter<File>, rows: &[&Vec<Value>]) -> std::io::Result<()> {
+        for tuple in rows.iter() {
+            let mut object = serde_json::Map::new();
+            for (name, offset) in self.columns.iter() {
+                object.insert(
+                    name.clone(),
+                    serde_json::Value::from(tuple[*offset].clone()),
+                );
+            }
+
+            serde_json::to_writer(&mut *file, &object)?;
+            writeln!(file)?;
+        }
+
+        Ok(())
+    }
+}
@@ -24,6 +24,13 @@ pub mod assoc_in;
 #[cfg(feature = "serde_json")]
 pub use self::assoc_in::AssocIn;
 
+#[cfg(any(feature = "csv-source", feature = "serde_json"))]
+pub mod export;
+#[cfg(feature = "csv-source")]
+pub use self::export::CsvSink;
+#[cfg(feature = "serde_json")]
+pub use self::export::JsonSink;
+
 /// A struct encapsulating any state required to create sinks.
 pub struct SinkingContext {
     /// The name of the dataflow feeding this sink.
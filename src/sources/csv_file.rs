@@ -1,17 +1,131 @@
 //! Operator and utilities to source data from csv files.
 
+use std::collections::HashMap;
 use std::rc::Rc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
 use timely::dataflow::{Scope, Stream};
 
 // use chrono::DateTime;
 
-use crate::sources::{Sourceable, SourcingContext};
+use crate::interning::SymbolTable;
+use crate::sources::{coerce, Partitioner, Partitioning, Sourceable, SourcingContext};
 use crate::{Aid, Eid, Value};
 use crate::{AttributeConfig, InputSemantics};
 
+/// Policy for dealing with malformed records.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub enum OnError {
+    /// Ignore malformed records.
+    Skip,
+    /// Abort on the first malformed record.
+    Fail,
+    /// Introduce malformed records as (line, error) pairs into the
+    /// specified attribute, s.t. they can be inspected later on.
+    Collect(Aid),
+}
+
+impl Default for OnError {
+    fn default() -> Self {
+        OnError::Fail
+    }
+}
+
+/// A column holding a list of values, separated by `sep`. Each
+/// element is introduced as a separate fact, all of them about the
+/// entity of the record. Empty elements are skipped, s.t. empty cells
+/// produce no facts at all.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct MultiValue {
+    /// Column offset.
+    pub offset: usize,
+    /// Type of the individual elements.
+    pub inner: Value,
+    /// Separator between elements.
+    pub sep: char,
+}
+
+/// Time of the last modification and length of the file at the
+/// given path, if available. Modification times can be as coarse as
+/// a second, taking the length into account ensures that appends
+/// are noticed regardless.
+fn modified_at(path: &str) -> Option<(SystemTime, u64)> {
+    std::fs::metadata(path)
+        .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+        .ok()
+}
+
+/// Parses the entity id of a record.
+fn parse_eid(record: &csv::StringRecord, offset: usize) -> Result<Value, String> {
+    match record.get(offset) {
+        None => Err(format!("missing eid column {}", offset)),
+        Some(field) => field
+            .parse::<Eid>()
+            .map(Value::Eid)
+            .map_err(|error| format!("column {} is not a eid ({})", offset, error)),
+    }
+}
+
+/// Derives an entity id from several columns of a record, by hashing
+/// their contents with 64-bit FNV-1a. The hash is stable across
+/// workers, processes, and runs, s.t. the same key always maps to the
+/// same entity. Fields are separated by a zero byte, s.t. e.g. ("ab",
+/// "c") and ("a", "bc") don't collide trivially.
+fn composite_eid(record: &csv::StringRecord, columns: &[usize]) -> Result<Value, String> {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+
+    for offset in columns.iter() {
+        let field = record
+            .get(*offset)
+            .ok_or_else(|| format!("missing eid column {}", offset))?;
+
+        for byte in field.bytes().chain(std::iter::once(0)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    Ok(Value::Eid(hash as Eid))
+}
+
+/// Parses the columns of a record, as specified by the schema,
+/// followed by the elements of each multi-valued column.
+fn parse_values(
+    record: &csv::StringRecord,
+    schema: &[(Aid, (usize, Value))],
+    multi_valued: &[(Aid, MultiValue)],
+    symbols: &SymbolTable,
+) -> Result<Vec<Vec<Value>>, String> {
+    let field = |offset: usize| {
+        record
+            .get(offset)
+            .ok_or_else(|| format!("missing column {}", offset))
+    };
+
+    let single = schema.iter().map(|(_aid, (offset, type_hint))| {
+        coerce(field(*offset)?, type_hint, symbols)
+            .map(|value| vec![value])
+            .map_err(|error| format!("column {} is {}", offset, error))
+    });
+
+    let multi = multi_valued.iter().map(|(_aid, column)| {
+        field(column.offset)?
+            .split(column.sep)
+            .filter(|element| !element.is_empty())
+            .map(|element| {
+                coerce(element, &column.inner, symbols)
+                    .map_err(|error| format!("element of column {} is {}", column.offset, error))
+            })
+            .collect::<Result<Vec<Value>, String>>()
+    });
+
+    single.chain(multi).collect()
+}
+
 /// A local filesystem data source.
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
 pub struct CsvFile {
@@ -23,19 +137,65 @@ pub struct CsvFile {
     pub delimiter: u8,
     /// Comment variable to use.
     pub comment: Option<u8>,
+    /// Quote character to use. Defaults to `"`.
+    pub quote: Option<u8>,
+    /// Escape character to use. By default, quotes are escaped by
+    /// doubling them instead.
+    pub escape: Option<u8>,
+    /// Are doubled quotes interpreted as escaped quotes? Defaults to
+    /// true.
+    pub double_quote: Option<bool>,
     /// Allow flexible length records?
     pub flexible: bool,
     /// Special column offset for the entity id.
     pub eid_offset: usize,
+    /// Columns forming a composite entity key. If specified, entity
+    /// ids are derived by hashing the contents of these columns (in
+    /// order) and `eid_offset` is ignored.
+    pub eid_columns: Option<Vec<usize>>,
     /// Special column offset for the timestamp.
     pub timestamp_offset: Option<usize>,
     /// Specifies the column offsets and their value types, that
     /// should be introduced.
     pub schema: Vec<(Aid, (usize, Value))>,
-    /// Batch size.
+    /// Columns holding lists of values, each introduced into the
+    /// specified attribute element by element.
+    #[serde(default)]
+    pub multi_valued: Vec<(Aid, MultiValue)>,
+    /// Maximum number of records read per activation. Defaults to
+    /// 256.
     pub fuel: Option<usize>,
     /// Scheduling interval.
     pub interval: Option<Duration>,
+    /// Strategy for distributing records across workers. Defaults to
+    /// round-robin.
+    pub partitioning: Option<Partitioning>,
+    /// What to do about malformed records. Defaults to failing.
+    pub on_error: Option<OnError>,
+    /// Keep watching the file after it has been read completely? If
+    /// set, the file is polled for modifications (of its mtime or
+    /// length) at the scheduling interval and re-read whenever it
+    /// changes. Only the difference
+    /// to the previous contents is introduced, i.e. removed rows are
+    /// retracted and new ones added. Updates are held back until the
+    /// file has been read completely.
+    pub watch: bool,
+}
+
+impl CsvFile {
+    /// Opens a reader configured as specified.
+    fn reader(&self) -> csv::StringRecordsIntoIter<std::fs::File> {
+        csv::ReaderBuilder::new()
+            .has_headers(self.has_headers)
+            .delimiter(self.delimiter)
+            .comment(self.comment)
+            .quote(self.quote.unwrap_or(b'"'))
+            .escape(self.escape)
+            .double_quote(self.double_quote.unwrap_or(true))
+            .from_path(&self.path)
+            .expect("failed to create reader")
+            .into_records()
+    }
 }
 
 impl<S: Scope<Timestamp = Duration>> Sourceable<S> for CsvFile {
@@ -61,36 +221,58 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for CsvFile {
         // Order is very important here, because otherwise the
         // capabilities won't match up with the output streams later
         // on (when creating sessions). We stick to the order dictated
-        // by the schema.
-        let mut wrappers = Vec::with_capacity(self.schema.len());
-        let mut streams = Vec::with_capacity(self.schema.len());
+        // by the schema, followed by multi-valued columns.
+        let num_attributes = self.schema.len() + self.multi_valued.len();
+        let mut wrappers = Vec::with_capacity(num_attributes);
+        let mut streams = Vec::with_capacity(num_attributes);
+
+        for _ in 0..num_attributes {
+            let (wrapper, stream) = demux.new_output();
+            wrappers.push(wrapper);
+            streams.push(stream);
+        }
 
-        for _ in self.schema.iter() {
+        // Malformed records are collected into an additional output,
+        // following all attribute outputs.
+        let on_error = self.on_error.clone().unwrap_or_default();
+        if let OnError::Collect(_) = on_error {
             let (wrapper, stream) = demux.new_output();
             wrappers.push(wrapper);
             streams.push(stream);
         }
 
+        let policy = on_error.clone();
+
         demux.build(move |mut capabilities| {
             let activator = Rc::new(scope.activator_for(&operator_info.address[..]));
 
             let worker_index = scope.index();
-            // let num_workers = scope.peers();
-
-            let reader = csv::ReaderBuilder::new()
-                .has_headers(self.has_headers)
-                .delimiter(self.delimiter)
-                .comment(self.comment)
-                .from_path(&filename)
-                .expect("failed to create reader");
-
-            let mut iterator = reader.into_records();
+            let num_workers = scope.peers();
+
+            let config = self.clone();
+            let mut modified = modified_at(&filename);
+            let mut iterator = config.reader();
+
+            // When watching, tuples are counted per output instead of
+            // being introduced right away, s.t. each (re-)read can be
+            // reconciled against the previous one.
+            let num_outputs = wrappers.len();
+            let mut loaded: Vec<HashMap<(Value, Value), isize>> = vec![HashMap::new(); num_outputs];
+            let mut pending: Option<Vec<HashMap<(Value, Value), isize>>> = if self.watch {
+                Some(vec![HashMap::new(); num_outputs])
+            } else {
+                None
+            };
 
             let mut num_datums_read = 0;
             let mut datum_index = 0;
 
             let schema = self.schema.clone();
+            let multi_valued = self.multi_valued.clone();
             let eid_offset = self.eid_offset;
+            let eid_columns = self.eid_columns.clone();
+            let symbols = context.symbols.clone();
+            let partitioning = self.partitioning.clone().unwrap_or_default();
             // let timestamp_offset = self.timestamp_offset;
             let total_fuel: i64 = self.fuel.unwrap_or(256) as i64;
 
@@ -100,6 +282,34 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for CsvFile {
             let interval = self.interval.unwrap_or(Duration::from_secs(1));
 
             move |_frontiers| {
+                if pending.is_some() && iterator.reader().is_done() {
+                    let last_modified = modified_at(&filename);
+                    if last_modified.is_some() && last_modified != modified {
+                        info!("[W{}] {} changed, re-reading", worker_index, filename);
+
+                        modified = last_modified;
+                        iterator = config.reader();
+                        datum_index = 0;
+                    } else {
+                        // Nothing to do, but downstream operators
+                        // should still see time passing.
+                        let time = Instant::now().duration_since(t0);
+
+                        for cap in capabilities.iter_mut() {
+                            cap.downgrade(&time);
+                        }
+
+                        scheduler
+                            .upgrade()
+                            .unwrap()
+                            .borrow_mut()
+                            .realtime
+                            .schedule_after(interval, Rc::downgrade(&activator));
+
+                        return;
+                    }
+                }
+
                 if iterator.reader().is_done() {
                     info!(
                         "[W{}] read {} out of {} datums",
@@ -109,12 +319,12 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for CsvFile {
                 } else {
                     let mut fuel = total_fuel;
 
-                    let mut handles = Vec::with_capacity(schema.len());
+                    let mut handles = Vec::with_capacity(wrappers.len());
                     for wrapper in wrappers.iter_mut() {
                         handles.push(wrapper.activate());
                     }
 
-                    let mut sessions = Vec::with_capacity(schema.len());
+                    let mut sessions = Vec::with_capacity(wrappers.len());
                     for (idx, handle) in handles.iter_mut().enumerate() {
                         sessions.push(handle.session(&capabilities[idx]));
                     }
@@ -124,47 +334,89 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for CsvFile {
                     info!("Ingesting at {:?}", time);
 
                     while let Some(result) = iterator.next() {
-                        let record = result.expect("read error");
-
-                        // if datum_index % num_workers == worker_index {
-                        let eid = Value::Eid(record[eid_offset].parse::<Eid>().expect("not a eid"));
-                        // let time = match timestamp_offset {
-                        //     None => Default::default(),
-                        //     Some(timestamp_offset) => {
-                        //         let epoch =
-                        //             DateTime::parse_from_rfc3339(&record[timestamp_offset])
-                        //                 .expect("not a valid rfc3339 datetime")
-                        //                 .timestamp();
-
-                        //         if epoch >= 0 {
-                        //             epoch as u64
-                        //         } else {
-                        //             panic!("invalid epoch");
-                        //         }
-                        //     }
-                        // };
-
-                        for (idx, (_aid, (offset, type_hint))) in schema.iter().enumerate() {
-                            let v = match type_hint {
-                                Value::String(_) => Value::String(record[*offset].to_string()),
-                                Value::Number(_) => Value::Number(
-                                    record[*offset].parse::<i64>().expect("not a number"),
-                                ),
-                                Value::Eid(_) => {
-                                    Value::Eid(record[*offset].parse::<Eid>().expect("not a eid"))
+                        let (line, row) = match result {
+                            Err(error) => (
+                                error.position().map(|p| p.line()),
+                                Err(error.to_string()),
+                            ),
+                            Ok(record) => (
+                                record.position().map(|p| p.line()),
+                                match eid_columns {
+                                    None => parse_eid(&record, eid_offset),
+                                    Some(ref columns) => composite_eid(&record, columns),
+                                }
+                                .map(|eid| (record, eid)),
+                            ),
+                        };
+
+                        // Records that can't be attributed to an
+                        // entity are reported in round-robin fashion.
+                        let outcome = match row {
+                            Err(error) => {
+                                if datum_index % num_workers == worker_index {
+                                    Some(Err(error))
+                                } else {
+                                    None
+                                }
+                            }
+                            Ok((record, eid)) => {
+                                if partitioning.is_responsible(
+                                    worker_index,
+                                    num_workers,
+                                    datum_index,
+                                    &eid,
+                                ) {
+                                    Some(
+                                        parse_values(&record, &schema, &multi_valued, &symbols)
+                                            .map(|values| (eid, values)),
+                                    )
+                                } else {
+                                    None
+                                }
+                            }
+                        };
+
+                        match outcome {
+                            None => {}
+                            Some(Ok((eid, values))) => {
+                                for (idx, vs) in values.into_iter().enumerate() {
+                                    for v in vs.into_iter() {
+                                        let tuple = (eid.clone(), v);
+                                        match pending {
+                                            None => sessions[idx].give((tuple, time, 1)),
+                                            Some(ref mut pending) => {
+                                                *pending[idx].entry(tuple).or_insert(0) += 1
+                                            }
+                                        }
+                                    }
                                 }
-                                _ => panic!(
-                                    "Only String, Number, and Eid are supported at the moment."
-                                ),
-                            };
 
-                            let tuple = (eid.clone(), v);
-                            sessions[idx].give((tuple, time, 1));
+                                num_datums_read += 1;
+                            }
+                            Some(Err(error)) => {
+                                let line = line.unwrap_or(datum_index as u64 + 1);
+
+                                match policy {
+                                    OnError::Skip => {}
+                                    OnError::Fail => {
+                                        panic!("malformed record at line {}: {}", line, error)
+                                    }
+                                    OnError::Collect(_) => {
+                                        let tuple =
+                                            (Value::Number(line as i64), Value::String(error));
+                                        match pending {
+                                            None => sessions[num_attributes].give((tuple, time, 1)),
+                                            Some(ref mut pending) => {
+                                                *pending[num_attributes]
+                                                    .entry(tuple)
+                                                    .or_insert(0) += 1
+                                            }
+                                        }
+                                    }
+                                }
+                            }
                         }
 
-                        num_datums_read += 1;
-                        // }
-
                         datum_index += 1;
 
                         fuel -= 1;
@@ -178,6 +430,33 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for CsvFile {
                             "[W{}] read {} out of {} datums",
                             worker_index, num_datums_read, datum_index
                         );
+
+                        if let Some(ref mut pending) = pending {
+                            // Introduce the difference between this
+                            // read and the previous one.
+                            for (idx, (next, previous)) in
+                                pending.iter_mut().zip(loaded.iter_mut()).enumerate()
+                            {
+                                for (tuple, count) in next.iter() {
+                                    let diff = count - previous.get(tuple).cloned().unwrap_or(0);
+                                    if diff != 0 {
+                                        sessions[idx].give((tuple.clone(), time, diff));
+                                    }
+                                }
+
+                                for (tuple, count) in previous.iter() {
+                                    if !next.contains_key(tuple) {
+                                        sessions[idx].give((tuple.clone(), time, -count));
+                                    }
+                                }
+
+                                std::mem::swap(next, previous);
+                                next.clear();
+                            }
+                        }
+                    }
+
+                    if iterator.reader().is_done() && pending.is_none() {
                         capabilities.drain(..);
                     } else {
                         // Incorporate processing time in downgrade
@@ -203,12 +482,31 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for CsvFile {
 
         let mut out = Vec::with_capacity(streams.len());
         for (idx, stream) in streams.drain(..).enumerate() {
-            let aid = self.schema[idx].0.clone();
-            out.push((
-                aid.to_string(),
-                AttributeConfig::real_time(InputSemantics::Distinct),
-                stream,
-            ));
+            if idx < num_attributes {
+                // Attributes are declared with the type of their
+                // column, s.t. the domain checks values on ingest.
+                let (aid, value_type) = if idx < self.schema.len() {
+                    let (ref aid, (_offset, ref type_hint)) = self.schema[idx];
+                    (aid.clone(), type_hint.clone())
+                } else {
+                    let (ref aid, ref column) = self.multi_valued[idx - self.schema.len()];
+                    (aid.clone(), column.inner.clone())
+                };
+                out.push((
+                    aid.to_string(),
+                    AttributeConfig {
+                        value_type: Some(value_type),
+                        ..AttributeConfig::real_time(InputSemantics::Distinct)
+                    },
+                    stream,
+                ));
+            } else if let OnError::Collect(ref aid) = on_error {
+                out.push((
+                    aid.to_string(),
+                    AttributeConfig::real_time(InputSemantics::Raw),
+                    stream,
+                ));
+            }
         }
 
         out
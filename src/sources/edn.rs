@@ -0,0 +1,323 @@
+//! Decoding of EDN-encoded fact batches, as sent by clients of the
+//! reference 3DF implementation.
+//!
+//! A batch is a vector of datoms, each of the form
+//! `[e a v tx op]`. Entities are integers, attributes are keywords
+//! (e.g. `:person/name`) and transaction times are integers or
+//! `nil`. The operation is either one of the keywords `:db/add` and
+//! `:db/retract`, a boolean (Datomic's `added`), or an integer
+//! difference.
+//!
+//! Values are decoded as follows:
+//!
+//! * strings become `Value::String`
+//! * integers become `Value::Number`
+//! * ratios such as `1/3` become `Value::Rational32`
+//! * `true` and `false` become `Value::Bool`
+//! * keywords become `Value::Aid`
+//! * `nil` becomes `Value::Null`
+//! * `#uuid "..."` becomes `Value::Uuid` (requires the `uuid` feature)
+//!
+//! All other forms, such as floats, collections, or other tagged
+//! literals, are rejected.
+
+use crate::timestamp::Time;
+use crate::{Aid, Eid, Error, Rational32, TxData, Value};
+
+/// The subset of EDN forms relevant to datoms.
+#[derive(PartialEq, Clone, Debug)]
+enum Edn {
+    Nil,
+    Bool(bool),
+    Integer(i64),
+    Ratio(i64, i64),
+    String(String),
+    Keyword(String),
+    Symbol(String),
+    Vector(Vec<Edn>),
+    Tagged(String, Box<Edn>),
+}
+
+/// A recursive-descent reader over EDN text.
+struct Reader<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str) -> Self {
+        Reader { input, position: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.peek()?;
+        self.position += next.len_utf8();
+        Some(next)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, Error> {
+        Err(Error::parse(format!(
+            "{} at offset {}",
+            message, self.position
+        )))
+    }
+
+    /// Skips whitespace (including commas), comments, and discarded
+    /// forms.
+    fn skip_whitespace(&mut self) -> Result<(), Error> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() || c == ',' => {
+                    self.next();
+                }
+                Some(';') => {
+                    while let Some(c) = self.next() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                Some('#') if self.input[self.position..].starts_with("#_") => {
+                    self.position += 2;
+                    self.read()?;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Reads a token, i.e. everything up to the next delimiter.
+    fn token(&mut self) -> &'a str {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || c == ',' || "[](){}\";".contains(c) {
+                break;
+            }
+            self.next();
+        }
+
+        &self.input[start..self.position]
+    }
+
+    /// Reads the next form.
+    fn read(&mut self) -> Result<Edn, Error> {
+        self.skip_whitespace()?;
+
+        match self.peek() {
+            None => self.error("Unexpected end of input"),
+            Some('[') => {
+                self.next();
+                let mut elements = Vec::new();
+                loop {
+                    self.skip_whitespace()?;
+                    match self.peek() {
+                        None => return self.error("Unterminated vector"),
+                        Some(']') => {
+                            self.next();
+                            return Ok(Edn::Vector(elements));
+                        }
+                        Some(_) => elements.push(self.read()?),
+                    }
+                }
+            }
+            Some('"') => {
+                self.next();
+                self.string()
+            }
+            Some(':') => {
+                let token = self.token();
+                if token.len() < 2 {
+                    self.error("Empty keyword")
+                } else {
+                    Ok(Edn::Keyword(token.to_string()))
+                }
+            }
+            Some('#') => {
+                self.next();
+                let tag = self.token();
+                if tag.is_empty() {
+                    self.error("Unsupported dispatch")
+                } else {
+                    let form = self.read()?;
+                    Ok(Edn::Tagged(tag.to_string(), Box::new(form)))
+                }
+            }
+            Some(c) if "(){}]".contains(c) => self.error(&format!("Unsupported delimiter {}", c)),
+            Some(_) => {
+                let token = self.token();
+                self.atom(token)
+            }
+        }
+    }
+
+    /// Reads the remainder of a string literal.
+    fn string(&mut self) -> Result<Edn, Error> {
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                None => return self.error("Unterminated string"),
+                Some('"') => return Ok(Edn::String(string)),
+                Some('\\') => match self.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some('r') => string.push('\r'),
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    _ => return self.error("Unsupported escape sequence"),
+                },
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    /// Interprets a token as a scalar.
+    fn atom(&self, token: &str) -> Result<Edn, Error> {
+        match token {
+            "nil" => Ok(Edn::Nil),
+            "true" => Ok(Edn::Bool(true)),
+            "false" => Ok(Edn::Bool(false)),
+            _ => {
+                let numeric = token
+                    .trim_start_matches(|c| c == '+' || c == '-')
+                    .starts_with(|c: char| c.is_ascii_digit());
+
+                if !numeric {
+                    Ok(Edn::Symbol(token.to_string()))
+                } else if let Ok(integer) = token.parse::<i64>() {
+                    Ok(Edn::Integer(integer))
+                } else {
+                    let mut parts = token.splitn(2, '/');
+                    let numer = parts.next().and_then(|x| x.parse::<i64>().ok());
+                    let denom = parts.next().and_then(|x| x.parse::<i64>().ok());
+
+                    match (numer, denom) {
+                        (Some(numer), Some(denom)) if denom != 0 => Ok(Edn::Ratio(numer, denom)),
+                        _ => self.error(&format!("Unsupported number {}", token)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Converts a form into the value it represents.
+fn to_value(form: Edn) -> Result<Value, Error> {
+    match form {
+        Edn::Nil => Ok(Value::Null),
+        Edn::Bool(b) => Ok(Value::Bool(b)),
+        Edn::Integer(n) => Ok(Value::Number(n)),
+        Edn::Ratio(numer, denom) => {
+            if numer.abs() > i64::from(std::i32::MAX) || denom.abs() > i64::from(std::i32::MAX) {
+                Err(Error::parse(format!(
+                    "Ratio {}/{} out of range",
+                    numer, denom
+                )))
+            } else {
+                Ok(Value::Rational32(Rational32::new(
+                    numer as i32,
+                    denom as i32,
+                )))
+            }
+        }
+        Edn::String(s) => Ok(Value::String(s)),
+        Edn::Keyword(k) => Ok(Value::Aid(k)),
+        #[cfg(feature = "uuid")]
+        Edn::Tagged(ref tag, ref form) if tag == "uuid" => match **form {
+            Edn::String(ref s) => uuid::Uuid::parse_str(s)
+                .map(Value::Uuid)
+                .map_err(Error::parse),
+            _ => Err(Error::parse("#uuid expects a string")),
+        },
+        other => Err(Error::parse(format!("Unsupported value {:?}", other))),
+    }
+}
+
+/// Converts a single `[e a v tx op]` form into transaction data.
+fn to_tx_data(form: Edn) -> Result<TxData, Error> {
+    let mut elements = match form {
+        Edn::Vector(elements) => elements,
+        other => return Err(Error::parse(format!("Expected a datom, got {:?}", other))),
+    };
+
+    if elements.len() != 5 {
+        return Err(Error::parse(format!(
+            "Expected a datom [e a v tx op], got {} elements",
+            elements.len()
+        )));
+    }
+
+    let op = elements.pop().unwrap();
+    let tx = elements.pop().unwrap();
+    let v = elements.pop().unwrap();
+    let a = elements.pop().unwrap();
+    let e = elements.pop().unwrap();
+
+    let e: Eid = match e {
+        Edn::Integer(e) if e >= 0 => e as Eid,
+        other => {
+            return Err(Error::parse(format!(
+                "Expected an entity id, got {:?}",
+                other
+            )))
+        }
+    };
+
+    let a: Aid = match a {
+        Edn::Keyword(a) => a,
+        other => {
+            return Err(Error::parse(format!(
+                "Expected an attribute, got {:?}",
+                other
+            )))
+        }
+    };
+
+    let tx = match tx {
+        Edn::Nil => None,
+        Edn::Integer(tx) if tx >= 0 => Some(Time::TxId(tx as u64)),
+        other => {
+            return Err(Error::parse(format!(
+                "Expected a transaction, got {:?}",
+                other
+            )))
+        }
+    };
+
+    let diff = match op {
+        Edn::Bool(true) => 1,
+        Edn::Bool(false) => -1,
+        Edn::Integer(diff) => diff as isize,
+        Edn::Keyword(ref op) if op == ":db/add" => 1,
+        Edn::Keyword(ref op) if op == ":db/retract" => -1,
+        other => {
+            return Err(Error::parse(format!(
+                "Expected an operation, got {:?}",
+                other
+            )))
+        }
+    };
+
+    Ok(TxData(diff, Value::Eid(e), a, to_value(v)?, tx))
+}
+
+/// Decodes an EDN vector of `[e a v tx op]` datoms into transaction
+/// data, ready to be passed to `Server::transact`.
+pub fn parse_datoms(input: &str) -> Result<Vec<TxData>, Error> {
+    let mut reader = Reader::new(input);
+
+    let datoms = match reader.read()? {
+        Edn::Vector(datoms) => datoms,
+        other => return Err(Error::parse(format!("Expected a vector, got {:?}", other))),
+    };
+
+    reader.skip_whitespace()?;
+    if reader.peek().is_some() {
+        return reader.error("Unexpected trailing input");
+    }
+
+    datoms.into_iter().map(to_tx_data).collect()
+}
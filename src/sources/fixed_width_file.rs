@@ -0,0 +1,278 @@
+//! Operator and utilities to source data from fixed-width files.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use timely::dataflow::operators::generic::builder_rc::OperatorBuilder;
+use timely::dataflow::{Scope, Stream};
+
+use crate::interning::SymbolTable;
+use crate::sources::{coerce, Partitioner, Partitioning, Sourceable, SourcingContext};
+use crate::{Aid, Eid, Value};
+use crate::{AttributeConfig, InputSemantics};
+
+/// Strategies for determining the entity id of a record.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub enum EidStrategy {
+    /// Parse the entity id from the specified (start, end) byte
+    /// range.
+    Column((usize, usize)),
+    /// Use the (one-based) line number as entity id.
+    LineNumber,
+}
+
+/// Extracts the field at the specified (start, end) byte range of a
+/// line, optionally trimming padding whitespace.
+fn slice(line: &[u8], range: (usize, usize), trim: bool) -> Result<&str, String> {
+    let (start, end) = range;
+    let bytes = line
+        .get(start..end)
+        .ok_or_else(|| format!("missing column {}..{}", start, end))?;
+    let field = std::str::from_utf8(bytes)
+        .map_err(|error| format!("column {}..{} is not utf8 ({})", start, end, error))?;
+
+    if trim {
+        Ok(field.trim())
+    } else {
+        Ok(field)
+    }
+}
+
+/// Parses a single line, as specified by the eid strategy and the
+/// schema.
+fn parse_line(
+    line: &[u8],
+    line_number: usize,
+    eid_strategy: &EidStrategy,
+    schema: &[(Aid, ((usize, usize), Value))],
+    trim: bool,
+    symbols: &SymbolTable,
+) -> Result<(Value, Vec<Value>), String> {
+    let eid = match *eid_strategy {
+        EidStrategy::LineNumber => Value::Eid(line_number as Eid),
+        EidStrategy::Column(range) => {
+            let field = slice(line, range, true)?;
+            coerce(field, &Value::Eid(0), symbols)
+                .map_err(|error| format!("column {}..{} is {}", range.0, range.1, error))?
+        }
+    };
+
+    let values = schema
+        .iter()
+        .map(|(_aid, (range, type_hint))| {
+            let field = slice(line, *range, trim)?;
+            coerce(field, type_hint, symbols)
+                .map_err(|error| format!("column {}..{} is {}", range.0, range.1, error))
+        })
+        .collect::<Result<Vec<Value>, String>>()?;
+
+    Ok((eid, values))
+}
+
+/// A local filesystem data source, holding records whose columns are
+/// determined by byte offsets rather than delimiters, as is common
+/// for mainframe exports. Malformed records (e.g. lines too short for
+/// the schema) are logged and skipped.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct FixedWidthFile {
+    /// Path to a file on each workers local filesystem.
+    pub path: String,
+    /// Number of leading lines to skip (e.g. headers).
+    pub skip_lines: usize,
+    /// Strategy for determining the entity id of each record.
+    pub eid_strategy: EidStrategy,
+    /// Specifies the (start, end) byte ranges of columns and their
+    /// value types, that should be introduced.
+    pub schema: Vec<(Aid, ((usize, usize), Value))>,
+    /// Should padding whitespace be trimmed from fields?
+    pub trim: bool,
+    /// Maximum number of records read per activation. Defaults to
+    /// 256.
+    pub fuel: Option<usize>,
+    /// Scheduling interval.
+    pub interval: Option<Duration>,
+    /// Strategy for distributing records across workers. Defaults to
+    /// round-robin.
+    pub partitioning: Option<Partitioning>,
+}
+
+impl<S: Scope<Timestamp = Duration>> Sourceable<S> for FixedWidthFile {
+    fn source(
+        &self,
+        scope: &mut S,
+        context: SourcingContext<S::Timestamp>,
+    ) -> Vec<(
+        Aid,
+        AttributeConfig,
+        Stream<S, ((Value, Value), Duration, isize)>,
+    )> {
+        let filename = self.path.clone();
+
+        // See `CsvFile` for why we are using a builder directly.
+        let mut demux =
+            OperatorBuilder::new(format!("FixedWidthFile({})", filename), scope.clone());
+        let operator_info = demux.operator_info();
+        demux.set_notify(false);
+
+        let mut wrappers = Vec::with_capacity(self.schema.len());
+        let mut streams = Vec::with_capacity(self.schema.len());
+
+        for _ in self.schema.iter() {
+            let (wrapper, stream) = demux.new_output();
+            wrappers.push(wrapper);
+            streams.push(stream);
+        }
+
+        demux.build(move |mut capabilities| {
+            let activator = Rc::new(scope.activator_for(&operator_info.address[..]));
+
+            let worker_index = scope.index();
+            let num_workers = scope.peers();
+
+            let file = File::open(&filename).expect("failed to open file");
+            let mut iterator = BufReader::new(file).split(b'\n').skip(self.skip_lines);
+            let mut done = false;
+            let mut line_number = self.skip_lines;
+
+            let mut num_datums_read = 0;
+            let mut datum_index = 0;
+
+            let schema = self.schema.clone();
+            let eid_strategy = self.eid_strategy.clone();
+            let trim = self.trim;
+            let symbols = context.symbols.clone();
+            let partitioning = self.partitioning.clone().unwrap_or_default();
+            let total_fuel: i64 = self.fuel.unwrap_or(256) as i64;
+
+            // Grab scheduler handle for deferred re-activation.
+            let scheduler = context.scheduler;
+            let t0 = context.t0;
+            let interval = self.interval.unwrap_or(Duration::from_secs(1));
+
+            move |_frontiers| {
+                if done {
+                    capabilities.drain(..);
+                } else {
+                    let mut fuel = total_fuel;
+
+                    let mut handles = Vec::with_capacity(wrappers.len());
+                    for wrapper in wrappers.iter_mut() {
+                        handles.push(wrapper.activate());
+                    }
+
+                    let mut sessions = Vec::with_capacity(wrappers.len());
+                    for (idx, handle) in handles.iter_mut().enumerate() {
+                        sessions.push(handle.session(&capabilities[idx]));
+                    }
+
+                    let time = Instant::now().duration_since(t0);
+
+                    info!("Ingesting at {:?}", time);
+
+                    loop {
+                        match iterator.next() {
+                            None => {
+                                done = true;
+                                break;
+                            }
+                            Some(result) => {
+                                let mut line = result.expect("failed to read line");
+                                line_number += 1;
+
+                                if line.last() == Some(&b'\r') {
+                                    line.pop();
+                                }
+
+                                // Blank lines (e.g. at the end of the
+                                // file) don't count as records.
+                                if !line.is_empty() {
+                                    match parse_line(
+                                        &line,
+                                        line_number,
+                                        &eid_strategy,
+                                        &schema,
+                                        trim,
+                                        &symbols,
+                                    ) {
+                                        Err(error) => {
+                                            // Every worker reads every
+                                            // line, but only one of
+                                            // them should complain.
+                                            if datum_index % num_workers == worker_index {
+                                                warn!(
+                                                    "skipping malformed record at line {}: {}",
+                                                    line_number, error
+                                                );
+                                            }
+                                        }
+                                        Ok((eid, values)) => {
+                                            if partitioning.is_responsible(
+                                                worker_index,
+                                                num_workers,
+                                                datum_index,
+                                                &eid,
+                                            ) {
+                                                for (idx, v) in values.into_iter().enumerate() {
+                                                    let tuple = (eid.clone(), v);
+                                                    sessions[idx].give((tuple, time, 1));
+                                                }
+
+                                                num_datums_read += 1;
+                                            }
+                                        }
+                                    }
+
+                                    datum_index += 1;
+                                }
+                            }
+                        }
+
+                        fuel -= 1;
+                        if fuel <= 0 {
+                            break;
+                        }
+                    }
+
+                    if done {
+                        info!(
+                            "[W{}] read {} out of {} datums",
+                            worker_index, num_datums_read, datum_index
+                        );
+                        capabilities.drain(..);
+                    } else {
+                        // Incorporate processing time in downgrade
+                        let time = Instant::now().duration_since(t0);
+
+                        for cap in capabilities.iter_mut() {
+                            cap.downgrade(&time);
+                        }
+
+                        // Notify the server that we want to be scheduled again soon
+                        {
+                            scheduler
+                                .upgrade()
+                                .unwrap()
+                                .borrow_mut()
+                                .realtime
+                                .schedule_after(interval, Rc::downgrade(&activator))
+                        }
+                    }
+                }
+            }
+        });
+
+        let mut out = Vec::with_capacity(streams.len());
+        for (idx, stream) in streams.drain(..).enumerate() {
+            let aid = self.schema[idx].0.clone();
+            out.push((
+                aid.to_string(),
+                AttributeConfig::real_time(InputSemantics::Distinct),
+                stream,
+            ));
+        }
+
+        out
+    }
+}
@@ -0,0 +1,83 @@
+//! Source introducing facts from an in-process vector.
+
+use timely::dataflow::operators::ToStream;
+use timely::dataflow::{Scope, Stream};
+use timely::progress::Timestamp;
+
+use differential_dataflow::lattice::Lattice;
+
+use crate::sources::{Partitioner, Partitioning, Sourceable, SourcingContext};
+use crate::{Aid, Eid, Value};
+use crate::{AttributeConfig, InputSemantics};
+
+/// A source introducing a fixed set of facts, all of them at the
+/// initial timestamp. Attributes are created in the order in which
+/// they first appear amongst the facts. This is mostly useful for
+/// tests and small, derived inputs, that don't warrant a file.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub struct MemorySource {
+    /// Facts to introduce.
+    pub facts: Vec<(Eid, Aid, Value)>,
+    /// Strategy for distributing facts across workers. Defaults to
+    /// round-robin.
+    pub partitioning: Option<Partitioning>,
+}
+
+impl MemorySource {
+    /// Creates a source introducing the specified facts.
+    pub fn new(facts: Vec<(Eid, Aid, Value)>) -> Self {
+        MemorySource {
+            facts,
+            partitioning: None,
+        }
+    }
+}
+
+impl<S: Scope> Sourceable<S> for MemorySource
+where
+    S::Timestamp: Timestamp + Lattice,
+{
+    fn source(
+        &self,
+        scope: &mut S,
+        _context: SourcingContext<S::Timestamp>,
+    ) -> Vec<(
+        Aid,
+        AttributeConfig,
+        Stream<S, ((Value, Value), S::Timestamp, isize)>,
+    )> {
+        let worker_index = scope.index();
+        let num_workers = scope.peers();
+        let partitioning = self.partitioning.clone().unwrap_or_default();
+
+        let mut attributes: Vec<(Aid, Vec<((Value, Value), S::Timestamp, isize)>)> = Vec::new();
+
+        for (datum_index, (e, a, v)) in self.facts.iter().enumerate() {
+            let index = match attributes.iter().position(|(aid, _)| aid == a) {
+                Some(index) => index,
+                None => {
+                    attributes.push((a.clone(), Vec::new()));
+                    attributes.len() - 1
+                }
+            };
+
+            let eid = Value::Eid(*e);
+            if partitioning.is_responsible(worker_index, num_workers, datum_index, &eid) {
+                attributes[index]
+                    .1
+                    .push(((eid, v.clone()), S::Timestamp::minimum(), 1));
+            }
+        }
+
+        attributes
+            .drain(..)
+            .map(|(aid, updates)| {
+                (
+                    aid,
+                    AttributeConfig::uncompacted(InputSemantics::Distinct),
+                    updates.to_stream(scope),
+                )
+            })
+            .collect()
+    }
+}
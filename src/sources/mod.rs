@@ -11,22 +11,76 @@ use timely::progress::Timestamp;
 
 use differential_dataflow::lattice::Lattice;
 use differential_dataflow::logging::DifferentialEvent;
+use differential_dataflow::Hashable;
 
+use timely_sort::Unsigned;
+
+use crate::interning::SymbolTable;
 use crate::scheduling::Scheduler;
 use crate::AttributeConfig;
-use crate::{Aid, Value};
+use crate::{Aid, Eid, Rational32, Value};
 
 #[cfg(feature = "csv-source")]
 pub mod csv_file;
+#[cfg(feature = "edn")]
+pub mod edn;
 // pub mod declarative_logging;
 pub mod differential_logging;
+pub mod fixed_width_file;
+pub mod memory;
 // pub mod json_file;
 pub mod timely_logging;
 
 #[cfg(feature = "csv-source")]
-pub use self::csv_file::CsvFile;
+pub use self::csv_file::{CsvFile, MultiValue, OnError};
+pub use self::fixed_width_file::{EidStrategy, FixedWidthFile};
+pub use self::memory::MemorySource;
 // pub use self::json_file::JsonFile;
 
+/// Coerces a raw field into a value of the same type as the
+/// specified type hint.
+pub(crate) fn coerce(
+    field: &str,
+    type_hint: &Value,
+    symbols: &SymbolTable,
+) -> Result<Value, String> {
+    match type_hint {
+        Value::String(_) => Ok(Value::String(field.to_string())),
+        Value::Number(_) => field
+            .parse::<i64>()
+            .map(Value::Number)
+            .map_err(|error| format!("not a number ({})", error)),
+        Value::UNumber(_) => field
+            .parse::<u64>()
+            .map(Value::UNumber)
+            .map_err(|error| format!("not a number ({})", error)),
+        Value::Eid(_) => field
+            .parse::<Eid>()
+            .map(Value::Eid)
+            .map_err(|error| format!("not a eid ({})", error)),
+        Value::Rational32(_) => field
+            .parse::<Rational32>()
+            .map(Value::Rational32)
+            .map_err(|error| format!("not a rational ({})", error)),
+        Value::Bool(_) => {
+            if field.eq_ignore_ascii_case("true") {
+                Ok(Value::Bool(true))
+            } else if field.eq_ignore_ascii_case("false") {
+                Ok(Value::Bool(false))
+            } else {
+                Err(format!("not a bool ({})", field))
+            }
+        }
+        Value::Interned(_) => symbols
+            .intern(field)
+            .map(Value::Interned)
+            .map_err(|error| error.message),
+        _ => panic!(
+            "Only String, Interned, Number, UNumber, Rational32, Bool, and Eid are supported at the moment."
+        ),
+    }
+}
+
 /// A struct encapsulating any state required to create sources.
 pub struct SourcingContext<T: Timestamp> {
     /// The logical start of the computation, used by sources to
@@ -41,9 +95,65 @@ pub struct SourcingContext<T: Timestamp> {
     pub timely_events: Rc<EventLink<Duration, (Duration, usize, TimelyEvent)>>,
     /// A weak handle to Differential event link.
     pub differential_events: Rc<EventLink<Duration, (Duration, usize, DifferentialEvent)>>,
+    /// A handle to the symbol table into which strings are interned.
+    pub symbols: SymbolTable,
+}
+
+/// A strategy for distributing the records read by a source across
+/// workers.
+pub trait Partitioner {
+    /// Returns true iff the worker at `worker_index` (out of `peers`)
+    /// is responsible for introducing the record at `datum_index`,
+    /// which concerns the entity `eid`.
+    fn is_responsible(
+        &self,
+        worker_index: usize,
+        peers: usize,
+        datum_index: usize,
+        eid: &Value,
+    ) -> bool;
+}
+
+/// Built-in partitioning strategies.
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Serialize, Deserialize)]
+pub enum Partitioning {
+    /// Records are handed to workers in turn.
+    RoundRobin,
+    /// All records concerning the same entity are handed to the same
+    /// worker, s.t. facts about an entity end up colocated.
+    ByEid,
+}
+
+impl Default for Partitioning {
+    fn default() -> Self {
+        Partitioning::RoundRobin
+    }
+}
+
+impl Partitioner for Partitioning {
+    fn is_responsible(
+        &self,
+        worker_index: usize,
+        peers: usize,
+        datum_index: usize,
+        eid: &Value,
+    ) -> bool {
+        match *self {
+            Partitioning::RoundRobin => datum_index % peers == worker_index,
+            Partitioning::ByEid => eid.hashed().as_u64() % (peers as u64) == worker_index as u64,
+        }
+    }
 }
 
 /// An external data source that can provide Datoms.
+///
+/// The trait itself places no ordering requirements on timestamps
+/// beyond `Timestamp + Lattice`. Individual sources are free to pin
+/// the timestamp type they produce (e.g. the built-in sources all
+/// emit wall-clock `Duration`s relative to `SourcingContext::t0`). In
+/// order to feed such a source into a nested scope (e.g. one with
+/// `Product<Duration, u64>` timestamps), source it in the enclosing
+/// scope and `enter` the resulting streams.
 pub trait Sourceable<S>
 where
     S: Scope,
@@ -74,6 +184,10 @@ pub enum Source {
     /// CSV files
     #[cfg(feature = "csv-source")]
     CsvFile(CsvFile),
+    /// Files containing fixed-width records
+    FixedWidthFile(FixedWidthFile),
+    /// Facts held in memory
+    MemorySource(MemorySource),
     // /// Files containing json objects
     // JsonFile(JsonFile),
 }
@@ -95,6 +209,8 @@ impl<S: Scope<Timestamp = Duration>> Sourceable<S> for Source {
             // Source::DeclarativeLogging(ref source) => source.source(scope, context),
             #[cfg(feature = "csv-source")]
             Source::CsvFile(ref source) => source.source(scope, context),
+            Source::FixedWidthFile(ref source) => source.source(scope, context),
+            Source::MemorySource(ref source) => source.source(scope, context),
             _ => unimplemented!(),
         }
     }
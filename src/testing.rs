@@ -0,0 +1,95 @@
+//! Utilities for testing plans against fixture data.
+
+use std::collections::HashMap;
+use std::sync::mpsc::channel;
+
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Operator;
+
+use crate::plan::Implementable;
+use crate::server::Server;
+use crate::{Aid, Eid, Plan, Rule, TxData, Value};
+use crate::{AttributeConfig, IndexDirection, InputSemantics, QuerySupport};
+
+/// Runs the plan against the given facts and returns the resulting
+/// tuples once the computation has settled, sorted and with each
+/// tuple repeated according to its multiplicity.
+///
+/// Every attribute mentioned by the facts or the plan is created on
+/// a fresh, single-worker server, indexed in both directions and
+/// with support for worst-case optimal joins, s.t. any kind of plan
+/// can be implemented on top of it. Facts are transacted all at once,
+/// with raw input semantics. Plans may therefore only refer to
+/// attributes, not to other rules or relations.
+pub fn run_plan(facts: Vec<(Aid, Eid, Value)>, plan: &Plan) -> Vec<Vec<Value>> {
+    let plan = plan.clone();
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        let mut attributes: Vec<Aid> = facts.iter().map(|(a, _e, _v)| a.clone()).collect();
+        attributes.extend(plan.dependencies().attributes.into_iter());
+        attributes.sort();
+        attributes.dedup();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in attributes.iter() {
+                let config = AttributeConfig {
+                    input_semantics: InputSemantics::Raw,
+                    index_direction: IndexDirection::Both,
+                    query_support: QuerySupport::AdaptiveWCO,
+                    ..Default::default()
+                };
+
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(aid, config, scope)
+                    .unwrap();
+            }
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "run_plan".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _t, diff) in data.iter() {
+                            send_results.send((tuple.clone(), *diff)).unwrap();
+                        }
+                    });
+                });
+        });
+
+        let tx_data = facts
+            .into_iter()
+            .map(|(a, e, v)| TxData::add(e, &a, v))
+            .collect();
+
+        server.transact(tx_data, 0, 0).unwrap();
+        server.advance_domain(None, 1).unwrap();
+
+        worker.step_while(|| server.is_any_outdated());
+
+        let mut accumulated = HashMap::new();
+        for (tuple, diff) in results.try_iter() {
+            *accumulated.entry(tuple).or_insert(0) += diff;
+        }
+
+        let mut tuples = Vec::new();
+        for (tuple, count) in accumulated.into_iter() {
+            for _i in 0..count {
+                tuples.push(tuple.clone());
+            }
+        }
+
+        tuples.sort();
+        tuples
+    })
+}
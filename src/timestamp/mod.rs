@@ -2,6 +2,8 @@
 
 use std::time::Duration;
 
+use crate::Value;
+
 pub mod altneu;
 pub mod pair;
 
@@ -152,9 +154,40 @@ impl Coarsen for pair::Pair<Duration, u64> {
     }
 }
 
+/// Extension trait for timestamp types that can be encoded as values,
+/// s.t. they can be bound to variables.
+pub trait TimestampValue {
+    /// Encodes the timestamp as a value. Transaction ids become
+    /// unsigned numbers, real times become instants (in milliseconds
+    /// since the epoch). Returns `None` for timestamps without a
+    /// single natural encoding, e.g. bitemporal ones.
+    fn to_value(&self) -> Option<Value>;
+}
+
+impl TimestampValue for u64 {
+    fn to_value(&self) -> Option<Value> {
+        Some(Value::UNumber(*self))
+    }
+}
+
+impl TimestampValue for Duration {
+    fn to_value(&self) -> Option<Value> {
+        Some(Value::Instant(
+            self.as_secs() * 1000 + u64::from(self.subsec_millis()),
+        ))
+    }
+}
+
+impl TimestampValue for pair::Pair<Duration, u64> {
+    fn to_value(&self) -> Option<Value> {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Coarsen, Rewind};
+    use super::{Coarsen, Rewind, TimestampValue};
+    use crate::Value;
     use std::time::Duration;
 
     #[test]
@@ -187,4 +220,17 @@ mod tests {
             Duration::from_secs(20),
         );
     }
+
+    #[test]
+    fn test_to_value() {
+        assert_eq!((7 as u64).to_value(), Some(Value::UNumber(7)));
+        assert_eq!(
+            Duration::new(2, 5_000_000).to_value(),
+            Some(Value::Instant(2005))
+        );
+        assert_eq!(
+            super::pair::Pair::new(Duration::from_secs(2), 7).to_value(),
+            None
+        );
+    }
 }
@@ -7,7 +7,9 @@ use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::Operator;
 
 use declarative_dataflow::binding::Binding;
-use declarative_dataflow::plan::{Aggregate, AggregationFn, Implementable, Join, Project};
+use declarative_dataflow::plan::{
+    Aggregate, AggregateBy, AggregationFn, ComparatorFn, Function, Implementable, Join, Project,
+};
 use declarative_dataflow::server::Server;
 use declarative_dataflow::{Aid, Value};
 use declarative_dataflow::{AttributeConfig, InputSemantics, Plan, Rule, TxData};
@@ -129,17 +131,17 @@ fn count() {
     run_cases(vec![
         Case {
             description: "[:find (count ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![amount],
-                plan: Box::new(Plan::Project(Project {
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![amount],
+                Plan::Project(Project {
                     variables: vec![amount],
                     plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                })),
-                aggregation_fns: vec![AggregationFn::COUNT],
-                key_variables: vec![],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+                }),
+                vec![AggregationFn::COUNT],
+                vec![],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![(vec![Number(6)], 0, 1)]],
             // set-semantics
@@ -149,14 +151,14 @@ fn count() {
         },
         Case {
             description: "[:find ?e (count ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![e, amount],
-                plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                aggregation_fns: vec![AggregationFn::COUNT],
-                key_variables: vec![e],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![e, amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::COUNT],
+                vec![e],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![
                 (vec![Eid(1), Number(4)], 0, 1),
@@ -173,6 +175,151 @@ fn count() {
     ]);
 }
 
+#[test]
+fn global_count() {
+    let (e, amount) = (1, 2);
+
+    run_cases(vec![Case {
+        description: "[:find (count ?amount) :where [?e :amount ?amount]]",
+        plan: Plan::Aggregate(Aggregate {
+            default_on_empty: true,
+            ..Aggregate::new(
+                vec![amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::COUNT],
+                vec![],
+                vec![amount],
+                vec![],
+            )
+        }),
+        transactions: vec![
+            vec![
+                TxData::add(1, ":amount", Number(5)),
+                TxData::add(2, ":amount", Number(10)),
+            ],
+            vec![TxData::add(3, ":amount", Number(7))],
+            vec![
+                TxData::retract(1, ":amount", Number(5)),
+                TxData::retract(2, ":amount", Number(10)),
+                TxData::retract(3, ":amount", Number(7)),
+            ],
+        ],
+        expectations: vec![
+            vec![(vec![Number(2)], 0, 1)],
+            vec![(vec![Number(2)], 1, -1), (vec![Number(3)], 1, 1)],
+            // An empty source has no groups at all.
+            vec![(vec![Number(3)], 2, -1)],
+        ],
+    }]);
+}
+
+#[test]
+fn count_rows() {
+    let (e, amount, count) = (1, 2, 3);
+
+    run_cases(vec![Case {
+        description: "[:find ?e (count-rows) :where [?e :amount ?amount]]",
+        plan: Plan::Aggregate(Aggregate::new(
+            vec![e, count],
+            Plan::MatchA(e, ":amount".to_string(), amount),
+            vec![AggregationFn::CountRows],
+            vec![e],
+            vec![count],
+            vec![],
+        )),
+        transactions: vec![
+            vec![
+                TxData::add(1, ":amount", Number(5)),
+                TxData::add(1, ":amount", Number(2)),
+                TxData::add(2, ":amount", Number(10)),
+            ],
+            vec![
+                TxData::retract(1, ":amount", Number(5)),
+                TxData::retract(2, ":amount", Number(10)),
+            ],
+        ],
+        expectations: vec![
+            vec![
+                (vec![Eid(1), Number(2)], 0, 1),
+                (vec![Eid(2), Number(1)], 0, 1),
+            ],
+            vec![
+                (vec![Eid(1), Number(2)], 1, -1),
+                (vec![Eid(1), Number(1)], 1, 1),
+                (vec![Eid(2), Number(1)], 1, -1),
+            ],
+        ],
+    }]);
+}
+
+#[test]
+fn group_fn() {
+    let (e, t, hour, count) = (1, 2, 3, 4);
+
+    run_cases(vec![Case {
+        description: "[:find (truncate ?t :hour) (count-rows) :where [?e :at ?t]]",
+        plan: Plan::Aggregate(Aggregate {
+            group_fn: Some((
+                Function::TRUNCATE,
+                vec![None, Some(String(":hour".to_string()))],
+            )),
+            ..Aggregate::new(
+                vec![hour, count],
+                Plan::MatchA(e, ":at".to_string(), t),
+                vec![AggregationFn::CountRows],
+                vec![t],
+                vec![count],
+                vec![],
+            )
+        }),
+        transactions: vec![vec![
+            TxData::add(1, ":at", Value::Instant(1_000)),
+            TxData::add(2, ":at", Value::Instant(2_000)),
+            TxData::add(3, ":at", Value::Instant(3_600_005)),
+        ]],
+        expectations: vec![vec![
+            (vec![Value::Instant(0), Number(2)], 0, 1),
+            (vec![Value::Instant(3_600_000), Number(1)], 0, 1),
+        ]],
+    }]);
+}
+
+#[test]
+fn group_fn_multiple_variables() {
+    let (e, x, y, sum, count) = (1, 2, 3, 4, 5);
+
+    run_cases(vec![Case {
+        description: "[:find (count-rows) (+ ?x ?y) :where [?e :x ?x] [?e :y ?y]]",
+        plan: Plan::Aggregate(Aggregate {
+            group_fn: Some((Function::ADD, vec![None, None])),
+            ..Aggregate::new(
+                vec![count, sum],
+                Plan::Join(Join {
+                    variables: vec![e],
+                    left_plan: Box::new(Plan::MatchA(e, ":x".to_string(), x)),
+                    right_plan: Box::new(Plan::MatchA(e, ":y".to_string(), y)),
+                }),
+                vec![AggregationFn::CountRows],
+                vec![x, y],
+                vec![count],
+                vec![],
+            )
+        }),
+        transactions: vec![vec![
+            TxData::add(1, ":x", Number(1)),
+            TxData::add(1, ":y", Number(2)),
+            TxData::add(2, ":x", Number(2)),
+            TxData::add(2, ":y", Number(1)),
+            TxData::add(3, ":x", Number(0)),
+            TxData::add(3, ":y", Number(5)),
+        ]],
+        expectations: vec![vec![
+            (vec![Number(2), Number(3)], 0, 1),
+            (vec![Number(1), Number(5)], 0, 1),
+        ]],
+    }]);
+}
+
 #[test]
 fn max() {
     let (e, amount) = (1, 2);
@@ -188,30 +335,30 @@ fn max() {
     run_cases(vec![
         Case {
             description: "[:find (max ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![amount],
-                plan: Box::new(Plan::Project(Project {
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![amount],
+                Plan::Project(Project {
                     variables: vec![amount],
                     plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                })),
-                aggregation_fns: vec![AggregationFn::MAX],
-                key_variables: vec![],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+                }),
+                vec![AggregationFn::MAX],
+                vec![],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![(vec![Number(10)], 0, 1)]],
         },
         Case {
             description: "[:find ?e (max ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![e, amount],
-                plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                aggregation_fns: vec![AggregationFn::MAX],
-                key_variables: vec![e],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![e, amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::MAX],
+                vec![e],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![
                 (vec![Eid(1), Number(6)], 0, 1),
@@ -221,6 +368,114 @@ fn max() {
     ]);
 }
 
+/// Orders dotted version strings by their numeric components.
+fn compare_versions(a: &Value, b: &Value) -> std::cmp::Ordering {
+    let components = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map(|x| x.parse::<u64>().unwrap_or(0))
+            .collect()
+    };
+
+    match (a, b) {
+        (String(a), String(b)) => components(a).cmp(&components(b)),
+        _ => a.cmp(b),
+    }
+}
+
+#[test]
+fn comparator() {
+    let (e, version) = (1, 2);
+    let data = vec![
+        TxData::add(1, ":version", String("1.9.0".to_string())),
+        TxData::add(1, ":version", String("1.10.0".to_string())),
+        TxData::add(1, ":version", String("1.2.3".to_string())),
+    ];
+
+    let comparator = ComparatorFn::new(compare_versions);
+    let case =
+        |description, variables, aggregation_fns, aggregation_variables, expectations| Case {
+            description,
+            plan: Plan::AggregateBy(AggregateBy {
+                aggregate: Aggregate::new(
+                    variables,
+                    Plan::MatchA(e, ":version".to_string(), version),
+                    aggregation_fns,
+                    vec![e],
+                    aggregation_variables,
+                    vec![],
+                ),
+                comparator: comparator.clone(),
+            }),
+            transactions: vec![data.clone()],
+            expectations,
+        };
+
+    run_cases(vec![
+        case(
+            "[:find ?e (max ?version) :where [?e :version ?version]]",
+            vec![e, version],
+            vec![AggregationFn::MAX],
+            vec![version],
+            vec![vec![(vec![Eid(1), String("1.10.0".to_string())], 0, 1)]],
+        ),
+        case(
+            "[:find ?e (min ?version) :where [?e :version ?version]]",
+            vec![e, version],
+            vec![AggregationFn::MIN],
+            vec![version],
+            vec![vec![(vec![Eid(1), String("1.2.3".to_string())], 0, 1)]],
+        ),
+        case(
+            "[:find ?e (min ?version) (max ?version) :where [?e :version ?version]]",
+            vec![e, version, version],
+            vec![AggregationFn::MIN, AggregationFn::MAX],
+            vec![version, version],
+            vec![vec![(
+                vec![
+                    Eid(1),
+                    String("1.2.3".to_string()),
+                    String("1.10.0".to_string()),
+                ],
+                0,
+                1,
+            )]],
+        ),
+    ]);
+}
+
+#[test]
+fn arg_max() {
+    let (e, time, payload) = (1, 2, 3);
+
+    run_cases(vec![Case {
+        description: "[:find (arg-max ?time ?time) (arg-max ?time ?payload) \
+                      :where [?e :time ?time] [?e :payload ?payload]]",
+        plan: Plan::Aggregate(Aggregate::new(
+            vec![time, payload],
+            Plan::Join(Join {
+                variables: vec![e],
+                left_plan: Box::new(Plan::MatchA(e, ":time".to_string(), time)),
+                right_plan: Box::new(Plan::MatchA(e, ":payload".to_string(), payload)),
+            }),
+            vec![AggregationFn::ArgMax(time), AggregationFn::ArgMax(time)],
+            vec![],
+            vec![time, payload],
+            vec![],
+        )),
+        transactions: vec![vec![
+            TxData::add(1, ":time", Number(5)),
+            TxData::add(1, ":payload", String("a".to_string())),
+            TxData::add(2, ":time", Number(9)),
+            TxData::add(2, ":payload", String("b".to_string())),
+            TxData::add(3, ":time", Number(9)),
+            TxData::add(3, ":payload", String("c".to_string())),
+        ]],
+        // Ties on ?time are broken by ?e.
+        expectations: vec![vec![(vec![Number(9), String("c".to_string())], 0, 1)]],
+    }]);
+}
+
 #[test]
 fn min() {
     let (e, amount) = (1, 2);
@@ -236,30 +491,30 @@ fn min() {
     run_cases(vec![
         Case {
             description: "[:find (min ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![amount],
-                plan: Box::new(Plan::Project(Project {
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![amount],
+                Plan::Project(Project {
                     variables: vec![amount],
                     plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                })),
-                aggregation_fns: vec![AggregationFn::MIN],
-                key_variables: vec![],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+                }),
+                vec![AggregationFn::MIN],
+                vec![],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![(vec![Number(2)], 0, 1)]],
         },
         Case {
             description: "[:find ?e (min ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![e, amount],
-                plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                aggregation_fns: vec![AggregationFn::MIN],
-                key_variables: vec![e],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![e, amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::MIN],
+                vec![e],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![
                 (vec![Eid(1), Number(2)], 0, 1),
@@ -284,17 +539,17 @@ fn sum() {
     run_cases(vec![
         Case {
             description: "[:find (sum ?amount) :with ?e :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![amount],
-                plan: Box::new(Plan::Project(Project {
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![amount],
+                Plan::Project(Project {
                     variables: vec![amount],
                     plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                })),
-                aggregation_fns: vec![AggregationFn::SUM],
-                key_variables: vec![],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+                }),
+                vec![AggregationFn::SUM],
+                vec![],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![(vec![Number(37)], 0, 1)]],
             // set-semantics
@@ -304,14 +559,14 @@ fn sum() {
         },
         Case {
             description: "[:find ?e (sum ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![e, amount],
-                plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                aggregation_fns: vec![AggregationFn::SUM],
-                key_variables: vec![e],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![e, amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::SUM],
+                vec![e],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![
                 (vec![Eid(1), Number(17)], 0, 1),
@@ -328,6 +583,86 @@ fn sum() {
     ]);
 }
 
+#[test]
+fn sum_default_on_empty() {
+    let (e, amount) = (1, 2);
+
+    run_cases(vec![Case {
+        description: "[:find ?e (sum ?amount) :where [?e :amount ?amount]]",
+        plan: Plan::Aggregate(Aggregate {
+            default_on_empty: true,
+            ..Aggregate::new(
+                vec![e, amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::SUM],
+                vec![e],
+                vec![amount],
+                vec![],
+            )
+        }),
+        transactions: vec![vec![
+            TxData::add(1, ":amount", Number(3)),
+            TxData::add(1, ":amount", Number(-3)),
+            TxData::add(2, ":amount", Number(10)),
+        ]],
+        expectations: vec![vec![
+            (vec![Eid(1), Number(0)], 0, 1),
+            (vec![Eid(2), Number(10)], 0, 1),
+        ]],
+    }]);
+}
+
+#[test]
+fn sum_zero_alongside_count() {
+    let (e, amount) = (1, 2);
+
+    run_cases(vec![Case {
+        description: "[:find ?e (count ?amount) (sum ?amount) :where [?e :amount ?amount]]",
+        plan: Plan::Aggregate(Aggregate::new(
+            vec![e, amount, amount],
+            Plan::MatchA(e, ":amount".to_string(), amount),
+            vec![AggregationFn::COUNT, AggregationFn::SUM],
+            vec![e],
+            vec![amount, amount],
+            vec![],
+        )),
+        transactions: vec![vec![
+            TxData::add(1, ":amount", Number(3)),
+            TxData::add(1, ":amount", Number(-3)),
+            TxData::add(2, ":amount", Number(10)),
+        ]],
+        // A zero sum must not take the group's count down with it.
+        expectations: vec![vec![
+            (vec![Eid(1), Number(2), Number(0)], 0, 1),
+            (vec![Eid(2), Number(1), Number(10)], 0, 1),
+        ]],
+    }]);
+}
+
+#[test]
+#[should_panic(expected = "HyperLogLog precision must be between")]
+fn approx_count_distinct_precision_alongside_count() {
+    let (e, amount) = (1, 2);
+
+    run_cases(vec![Case {
+        description: "[:find ?e (count ?amount) (approx-count-distinct ?amount) \
+                      :where [?e :amount ?amount]]",
+        plan: Plan::Aggregate(Aggregate::new(
+            vec![e, amount, amount],
+            Plan::MatchA(e, ":amount".to_string(), amount),
+            vec![
+                AggregationFn::COUNT,
+                AggregationFn::ApproxCountDistinct { precision: 0 },
+            ],
+            vec![e],
+            vec![amount, amount],
+            vec![],
+        )),
+        transactions: vec![vec![TxData::add(1, ":amount", Number(3))]],
+        expectations: vec![vec![]],
+    }]);
+}
+
 #[test]
 fn avg() {
     let (e, amount) = (1, 2);
@@ -343,17 +678,17 @@ fn avg() {
     run_cases(vec![
         Case {
             description: "[:find (avg ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![amount],
-                plan: Box::new(Plan::Project(Project {
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![amount],
+                Plan::Project(Project {
                     variables: vec![amount],
                     plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                })),
-                aggregation_fns: vec![AggregationFn::AVG],
-                key_variables: vec![],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+                }),
+                vec![AggregationFn::AVG],
+                vec![],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![(vec![Rational32(Ratio::new(37, 6))], 0, 1)]],
             // set-semantics
@@ -363,14 +698,14 @@ fn avg() {
         },
         Case {
             description: "[:find ?e (avg ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![e, amount],
-                plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                aggregation_fns: vec![AggregationFn::AVG],
-                key_variables: vec![e],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![e, amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::AVG],
+                vec![e],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![
                 (vec![Eid(1), Rational32(Ratio::new(17, 4))], 0, 1),
@@ -402,17 +737,17 @@ fn variance() {
     run_cases(vec![
         Case {
             description: "[:find (variance ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![amount],
-                plan: Box::new(Plan::Project(Project {
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![amount],
+                Plan::Project(Project {
                     variables: vec![amount],
                     plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                })),
-                aggregation_fns: vec![AggregationFn::VARIANCE],
-                key_variables: vec![],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+                }),
+                vec![AggregationFn::VARIANCE],
+                vec![],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![(vec![Rational32(Ratio::new(317, 36))], 0, 1)]],
             // set-semantics
@@ -422,14 +757,14 @@ fn variance() {
         },
         Case {
             description: "[:find ?e (variance ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![e, amount],
-                plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                aggregation_fns: vec![AggregationFn::VARIANCE],
-                key_variables: vec![e],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![e, amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::VARIANCE],
+                vec![e],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![
                 (vec![Eid(1), Rational32(Ratio::new(35, 16))], 0, 1),
@@ -454,30 +789,30 @@ fn median() {
     run_cases(vec![
         Case {
             description: "[:find (median ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![amount],
-                plan: Box::new(Plan::Project(Project {
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![amount],
+                Plan::Project(Project {
                     variables: vec![amount],
                     plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                })),
-                aggregation_fns: vec![AggregationFn::MEDIAN],
-                key_variables: vec![],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+                }),
+                vec![AggregationFn::MEDIAN],
+                vec![],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![(vec![Number(5)], 0, 1)]],
         },
         Case {
             description: "[:find ?e (median ?amount) :where [?e :amount ?amount]]",
-            plan: Plan::Aggregate(Aggregate {
-                variables: vec![e, amount],
-                plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
-                aggregation_fns: vec![AggregationFn::MEDIAN],
-                key_variables: vec![e],
-                aggregation_variables: vec![amount],
-                with_variables: vec![],
-            }),
+            plan: Plan::Aggregate(Aggregate::new(
+                vec![e, amount],
+                Plan::MatchA(e, ":amount".to_string(), amount),
+                vec![AggregationFn::MEDIAN],
+                vec![e],
+                vec![amount],
+                vec![],
+            )),
             transactions: vec![data.clone()],
             expectations: vec![vec![
                 (vec![Eid(1), Number(5)], 0, 1),
@@ -496,26 +831,19 @@ fn multiple_aggregations() {
              :where [?e :amount ?amount][?e :debt ?debt]]",
             plan: {
                 let (e, amount, debt) = (1, 2, 3);
-                Plan::Aggregate(Aggregate {
-                    variables: vec![amount, debt, amount, debt],
-                    plan: Box::new(Plan::Project(Project {
+                Plan::Aggregate(Aggregate::new(vec![amount, debt, amount, debt], Plan::Project(Project {
                         variables: vec![amount, debt],
                         plan: Box::new(Plan::Join(Join {
                             variables: vec![e],
                             left_plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
                             right_plan: Box::new(Plan::MatchA(e, ":debt".to_string(), debt)),
                         })),
-                    })),
-                    aggregation_fns: vec![
+                    }), vec![
                         AggregationFn::MAX,
                         AggregationFn::MIN,
                         AggregationFn::SUM,
                         AggregationFn::AVG,
-                    ],
-                    key_variables: vec![],
-                    aggregation_variables: vec![amount, debt, amount, debt],
-                    with_variables: vec![],
-                })
+                    ], vec![], vec![amount, debt, amount, debt], vec![]))
             },
             transactions: vec![
                 vec![
@@ -547,23 +875,41 @@ fn multiple_aggregations() {
             //     ],
             // ],
         },
+        Case {
+            description: "[:find ?e (count ?amount) (sum ?amount) (max ?amount) \
+                          :where [?e :amount ?amount]]",
+            plan: {
+                let (e, amount) = (1, 2);
+                Plan::Aggregate(Aggregate::new(vec![e, amount, amount, amount], Plan::MatchA(e, ":amount".to_string(), amount), vec![
+                        AggregationFn::COUNT,
+                        AggregationFn::SUM,
+                        AggregationFn::MAX,
+                    ], vec![e], vec![amount, amount, amount], vec![]))
+            },
+            transactions: vec![vec![
+                TxData::add(1, ":amount", Number(5)),
+                TxData::add(1, ":amount", Number(2)),
+                TxData::add(2, ":amount", Number(10)),
+            ]],
+            expectations: vec![vec![
+                (vec![Eid(1), Number(2), Number(7), Number(5)], 0, 1),
+                (vec![Eid(2), Number(1), Number(10), Number(10)], 0, 1),
+            ]],
+        },
         Case {
             description:
             "[:find ?e (min ?amount) (max ?amount) (median ?amount) (count ?amount) (min ?debt) (max ?debt) (median ?debt) (count ?debt) \
              :where [?e :amount ?amount][?e :debt ?debt]]",
             plan: {
                 let (e, amount, debt) = (1, 2, 3);
-                Plan::Aggregate(Aggregate {
-                    variables: vec![e, amount, amount, amount, amount, debt, debt, debt, debt],
-                    plan: Box::new(Plan::Project(Project {
+                Plan::Aggregate(Aggregate::new(vec![e, amount, amount, amount, amount, debt, debt, debt, debt], Plan::Project(Project {
                         variables: vec![e, amount, debt],
                         plan: Box::new(Plan::Join(Join {
                             variables: vec![e],
                             left_plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
                             right_plan: Box::new(Plan::MatchA(e, ":debt".to_string(), debt)),
                         })),
-                    })),
-                    aggregation_fns: vec![
+                    }), vec![
                         AggregationFn::MIN,
                         AggregationFn::MAX,
                         AggregationFn::MEDIAN,
@@ -572,11 +918,7 @@ fn multiple_aggregations() {
                         AggregationFn::MAX,
                         AggregationFn::MEDIAN,
                         AggregationFn::COUNT,
-                    ],
-                    key_variables: vec![e],
-                    aggregation_variables: vec![amount, amount, amount, amount, debt, debt, debt, debt],
-                    with_variables: vec![],
-                })
+                    ], vec![e], vec![amount, amount, amount, amount, debt, debt, debt, debt], vec![]))
             },
             transactions: vec![
                 vec![
@@ -617,21 +959,14 @@ fn multiple_aggregations() {
              :where [?e :monster ?monster] [?e :head ?head]]",
             plan: {
                 let (e, monster, heads) = (1, 2, 3);
-                Plan::Aggregate(Aggregate {
-                    variables: vec![heads],
-                    plan: Box::new(Plan::Project(Project {
+                Plan::Aggregate(Aggregate::new(vec![heads], Plan::Project(Project {
                         variables: vec![heads, monster],
                         plan: Box::new(Plan::Join(Join {
                             variables: vec![e],
                             left_plan: Box::new(Plan::MatchA(e, ":monster".to_string(), monster)),
                             right_plan: Box::new(Plan::MatchA(e, ":heads".to_string(), heads)),
                         })),
-                    })),
-                    aggregation_fns: vec![AggregationFn::SUM],
-                    key_variables: vec![],
-                    aggregation_variables: vec![heads],
-                    with_variables: vec![monster],
-                })
+                    }), vec![AggregationFn::SUM], vec![], vec![heads], vec![monster]))
             },
             transactions: vec![
                 vec![
@@ -651,3 +986,145 @@ fn multiple_aggregations() {
         },
     ]);
 }
+
+#[test]
+fn approx_count_distinct() {
+    let (e, amount) = (1, 2);
+
+    run_cases(vec![Case {
+        description: "[:find ?e (approx-count-distinct ?amount) :where [?e :amount ?amount]]",
+        plan: Plan::Aggregate(Aggregate::new(
+            vec![e, amount],
+            Plan::MatchA(e, ":amount".to_string(), amount),
+            vec![AggregationFn::ApproxCountDistinct { precision: 14 }],
+            vec![e],
+            vec![amount],
+            vec![],
+        )),
+        transactions: vec![
+            vec![
+                TxData::add(1, ":amount", Number(5)),
+                TxData::add(1, ":amount", Number(2)),
+                TxData::add(1, ":amount", Number(7)),
+                TxData::add(2, ":amount", Number(10)),
+            ],
+            vec![TxData::retract(1, ":amount", Number(7))],
+        ],
+        expectations: vec![
+            vec![
+                (vec![Eid(1), Number(3)], 0, 1),
+                (vec![Eid(2), Number(1)], 0, 1),
+            ],
+            vec![
+                (vec![Eid(1), Number(3)], 1, -1),
+                (vec![Eid(1), Number(2)], 1, 1),
+            ],
+        ],
+    }]);
+}
+
+#[test]
+fn hyperloglog_accuracy() {
+    use declarative_dataflow::plan::hyperloglog::{observe, Sketch};
+
+    let precision = 12;
+    let mut sketch = Sketch::new(precision);
+
+    for i in 0..100_000 {
+        let (register, rank) = observe(&Number(i), precision);
+        sketch.insert(register, rank);
+    }
+
+    let estimate = sketch.estimate();
+    assert!(estimate > 95_000 && estimate < 105_000, "{}", estimate);
+}
+
+#[test]
+fn sum_overflow() {
+    use std::i64::{MAX, MIN};
+
+    let (e, amount) = (1, 2);
+
+    run_cases(vec![Case {
+        description: "[:find (sum ?amount) :with ?e :where [?e :amount ?amount]]",
+        plan: Plan::Aggregate(Aggregate::new(
+            vec![amount],
+            Plan::Project(Project {
+                variables: vec![amount],
+                plan: Box::new(Plan::MatchA(e, ":amount".to_string(), amount)),
+            }),
+            vec![AggregationFn::SUM],
+            vec![],
+            vec![amount],
+            vec![],
+        )),
+        transactions: vec![
+            vec![
+                TxData::add(1, ":amount", Number(MAX)),
+                TxData::add(2, ":amount", Number(MAX)),
+            ],
+            vec![TxData::add(3, ":amount", Number(MIN))],
+        ],
+        expectations: vec![
+            // The sum exceeds the range of Number and saturates.
+            vec![(vec![Number(MAX)], 0, 1)],
+            // Sums are accumulated exactly nonetheless.
+            vec![(vec![Number(MAX)], 1, -1), (vec![Number(MAX - 1)], 1, 1)],
+        ],
+    }]);
+}
+
+#[test]
+fn min_max_retraction() {
+    let (e, amount) = (1, 2);
+    let transactions = vec![
+        vec![
+            TxData::add(1, ":amount", Number(5)),
+            TxData::add(1, ":amount", Number(2)),
+            TxData::add(1, ":amount", Number(9)),
+        ],
+        vec![
+            TxData::retract(1, ":amount", Number(2)),
+            TxData::retract(1, ":amount", Number(9)),
+        ],
+    ];
+
+    let case = |description, aggregation_fn, expectations| Case {
+        description,
+        plan: Plan::Aggregate(Aggregate::new(
+            vec![e, amount],
+            Plan::MatchA(e, ":amount".to_string(), amount),
+            vec![aggregation_fn],
+            vec![e],
+            vec![amount],
+            vec![],
+        )),
+        transactions: transactions.clone(),
+        expectations,
+    };
+
+    run_cases(vec![
+        case(
+            "[:find ?e (min ?amount) :where [?e :amount ?amount]]",
+            AggregationFn::MIN,
+            vec![
+                vec![(vec![Eid(1), Number(2)], 0, 1)],
+                vec![
+                    (vec![Eid(1), Number(2)], 1, -1),
+                    (vec![Eid(1), Number(5)], 1, 1),
+                ],
+            ],
+        ),
+        case(
+            "[:find ?e (max ?amount) :where [?e :amount ?amount]]",
+            AggregationFn::MAX,
+            vec![
+                vec![(vec![Eid(1), Number(9)], 0, 1)],
+                vec![
+                    (vec![Eid(1), Number(9)], 1, -1),
+                    (vec![Eid(1), Number(5)], 1, 1),
+                ],
+            ],
+        ),
+    ]);
+}
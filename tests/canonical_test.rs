@@ -0,0 +1,111 @@
+use declarative_dataflow::binding::Binding;
+use declarative_dataflow::plan::{Hector, Join, Project, Union};
+use declarative_dataflow::Plan;
+
+#[test]
+fn variable_numbering() {
+    let plan = |e, n, a| {
+        Plan::Join(Join {
+            variables: vec![e],
+            left_plan: Box::new(Plan::MatchA(e, ":name".to_string(), n)),
+            right_plan: Box::new(Plan::MatchA(e, ":age".to_string(), a)),
+        })
+    };
+
+    assert_eq!(
+        plan(0, 1, 2).canonical_hash(),
+        plan(7, 3, 5).canonical_hash()
+    );
+    assert_eq!(
+        plan(0, 1, 2).canonical_hash(),
+        plan(0, 2, 1).canonical_hash()
+    );
+
+    let project = |variables| {
+        Plan::Project(Project {
+            variables,
+            plan: Box::new(Plan::MatchA(0, ":name".to_string(), 1)),
+        })
+    };
+
+    assert_ne!(
+        project(vec![0, 1]).canonical_hash(),
+        project(vec![1, 0]).canonical_hash()
+    );
+}
+
+#[test]
+fn union_operand_order() {
+    let union = |plans| {
+        Plan::Union(Union {
+            variables: vec![0, 1],
+            plans,
+        })
+    };
+
+    let name = Plan::MatchA(0, ":name".to_string(), 1);
+    let alias = Plan::MatchA(0, ":alias".to_string(), 1);
+    let parent = Plan::MatchA(1, ":parent".to_string(), 0);
+
+    assert_eq!(
+        union(vec![name.clone(), alias.clone()]).canonical_hash(),
+        union(vec![alias.clone(), name.clone()]).canonical_hash()
+    );
+    assert_ne!(
+        union(vec![name.clone(), alias.clone()]).canonical_hash(),
+        union(vec![name.clone(), parent.clone()]).canonical_hash()
+    );
+}
+
+#[test]
+fn join_operand_order() {
+    let join = |left: &Plan, right: &Plan| {
+        Plan::Join(Join {
+            variables: vec![0],
+            left_plan: Box::new(left.clone()),
+            right_plan: Box::new(right.clone()),
+        })
+    };
+
+    let name = Plan::MatchA(0, ":name".to_string(), 1);
+    let age = Plan::MatchA(0, ":age".to_string(), 2);
+
+    // Swapping the operands changes the layout of the output tuples,
+    // which a projection restores.
+    assert_ne!(
+        join(&name, &age).canonical_hash(),
+        join(&age, &name).canonical_hash()
+    );
+    assert_eq!(
+        join(&name, &age).canonical_hash(),
+        Plan::Project(Project {
+            variables: vec![0, 1, 2],
+            plan: Box::new(join(&age, &name)),
+        })
+        .canonical_hash()
+    );
+}
+
+#[test]
+fn hector_binding_order() {
+    let hector = |bindings| {
+        Plan::Hector(Hector {
+            variables: vec![0, 1, 2],
+            bindings,
+            order: None,
+        })
+    };
+
+    let edges = vec![
+        Binding::attribute(0, ":edge", 1),
+        Binding::attribute(1, ":edge", 2),
+        Binding::attribute(0, ":edge", 2),
+    ];
+    let mut reversed = edges.clone();
+    reversed.reverse();
+
+    assert_eq!(
+        hector(edges).canonical_hash(),
+        hector(reversed).canonical_hash()
+    );
+}
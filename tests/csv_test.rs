@@ -0,0 +1,575 @@
+#[cfg(feature = "csv-source")]
+#[test]
+fn bool_column() {
+    use std::collections::HashSet;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use timely::dataflow::channels::pact::Pipeline;
+    use timely::dataflow::operators::Operator;
+
+    use declarative_dataflow::plan::{Filter, Predicate};
+    use declarative_dataflow::server::Server;
+    use declarative_dataflow::sources::CsvFile;
+    use declarative_dataflow::{Plan, Rule, Value};
+    use Value::{Bool, Eid};
+
+    let path = std::env::temp_dir().join("declarative_dataflow_bool_column.csv");
+    std::fs::write(&path, "1,true\n2,false\n3,TRUE\n").unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Duration, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            let source = CsvFile {
+                path: path.clone(),
+                has_headers: false,
+                delimiter: b',',
+                comment: None,
+                quote: None,
+                escape: None,
+                double_quote: None,
+                flexible: false,
+                eid_offset: 0,
+                eid_columns: None,
+                timestamp_offset: None,
+                schema: vec![(":admin?".to_string(), (1, Bool(false)))],
+                multi_valued: vec![],
+                fuel: None,
+                interval: None,
+                partitioning: None,
+                on_error: None,
+                watch: false,
+            };
+
+            server.register_source(Box::new(source), scope).unwrap();
+
+            let (e, admin) = (0, 1);
+            let plan = Plan::Filter(Filter {
+                variables: vec![admin],
+                predicate: Predicate::EQ,
+                plan: Box::new(Plan::MatchA(e, ":admin?".to_string(), admin)),
+                constants: vec![None, Some(Bool(true))],
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "admins".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _time, diff) in data.iter() {
+                            send_results.send((tuple.clone(), *diff)).unwrap()
+                        }
+                    });
+                });
+        });
+
+        let mut received = HashSet::new();
+        for _ in 0..1000 {
+            worker.step();
+            received.extend(results.try_iter());
+
+            if received.len() >= 2 {
+                break;
+            }
+        }
+
+        let expected: HashSet<(Vec<Value>, isize)> =
+            vec![(vec![Eid(1), Bool(true)], 1), (vec![Eid(3), Bool(true)], 1)]
+                .into_iter()
+                .collect();
+
+        assert_eq!(received, expected);
+    });
+}
+
+#[cfg(feature = "csv-source")]
+#[test]
+fn multi_valued_column() {
+    use std::collections::HashSet;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use timely::dataflow::channels::pact::Pipeline;
+    use timely::dataflow::operators::Operator;
+
+    use declarative_dataflow::server::Server;
+    use declarative_dataflow::sources::{CsvFile, MultiValue};
+    use declarative_dataflow::{Plan, Rule, Value};
+    use Value::{Eid, String};
+
+    let path = std::env::temp_dir().join("declarative_dataflow_multi_valued_column.csv");
+    std::fs::write(&path, "1,a;b;c\n2,\n3,b\n").unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Duration, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            let source = CsvFile {
+                path: path.clone(),
+                has_headers: false,
+                delimiter: b',',
+                comment: None,
+                quote: None,
+                escape: None,
+                double_quote: None,
+                flexible: false,
+                eid_offset: 0,
+                eid_columns: None,
+                timestamp_offset: None,
+                schema: vec![],
+                multi_valued: vec![(
+                    ":tag".to_string(),
+                    MultiValue {
+                        offset: 1,
+                        inner: String("".to_string()),
+                        sep: ';',
+                    },
+                )],
+                fuel: None,
+                interval: None,
+                partitioning: None,
+                on_error: None,
+                watch: false,
+            };
+
+            server.register_source(Box::new(source), scope).unwrap();
+
+            let (e, tag) = (0, 1);
+            let plan = Plan::MatchA(e, ":tag".to_string(), tag);
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "tags".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _time, diff) in data.iter() {
+                            send_results.send((tuple.clone(), *diff)).unwrap()
+                        }
+                    });
+                });
+        });
+
+        let mut received = HashSet::new();
+        for _ in 0..1000 {
+            worker.step();
+            received.extend(results.try_iter());
+
+            if received.len() >= 4 {
+                break;
+            }
+        }
+
+        let expected: HashSet<(Vec<Value>, isize)> = vec![
+            (vec![Eid(1), String("a".to_string())], 1),
+            (vec![Eid(1), String("b".to_string())], 1),
+            (vec![Eid(1), String("c".to_string())], 1),
+            (vec![Eid(3), String("b".to_string())], 1),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(received, expected);
+    });
+}
+
+#[cfg(feature = "csv-source")]
+#[test]
+fn mistyped_column() {
+    use std::collections::HashSet;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use timely::dataflow::channels::pact::Pipeline;
+    use timely::dataflow::operators::Operator;
+
+    use declarative_dataflow::server::Server;
+    use declarative_dataflow::sources::{CsvFile, OnError};
+    use declarative_dataflow::{Plan, Rule, Value};
+    use Value::{Eid, Number};
+
+    let path = std::env::temp_dir().join("declarative_dataflow_mistyped_column.csv");
+    std::fs::write(&path, "1,30\n2,thirty\n3,40\n").unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Duration, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            let source = CsvFile {
+                path: path.clone(),
+                has_headers: false,
+                delimiter: b',',
+                comment: None,
+                quote: None,
+                escape: None,
+                double_quote: None,
+                flexible: false,
+                eid_offset: 0,
+                eid_columns: None,
+                timestamp_offset: None,
+                schema: vec![(":age".to_string(), (1, Number(0)))],
+                multi_valued: vec![],
+                fuel: None,
+                interval: None,
+                partitioning: None,
+                on_error: Some(OnError::Skip),
+                watch: false,
+            };
+
+            server.register_source(Box::new(source), scope).unwrap();
+
+            let (e, age) = (0, 1);
+            let plan = Plan::MatchA(e, ":age".to_string(), age);
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "ages".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _time, diff) in data.iter() {
+                            send_results.send((tuple.clone(), *diff)).unwrap()
+                        }
+                    });
+                });
+        });
+
+        // The attribute is declared with the type of its column.
+        assert_eq!(
+            server.context.internal.attributes[":age"].value_type,
+            Some(Number(0))
+        );
+
+        let mut received = HashSet::new();
+        for _ in 0..1000 {
+            worker.step();
+            received.extend(results.try_iter());
+
+            if received.len() >= 2 {
+                break;
+            }
+        }
+
+        let expected: HashSet<(Vec<Value>, isize)> =
+            vec![(vec![Eid(1), Number(30)], 1), (vec![Eid(3), Number(40)], 1)]
+                .into_iter()
+                .collect();
+
+        assert_eq!(received, expected);
+    });
+}
+
+#[cfg(feature = "csv-source")]
+#[test]
+fn composite_eid() {
+    use std::collections::HashMap;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use timely::dataflow::channels::pact::Pipeline;
+    use timely::dataflow::operators::Operator;
+
+    use declarative_dataflow::server::Server;
+    use declarative_dataflow::sources::CsvFile;
+    use declarative_dataflow::{Plan, Rule, Value};
+    use Value::{Eid, Number};
+
+    let path = std::env::temp_dir().join("declarative_dataflow_composite_eid.csv");
+    std::fs::write(&path, "a,x,1\na,x,2\nab,c,3\na,bc,4\n").unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Duration, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            let source = CsvFile {
+                path: path.clone(),
+                has_headers: false,
+                delimiter: b',',
+                comment: None,
+                quote: None,
+                escape: None,
+                double_quote: None,
+                flexible: false,
+                eid_offset: 0,
+                eid_columns: Some(vec![0, 1]),
+                timestamp_offset: None,
+                schema: vec![(":value".to_string(), (2, Number(0)))],
+                multi_valued: vec![],
+                fuel: None,
+                interval: None,
+                partitioning: None,
+                on_error: None,
+                watch: false,
+            };
+
+            server.register_source(Box::new(source), scope).unwrap();
+
+            let (e, value) = (0, 1);
+            let plan = Plan::MatchA(e, ":value".to_string(), value);
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "values".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _time, _diff) in data.iter() {
+                            send_results.send(tuple.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        // Entity ids by value.
+        let mut eids = HashMap::new();
+        for _ in 0..1000 {
+            worker.step();
+
+            for tuple in results.try_iter() {
+                eids.insert(tuple[1].clone(), tuple[0].clone());
+            }
+
+            if eids.len() >= 4 {
+                break;
+            }
+        }
+
+        // The same key always maps to the same entity, across runs.
+        assert_eq!(eids[&Number(1)], Eid(12_361_144_585_123_005_948));
+        assert_eq!(eids[&Number(1)], eids[&Number(2)]);
+
+        // Different keys map to different entities, even if their
+        // concatenations are equal.
+        assert_ne!(eids[&Number(1)], eids[&Number(3)]);
+        assert_ne!(eids[&Number(3)], eids[&Number(4)]);
+    });
+}
+
+#[cfg(feature = "csv-source")]
+#[test]
+fn watch_append() {
+    use std::io::Write;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use timely::dataflow::channels::pact::Pipeline;
+    use timely::dataflow::operators::Operator;
+
+    use declarative_dataflow::server::Server;
+    use declarative_dataflow::sources::CsvFile;
+    use declarative_dataflow::{Plan, Rule, Value};
+    use Value::{Eid, String};
+
+    let path = std::env::temp_dir().join("declarative_dataflow_watch_append.csv");
+    std::fs::write(&path, "1,Dipper\n").unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Duration, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            let source = CsvFile {
+                path: path.clone(),
+                has_headers: false,
+                delimiter: b',',
+                comment: None,
+                quote: None,
+                escape: None,
+                double_quote: None,
+                flexible: false,
+                eid_offset: 0,
+                eid_columns: None,
+                timestamp_offset: None,
+                schema: vec![(":name".to_string(), (1, String("".to_string())))],
+                multi_valued: vec![],
+                fuel: None,
+                interval: Some(Duration::from_millis(10)),
+                partitioning: None,
+                on_error: None,
+                watch: true,
+            };
+
+            server.register_source(Box::new(source), scope).unwrap();
+
+            let (e, name) = (0, 1);
+            let plan = Plan::MatchA(e, ":name".to_string(), name);
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "names".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _time, diff) in data.iter() {
+                            send_results.send((tuple.clone(), *diff)).unwrap()
+                        }
+                    });
+                });
+        });
+
+        let mut received = Vec::new();
+        let mut step_until = |count: usize, received: &mut Vec<(Vec<Value>, isize)>| {
+            for _ in 0..1000 {
+                worker.step();
+
+                let activators: Vec<_> = server.scheduler.borrow_mut().realtime.by_ref().collect();
+                for activator in activators {
+                    activator.schedule();
+                }
+
+                received.extend(results.try_iter());
+
+                if received.len() >= count {
+                    break;
+                }
+
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        step_until(1, &mut received);
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"2,Mabel\n").unwrap();
+        file.sync_all().unwrap();
+
+        // Only the appended row is introduced.
+        step_until(2, &mut received);
+        received.sort();
+
+        assert_eq!(
+            received,
+            vec![
+                (vec![Eid(1), String("Dipper".to_string())], 1),
+                (vec![Eid(2), String("Mabel".to_string())], 1),
+            ]
+        );
+    });
+}
+
+#[cfg(feature = "csv-source")]
+#[test]
+fn partitioning_by_eid() {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use timely::dataflow::operators::Inspect;
+
+    use declarative_dataflow::server::Server;
+    use declarative_dataflow::sources::{CsvFile, Partitioning, Sourceable};
+    use declarative_dataflow::Value;
+    use Value::{Eid, String};
+
+    let path = std::env::temp_dir().join("declarative_dataflow_partitioning_by_eid.csv");
+    std::fs::write(&path, "1,a\n1,b\n2,c\n3,d\n4,e\n").unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    // (worker, eid, value) for every datum introduced.
+    let introduced = Arc::new(Mutex::new(Vec::new()));
+    let shared = introduced.clone();
+
+    timely::execute(timely::Configuration::Process(2), move |worker| {
+        let server = Server::<Duration, u64>::new(Default::default());
+        let index = worker.index();
+        let introduced = shared.clone();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            let source = CsvFile {
+                path: path.clone(),
+                has_headers: false,
+                delimiter: b',',
+                comment: None,
+                quote: None,
+                escape: None,
+                double_quote: None,
+                flexible: false,
+                eid_offset: 0,
+                eid_columns: None,
+                timestamp_offset: None,
+                schema: vec![(":tag".to_string(), (1, String("".to_string())))],
+                multi_valued: vec![],
+                fuel: None,
+                interval: None,
+                partitioning: Some(Partitioning::ByEid),
+                on_error: None,
+                watch: false,
+            };
+
+            for (_aid, _config, datoms) in source.source(scope, server.make_sourcing_context()) {
+                let introduced = introduced.clone();
+                datoms.inspect(move |((e, v), _t, _diff)| {
+                    introduced
+                        .lock()
+                        .unwrap()
+                        .push((index, e.clone(), v.clone()));
+                });
+            }
+        });
+    })
+    .unwrap();
+
+    let introduced = introduced.lock().unwrap();
+
+    // Every row is introduced exactly once...
+    let mut rows: Vec<(Value, Value)> = introduced
+        .iter()
+        .map(|(_worker, e, v)| (e.clone(), v.clone()))
+        .collect();
+    rows.sort();
+    assert_eq!(
+        rows,
+        vec![
+            (Eid(1), String("a".to_string())),
+            (Eid(1), String("b".to_string())),
+            (Eid(2), String("c".to_string())),
+            (Eid(3), String("d".to_string())),
+            (Eid(4), String("e".to_string())),
+        ]
+    );
+
+    // ...and all rows about an entity by the same worker.
+    let mut workers = HashMap::new();
+    for (worker, e, _v) in introduced.iter() {
+        assert_eq!(*workers.entry(e.clone()).or_insert(*worker), *worker);
+    }
+}
@@ -1,11 +1,16 @@
-use timely::dataflow::operators::UnorderedInput;
+use std::collections::HashSet;
+use std::sync::mpsc::channel;
+
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::{Operator, ToStream, UnorderedInput};
 use timely::progress::frontier::AntichainRef;
 
 use differential_dataflow::trace::TraceReader;
 
 use declarative_dataflow::domain::Domain;
-use declarative_dataflow::Value;
-use declarative_dataflow::{AttributeConfig, InputSemantics};
+use declarative_dataflow::server::Server;
+use declarative_dataflow::{AttributeConfig, ErrorKind, InputSemantics};
+use declarative_dataflow::{Plan, Rule, TxData, Value};
 
 #[test]
 fn test_advance_epoch() {
@@ -209,3 +214,217 @@ fn test_advance_only_source() {
         );
     });
 }
+
+#[test]
+fn test_source_completion() {
+    timely::execute_directly(move |worker| {
+        let mut domain = Domain::<u64>::new(0);
+
+        let (mut handle, cap) = worker.dataflow::<u64, _, _>(|scope| {
+            let ((handle, cap), pairs) =
+                scope.new_unordered_input::<((Value, Value), u64, isize)>();
+
+            domain
+                .create_sourced_attribute(
+                    "source_test",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    &pairs,
+                )
+                .unwrap();
+
+            (handle, cap)
+        });
+
+        assert!(domain.is_source_complete("unknown").is_err());
+
+        handle
+            .session(cap.clone())
+            .give(((Value::Eid(1), Value::Number(1)), 0, 1));
+        worker.step();
+        assert!(!domain.is_source_complete("source_test").unwrap());
+
+        // The source completes once it drops its last capability.
+        drop(cap);
+        worker.step_while(|| !domain.is_source_complete("source_test").unwrap());
+        assert!(domain.is_source_complete("source_test").unwrap());
+    });
+}
+
+#[test]
+fn test_transact_at() {
+    timely::execute_directly(move |worker| {
+        let mut domain = Domain::<u64>::new(0);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            domain
+                .create_transactable_attribute(
+                    ":name",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            domain
+                .create_transactable_attribute(
+                    ":age",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+        });
+
+        // Unknown attributes fail the entire transaction.
+        assert!(domain
+            .transact_at(
+                vec![
+                    (":name".to_string(), 1, Value::String("Mabel".to_string()), 1),
+                    (":unknown".to_string(), 1, Value::Number(12), 1),
+                ],
+                1,
+            )
+            .is_err());
+        assert_eq!(domain.epoch(), &0);
+
+        assert!(domain
+            .transact_at(
+                vec![
+                    (":name".to_string(), 1, Value::String("Mabel".to_string()), 1),
+                    (":age".to_string(), 1, Value::Number(12), 1),
+                ],
+                1,
+            )
+            .is_ok());
+        assert_eq!(domain.epoch(), &1);
+
+        // We can't transact into the past.
+        assert!(domain
+            .transact_at(vec![(":age".to_string(), 1, Value::Number(13), 1)], 0)
+            .is_err());
+    });
+}
+
+#[test]
+fn test_value_type() {
+    timely::execute_directly(move |worker| {
+        let mut domain = Domain::<u64>::new(0);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            domain
+                .create_transactable_attribute(
+                    ":age",
+                    AttributeConfig {
+                        value_type: Some(Value::Number(0)),
+                        ..AttributeConfig::tx_time(InputSemantics::Raw)
+                    },
+                    scope,
+                )
+                .unwrap();
+        });
+
+        assert!(domain
+            .transact(vec![TxData::add(1, ":age", Value::Number(12))])
+            .is_ok());
+        assert!(domain
+            .transact(vec![TxData::add(1, ":age", Value::Null)])
+            .is_ok());
+
+        let error = domain
+            .transact(vec![TxData::add(1, ":age", Value::String("12".to_string()))])
+            .unwrap_err();
+        assert_eq!(error.category, "df.error.category/incorrect");
+        assert_eq!(
+            error.kind,
+            ErrorKind::TypeMismatch {
+                aid: ":age".to_string(),
+                expected: Value::Number(0),
+                actual: Value::String("12".to_string()),
+            }
+        );
+    });
+}
+
+#[test]
+fn test_sourced_value_type() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let pairs = vec![
+                ((Value::Eid(1), Value::Number(12)), 0, 1),
+                ((Value::Eid(2), Value::String("12".to_string())), 0, 1),
+                ((Value::Eid(3), Value::Null), 0, 1),
+            ]
+            .to_stream(scope);
+
+            server
+                .context
+                .internal
+                .create_sourced_attribute(
+                    ":age",
+                    AttributeConfig {
+                        value_type: Some(Value::Number(0)),
+                        ..AttributeConfig::tx_time(InputSemantics::Raw)
+                    },
+                    &pairs,
+                )
+                .unwrap();
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "ages".to_string(),
+                        plan: Plan::MatchA(0, ":age".to_string(), 1),
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _time, diff) in data.iter() {
+                            send_results.send((tuple.clone(), *diff)).unwrap()
+                        }
+                    });
+                });
+        });
+
+        let mut received = HashSet::new();
+        for _ in 0..1000 {
+            worker.step();
+            received.extend(results.try_iter());
+
+            if received.len() >= 2 {
+                break;
+            }
+        }
+
+        // The mistyped value is dropped on ingest.
+        let expected: HashSet<(Vec<Value>, isize)> = vec![
+            (vec![Value::Eid(1), Value::Number(12)], 1),
+            (vec![Value::Eid(3), Value::Null], 1),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(received, expected);
+    });
+}
+
+#[test]
+fn test_unknown_attribute() {
+    let mut domain = Domain::<u64>::new(0);
+
+    let error = domain
+        .transact(vec![TxData::add(1, ":age", Value::Number(12))])
+        .unwrap_err();
+
+    assert_eq!(error.category, "df.error.category/not-found");
+    assert_eq!(error.kind, ErrorKind::AttributeNotFound(":age".to_string()));
+    assert_eq!(
+        error.to_string(),
+        "df.error.category/not-found: Attribute :age does not exist."
+    );
+
+    let error: Box<dyn std::error::Error> = Box::new(error);
+    assert!(error.source().is_none());
+}
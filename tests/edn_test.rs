@@ -0,0 +1,66 @@
+#[cfg(feature = "edn")]
+#[test]
+fn parse_datoms() {
+    use declarative_dataflow::sources::edn::parse_datoms;
+    use declarative_dataflow::timestamp::Time;
+    use declarative_dataflow::{Rational32, TxData, Value};
+    use Value::{Aid, Bool, Eid, Null, Number, String};
+
+    let datoms = parse_datoms(
+        r#"[[1 :person/name "Dipper \"Pines\"" 100 :db/add]
+            [1 :person/age 12 nil true]
+            ; comments and commas are ignored
+            [1, :person/twin?, false, nil, -1]
+            [2 :person/role :role/admin nil :db/retract]
+            #_[2 :person/name "discarded" nil :db/add]
+            [2 :person/share 1/3 nil 1]
+            [2 :person/nickname nil nil false]]"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        datoms,
+        vec![
+            TxData(
+                1,
+                Eid(1),
+                ":person/name".to_string(),
+                String("Dipper \"Pines\"".to_string()),
+                Some(Time::TxId(100)),
+            ),
+            TxData(1, Eid(1), ":person/age".to_string(), Number(12), None),
+            TxData(-1, Eid(1), ":person/twin?".to_string(), Bool(false), None),
+            TxData(
+                -1,
+                Eid(2),
+                ":person/role".to_string(),
+                Aid(":role/admin".to_string()),
+                None,
+            ),
+            TxData(
+                1,
+                Eid(2),
+                ":person/share".to_string(),
+                Value::Rational32(Rational32::new(1, 3)),
+                None,
+            ),
+            TxData(-1, Eid(2), ":person/nickname".to_string(), Null, None),
+        ]
+    );
+
+    for malformed in [
+        "[[1 :person/name \"Dipper\" nil]]",
+        "[[1 :person/name \"Dipper\" nil :db/add]",
+        "[[1 person/name \"Dipper\" nil :db/add]]",
+        "[[1 :person/height 1.5 nil :db/add]]",
+        "[[1 :person/tags [\"a\"] nil :db/add]]",
+        "[] []",
+    ]
+    .iter()
+    {
+        assert_eq!(
+            parse_datoms(malformed).unwrap_err().category,
+            "df.error.category/incorrect"
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use declarative_dataflow::plan::{Filter, Join, Predicate};
+use declarative_dataflow::server::Server;
+use declarative_dataflow::{AttributeConfig, InputSemantics};
+use declarative_dataflow::{Plan, TxData, Value};
+use Value::String;
+
+#[test]
+fn estimate() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in &[":name", ":parent"] {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":name", String("Dipper".to_string())),
+                    TxData::add(2, ":name", String("Mabel".to_string())),
+                    TxData::add(3, ":name", String("Stan".to_string())),
+                    TxData::add(1, ":parent", Value::Eid(3)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let names = Plan::MatchA(0, ":name".to_string(), 1);
+        let parents = Plan::MatchA(0, ":parent".to_string(), 2);
+
+        let scan = names.estimate(&mut server.context);
+        assert_eq!(scan.cardinality, 3);
+
+        let lookup = Plan::MatchEA(1, ":name".to_string(), 1).estimate(&mut server.context);
+        assert!(lookup.cardinality <= scan.cardinality);
+
+        let filtered = Plan::Filter(Filter {
+            variables: vec![0, 1],
+            predicate: Predicate::NEQ,
+            plan: Box::new(names.clone()),
+            constants: vec![None, None],
+        })
+        .estimate(&mut server.context);
+        assert!(filtered.cardinality <= scan.cardinality);
+        assert!(filtered.cost > scan.cost);
+
+        let join = Plan::Join(Join {
+            variables: vec![0],
+            left_plan: Box::new(names),
+            right_plan: Box::new(parents),
+        })
+        .estimate(&mut server.context);
+        assert_eq!(join.cardinality, 1);
+        assert!(join.cost > scan.cost);
+    });
+}
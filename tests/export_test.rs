@@ -0,0 +1,106 @@
+#[cfg(feature = "csv-source")]
+#[test]
+fn csv_sink() {
+    use differential_dataflow::input::Input;
+
+    use declarative_dataflow::sinks::CsvSink;
+    use declarative_dataflow::Value::{Eid, String};
+
+    let path = std::env::temp_dir().join("declarative_dataflow_csv_sink.csv");
+    let path = path.to_str().unwrap().to_string();
+
+    let sink = CsvSink {
+        path: path.clone(),
+        has_headers: true,
+        delimiter: b',',
+        columns: vec![("name".to_string(), 1), ("id".to_string(), 0)],
+    };
+
+    timely::execute_directly(move |worker| {
+        let (mut input, probe) = worker.dataflow::<u64, _, _>(|scope| {
+            let (input, collection) = scope.new_collection();
+            let probe = sink.attach(&collection).unwrap();
+
+            (input, probe)
+        });
+
+        input.insert(vec![Eid(1), String("Dipper".to_string())]);
+        input.insert(vec![Eid(2), String("Mabel".to_string())]);
+        input.advance_to(1);
+        input.flush();
+        worker.step_while(|| probe.less_than(input.time()));
+
+        input.remove(vec![Eid(1), String("Dipper".to_string())]);
+        input.insert(vec![Eid(3), String("Soos".to_string())]);
+        input.close();
+        while worker.step() {}
+    });
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "name,id\nMabel,2\nSoos,3\n"
+    );
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn json_sink() {
+    use differential_dataflow::input::Input;
+
+    use declarative_dataflow::interning::SymbolTable;
+    use declarative_dataflow::sinks::JsonSink;
+    use declarative_dataflow::Value::{Eid, Instant, Interned};
+
+    let path = std::env::temp_dir().join("declarative_dataflow_json_sink.json");
+    let path = path.to_str().unwrap().to_string();
+
+    let sink = JsonSink {
+        path: path.clone(),
+        columns: vec![
+            ("id".to_string(), 0),
+            ("name".to_string(), 1),
+            ("born".to_string(), 2),
+        ],
+    };
+
+    let dipper = SymbolTable::global().intern("Dipper").unwrap();
+    let mabel = SymbolTable::global().intern("Mabel").unwrap();
+
+    timely::execute_directly(move |worker| {
+        let (mut input, probe) = worker.dataflow::<u64, _, _>(|scope| {
+            let (input, collection) = scope.new_collection();
+            let probe = sink.attach(&collection).unwrap();
+
+            (input, probe)
+        });
+
+        input.insert(vec![Eid(1), Interned(dipper), Instant(100)]);
+        input.advance_to(1);
+        input.flush();
+        worker.step_while(|| probe.less_than(input.time()));
+
+        // Additions are appended to the existing export.
+        input.insert(vec![Eid(2), Interned(mabel), Instant(100)]);
+        input.advance_to(2);
+        input.flush();
+        worker.step_while(|| probe.less_than(input.time()));
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            concat!(
+                "{\"born\":100,\"id\":\"1\",\"name\":\"Dipper\"}\n",
+                "{\"born\":100,\"id\":\"2\",\"name\":\"Mabel\"}\n",
+            )
+        );
+
+        // Retractions rewrite it.
+        input.remove(vec![Eid(1), Interned(dipper), Instant(100)]);
+        input.close();
+        while worker.step() {}
+    });
+
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "{\"born\":100,\"id\":\"2\",\"name\":\"Mabel\"}\n"
+    );
+}
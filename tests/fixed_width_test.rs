@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Operator;
+
+use declarative_dataflow::server::Server;
+use declarative_dataflow::sources::{EidStrategy, FixedWidthFile};
+use declarative_dataflow::{Plan, Rule, Value};
+use Value::{Eid, String};
+
+#[test]
+fn short_line() {
+    let path = std::env::temp_dir().join("declarative_dataflow_short_line.txt");
+    std::fs::write(&path, "001Dipper\n002Ma\n").unwrap();
+    let path = path.to_str().unwrap().to_string();
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<Duration, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<Duration, _, _>(|scope| {
+            let source = FixedWidthFile {
+                path: path.clone(),
+                skip_lines: 0,
+                eid_strategy: EidStrategy::Column((0, 3)),
+                schema: vec![(":name".to_string(), ((3, 9), String("".to_string())))],
+                trim: true,
+                fuel: None,
+                interval: None,
+                partitioning: None,
+            };
+
+            server.register_source(Box::new(source), scope).unwrap();
+
+            let (e, name) = (0, 1);
+            let plan = Plan::MatchA(e, ":name".to_string(), name);
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "names".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _time, diff) in data.iter() {
+                            send_results.send((tuple.clone(), *diff)).unwrap()
+                        }
+                    });
+                });
+        });
+
+        // The short line is skipped, instead of taking down the
+        // worker.
+        worker.step_while(|| !server.context.internal.is_source_complete(":name").unwrap());
+
+        let mut received = HashSet::new();
+        for _ in 0..1000 {
+            worker.step();
+            received.extend(results.try_iter());
+
+            if !received.is_empty() {
+                break;
+            }
+        }
+
+        let expected: HashSet<(Vec<Value>, isize)> =
+            vec![(vec![Eid(1), String("Dipper".to_string())], 1)]
+                .into_iter()
+                .collect();
+
+        assert_eq!(received, expected);
+    });
+}
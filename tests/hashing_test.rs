@@ -0,0 +1,29 @@
+use std::hash::{Hash, Hasher};
+
+use declarative_dataflow::hashing::StableHasher;
+use declarative_dataflow::Value;
+
+#[test]
+fn fnv_test_vectors() {
+    let hash = |bytes: &[u8]| {
+        let mut hasher = StableHasher::new();
+        hasher.write(bytes);
+        hasher.finish()
+    };
+
+    assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+}
+
+#[test]
+fn seeded_values() {
+    let value = Value::String("Dipper".to_string());
+
+    let mut hasher = StableHasher::new();
+    7u64.hash(&mut hasher);
+    value.hash(&mut hasher);
+
+    assert_eq!(value.hashed_with(7), hasher.finish());
+    assert_ne!(value.hashed_with(7), value.hashed_with(8));
+}
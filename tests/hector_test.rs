@@ -1,6 +1,6 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
-use std::sync::mpsc::channel;
+use std::sync::mpsc::{channel, Receiver};
 use std::time::Duration;
 
 use timely::dataflow::channels::pact::Pipeline;
@@ -8,8 +8,8 @@ use timely::dataflow::operators::Operator;
 
 use declarative_dataflow::binding::BinaryPredicate::LT;
 use declarative_dataflow::binding::{AsBinding, Binding};
-use declarative_dataflow::plan::hector::{plan_order, source_conflicts};
-use declarative_dataflow::plan::{Hector, Implementable};
+use declarative_dataflow::plan::hector::{order_variables, plan_order, source_conflicts};
+use declarative_dataflow::plan::{Antijoin, Filter, Hector, Implementable, Join};
 use declarative_dataflow::server::Server;
 use declarative_dataflow::timestamp::Time;
 use declarative_dataflow::{AttributeConfig, IndexDirection, QuerySupport};
@@ -107,6 +107,44 @@ fn conflicts() {
     );
 }
 
+/// Ensures that antijoins are expressed as negated bindings, which
+/// Hector can only handle if they don't bind anything new.
+#[test]
+fn antijoin_bindings() {
+    let (a, b, c) = (0, 1, 2);
+    let antijoin = Antijoin {
+        variables: vec![a, c],
+        left_plan: Box::new(Plan::Join(Join {
+            variables: vec![b],
+            left_plan: Box::new(Plan::MatchA(a, ":edge".to_string(), b)),
+            right_plan: Box::new(Plan::MatchA(b, ":edge".to_string(), c)),
+        })),
+        right_plan: Box::new(Plan::MatchA(a, ":blocked".to_string(), c)),
+    };
+
+    assert_eq!(
+        antijoin.into_bindings(),
+        vec![
+            Binding::attribute(a, ":edge", b),
+            Binding::attribute(b, ":edge", c),
+            Binding::not(Binding::attribute(a, ":blocked", c)),
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "can't be implemented via Hector")]
+fn antijoin_bindings_existential() {
+    let (a, b, x) = (0, 1, 2);
+    let antijoin = Antijoin {
+        variables: vec![a],
+        left_plan: Box::new(Plan::MatchA(a, ":edge".to_string(), b)),
+        right_plan: Box::new(Plan::MatchA(a, ":blocked".to_string(), x)),
+    };
+
+    antijoin.into_bindings();
+}
+
 /// Ensures that a valid variable order is chosen depending on the
 /// current source binding.
 #[test]
@@ -164,6 +202,92 @@ fn ordering() {
     }
 }
 
+/// Ensures that a preferred variable order is followed as far as the
+/// bindings permit.
+#[test]
+fn preferred_ordering() {
+    let (e, c, e2, a, n) = (0, 1, 2, 3, 4);
+    let bindings = vec![
+        Binding::attribute(e2, ":age", a),
+        Binding::attribute(e, ":age", a),
+        Binding::attribute(e, ":name", c),
+        Binding::attribute(e2, ":name", n),
+        Binding::constant(c, String("Ivan".to_string())),
+    ];
+
+    assert_eq!(order_variables(0, &bindings, &[n, e]), vec![e2, a, n, e, c]);
+
+    // Variables that can't be bound yet are postponed.
+    assert_eq!(
+        order_variables(2, &bindings, &[n, e2]),
+        vec![e, c, a, e2, n]
+    );
+
+    let hector = Hector {
+        variables: vec![e, c, e2, a, n],
+        bindings,
+        order: Some(vec![n]),
+    };
+
+    assert_eq!(hector.variable_order(0), vec![e2, a, n, e, c]);
+    assert_eq!(hector.variable_order(1), plan_order(1, &hector.bindings).0);
+}
+
+/// Ensures cyclic join trees are rewritten into a single Hector
+/// stage, while acyclic ones are left alone.
+#[test]
+fn optimize() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let config = AttributeConfig {
+                trace_slack: Some(Time::TxId(1)),
+                query_support: QuerySupport::AdaptiveWCO,
+                index_direction: IndexDirection::Both,
+                ..Default::default()
+            };
+
+            server
+                .context
+                .internal
+                .create_transactable_attribute(":edge", config, scope)
+                .unwrap();
+        });
+
+        let (a, b, c) = (0, 1, 2);
+
+        // [?a :edge ?b] [?b :edge ?c]
+        let path = Plan::Join(Join {
+            variables: vec![b],
+            left_plan: Box::new(Plan::MatchA(a, ":edge".to_string(), b)),
+            right_plan: Box::new(Plan::MatchA(b, ":edge".to_string(), c)),
+        });
+
+        assert_eq!(path.clone().optimize(&mut server.context), path);
+
+        // [?a :edge ?b] [?b :edge ?c] [?a :edge ?c]
+        let triangle = Plan::Join(Join {
+            variables: vec![a, c],
+            left_plan: Box::new(path),
+            right_plan: Box::new(Plan::MatchA(a, ":edge".to_string(), c)),
+        });
+
+        assert_eq!(
+            triangle.optimize(&mut server.context),
+            Plan::Hector(Hector {
+                variables: vec![a, c, b],
+                bindings: vec![
+                    Binding::attribute(a, ":edge", b),
+                    Binding::attribute(b, ":edge", c),
+                    Binding::attribute(a, ":edge", c),
+                ],
+                order: None,
+            })
+        );
+    });
+}
+
 #[test]
 fn run_hector_cases() {
     let mut cases: Vec<Case> = vec![
@@ -172,6 +296,7 @@ fn run_hector_cases() {
             plan: Hector {
                 variables: vec![0, 1],
                 bindings: vec![Binding::attribute(0, ":name", 1)],
+                order: None,
             },
             transactions: vec![vec![
                 TxData::add(1, ":name", String("Dipper".to_string())),
@@ -192,6 +317,7 @@ fn run_hector_cases() {
                     Binding::attribute(0, ":name", 1),
                     Binding::constant(1, String("Dipper".to_string())),
                 ],
+                order: None,
             },
             transactions: vec![vec![
                 TxData::add(1, ":name", String("Dipper".to_string())),
@@ -210,6 +336,7 @@ fn run_hector_cases() {
                         Binding::attribute(e, ":name", n),
                         Binding::attribute(e, ":age", a),
                     ],
+                    order: None,
                 },
                 transactions: vec![vec![
                     TxData::add(1, ":name", String("Dipper".to_string())),
@@ -235,6 +362,31 @@ fn run_hector_cases() {
                         Binding::attribute(b, "edge", c),
                         Binding::attribute(a, "edge", c),
                     ],
+                    order: None,
+                },
+                transactions: vec![vec![
+                    TxData::add(100, "edge", Eid(200)),
+                    TxData::add(200, "edge", Eid(300)),
+                    TxData::add(100, "edge", Eid(300)),
+                    TxData::add(100, "edge", Eid(400)),
+                    TxData::add(400, "edge", Eid(500)),
+                    TxData::add(500, "edge", Eid(100)),
+                ]],
+                expectations: vec![vec![(vec![Eid(100), Eid(200), Eid(300)], 0, 1)]],
+            }
+        },
+        {
+            let (a, b, c) = (1, 2, 3);
+            Case {
+                description: "[?a :edge ?b] [?b :edge ?c] [?a :edge ?c] (preferring ?c ?b ?a)",
+                plan: Hector {
+                    variables: vec![a, b, c],
+                    bindings: vec![
+                        Binding::attribute(a, "edge", b),
+                        Binding::attribute(b, "edge", c),
+                        Binding::attribute(a, "edge", c),
+                    ],
+                    order: Some(vec![c, b, a]),
                 },
                 transactions: vec![vec![
                     TxData::add(100, "edge", Eid(200)),
@@ -259,6 +411,7 @@ fn run_hector_cases() {
                         Binding::attribute(e, ":likes", c),
                         Binding::attribute(e, ":fears", d),
                     ],
+                    order: None,
                 },
                 transactions: vec![vec![
                     TxData::add(100, ":name", String("Dipper".to_string())),
@@ -291,6 +444,7 @@ fn run_hector_cases() {
                     Binding::attribute(0, ":num", 2),
                     Binding::binary_predicate(LT, 1, 2),
                 ],
+                order: None,
             },
             transactions: vec![vec![
                 TxData::add(100, ":num", Number(1)),
@@ -315,6 +469,7 @@ fn run_hector_cases() {
                     Binding::constant(1, Number(10)),
                     Binding::binary_predicate(LT, 2, 3),
                 ],
+                order: None,
             },
             transactions: vec![vec![
                 TxData::add(100, ":num", Number(1)),
@@ -340,6 +495,7 @@ fn run_hector_cases() {
                         Binding::attribute(e, ":admin?", admin),
                         Binding::constant(admin, Bool(true)),
                     ],
+                    order: None,
                 },
                 transactions: vec![vec![
                     TxData::add(100, ":name", String("Dipper".to_string())),
@@ -361,6 +517,41 @@ fn run_hector_cases() {
                 )]],
             }
         },
+        {
+            let (e, n, a) = (1, 2, 3);
+            Case {
+                description: "[?e :name ?n] [?e :age ?a] [(< ?a 18)]",
+                plan: Hector {
+                    variables: vec![e, n, a],
+                    bindings: Filter {
+                        variables: vec![a],
+                        predicate: LT,
+                        plan: Box::new(Plan::Hector(Hector {
+                            variables: vec![e, n, a],
+                            bindings: vec![
+                                Binding::attribute(e, ":name", n),
+                                Binding::attribute(e, ":age", a),
+                            ],
+                            order: None,
+                        })),
+                        constants: vec![None, Some(Number(18))],
+                    }
+                    .into_bindings(),
+                    order: None,
+                },
+                transactions: vec![vec![
+                    TxData::add(100, ":name", String("Dipper".to_string())),
+                    TxData::add(100, ":age", Number(12)),
+                    TxData::add(200, ":name", String("Stan".to_string())),
+                    TxData::add(200, ":age", Number(60)),
+                ]],
+                expectations: vec![vec![(
+                    vec![Eid(100), String("Dipper".to_string()), Number(12)],
+                    0,
+                    1,
+                )]],
+            }
+        },
     ];
 
     for case in cases.drain(..) {
@@ -447,3 +638,163 @@ fn run_hector_cases() {
         });
     }
 }
+
+/// Accumulates the results received so far. Delta pipelines may
+/// report changes that cancel each other out.
+fn consolidated(results: &Receiver<(Vec<Value>, u64, isize)>) -> HashMap<(Vec<Value>, u64), isize> {
+    let mut accumulated = HashMap::new();
+    for (tuple, time, diff) in results.try_iter() {
+        *accumulated.entry((tuple, time)).or_insert(0) += diff;
+    }
+
+    accumulated.retain(|_, diff| *diff != 0);
+    accumulated
+}
+
+/// Ensures that changes to negated bindings are reflected in the
+/// results, in both directions.
+#[test]
+fn negated_bindings() {
+    let (a, b, c) = (0, 1, 2);
+
+    // Triangles [?a ?b ?c] without a :blocked edge between ?a and ?c.
+    let plan = Hector {
+        variables: vec![a, b, c],
+        bindings: Antijoin {
+            variables: vec![a, c],
+            left_plan: Box::new(Plan::Hector(Hector {
+                variables: vec![a, b, c],
+                bindings: vec![
+                    Binding::attribute(a, ":edge", b),
+                    Binding::attribute(b, ":edge", c),
+                    Binding::attribute(a, ":edge", c),
+                ],
+                order: None,
+            })),
+            right_plan: Box::new(Plan::MatchA(a, ":blocked".to_string(), c)),
+        }
+        .into_bindings(),
+        order: None,
+    };
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        let deps = plan.dependencies();
+        assert!(deps.attributes.contains(":blocked"));
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for dep in deps.attributes.iter() {
+                let config = AttributeConfig {
+                    trace_slack: Some(Time::TxId(1)),
+                    query_support: QuerySupport::AdaptiveWCO,
+                    index_direction: IndexDirection::Both,
+                    ..Default::default()
+                };
+
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(dep, config, scope)
+                    .unwrap();
+            }
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "unblocked_triangles".to_string(),
+                        plan: Plan::Hector(plan),
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":edge", Eid(2)),
+                    TxData::add(2, ":edge", Eid(3)),
+                    TxData::add(1, ":edge", Eid(3)),
+                    TxData::add(4, ":edge", Eid(5)),
+                    TxData::add(5, ":edge", Eid(6)),
+                    TxData::add(4, ":edge", Eid(6)),
+                    TxData::add(4, ":blocked", Eid(6)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected = HashMap::from_iter(vec![((vec![Eid(1), Eid(2), Eid(3)], 0), 1)]);
+
+        assert_eq!(consolidated(&results), expected);
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":blocked", Eid(3)),
+                    TxData::retract(4, ":blocked", Eid(6)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected = HashMap::from_iter(vec![
+            ((vec![Eid(1), Eid(2), Eid(3)], 1), -1),
+            ((vec![Eid(4), Eid(5), Eid(6)], 1), 1),
+        ]);
+
+        assert_eq!(consolidated(&results), expected);
+    });
+}
+
+#[test]
+fn filter_bindings_draw_symbols_from_context() {
+    use declarative_dataflow::binding::BinaryPredicateBinding;
+    use declarative_dataflow::plan::ImplContext;
+
+    let mut server = Server::<u64, u64>::new(Default::default());
+    let (e, a) = (1, 2);
+
+    // [?e :age ?a] [(< ?a 18)]
+    let filter = Filter {
+        variables: vec![a],
+        predicate: LT,
+        plan: Box::new(Plan::MatchA(e, ":age".to_string(), a)),
+        constants: vec![None, Some(Number(18))],
+    };
+
+    let before = server.context.gensym();
+    let bindings = filter.into_bindings_with(&mut || server.context.gensym());
+    let sym = before - 1;
+
+    assert_eq!(
+        bindings,
+        vec![
+            Binding::attribute(e, ":age", a),
+            Binding::constant(sym, Number(18)),
+            Binding::BinaryPredicate(BinaryPredicateBinding {
+                variables: (a, sym),
+                predicate: LT,
+            }),
+        ]
+    );
+
+    assert_eq!(server.context.gensym(), sym - 1);
+}
@@ -490,3 +490,116 @@ fn bitemporal() {
     ]
     .run();
 }
+
+#[test]
+fn reclock() {
+    use timely::dataflow::operators::capture::Extract;
+    use timely::dataflow::operators::{Capture, Input};
+
+    use declarative_dataflow::operators::Reclock;
+
+    timely::execute_directly(move |worker| {
+        let (mut input, captured) = worker.dataflow::<u64, _, _>(|scope| {
+            let (input, stream) = scope.new_input::<(Value, u64, isize)>();
+            (input, stream.reclock().capture())
+        });
+
+        // Updates arrive out of order, and one of them late.
+        input.send((Number(3), 5, 1));
+        input.send((Number(1), 3, 1));
+        input.advance_to(4);
+        input.send((Number(2), 2, 1));
+        input.send((Number(4), 5, 1));
+        drop(input);
+
+        while worker.step() {}
+
+        let mut updates = Vec::new();
+        for (_time, mut data) in captured.extract() {
+            updates.append(&mut data);
+        }
+
+        assert_eq!(
+            updates,
+            vec![
+                (Number(1), 3, 1),
+                (Number(2), 4, 1),
+                (Number(3), 5, 1),
+                (Number(4), 5, 1),
+            ]
+        );
+    });
+}
+
+#[test]
+fn retime_into() {
+    use timely::dataflow::operators::capture::Extract;
+    use timely::dataflow::operators::{Capture, Input, Probe};
+
+    use differential_dataflow::input::Input as DifferentialInput;
+    use differential_dataflow::operators::Join;
+
+    use declarative_dataflow::operators::Retime;
+
+    timely::execute_directly(move |worker| {
+        let (mut names, lifted) = worker.dataflow::<u64, _, _>(|scope| {
+            let (names, collection) = scope.new_collection::<(Value, Value), isize>();
+            (names, collection)
+        });
+
+        let (mut ages, probe, captured) = worker.dataflow::<Pair<Duration, u64>, _, _>(|scope| {
+            let (ages, collection) = scope.new_collection::<(Value, Value), isize>();
+
+            // Transaction n is taken to have happened n seconds in.
+            let names =
+                lifted.retime_into(scope, |tx: &u64| Pair::new(Duration::from_secs(*tx), 0));
+
+            let joined = names
+                .join(&collection)
+                .map(|(e, (name, age))| (e, name, age));
+            let probe = joined.probe();
+
+            (ages, probe, joined.inner.capture())
+        });
+
+        names.insert((Eid(1), Value::String("Dipper".to_string())));
+        names.insert((Eid(2), Value::String("Mabel".to_string())));
+        names.advance_to(2);
+        names.remove((Eid(2), Value::String("Mabel".to_string())));
+        names.close();
+
+        ages.insert((Eid(1), Number(12)));
+        ages.insert((Eid(2), Number(12)));
+        ages.advance_to(Pair::new(Duration::from_secs(1), 5));
+        ages.close();
+
+        worker.step_while(|| probe.less_than(&Pair::new(Duration::from_secs(3), 0)));
+
+        let mut updates = Vec::new();
+        for (_time, mut data) in captured.extract() {
+            updates.append(&mut data);
+        }
+        updates.sort();
+
+        assert_eq!(
+            updates,
+            vec![
+                (
+                    (Eid(1), Value::String("Dipper".to_string()), Number(12)),
+                    Pair::new(Duration::from_secs(0), 0),
+                    1,
+                ),
+                (
+                    (Eid(2), Value::String("Mabel".to_string()), Number(12)),
+                    Pair::new(Duration::from_secs(0), 0),
+                    1,
+                ),
+                (
+                    (Eid(2), Value::String("Mabel".to_string()), Number(12)),
+                    Pair::new(Duration::from_secs(2), 0),
+                    -1,
+                ),
+            ]
+        );
+    });
+}
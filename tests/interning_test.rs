@@ -0,0 +1,61 @@
+use declarative_dataflow::interning::SymbolTable;
+use declarative_dataflow::Value;
+
+#[test]
+fn interning() {
+    let symbols = SymbolTable::new();
+    let shared = symbols.clone();
+
+    let a = symbols.intern("apple").unwrap();
+    let b = shared.intern("banana").unwrap();
+
+    assert_ne!(a, b);
+    assert_eq!(shared.intern("apple").unwrap(), a);
+    assert_eq!(symbols.lookup("banana"), Some(b));
+    assert_eq!(symbols.lookup("cherry"), None);
+    assert_eq!(symbols.len(), 2);
+
+    assert_eq!(
+        symbols.resolve_value(Value::Interned(a)),
+        Value::String("apple".to_string())
+    );
+    assert_eq!(symbols.resolve_value(Value::Number(1)), Value::Number(1));
+
+    // Symbols interned elsewhere are passed through.
+    let unknown = SymbolTable::new().intern("cherry").unwrap();
+    assert_eq!(
+        symbols.resolve_value(Value::Interned(unknown)),
+        Value::Interned(unknown)
+    );
+}
+
+#[test]
+fn symbols_agree_across_tables() {
+    // Separate tables, e.g. those of workers ingesting different
+    // records, must assign the same symbols.
+    let first = SymbolTable::new();
+    let second = SymbolTable::new();
+
+    second.intern("banana").unwrap();
+
+    assert_eq!(
+        first.intern("apple").unwrap(),
+        second.intern("apple").unwrap()
+    );
+    assert_eq!(first.lookup("banana"), None);
+    assert_eq!(
+        first.intern("banana").unwrap(),
+        second.lookup("banana").unwrap()
+    );
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn interned_to_json() {
+    let symbol = SymbolTable::global().intern("apple").unwrap();
+
+    assert_eq!(
+        serde_json::Value::from(Value::Interned(symbol)),
+        serde_json::Value::String("apple".to_string())
+    );
+}
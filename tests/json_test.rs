@@ -0,0 +1,60 @@
+#[cfg(feature = "serde_json")]
+#[test]
+fn rational_round_trip() {
+    use std::convert::TryFrom;
+
+    use declarative_dataflow::{Rational32, Value};
+
+    for rational in vec![
+        Rational32::new(3, 4),
+        Rational32::new(-7, 2),
+        Rational32::from_integer(0),
+        Rational32::new(i32::max_value(), 3),
+    ] {
+        let json = serde_json::Value::from(Value::Rational32(rational));
+        let parsed = serde_json::from_str::<serde_json::Value>(&json.to_string()).unwrap();
+
+        assert_eq!(
+            Value::try_from(parsed).unwrap(),
+            Value::Rational32(rational)
+        );
+    }
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn invalid_rationals() {
+    use std::convert::TryFrom;
+
+    use declarative_dataflow::{ErrorKind, Value};
+
+    for input in vec![
+        r#"{"num": 1, "den": 0}"#,
+        r#"{"num": 1, "den": 4294967296}"#,
+        r#"{"num": 1.5, "den": 2}"#,
+        r#"{"num": 1, "denominator": 2}"#,
+    ] {
+        let json = serde_json::from_str::<serde_json::Value>(input).unwrap();
+
+        assert_eq!(Value::try_from(json).unwrap_err().kind, ErrorKind::Parse);
+    }
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn scalars_from_json() {
+    use std::convert::TryFrom;
+
+    use declarative_dataflow::Value;
+
+    for value in vec![
+        Value::Null,
+        Value::Bool(true),
+        Value::Number(-3),
+        Value::UNumber(u64::max_value()),
+        Value::String("Dipper".to_string()),
+    ] {
+        let json = serde_json::Value::from(value.clone());
+        assert_eq!(Value::try_from(json).unwrap(), value);
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashSet;
+use std::sync::mpsc::channel;
+
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::Operator;
+
+use declarative_dataflow::plan::Join;
+use declarative_dataflow::server::Server;
+use declarative_dataflow::sources::MemorySource;
+use declarative_dataflow::{Plan, Rule, Value};
+use Value::{Eid, Number, String};
+
+#[test]
+fn memory_source() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let source = MemorySource::new(vec![
+                (1, ":name".to_string(), String("Dipper".to_string())),
+                (1, ":age".to_string(), Number(12)),
+                (2, ":name".to_string(), String("Mabel".to_string())),
+                (2, ":age".to_string(), Number(12)),
+                (3, ":name".to_string(), String("Soos".to_string())),
+            ]);
+
+            server.register_source(Box::new(source), scope).unwrap();
+
+            let (e, name, age) = (0, 1, 2);
+            let plan = Plan::Join(Join {
+                variables: vec![e],
+                left_plan: Box::new(Plan::MatchA(e, ":name".to_string(), name)),
+                right_plan: Box::new(Plan::MatchA(e, ":age".to_string(), age)),
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "people".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for (tuple, _time, diff) in data.iter() {
+                            send_results.send((tuple.clone(), *diff)).unwrap()
+                        }
+                    });
+                });
+        });
+
+        let mut received = HashSet::new();
+        for _ in 0..1000 {
+            worker.step();
+            received.extend(results.try_iter());
+
+            if received.len() >= 2 {
+                break;
+            }
+        }
+
+        let expected: HashSet<(Vec<Value>, isize)> = vec![
+            (vec![Eid(1), String("Dipper".to_string()), Number(12)], 1),
+            (vec![Eid(2), String("Mabel".to_string()), Number(12)], 1),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(received, expected);
+    });
+}
@@ -0,0 +1,120 @@
+#[cfg(feature = "metrics")]
+#[test]
+fn join_throughput() {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use declarative_dataflow::metrics::MetricsSink;
+    use declarative_dataflow::plan::Join;
+    use declarative_dataflow::server::Server;
+    use declarative_dataflow::{AttributeConfig, InputSemantics};
+    use declarative_dataflow::{Plan, Rule, TxData, Value};
+    use Value::{Number, String, UNumber};
+
+    #[derive(Default)]
+    struct CountingSink {
+        counts: Mutex<HashMap<std::string::String, usize>>,
+        inputs: Mutex<HashMap<std::string::String, usize>>,
+        frontiers: Mutex<HashMap<std::string::String, Vec<Value>>>,
+    }
+
+    impl MetricsSink for CountingSink {
+        fn record(&self, operator: &str, tuples_processed: usize) {
+            *self
+                .counts
+                .lock()
+                .unwrap()
+                .entry(operator.to_string())
+                .or_insert(0) += tuples_processed;
+        }
+
+        fn record_input(&self, operator: &str, tuples: usize) {
+            *self
+                .inputs
+                .lock()
+                .unwrap()
+                .entry(operator.to_string())
+                .or_insert(0) += tuples;
+        }
+
+        fn record_frontier(&self, operator: &str, frontier: &[Value]) {
+            self.frontiers
+                .lock()
+                .unwrap()
+                .insert(operator.to_string(), frontier.to_vec());
+        }
+    }
+
+    let sink = Arc::new(CountingSink::default());
+    let worker_sink = sink.clone();
+    let frontier_sink = sink.clone();
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        server.context.metrics = Some(worker_sink as Arc<dyn MetricsSink>);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in &[":name", ":age"] {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+
+            let (e, n, a) = (0, 1, 2);
+            server.test_single(
+                scope,
+                Rule {
+                    name: "people".to_string(),
+                    plan: Plan::Join(Join {
+                        variables: vec![e],
+                        left_plan: Box::new(Plan::MatchA(e, ":name".to_string(), n)),
+                        right_plan: Box::new(Plan::MatchA(e, ":age".to_string(), a)),
+                    }),
+                },
+            );
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":name", String("Dipper".to_string())),
+                    TxData::add(1, ":age", Number(12)),
+                    TxData::add(2, ":name", String("Mabel".to_string())),
+                    TxData::add(2, ":age", Number(12)),
+                    TxData::add(3, ":name", String("Soos".to_string())),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(
+            frontier_sink.frontiers.lock().unwrap().get("Join on [0]"),
+            Some(&vec![UNumber(1)])
+        );
+    });
+
+    let counts = sink.counts.lock().unwrap();
+
+    assert_eq!(counts.get("Join on [0]"), Some(&2));
+    // Patterns are served directly from attributes.
+    assert_eq!(counts.len(), 1);
+
+    // Both attributes feed the join.
+    let inputs = sink.inputs.lock().unwrap();
+    assert_eq!(inputs.get("Join on [0]"), Some(&5));
+    assert_eq!(inputs.len(), 1);
+
+    // The join completes once its inputs are dropped.
+    let frontiers = sink.frontiers.lock().unwrap();
+    assert_eq!(frontiers.get("Join on [0]"), Some(&vec![]));
+}
@@ -11,7 +11,7 @@ use declarative_dataflow::plan::{Hector, Implementable, Union};
 use declarative_dataflow::server::Server;
 use declarative_dataflow::timestamp::Time;
 use declarative_dataflow::{Aid, Value};
-use declarative_dataflow::{AttributeConfig, IndexDirection, QuerySupport};
+use declarative_dataflow::{AttributeConfig, IndexDirection, InputSemantics, QuerySupport};
 use declarative_dataflow::{Plan, Rule, TxData};
 use Value::{Eid, Number, String};
 
@@ -152,6 +152,7 @@ fn or() {
                             Binding::attribute(0, ":name", 1),
                             Binding::constant(1, String("Oleg".to_string())),
                         ],
+                        order: None,
                     }),
                     Plan::Hector(Hector {
                         variables: vec![0],
@@ -159,6 +160,7 @@ fn or() {
                             Binding::attribute(0, ":age", 1),
                             Binding::constant(1, Number(10)),
                         ],
+                        order: None,
                     }),
                 ],
             }),
@@ -181,6 +183,7 @@ fn or() {
                             Binding::attribute(0, ":name", 1),
                             Binding::constant(1, String("Oleg".to_string())),
                         ],
+                        order: None,
                     }),
                     Plan::Hector(Hector {
                         variables: vec![0],
@@ -188,6 +191,7 @@ fn or() {
                             Binding::attribute(0, ":age", 1),
                             Binding::constant(1, Number(30)),
                         ],
+                        order: None,
                     }),
                 ],
             }),
@@ -205,6 +209,7 @@ fn or() {
                             Binding::attribute(0, ":name", 1),
                             Binding::constant(1, String("Petr".to_string())),
                         ],
+                        order: None,
                     }),
                     Plan::Hector(Hector {
                         variables: vec![0],
@@ -212,6 +217,7 @@ fn or() {
                             Binding::attribute(0, ":age", 1),
                             Binding::constant(1, Number(30)),
                         ],
+                        order: None,
                     }),
                 ],
             }),
@@ -269,6 +275,7 @@ fn or() {
                             Binding::attribute(3, ":age", 2),
                             Binding::constant(3, Eid(1)),
                         ],
+                        order: None,
                     }),
                     Plan::Hector(Hector {
                         variables: vec![0],
@@ -279,6 +286,7 @@ fn or() {
                             Binding::attribute(3, ":age", 2),
                             Binding::constant(3, Eid(2)),
                         ],
+                        order: None,
                     }),
                 ],
             }),
@@ -321,6 +329,7 @@ fn or_join() {
                 Plan::Hector(Hector {
                     variables: vec![0],
                     bindings: vec![Binding::attribute(0, ":name", 2)],
+                    order: None,
                 }),
                 Plan::Hector(Hector {
                     variables: vec![0],
@@ -328,6 +337,7 @@ fn or_join() {
                         Binding::attribute(0, ":age", 1),
                         Binding::attribute(0, ":name", 2),
                     ],
+                    order: None,
                 }),
             ],
         }),
@@ -342,3 +352,75 @@ fn or_join() {
         ]],
     }]);
 }
+
+#[test]
+fn or_same_attribute() {
+    let data = vec![
+        TxData::add(1, ":name", String("Ivan".to_string())),
+        TxData::add(1, ":age", Number(10)),
+        TxData::add(2, ":name", String("Oleg".to_string())),
+        TxData::add(2, ":age", Number(20)),
+        TxData::add(3, ":name", String("Petr".to_string())),
+        TxData::add(3, ":age", Number(30)),
+        TxData::add(4, ":name", String("Ivan".to_string())),
+        TxData::add(4, ":age", Number(40)),
+    ];
+
+    run_cases(vec![Case {
+        description:
+            "[:find ?e :where (or [?e :name Ivan] [?e :name Oleg] [?e :age 30] [?e :name Ivan])]",
+        plan: Plan::Union(Union {
+            variables: vec![0],
+            plans: vec![
+                Plan::MatchAV(0, ":name".to_string(), String("Ivan".to_string())),
+                Plan::MatchAV(0, ":name".to_string(), String("Oleg".to_string())),
+                Plan::MatchAV(0, ":age".to_string(), Number(30)),
+                Plan::MatchAV(0, ":name".to_string(), String("Ivan".to_string())),
+            ],
+        }),
+        transactions: vec![data.clone()],
+        expectations: vec![vec![
+            (vec![Eid(1)], 0, 1),
+            (vec![Eid(2)], 0, 1),
+            (vec![Eid(3)], 0, 1),
+            (vec![Eid(4)], 0, 1),
+        ]],
+    }]);
+}
+
+#[test]
+#[should_panic(expected = "Source 1 of the union over [0, 1] doesn't bind variable 1")]
+fn or_incompatible() {
+    let (e, v) = (0, 1);
+    let plan = Plan::Union(Union {
+        variables: vec![e, v],
+        plans: vec![
+            Plan::MatchA(e, ":name".to_string(), v),
+            Plan::MatchAV(e, ":name".to_string(), String("Ivan".to_string())),
+        ],
+    });
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":name",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            server.test_single(
+                scope,
+                Rule {
+                    name: "incompatible".to_string(),
+                    plan,
+                },
+            );
+        });
+    });
+}
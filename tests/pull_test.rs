@@ -6,7 +6,9 @@ use std::time::Duration;
 use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::Operator;
 
-use declarative_dataflow::plan::{Implementable, PullLevel};
+use declarative_dataflow::plan::{
+    Implementable, Predicate, PullCondition, PullEntities, PullLevel, PullPath,
+};
 use declarative_dataflow::server::Server;
 use declarative_dataflow::timestamp::Time;
 use declarative_dataflow::{AttributeConfig, IndexDirection, QuerySupport};
@@ -125,6 +127,7 @@ fn pull_level() {
             pull_attributes: vec!["name".to_string(), "age".to_string()],
             path_attributes: vec![],
             cardinality_many: false,
+            condition: None,
         }),
         transactions: vec![vec![
             TxData::add(100, "admin?", Bool(true)),
@@ -160,6 +163,134 @@ fn pull_level() {
     }]);
 }
 
+#[test]
+fn pull_level_condition() {
+    run_cases(vec![Case {
+        description: "[:find (pull ?e [:name]) :where [?e :age ?a]] for ?e with [?e :admin? false]",
+        plan: Plan::PullLevel(PullLevel {
+            variables: vec![],
+            pull_variable: 0,
+            plan: Box::new(Plan::MatchA(0, "age".to_string(), 1)),
+            pull_attributes: vec!["name".to_string()],
+            path_attributes: vec![],
+            cardinality_many: false,
+            condition: Some(PullCondition {
+                attribute: "admin?".to_string(),
+                predicate: Predicate::EQ,
+                value: Bool(false),
+            }),
+        }),
+        transactions: vec![vec![
+            TxData::add(100, "admin?", Bool(true)),
+            TxData::add(200, "admin?", Bool(false)),
+            TxData::add(100, "name", String("Mabel".to_string())),
+            TxData::add(200, "name", String("Dipper".to_string())),
+            TxData::add(300, "name", String("Soos".to_string())),
+            TxData::add(100, "age", Number(12)),
+            TxData::add(200, "age", Number(13)),
+            TxData::add(300, "age", Number(22)),
+        ]],
+        expectations: vec![vec![(
+            vec![
+                Eid(200),
+                Aid("name".to_string()),
+                String("Dipper".to_string()),
+            ],
+            0,
+            1,
+        )]],
+    }]);
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn pull_to_json() {
+    use declarative_dataflow::plan::pull_to_json;
+    use serde_json::json;
+
+    let results = vec![
+        vec![Eid(100), Value::aid("name"), Value::from("Alice")],
+        vec![Eid(100), Value::aid("alias"), Value::from("Al")],
+        vec![Eid(100), Value::aid("alias"), Value::from("Ally")],
+        vec![
+            Eid(100),
+            Value::aid("friend"),
+            Eid(200),
+            Value::aid("name"),
+            Value::from("Bob"),
+        ],
+        vec![
+            Eid(100),
+            Value::aid("friend"),
+            Eid(300),
+            Value::aid("name"),
+            Value::from("Mabel"),
+        ],
+        vec![
+            Eid(100),
+            Value::aid("best-friend"),
+            Value::aid("name"),
+            Value::from("Mabel"),
+        ],
+    ];
+
+    assert_eq!(
+        pull_to_json(&results),
+        json!({
+            "100": {
+                "name": "Alice",
+                "alias": ["Al", "Ally"],
+                "friend": [{"name": "Bob"}, {"name": "Mabel"}],
+                "best-friend": {"name": "Mabel"},
+            }
+        })
+    );
+
+    // The order in which results arrive must not matter.
+    let mut reversed = results.clone();
+    reversed.reverse();
+
+    assert_eq!(pull_to_json(&reversed), pull_to_json(&results));
+}
+
+#[test]
+fn sort_pulled() {
+    use declarative_dataflow::plan::sort_pulled;
+
+    let mut results = vec![
+        vec![Eid(200), Value::aid("name"), Value::from("Bob")],
+        vec![
+            Eid(100),
+            Value::aid("friend"),
+            Eid(200),
+            Value::aid("name"),
+            Value::from("Bob"),
+        ],
+        vec![Eid(100), Value::aid("name"), Value::from("Alice")],
+        vec![Eid(100), Value::aid("age"), Number(30)],
+        vec![Eid(200), Value::aid("age"), Number(25)],
+    ];
+
+    sort_pulled(&mut results);
+
+    assert_eq!(
+        results,
+        vec![
+            vec![Eid(100), Value::aid("age"), Number(30)],
+            vec![Eid(200), Value::aid("age"), Number(25)],
+            vec![
+                Eid(100),
+                Value::aid("friend"),
+                Eid(200),
+                Value::aid("name"),
+                Value::from("Bob"),
+            ],
+            vec![Eid(100), Value::aid("name"), Value::from("Alice")],
+            vec![Eid(200), Value::aid("name"), Value::from("Bob")],
+        ]
+    );
+}
+
 #[cfg(feature = "graphql")]
 #[test]
 #[rustfmt::skip]
@@ -263,3 +394,78 @@ fn graph_ql() {
         }
     ]);
 }
+
+#[test]
+fn pull_path() {
+    run_cases(vec![Case {
+        description:
+            "[:find ?e ?address :where [?e :person/company ?c] [?c :company/address ?address]]",
+        plan: Plan::PullPath(PullPath {
+            entity: 0,
+            path: vec![
+                ":person/company".to_string(),
+                ":company/address".to_string(),
+            ],
+            value: 1,
+        }),
+        transactions: vec![vec![
+            TxData::add(1, ":person/company", Eid(10)),
+            TxData::add(2, ":person/company", Eid(10)),
+            TxData::add(3, ":person/company", Eid(11)),
+            TxData::add(10, ":company/address", String("Main St".to_string())),
+        ]],
+        expectations: vec![vec![
+            (vec![Eid(1), String("Main St".to_string())], 0, 1),
+            (vec![Eid(2), String("Main St".to_string())], 0, 1),
+        ]],
+    }]);
+}
+
+#[test]
+fn pull_entities() {
+    run_cases(vec![Case {
+        description: "[:find (pull ?e [:name :age]) :in [?e ...]] for [100 300]",
+        plan: Plan::PullEntities(PullEntities {
+            variables: vec![],
+            entities: vec![Eid(300), Eid(100)],
+            pull_attributes: vec!["name".to_string(), "age".to_string()],
+        }),
+        transactions: vec![
+            vec![
+                TxData::add(100, "name", String("Dipper".to_string())),
+                TxData::add(100, "age", Number(12)),
+                TxData::add(200, "name", String("Mabel".to_string())),
+                TxData::add(200, "age", Number(12)),
+                TxData::add(300, "name", String("Soos".to_string())),
+            ],
+            vec![
+                TxData::add(200, "age", Number(13)),
+                TxData::add(300, "age", Number(22)),
+            ],
+        ],
+        expectations: vec![
+            vec![
+                (
+                    vec![
+                        Eid(100),
+                        Aid("name".to_string()),
+                        String("Dipper".to_string()),
+                    ],
+                    0,
+                    1,
+                ),
+                (vec![Eid(100), Aid("age".to_string()), Number(12)], 0, 1),
+                (
+                    vec![
+                        Eid(300),
+                        Aid("name".to_string()),
+                        String("Soos".to_string()),
+                    ],
+                    0,
+                    1,
+                ),
+            ],
+            vec![(vec![Eid(300), Aid("age".to_string()), Number(22)], 1, 1)],
+        ],
+    }]);
+}
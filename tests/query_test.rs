@@ -7,7 +7,11 @@ use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::Operator;
 
 use declarative_dataflow::binding::Binding;
-use declarative_dataflow::plan::{Implementable, Join, Project};
+use declarative_dataflow::plan::{
+    reset_counters, Antijoin, AsOfValid, ComparatorFn, CountIndex, Filter, FilterBy, FilterFn,
+    Implementable, Join, JoinAny, Missing, Predicate, PredicateFn, Project, Rename, Sample,
+    UnionTagged,
+};
 use declarative_dataflow::server::Server;
 use declarative_dataflow::timestamp::Time;
 use declarative_dataflow::{q, Aid, Plan, Rule, TxData, Value};
@@ -33,7 +37,13 @@ fn dependencies(case: &Case) -> HashSet<Aid> {
     deps
 }
 
-fn run_cases(mut cases: Vec<Case>) {
+fn run_cases(cases: Vec<Case>) {
+    run_cases_with(cases, dependencies)
+}
+
+/// Runs cases whose attributes are determined by `dependencies`,
+/// e.g. for plans that can't be expressed as bindings.
+fn run_cases_with(mut cases: Vec<Case>, dependencies: fn(&Case) -> HashSet<Aid>) {
     for case in cases.drain(..) {
         timely::execute_directly(move |worker| {
             let mut server = Server::<u64, u64>::new(Default::default());
@@ -269,6 +279,691 @@ fn wco_base_patterns() {
     ]);
 }
 
+#[test]
+fn free_variables() {
+    let (e, a, n, x) = (0, 1, 2, 3);
+
+    // [?e :age ?a] [?e :name ?n]
+    let join = Plan::Join(Join {
+        variables: vec![e],
+        left_plan: Box::new(Plan::MatchA(e, ":age".to_string(), a)),
+        right_plan: Box::new(Plan::MatchA(e, ":name".to_string(), n)),
+    });
+
+    assert!(join.free_variables().is_empty());
+
+    let projection = Plan::Project(Project {
+        variables: vec![n, a],
+        plan: Box::new(join.clone()),
+    });
+
+    assert!(projection.free_variables().is_empty());
+
+    // (< ?a ?x) with ?x unbound
+    let filter = Plan::Filter(Filter {
+        variables: vec![a, x],
+        predicate: Predicate::LT,
+        plan: Box::new(join),
+        constants: vec![],
+    });
+
+    assert_eq!(filter.free_variables(), vec![x]);
+
+    let projection = Plan::Project(Project {
+        variables: vec![e],
+        plan: Box::new(filter),
+    });
+
+    assert_eq!(projection.free_variables(), vec![x]);
+}
+
+#[test]
+fn deterministic_bindings() {
+    let plan = Plan::MatchEA(100, ":name".to_string(), 1);
+
+    reset_counters();
+    let first = plan.into_bindings();
+
+    reset_counters();
+    let second = plan.into_bindings();
+
+    assert_eq!(first, second);
+}
+
+#[test]
+fn bindings_agree_across_workers() {
+    use declarative_dataflow::plan::{gensym, ImplContext};
+
+    let guards = timely::execute(timely::Configuration::Process(2), |worker| {
+        let (e, n) = (0, 1);
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        // [?e :name ?n] [(< ?n "b")] [100 :name ?n]
+        let plan = Plan::Join(Join::on(
+            Plan::Filter(Filter {
+                variables: vec![n],
+                predicate: Predicate::LT,
+                plan: Box::new(Plan::MatchA(e, ":name".to_string(), n)),
+                constants: vec![None, Some(String("b".to_string()))],
+            }),
+            Plan::MatchEA(100, ":name".to_string(), n),
+        ));
+
+        // Symbols handed out on behalf of unrelated compilations on
+        // this thread must not leak into the bindings.
+        for _ in 0..worker.index() {
+            gensym();
+        }
+
+        plan.into_bindings_with(&mut || server.context.gensym())
+    })
+    .unwrap();
+
+    let bindings: Vec<Vec<Binding>> = guards.join().into_iter().map(Result::unwrap).collect();
+
+    assert_eq!(bindings.len(), 2);
+    assert_eq!(bindings[0], bindings[1]);
+}
+
+#[test]
+fn natural_join() {
+    let (e, a, n, x) = (0, 1, 2, 3);
+
+    // [?e :age ?a] [?e :name ?n]
+    let join = Join::on(
+        Plan::MatchA(e, ":age".to_string(), a),
+        Plan::MatchA(e, ":name".to_string(), n),
+    );
+
+    assert_eq!(join.variables, vec![e]);
+
+    // [?e :age ?a] [?e :min-age ?a]
+    let join = Join::on(
+        Plan::MatchA(e, ":age".to_string(), a),
+        Plan::MatchA(e, ":min-age".to_string(), a),
+    );
+
+    assert_eq!(join.variables, vec![e, a]);
+
+    // [?e :age ?a] [?x :name ?n]
+    let join = Join::on(
+        Plan::MatchA(e, ":age".to_string(), a),
+        Plan::MatchA(x, ":name".to_string(), n),
+    );
+
+    assert!(join.variables.is_empty());
+}
+
+#[test]
+fn negation() {
+    let data = vec![
+        TxData::add(100, ":name", String("Dipper".to_string())),
+        TxData::add(100, ":name", String("Alias".to_string())),
+        TxData::add(200, ":name", String("Mabel".to_string())),
+    ];
+
+    run_cases(vec![Case {
+        description: "negated [:find ?e :where [?e :name ?n]]",
+        plan: Plan::Negate(Box::new(Plan::Project(Project {
+            variables: vec![0],
+            plan: Box::new(Plan::MatchA(0, ":name".to_string(), 1)),
+        }))),
+        transactions: vec![data.clone()],
+        expectations: vec![vec![(vec![Eid(100)], 0, -1), (vec![Eid(200)], 0, -1)]],
+    }]);
+}
+
+#[test]
+fn consolidation() {
+    let data = vec![
+        TxData::add(100, ":name", String("Dipper".to_string())),
+        TxData::add(100, ":name", String("Alias".to_string())),
+        TxData::add(200, ":name", String("Mabel".to_string())),
+    ];
+
+    run_cases(vec![Case {
+        description: "consolidated [:find ?e :where [?e :name ?n]]",
+        plan: Plan::Consolidate(Box::new(Plan::Project(Project {
+            variables: vec![0],
+            plan: Box::new(Plan::MatchA(0, ":name".to_string(), 1)),
+        }))),
+        transactions: vec![data.clone()],
+        expectations: vec![vec![(vec![Eid(100)], 0, 2), (vec![Eid(200)], 0, 1)]],
+    }]);
+}
+
+#[test]
+fn count_index() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let config = AttributeConfig {
+                input_semantics: InputSemantics::Distinct,
+                trace_slack: Some(Time::TxId(1)),
+                query_support: QuerySupport::AdaptiveWCO,
+                index_direction: IndexDirection::Both,
+                ..Default::default()
+            };
+
+            server
+                .context
+                .internal
+                .create_transactable_attribute(":friend", config, scope)
+                .unwrap();
+
+            let forward = Plan::CountIndex(CountIndex {
+                key: 0,
+                attribute: ":friend".to_string(),
+                count: 1,
+                reverse: false,
+            });
+
+            let reverse = Plan::CountIndex(CountIndex {
+                key: 0,
+                attribute: ":friend".to_string(),
+                count: 1,
+                reverse: true,
+            });
+
+            for (name, plan) in vec![("friends", forward), ("befriended", reverse)] {
+                let send_results = send_results.clone();
+                server
+                    .test_single(
+                        scope,
+                        Rule {
+                            name: name.to_string(),
+                            plan,
+                        },
+                    )
+                    .inner
+                    .sink(Pipeline, "Results", move |input| {
+                        input.for_each(|_time, data| {
+                            for datum in data.iter() {
+                                send_results.send((name, datum.clone())).unwrap()
+                            }
+                        });
+                    });
+            }
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":friend", Eid(2)),
+                    TxData::add(1, ":friend", Eid(3)),
+                    TxData::add(2, ":friend", Eid(3)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(&str, (Vec<Value>, u64, isize))> = HashSet::from_iter(vec![
+            ("friends", (vec![Eid(1), Number(2)], 0, 1)),
+            ("friends", (vec![Eid(2), Number(1)], 0, 1)),
+            ("befriended", (vec![Eid(2), Number(1)], 0, 1)),
+            ("befriended", (vec![Eid(3), Number(2)], 0, 1)),
+        ]);
+
+        let actual: HashSet<(&str, (Vec<Value>, u64, isize))> =
+            HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
+
+#[test]
+fn filter_fn() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":age",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            let (e, age) = (0, 1);
+            let plan = Plan::FilterFn(FilterFn {
+                variables: vec![age],
+                predicate: PredicateFn::new(|args| match args[0] {
+                    Number(age) => age % 2 == 0,
+                    _ => false,
+                }),
+                plan: Box::new(Plan::MatchA(e, ":age".to_string(), age)),
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "even_ages".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":age", Number(10)),
+                    TxData::add(2, ":age", Number(11)),
+                    TxData::add(3, ":age", Number(12)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(vec![
+            (vec![Eid(1), Number(10)], 0, 1),
+            (vec![Eid(3), Number(12)], 0, 1),
+        ]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
+
+#[test]
+fn antijoins() {
+    run_cases(vec![{
+        let (c, p) = (0, 1);
+        Case {
+            description: "[:find ?c ?p :where [?c :purchased ?p] (not [?c :returned ?p])]",
+            plan: Plan::Antijoin(Antijoin {
+                variables: vec![c, p],
+                left_plan: Box::new(Plan::MatchA(c, ":purchased".to_string(), p)),
+                right_plan: Box::new(Plan::MatchA(c, ":returned".to_string(), p)),
+            }),
+            transactions: vec![vec![
+                TxData::add(1, ":purchased", Eid(10)),
+                TxData::add(1, ":purchased", Eid(11)),
+                TxData::add(2, ":purchased", Eid(10)),
+                TxData::add(1, ":returned", Eid(10)),
+                TxData::add(2, ":returned", Eid(11)),
+            ]],
+            expectations: vec![vec![
+                (vec![Eid(1), Eid(11)], 0, 1),
+                (vec![Eid(2), Eid(10)], 0, 1),
+            ]],
+        }
+    }]);
+
+    // Existential antijoins can't be expressed as bindings.
+    run_cases_with(
+        vec![
+            {
+                let (c, p, x) = (0, 1, 2);
+                Case {
+                    description: "[:find ?c ?p :where [?c :purchased ?p] (not [?c :returned ?x])]",
+                    plan: Plan::Antijoin(Antijoin {
+                        variables: vec![c],
+                        left_plan: Box::new(Plan::MatchA(c, ":purchased".to_string(), p)),
+                        right_plan: Box::new(Plan::MatchA(c, ":returned".to_string(), x)),
+                    }),
+                    transactions: vec![
+                        vec![
+                            TxData::add(1, ":purchased", Eid(10)),
+                            TxData::add(1, ":purchased", Eid(11)),
+                            TxData::add(2, ":purchased", Eid(10)),
+                            TxData::add(1, ":returned", Eid(10)),
+                            TxData::add(1, ":returned", Eid(11)),
+                        ],
+                        vec![TxData::add(2, ":returned", Eid(12))],
+                    ],
+                    expectations: vec![
+                        vec![(vec![Eid(2), Eid(10)], 0, 1)],
+                        vec![(vec![Eid(2), Eid(10)], 1, -1)],
+                    ],
+                }
+            },
+            {
+                let (c, p, x) = (0, 1, 2);
+                Case {
+                    description: "[:find ?p ?c :where [?c :purchased ?p] (not [?x :recalled ?p])]",
+                    plan: Plan::Antijoin(Antijoin {
+                        variables: vec![p],
+                        left_plan: Box::new(Plan::MatchA(c, ":purchased".to_string(), p)),
+                        right_plan: Box::new(Plan::MatchA(x, ":recalled".to_string(), p)),
+                    }),
+                    transactions: vec![vec![
+                        TxData::add(1, ":purchased", Eid(10)),
+                        TxData::add(1, ":purchased", Eid(11)),
+                        TxData::add(2, ":purchased", Eid(10)),
+                        TxData::add(100, ":recalled", Eid(10)),
+                        TxData::add(101, ":recalled", Eid(10)),
+                    ]],
+                    expectations: vec![vec![(vec![Eid(11), Eid(1)], 0, 1)]],
+                }
+            },
+        ],
+        |case| case.plan.dependencies().attributes,
+    );
+}
+
+#[test]
+fn antijoin_without_count_index() {
+    timely::execute_directly(|worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in &[":purchased", ":returned"] {
+                // Basic query support doesn't maintain count indices,
+                // the antijoin has to use the propose index instead.
+                let config = AttributeConfig {
+                    input_semantics: InputSemantics::Distinct,
+                    trace_slack: Some(Time::TxId(1)),
+                    query_support: QuerySupport::Basic,
+                    ..Default::default()
+                };
+
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(aid, config, scope)
+                    .unwrap();
+            }
+
+            let (c, p, x) = (0, 1, 2);
+            let plan = Plan::Antijoin(Antijoin {
+                variables: vec![c],
+                left_plan: Box::new(Plan::MatchA(c, ":purchased".to_string(), p)),
+                right_plan: Box::new(Plan::MatchA(c, ":returned".to_string(), x)),
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "query".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":purchased", Eid(10)),
+                    TxData::add(2, ":purchased", Eid(10)),
+                    TxData::add(1, ":returned", Eid(10)),
+                    TxData::add(1, ":returned", Eid(11)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let received: Vec<(Vec<Value>, u64, isize)> = results.try_iter().collect();
+        assert_eq!(received, vec![(vec![Eid(2), Eid(10)], 0, 1)]);
+    });
+}
+
+#[test]
+fn antijoin_into_bindings() {
+    // [:find ?c ?p :where [?c :purchased ?p] (not [?c :returned ?p])]
+    let (c, p) = (0, 1);
+    let plan = Plan::Antijoin(Antijoin {
+        variables: vec![c, p],
+        left_plan: Box::new(Plan::MatchA(c, ":purchased".to_string(), p)),
+        right_plan: Box::new(Plan::MatchA(c, ":returned".to_string(), p)),
+    });
+
+    assert_eq!(
+        plan.into_bindings(),
+        vec![
+            Binding::attribute(c, ":purchased", p),
+            Binding::not(Binding::attribute(c, ":returned", p)),
+        ]
+    );
+
+    // [:find ?c :where [?c :purchased ?p] (not [?c :returned ?x])]
+    let x = 2;
+    let existential = Plan::Antijoin(Antijoin {
+        variables: vec![c],
+        left_plan: Box::new(Plan::MatchA(c, ":purchased".to_string(), p)),
+        right_plan: Box::new(Plan::MatchA(c, ":returned".to_string(), x)),
+    });
+
+    let bindings = std::panic::AssertUnwindSafe(|| existential.into_bindings());
+    assert!(std::panic::catch_unwind(bindings).is_err());
+    assert!(existential
+        .dependencies()
+        .attributes
+        .contains(&":returned".to_string()));
+}
+
+#[test]
+fn filter_variables() {
+    run_cases(vec![{
+        let (e, start, end) = (0, 1, 2);
+        Case {
+            description: "[:find ?e ?s ?f :where [?e :start ?s] [?e :end ?f] [(< ?s ?f)]]",
+            plan: Plan::Filter(Filter {
+                variables: vec![start, end],
+                predicate: Predicate::LT,
+                plan: Box::new(Plan::Join(Join {
+                    variables: vec![e],
+                    left_plan: Box::new(Plan::MatchA(e, ":start".to_string(), start)),
+                    right_plan: Box::new(Plan::MatchA(e, ":end".to_string(), end)),
+                })),
+                constants: vec![],
+            }),
+            transactions: vec![vec![
+                TxData::add(1, ":start", Number(5)),
+                TxData::add(1, ":end", Number(10)),
+                TxData::add(2, ":start", Number(7)),
+                TxData::add(2, ":end", Number(3)),
+            ]],
+            expectations: vec![vec![(vec![Eid(1), Number(5), Number(10)], 0, 1)]],
+        }
+    }]);
+}
+
+#[test]
+fn filter_combinators() {
+    run_cases(vec![{
+        let (e, start, end) = (0, 1, 2);
+        Case {
+            description: "[:find ?e ?s ?f :where [?e :start ?s] [?e :end ?f] (not (or [(< ?s ?f)] [(= ?s ?f)]))]",
+            plan: Plan::Filter(Filter {
+                variables: vec![start, end],
+                predicate: Predicate::Not(Box::new(Predicate::Or(vec![
+                    Predicate::LT,
+                    Predicate::EQ,
+                ]))),
+                plan: Box::new(Plan::Join(Join {
+                    variables: vec![e],
+                    left_plan: Box::new(Plan::MatchA(e, ":start".to_string(), start)),
+                    right_plan: Box::new(Plan::MatchA(e, ":end".to_string(), end)),
+                })),
+                constants: vec![],
+            }),
+            transactions: vec![vec![
+                TxData::add(1, ":start", Number(5)),
+                TxData::add(1, ":end", Number(10)),
+                TxData::add(2, ":start", Number(7)),
+                TxData::add(2, ":end", Number(3)),
+                TxData::add(3, ":start", Number(4)),
+                TxData::add(3, ":end", Number(4)),
+            ]],
+            expectations: vec![vec![(vec![Eid(2), Number(7), Number(3)], 0, 1)]],
+        }
+    }]);
+}
+
+#[test]
+fn filter_range() {
+    let data = vec![
+        TxData::add(1, ":age", Number(12)),
+        TxData::add(2, ":age", Number(18)),
+        TxData::add(3, ":age", Number(25)),
+        TxData::add(4, ":age", Number(30)),
+        TxData::add(5, ":age", String("unknown".to_string())),
+    ];
+
+    run_cases(vec![
+        {
+            let (e, age) = (0, 1);
+            Case {
+                description: "[:find ?e ?age :where [?e :age ?age] [(>= ?age 18)] [(< ?age 30)]]",
+                plan: Plan::Filter(Filter {
+                    variables: vec![age],
+                    predicate: Predicate::LT,
+                    plan: Box::new(Plan::Filter(Filter {
+                        variables: vec![age],
+                        predicate: Predicate::GTE,
+                        plan: Box::new(Plan::MatchA(e, ":age".to_string(), age)),
+                        constants: vec![None, Some(Number(18))],
+                    })),
+                    constants: vec![None, Some(Number(30))],
+                }),
+                transactions: vec![
+                    data.clone(),
+                    vec![
+                        TxData::retract(3, ":age", Number(25)),
+                        TxData::add(3, ":age", Number(26)),
+                        TxData::add(6, ":age", Number(29)),
+                    ],
+                ],
+                expectations: vec![
+                    vec![
+                        (vec![Eid(2), Number(18)], 0, 1),
+                        (vec![Eid(3), Number(25)], 0, 1),
+                    ],
+                    vec![
+                        (vec![Eid(3), Number(25)], 1, -1),
+                        (vec![Eid(3), Number(26)], 1, 1),
+                        (vec![Eid(6), Number(29)], 1, 1),
+                    ],
+                ],
+            }
+        },
+        {
+            let (e, age) = (0, 1);
+            Case {
+                description: "[:find ?e ?age :where [?e :age ?age] [(< 20 ?age)] [(<= ?age 30)]]",
+                plan: Plan::Filter(Filter {
+                    variables: vec![age],
+                    predicate: Predicate::LTE,
+                    plan: Box::new(Plan::Filter(Filter {
+                        variables: vec![age],
+                        predicate: Predicate::LT,
+                        plan: Box::new(Plan::MatchA(e, ":age".to_string(), age)),
+                        constants: vec![Some(Number(20))],
+                    })),
+                    constants: vec![None, Some(Number(30))],
+                }),
+                transactions: vec![data.clone()],
+                expectations: vec![vec![
+                    (vec![Eid(3), Number(25)], 0, 1),
+                    (vec![Eid(4), Number(30)], 0, 1),
+                ]],
+            }
+        },
+        {
+            let (e, age) = (0, 1);
+            Case {
+                description: "[:find ?e ?age :where [?e :age ?age] [(= ?age 30)]]",
+                plan: Plan::Filter(Filter {
+                    variables: vec![age],
+                    predicate: Predicate::EQ,
+                    plan: Box::new(Plan::MatchA(e, ":age".to_string(), age)),
+                    constants: vec![None, Some(Number(30))],
+                }),
+                transactions: vec![data],
+                expectations: vec![vec![(vec![Eid(4), Number(30)], 0, 1)]],
+            }
+        },
+    ]);
+}
+
+#[test]
+fn filter_ignore_case() {
+    let data = vec![
+        TxData::add(1, ":name", String("Dipper".to_string())),
+        TxData::add(2, ":name", String("DIPPER".to_string())),
+        TxData::add(3, ":name", String("Mabel".to_string())),
+        TxData::add(4, ":name", String("Grunkle Dipper".to_string())),
+        TxData::add(5, ":name", Number(12)),
+    ];
+
+    run_cases(vec![
+        {
+            let (e, n) = (0, 1);
+            Case {
+                description:
+                    "[:find ?e ?n :where [?e :name ?n] [(equal-ignore-case ?n \"dipper\")]]",
+                plan: Plan::Filter(Filter {
+                    variables: vec![n],
+                    predicate: Predicate::EqualIgnoreCase,
+                    plan: Box::new(Plan::MatchA(e, ":name".to_string(), n)),
+                    constants: vec![None, Some(String("dipper".to_string()))],
+                }),
+                transactions: vec![data.clone()],
+                expectations: vec![vec![
+                    (vec![Eid(1), String("Dipper".to_string())], 0, 1),
+                    (vec![Eid(2), String("DIPPER".to_string())], 0, 1),
+                ]],
+            }
+        },
+        {
+            let (e, n) = (0, 1);
+            Case {
+                description:
+                    "[:find ?e ?n :where [?e :name ?n] [(contains-ignore-case ?n \"dIpP\")]]",
+                plan: Plan::Filter(Filter {
+                    variables: vec![n],
+                    predicate: Predicate::ContainsIgnoreCase,
+                    plan: Box::new(Plan::MatchA(e, ":name".to_string(), n)),
+                    constants: vec![None, Some(String("dIpP".to_string()))],
+                }),
+                transactions: vec![data.clone()],
+                expectations: vec![vec![
+                    (vec![Eid(1), String("Dipper".to_string())], 0, 1),
+                    (vec![Eid(2), String("DIPPER".to_string())], 0, 1),
+                    (vec![Eid(4), String("Grunkle Dipper".to_string())], 0, 1),
+                ]],
+            }
+        },
+    ]);
+}
+
 #[test]
 fn joins() {
     run_cases(vec![{
@@ -461,3 +1156,719 @@ fn wco_join_many() {
 //         ],
 //     }
 // },
+
+#[test]
+fn explain() {
+    let (a, b, name, x) = (0, 1, 2, 3);
+    let plan = Plan::Project(Project {
+        variables: vec![a, name],
+        plan: Box::new(Plan::Antijoin(Antijoin {
+            variables: vec![b],
+            left_plan: Box::new(Plan::Join(Join {
+                variables: vec![b],
+                left_plan: Box::new(Plan::MatchA(a, ":follows".to_string(), b)),
+                right_plan: Box::new(Plan::MatchA(b, ":name".to_string(), name)),
+            })),
+            right_plan: Box::new(Plan::NameExpr(vec![b, x], "blocked".to_string())),
+        })),
+    });
+
+    assert_eq!(
+        plan.explain(),
+        "Project binds [0, 2]
+  Antijoin on [1] binds [1]
+    Join on [1] binds [1] (reverse indices: :follows)
+      MatchA [0 :follows 1] binds [0, 1] (attributes: :follows)
+      MatchA [1 :name 2] binds [1, 2] (attributes: :name)
+    NameExpr blocked binds [1, 3] (names: blocked)
+"
+    );
+}
+
+#[test]
+fn rename() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in &[":name", ":owner"] {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+
+            let (e, name, pet, owner) = (0, 1, 2, 3);
+            let plan = Plan::Join(Join {
+                variables: vec![e],
+                left_plan: Box::new(Plan::MatchA(e, ":name".to_string(), name)),
+                right_plan: Box::new(Plan::Rename(Rename {
+                    plan: Box::new(Plan::MatchA(pet, ":owner".to_string(), owner)),
+                    mapping: vec![(owner, e)],
+                })),
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "owners".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":name", String("Dipper".to_string())),
+                    TxData::add(2, ":name", String("Mabel".to_string())),
+                    TxData::add(10, ":owner", Eid(2)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(vec![(
+            vec![Eid(2), String("Mabel".to_string()), Eid(10)],
+            0,
+            1,
+        )]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
+
+#[test]
+#[should_panic]
+fn rename_collision() {
+    let (e, v) = (0, 1);
+    let plan = Plan::Rename(Rename {
+        plan: Box::new(Plan::MatchA(e, ":name".to_string(), v)),
+        mapping: vec![(v, e)],
+    });
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":name",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            server.test_single(
+                scope,
+                Rule {
+                    name: "collision".to_string(),
+                    plan,
+                },
+            );
+        });
+    });
+}
+
+#[test]
+fn as_of_valid() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in &[":policy/holder", ":valid/from", ":valid/to"] {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+
+            let (e, holder) = (0, 1);
+            let plan = Plan::AsOfValid(AsOfValid {
+                variable: e,
+                plan: Box::new(Plan::MatchA(e, ":policy/holder".to_string(), holder)),
+                valid_from: ":valid/from".to_string(),
+                valid_to: ":valid/to".to_string(),
+                valid_time: Number(18),
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "policies".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":policy/holder", String("Dipper".to_string())),
+                    TxData::add(1, ":valid/from", Number(10)),
+                    TxData::add(1, ":valid/to", Number(20)),
+                    TxData::add(2, ":policy/holder", String("Mabel".to_string())),
+                    TxData::add(2, ":valid/from", Number(15)),
+                    TxData::add(3, ":policy/holder", String("Stan".to_string())),
+                    TxData::add(3, ":valid/from", Number(25)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(vec![
+            (vec![Eid(1), String("Dipper".to_string())], 0, 1),
+            (vec![Eid(2), String("Mabel".to_string())], 0, 1),
+        ]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+
+        // Closing an interval retroactively retracts the entity.
+        server
+            .transact(vec![TxData::add(2, ":valid/to", Number(17))], 0, 0)
+            .unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> =
+            HashSet::from_iter(vec![(vec![Eid(2), String("Mabel".to_string())], 1, -1)]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
+
+#[test]
+fn missing() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in &[":name", ":email"] {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+
+            // [:find ?e ?name :where [?e :name ?name] (not [?e :email _])]
+            let (e, name) = (0, 1);
+            let plan = Plan::Missing(Missing {
+                variable: e,
+                plan: Box::new(Plan::MatchA(e, ":name".to_string(), name)),
+                attribute: ":email".to_string(),
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "unreachable_by_email".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":name", String("Dipper".to_string())),
+                    TxData::add(1, ":email", String("dipper@mystery.shack".to_string())),
+                    TxData::add(2, ":name", String("Mabel".to_string())),
+                    TxData::add(3, ":email", String("stan@mystery.shack".to_string())),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> =
+            HashSet::from_iter(vec![(vec![Eid(2), String("Mabel".to_string())], 0, 1)]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+
+        // Adding the attribute retracts the entity.
+        server
+            .transact(
+                vec![TxData::add(
+                    2,
+                    ":email",
+                    String("mabel@mystery.shack".to_string()),
+                )],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> =
+            HashSet::from_iter(vec![(vec![Eid(2), String("Mabel".to_string())], 1, -1)]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
+
+#[test]
+fn filter_comparator() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":version",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            // Versions are ordered by their numeric components,
+            // rather than lexicographically.
+            let comparator = ComparatorFn::new(|a, b| {
+                let components = |version: &Value| -> Vec<u64> {
+                    match version {
+                        String(version) => version
+                            .split('.')
+                            .map(|x| x.parse::<u64>().unwrap_or(0))
+                            .collect(),
+                        _ => Vec::new(),
+                    }
+                };
+
+                components(a).cmp(&components(b))
+            });
+
+            let (e, version) = (0, 1);
+            let plan = Plan::FilterBy(FilterBy {
+                filter: Filter {
+                    variables: vec![version],
+                    predicate: Predicate::GT,
+                    plan: Box::new(Plan::MatchA(e, ":version".to_string(), version)),
+                    constants: vec![None, Some(String("1.9.0".to_string()))],
+                },
+                comparator,
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "recent".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":version", String("1.2.3".to_string())),
+                    TxData::add(2, ":version", String("1.9.0".to_string())),
+                    TxData::add(3, ":version", String("1.10.0".to_string())),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> =
+            HashSet::from_iter(vec![(vec![Eid(3), String("1.10.0".to_string())], 0, 1)]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn filter_by_is_not_serializable() {
+    let (e, version) = (0, 1);
+    let filter = Filter {
+        variables: vec![version],
+        predicate: Predicate::GT,
+        plan: Box::new(Plan::MatchA(e, ":version".to_string(), version)),
+        constants: vec![None, Some(String("1.9.0".to_string()))],
+    };
+
+    assert!(serde_json::to_string(&Plan::Filter(filter.clone())).is_ok());
+
+    // Comparators can't be sent over the wire, so serializing must
+    // fail rather than silently falling back to the natural order.
+    let plan = Plan::FilterBy(FilterBy {
+        filter,
+        comparator: ComparatorFn::new(|a, b| b.cmp(a)),
+    });
+
+    assert!(serde_json::to_string(&plan).is_err());
+}
+
+#[test]
+fn union_tagged() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in &[":crm/email", ":billing/email"] {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+
+            let (source, e, email) = (0, 1, 2);
+            let plan = Plan::UnionTagged(UnionTagged {
+                tag: source,
+                variables: vec![e, email],
+                plans: vec![
+                    Plan::MatchA(e, ":crm/email".to_string(), email),
+                    Plan::MatchA(e, ":billing/email".to_string(), email),
+                ],
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "emails".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":crm/email", String("dipper@mystery.shack".to_string())),
+                    TxData::add(
+                        1,
+                        ":billing/email",
+                        String("dipper@mystery.shack".to_string()),
+                    ),
+                    TxData::add(
+                        2,
+                        ":billing/email",
+                        String("mabel@mystery.shack".to_string()),
+                    ),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(vec![
+            (
+                vec![
+                    Number(0),
+                    Eid(1),
+                    String("dipper@mystery.shack".to_string()),
+                ],
+                0,
+                1,
+            ),
+            (
+                vec![
+                    Number(1),
+                    Eid(1),
+                    String("dipper@mystery.shack".to_string()),
+                ],
+                0,
+                1,
+            ),
+            (
+                vec![Number(1), Eid(2), String("mabel@mystery.shack".to_string())],
+                0,
+                1,
+            ),
+        ]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
+
+#[test]
+fn sample() {
+    use declarative_dataflow::Rational32;
+
+    let (e, v) = (0, 1);
+    let sample = |rate, seed| Sample {
+        plan: Box::new(Plan::MatchA(e, ":num".to_string(), v)),
+        rate,
+        seed,
+    };
+
+    let tuples: Vec<Vec<Value>> = (0..1000).map(|i| vec![Eid(i), Number(i as i64)]).collect();
+
+    assert!(tuples
+        .iter()
+        .all(|tuple| !sample(Rational32::from_integer(0), 7).retains(tuple)));
+    assert!(tuples
+        .iter()
+        .all(|tuple| sample(Rational32::from_integer(1), 7).retains(tuple)));
+
+    let half = sample(Rational32::new(1, 2), 7);
+    let retained: HashSet<Vec<Value>> = tuples
+        .iter()
+        .filter(|tuple| half.retains(tuple))
+        .cloned()
+        .collect();
+
+    assert!(retained.len() > 400 && retained.len() < 600);
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":num",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "sample".to_string(),
+                        plan: Plan::Sample(half),
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                (0..1000)
+                    .map(|i| TxData::add(i, ":num", Number(i as i64)))
+                    .collect(),
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> =
+            retained.iter().map(|tuple| (tuple.clone(), 0, 1)).collect();
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+
+        // Retractions only affect tuples that were sampled before.
+        server
+            .transact(
+                (0..1000)
+                    .map(|i| TxData::retract(i, ":num", Number(i as i64)))
+                    .collect(),
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> = retained
+            .iter()
+            .map(|tuple| (tuple.clone(), 1, -1))
+            .collect();
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
+
+#[test]
+fn join_any() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in &[":order/buyer", ":person/alias"] {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+
+            // [:find ?o ?b ?p ?a
+            //  :where [?o :order/buyer ?b] [?p :person/alias ?a]
+            //         (or [(= ?b ?p)] [(= ?b ?a)])]
+            let (o, b, p, a) = (0, 1, 2, 3);
+            let plan = Plan::JoinAny(JoinAny {
+                conditions: vec![vec![(b, p)], vec![(b, a)]],
+                left_plan: Box::new(Plan::MatchA(o, ":order/buyer".to_string(), b)),
+                right_plan: Box::new(Plan::MatchA(p, ":person/alias".to_string(), a)),
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "orders_by_alias".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(10, ":person/alias", Eid(11)),
+                    TxData::add(12, ":person/alias", Eid(12)),
+                    TxData::add(1, ":order/buyer", Eid(10)),
+                    TxData::add(2, ":order/buyer", Eid(11)),
+                    TxData::add(3, ":order/buyer", Eid(12)),
+                    TxData::add(4, ":order/buyer", Eid(13)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        // Order 3 satisfies both conditions, but is reported once.
+        let expected: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(vec![
+            (vec![Eid(1), Eid(10), Eid(10), Eid(11)], 0, 1),
+            (vec![Eid(2), Eid(11), Eid(10), Eid(11)], 0, 1),
+            (vec![Eid(3), Eid(12), Eid(12), Eid(12)], 0, 1),
+        ]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}
@@ -1,9 +1,9 @@
 use std::sync::mpsc::channel;
 
 use declarative_dataflow::plan::{Join, Project};
-use declarative_dataflow::server::Server;
+use declarative_dataflow::server::{Server, SubscriptionEvent};
 use declarative_dataflow::timestamp::Time;
-use declarative_dataflow::{AttributeConfig, IndexDirection, QuerySupport};
+use declarative_dataflow::{AttributeConfig, IndexDirection, InputSemantics, QuerySupport};
 use declarative_dataflow::{Plan, Rule, TxData, Value};
 use Value::{Eid, String};
 
@@ -72,6 +72,36 @@ fn match_ea_after_input() {
     });
 }
 
+#[test]
+fn match_unknown_attribute() {
+    use declarative_dataflow::server::Register;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        server
+            .register(Register {
+                rules: vec![
+                    Rule {
+                        name: "by_entity".to_string(),
+                        plan: Plan::MatchEA(1, ":unknown".to_string(), 0),
+                    },
+                    Rule {
+                        name: "by_value".to_string(),
+                        plan: Plan::MatchAV(0, ":unknown".to_string(), String("x".to_string())),
+                    },
+                ],
+                publish: vec!["by_entity".to_string(), "by_value".to_string()],
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            assert!(server.interest("by_entity", scope).is_err());
+            assert!(server.interest("by_value", scope).is_err());
+        });
+    });
+}
+
 #[test]
 fn join_after_input() {
     timely::execute_directly(move |worker| {
@@ -180,3 +210,1400 @@ fn join_after_input() {
         assert_eq!(results.recv().unwrap(), (vec![Eid(101), Eid(1)], 1));
     });
 }
+
+#[test]
+fn materialize_as_attribute() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        let (e, parent, grandparent) = (0, 1, 2);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let config = AttributeConfig {
+                index_direction: IndexDirection::Both,
+                query_support: QuerySupport::Basic,
+                trace_slack: Some(Time::TxId(1)),
+                ..Default::default()
+            };
+
+            server
+                .context
+                .internal
+                .create_transactable_attribute(":parent", config.clone(), scope)
+                .unwrap();
+
+            // [?e :grandparent ?g] <- [?e :parent ?p] [?p :parent ?g]
+            server
+                .materialize_as_attribute(
+                    scope,
+                    &Plan::Join(Join {
+                        variables: vec![parent],
+                        left_plan: Box::new(Plan::MatchA(e, ":parent".to_string(), parent)),
+                        right_plan: Box::new(Plan::MatchA(
+                            parent,
+                            ":parent".to_string(),
+                            grandparent,
+                        )),
+                    }),
+                    ":grandparent",
+                    e,
+                    grandparent,
+                    config,
+                )
+                .unwrap();
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "grandparents".to_string(),
+                        plan: Plan::MatchA(e, ":grandparent".to_string(), grandparent),
+                    },
+                )
+                .inspect(move |x| {
+                    send_results.send((x.0.clone(), x.2)).unwrap();
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":parent", Eid(2)),
+                    TxData::add(2, ":parent", Eid(3)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(results.recv().unwrap(), (vec![Eid(1), Eid(3)], 1));
+    });
+}
+
+#[test]
+fn subscribe() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        let results = worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":name",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            server
+                .subscribe(
+                    scope,
+                    Rule {
+                        name: "names".to_string(),
+                        plan: Plan::MatchA(0, ":name".to_string(), 1),
+                    },
+                )
+                .unwrap()
+        });
+
+        server
+            .transact(
+                vec![TxData::add(1, ":name", String("Dipper".to_string()))],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(
+            results.recv().unwrap(),
+            SubscriptionEvent::Batch(vec![(vec![Eid(1), String("Dipper".to_string())], 0, 1)])
+        );
+        assert_eq!(
+            results.recv().unwrap(),
+            SubscriptionEvent::FrontierAdvanced(vec![1])
+        );
+    });
+}
+
+#[test]
+fn dump_relation() {
+    use timely::dataflow::operators::Probe;
+
+    use differential_dataflow::input::Input;
+    use differential_dataflow::operators::arrange::ArrangeBySelf;
+
+    use declarative_dataflow::plan::ImplContext;
+    use declarative_dataflow::RelationConfig;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        let (mut input, probe) = worker.dataflow::<u64, _, _>(|scope| {
+            let (input, collection) = scope.new_collection::<Vec<Value>, isize>();
+            let arranged = collection.arrange_by_self();
+
+            server.context.internal.register_arrangement(
+                "names".to_string(),
+                RelationConfig { trace_slack: None },
+                arranged.trace,
+            );
+
+            (input, arranged.stream.probe())
+        });
+
+        input.insert(vec![Eid(1), String("Dipper".to_string())]);
+        input.insert(vec![Eid(2), String("Mabel".to_string())]);
+        input.advance_to(1);
+        input.flush();
+
+        worker.step_while(|| probe.less_than(input.time()));
+
+        let mut tuples = server.context.dump_relation("names").unwrap();
+        tuples.sort();
+
+        assert_eq!(
+            tuples,
+            vec![
+                (vec![Eid(1), String("Dipper".to_string())], 0, 1),
+                (vec![Eid(2), String("Mabel".to_string())], 0, 1),
+            ]
+        );
+        assert!(server.context.dump_relation("unknown").is_none());
+    });
+}
+
+#[test]
+fn changes_between() {
+    use timely::dataflow::operators::Probe;
+
+    use differential_dataflow::input::Input;
+    use differential_dataflow::operators::arrange::ArrangeBySelf;
+    use differential_dataflow::trace::TraceReader;
+
+    use declarative_dataflow::plan::ImplContext;
+    use declarative_dataflow::RelationConfig;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        let (mut input, probe) = worker.dataflow::<u64, _, _>(|scope| {
+            let (input, collection) = scope.new_collection::<Vec<Value>, isize>();
+            let arranged = collection.arrange_by_self();
+
+            server.context.internal.register_arrangement(
+                "names".to_string(),
+                RelationConfig { trace_slack: None },
+                arranged.trace,
+            );
+
+            (input, arranged.stream.probe())
+        });
+
+        input.insert(vec![Eid(1), String("Dipper".to_string())]);
+        input.insert(vec![Eid(2), String("Mabel".to_string())]);
+        input.advance_to(1);
+        input.remove(vec![Eid(2), String("Mabel".to_string())]);
+        input.insert(vec![Eid(3), String("Stan".to_string())]);
+        input.advance_to(2);
+        input.insert(vec![Eid(4), String("Soos".to_string())]);
+        input.advance_to(3);
+        input.flush();
+
+        worker.step_while(|| probe.less_than(input.time()));
+
+        assert_eq!(
+            server.context.changes_between("names", 0, 1).unwrap(),
+            vec![
+                (vec![Eid(2), String("Mabel".to_string())], 1, -1),
+                (vec![Eid(3), String("Stan".to_string())], 1, 1),
+            ]
+        );
+        assert_eq!(
+            server.context.changes_between("names", 1, 2).unwrap(),
+            vec![(vec![Eid(4), String("Soos".to_string())], 2, 1)]
+        );
+        assert_eq!(
+            server
+                .context
+                .changes_between("unknown", 0, 1)
+                .unwrap_err()
+                .category,
+            "df.error.category/not-found"
+        );
+
+        // History before the compaction frontier is no longer available.
+        server
+            .context
+            .global_arrangement("names")
+            .unwrap()
+            .advance_by(&[2]);
+
+        assert_eq!(
+            server
+                .context
+                .changes_between("names", 0, 2)
+                .unwrap_err()
+                .category,
+            "df.error.category/incorrect"
+        );
+        assert!(server.context.changes_between("names", 2, 3).is_ok());
+    });
+}
+
+#[test]
+fn is_stable() {
+    use timely::dataflow::operators::Probe;
+
+    use differential_dataflow::input::Input;
+    use differential_dataflow::operators::arrange::ArrangeBySelf;
+
+    use declarative_dataflow::plan::ImplContext;
+    use declarative_dataflow::RelationConfig;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        let (mut input, probe) = worker.dataflow::<u64, _, _>(|scope| {
+            let (input, collection) = scope.new_collection::<Vec<Value>, isize>();
+            let arranged = collection.arrange_by_self();
+
+            server.context.internal.register_arrangement(
+                "names".to_string(),
+                RelationConfig { trace_slack: None },
+                arranged.trace,
+            );
+
+            (input, arranged.stream.probe())
+        });
+
+        input.insert(vec![Eid(1), String("Dipper".to_string())]);
+        input.advance_to(1);
+        input.insert(vec![Eid(2), String("Mabel".to_string())]);
+        input.advance_to(2);
+        input.flush();
+
+        worker.step_while(|| probe.less_than(input.time()));
+
+        assert_eq!(server.context.relation_frontier("names"), Some(vec![2]));
+        assert!(server.context.is_stable("names", &1));
+        assert!(!server.context.is_stable("names", &2));
+        assert!(!server.context.is_stable("unknown", &0));
+
+        input.advance_to(3);
+        input.flush();
+
+        worker.step_while(|| probe.less_than(input.time()));
+
+        assert!(server.context.is_stable("names", &2));
+    });
+}
+
+#[test]
+fn catalog() {
+    use declarative_dataflow::plan::ImplContext;
+    use declarative_dataflow::server::Register;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (e, v) = (0, 1);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in [":name", ":age"].iter() {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+        });
+
+        server
+            .register(Register {
+                rules: vec![
+                    Rule {
+                        name: "names".to_string(),
+                        plan: Plan::MatchA(e, ":name".to_string(), v),
+                    },
+                    Rule {
+                        name: "ages".to_string(),
+                        plan: Plan::MatchA(e, ":age".to_string(), v),
+                    },
+                ],
+                publish: vec![],
+            })
+            .unwrap();
+
+        assert_eq!(
+            server.context.attributes(),
+            vec![":age".to_string(), ":name".to_string()]
+        );
+
+        let names: Vec<&str> = server
+            .context
+            .rules()
+            .iter()
+            .map(|rule| rule.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["ages", "names"]);
+    });
+}
+
+#[test]
+fn consolidate_results() {
+    use timely::dataflow::channels::pact::Pipeline;
+    use timely::dataflow::operators::Operator;
+
+    use declarative_dataflow::plan::Antijoin;
+    use declarative_dataflow::server::Configuration;
+
+    timely::execute_directly(move |worker| {
+        let config = Configuration {
+            consolidate_results: true,
+            ..Default::default()
+        };
+        let mut server = Server::<u64, u64>::new(config);
+        let (send_results, results) = channel();
+        let (e, n, b) = (0, 1, 2);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in [":name", ":banned?"].iter() {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "allowed".to_string(),
+                        plan: Plan::Antijoin(Antijoin {
+                            variables: vec![e],
+                            left_plan: Box::new(Plan::MatchA(e, ":name".to_string(), n)),
+                            right_plan: Box::new(Plan::MatchA(e, ":banned?".to_string(), b)),
+                        }),
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":name", String("Dipper".to_string())),
+                    TxData::add(2, ":name", String("Gideon".to_string())),
+                    TxData::add(2, ":banned?", Value::Bool(true)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        // Gideon must not show up at all, not even as an addition
+        // cancelled out by a retraction.
+        let actual: Vec<(Vec<Value>, u64, isize)> = results.try_iter().collect();
+
+        assert_eq!(
+            actual,
+            vec![(vec![Eid(1), String("Dipper".to_string())], 0, 1)]
+        );
+    });
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn schema() {
+    use declarative_dataflow::plan::ImplContext;
+    use declarative_dataflow::server::Schema;
+
+    let json = r#"{
+        "attributes": [
+            {
+                "name": ":person/name",
+                "config": {
+                    "input_semantics": "LastWriteWins",
+                    "trace_slack": null,
+                    "index_direction": "Forward",
+                    "query_support": "Basic",
+                    "timeless": false,
+                    "value_type": {"String": ""}
+                }
+            },
+            {
+                "name": ":person/friend",
+                "config": {
+                    "input_semantics": "Distinct",
+                    "trace_slack": null,
+                    "index_direction": "Both",
+                    "query_support": "AdaptiveWCO",
+                    "timeless": false,
+                    "value_type": null
+                }
+            }
+        ]
+    }"#;
+
+    let schema = Schema::from_reader(json.as_bytes()).unwrap();
+    assert_eq!(schema.attributes.len(), 2);
+    assert!(schema.sources.is_empty());
+
+    assert_eq!(
+        Schema::from_reader("{\"attributes\": 42}".as_bytes())
+            .unwrap_err()
+            .category,
+        "df.error.category/incorrect"
+    );
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        worker
+            .dataflow::<u64, _, _>(|scope| schema.install(&mut server, scope))
+            .unwrap();
+
+        assert_eq!(
+            server.context.attributes(),
+            vec![":person/friend".to_string(), ":person/name".to_string()]
+        );
+
+        // Installing the same schema twice is a conflict.
+        assert!(worker
+            .dataflow::<u64, _, _>(|scope| schema.install(&mut server, scope))
+            .is_err());
+    });
+}
+
+#[test]
+fn register_collection() {
+    use differential_dataflow::input::Input;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        let mut input = worker.dataflow::<u64, _, _>(|scope| {
+            let (input, collection) = scope.new_collection::<Vec<Value>, isize>();
+
+            server.register_collection("names", &collection).unwrap();
+            assert!(server.register_collection("names", &collection).is_err());
+
+            input
+        });
+
+        input.insert(vec![Eid(1), String("Dipper".to_string())]);
+        input.insert(vec![Eid(2), String("Mabel".to_string())]);
+        input.advance_to(1);
+        input.flush();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "external".to_string(),
+                        plan: Plan::Project(Project {
+                            variables: vec![1],
+                            plan: Box::new(Plan::NameExpr(vec![0, 1], "names".to_string())),
+                        }),
+                    },
+                )
+                .inspect(move |x| {
+                    send_results.send((x.0.clone(), x.2)).unwrap();
+                });
+        });
+
+        server.advance_domain(None, 1).unwrap();
+
+        worker.step_while(|| server.is_any_outdated());
+
+        let mut received = vec![results.recv().unwrap(), results.recv().unwrap()];
+        received.sort();
+
+        assert_eq!(
+            received,
+            vec![
+                (vec![String("Dipper".to_string())], 1),
+                (vec![String("Mabel".to_string())], 1),
+            ]
+        );
+    });
+}
+
+#[test]
+fn budget() {
+    use declarative_dataflow::server::{Budget, Register};
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":name",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![Rule {
+                    name: "names".to_string(),
+                    plan: Plan::MatchA(0, ":name".to_string(), 1),
+                }],
+                publish: vec!["names".to_string()],
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let relation = server
+                .interest_with_budget("names", scope, Budget::Steps(1))
+                .unwrap();
+
+            relation.probe_with(&mut server.probe);
+        });
+
+        server
+            .interests
+            .entry("names".to_string())
+            .or_default()
+            .insert(7);
+
+        // Queries that keep up are left alone.
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+        assert!(server.enforce_budgets().is_empty());
+
+        // Queries lagging behind for too long are shut down.
+        server.advance_domain(None, 2).unwrap();
+        assert!(server.enforce_budgets().is_empty());
+
+        let exhausted = server.enforce_budgets();
+        assert_eq!(exhausted.len(), 1);
+        assert_eq!(exhausted[0].0, "names");
+        assert!(exhausted[0].1.contains(&7));
+        assert!(!server.interests.contains_key("names"));
+    });
+}
+
+#[test]
+fn budget_conflict() {
+    use declarative_dataflow::server::{Budget, Register};
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":name",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![Rule {
+                    name: "names".to_string(),
+                    plan: Plan::MatchA(0, ":name".to_string(), 1),
+                }],
+                publish: vec!["names".to_string()],
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .interest_with_budget("names", scope, Budget::Steps(1))
+                .unwrap();
+
+            // Repeating the same budget is fine, a different one
+            // must not silently replace it.
+            server
+                .interest_with_budget("names", scope, Budget::Steps(1))
+                .unwrap();
+
+            let error = server
+                .interest_with_budget("names", scope, Budget::Steps(10))
+                .unwrap_err();
+            assert_eq!(error.category, "df.error.category/conflict");
+        });
+
+        // The original budget still applies.
+        server.advance_domain(None, 1).unwrap();
+        assert!(server.enforce_budgets().is_empty());
+
+        let exhausted = server.enforce_budgets();
+        assert_eq!(exhausted.len(), 1);
+        assert_eq!(exhausted[0].0, "names");
+    });
+}
+
+#[test]
+fn reverse_index_on_demand() {
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        // [:find ?item ?person ?shop :where [?person :likes ?item] [?shop :sells ?item]]
+        let (person, item, shop) = (0, 1, 2);
+        let plan = Plan::Join(Join {
+            variables: vec![item],
+            left_plan: Box::new(Plan::MatchA(person, ":likes".to_string(), item)),
+            right_plan: Box::new(Plan::MatchA(shop, ":sells".to_string(), item)),
+        });
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in [":likes", ":sells"].iter() {
+                let config = AttributeConfig {
+                    index_direction: IndexDirection::Forward,
+                    trace_slack: Some(Time::TxId(1)),
+                    ..Default::default()
+                };
+
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(aid, config, scope)
+                    .unwrap();
+            }
+        });
+
+        assert!(!server
+            .context
+            .internal
+            .reverse_propose
+            .contains_key(":likes"));
+
+        let tx_data = vec![
+            TxData::add(1, ":likes", String("Pitt Cola".to_string())),
+            TxData::add(2, ":likes", String("Question Mark".to_string())),
+            TxData::add(100, ":sells", String("Pitt Cola".to_string())),
+        ];
+
+        server.transact(tx_data, 0, 0).unwrap();
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "shopping".to_string(),
+                        plan,
+                    },
+                )
+                .inspect(move |x| {
+                    send_results.send((x.0.clone(), x.2)).unwrap();
+                });
+        });
+
+        assert!(server
+            .context
+            .internal
+            .reverse_propose
+            .contains_key(":likes"));
+        assert!(server
+            .context
+            .internal
+            .reverse_propose
+            .contains_key(":sells"));
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(
+            results.recv().unwrap(),
+            (vec![String("Pitt Cola".to_string()), Eid(1), Eid(100)], 1)
+        );
+    });
+}
+
+#[test]
+fn reverse_index_outlives_query() {
+    use declarative_dataflow::server::Register;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        // [:find ?item ?person ?shop :where [?person :likes ?item] [?shop :sells ?item]]
+        let (person, item, shop) = (0, 1, 2);
+        let plan = Plan::Join(Join {
+            variables: vec![item],
+            left_plan: Box::new(Plan::MatchA(person, ":likes".to_string(), item)),
+            right_plan: Box::new(Plan::MatchA(shop, ":sells".to_string(), item)),
+        });
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in [":likes", ":sells"].iter() {
+                let config = AttributeConfig {
+                    index_direction: IndexDirection::Forward,
+                    trace_slack: Some(Time::TxId(1)),
+                    ..Default::default()
+                };
+
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(aid, config, scope)
+                    .unwrap();
+            }
+        });
+
+        server
+            .register(Register {
+                rules: vec![Rule {
+                    name: "first".to_string(),
+                    plan: plan.clone(),
+                }],
+                publish: vec!["first".to_string()],
+            })
+            .unwrap();
+
+        // The first query to need them creates the reverse indices.
+        worker.dataflow::<u64, _, _>(|scope| {
+            server.interest("first", scope).unwrap();
+        });
+
+        server
+            .interests
+            .entry("first".to_string())
+            .or_default()
+            .insert(7);
+
+        server.uninterest(7, "first").unwrap();
+
+        let tx_data = vec![
+            TxData::add(1, ":likes", String("Pitt Cola".to_string())),
+            TxData::add(100, ":sells", String("Pitt Cola".to_string())),
+        ];
+
+        server.transact(tx_data, 0, 0).unwrap();
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        // Later queries must still see data transacted after the
+        // first one went away.
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "second".to_string(),
+                        plan,
+                    },
+                )
+                .inspect(move |x| {
+                    send_results.send((x.0.clone(), x.2)).unwrap();
+                });
+        });
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(
+            results.recv().unwrap(),
+            (vec![String("Pitt Cola".to_string()), Eid(1), Eid(100)], 1)
+        );
+    });
+}
+
+#[test]
+fn stratification() {
+    use declarative_dataflow::plan::{Antijoin, Hector, Union};
+    use declarative_dataflow::server::Register;
+    use declarative_dataflow::Binding;
+
+    let mut server = Server::<u64, u64>::new(Default::default());
+    let (e, v) = (0, 1);
+
+    let register = |server: &mut Server<u64, u64>, name: &str, plan: Plan| {
+        server.register(Register {
+            rules: vec![Rule {
+                name: name.to_string(),
+                plan,
+            }],
+            publish: vec![],
+        })
+    };
+
+    // Negating a rule that doesn't depend on the negating one is fine.
+    assert!(register(
+        &mut server,
+        "parents",
+        Plan::MatchA(e, ":parent".to_string(), v)
+    )
+    .is_ok());
+    assert!(register(
+        &mut server,
+        "orphans",
+        Plan::Antijoin(Antijoin {
+            variables: vec![e],
+            left_plan: Box::new(Plan::MatchA(e, ":name".to_string(), v)),
+            right_plan: Box::new(Plan::NameExpr(vec![e, v], "parents".to_string())),
+        }),
+    )
+    .is_ok());
+
+    // So is positive recursion.
+    assert!(register(
+        &mut server,
+        "ancestors",
+        Plan::Union(Union {
+            variables: vec![e, v],
+            plans: vec![
+                Plan::NameExpr(vec![e, v], "parents".to_string()),
+                Plan::NameExpr(vec![e, v], "ancestors".to_string()),
+            ],
+        }),
+    )
+    .is_ok());
+
+    // Negation within a cycle is rejected, regardless of the order in
+    // which the rules involved are registered.
+    assert!(register(
+        &mut server,
+        "odd",
+        Plan::Negate(Box::new(Plan::NameExpr(vec![e], "even".to_string()))),
+    )
+    .is_ok());
+
+    let error = register(
+        &mut server,
+        "even",
+        Plan::NameExpr(vec![e], "odd".to_string()),
+    )
+    .unwrap_err();
+    assert_eq!(error.category, "df.error.category/incorrect");
+    assert!(!server.context.rules.contains_key("even"));
+
+    // The same holds for negated bindings of worst-case optimal
+    // joins, which match binary rules as attributes.
+    assert!(register(
+        &mut server,
+        "unreciprocated",
+        Plan::Hector(Hector {
+            variables: vec![e, v],
+            bindings: vec![
+                Binding::attribute(e, ":likes", v),
+                Binding::not(Binding::attribute(v, "reciprocated", e)),
+            ],
+            order: None,
+        }),
+    )
+    .is_ok());
+
+    let error = register(
+        &mut server,
+        "reciprocated",
+        Plan::NameExpr(vec![e, v], "unreciprocated".to_string()),
+    )
+    .unwrap_err();
+    assert_eq!(error.category, "df.error.category/incorrect");
+    assert!(!server.context.rules.contains_key("reciprocated"));
+}
+
+#[test]
+fn prepared() {
+    use timely::dataflow::channels::pact::Pipeline;
+    use timely::dataflow::operators::Operator;
+
+    use declarative_dataflow::server::Register;
+    use Value::Number;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = std::sync::mpsc::channel();
+
+        // [:find ?e :in ?age :where [?e :age ?age]]
+        let (e, age) = (0, 1);
+        server
+            .register(Register {
+                rules: vec![Rule {
+                    name: "aged".to_string(),
+                    plan: Plan::Project(Project {
+                        variables: vec![e, age],
+                        plan: Box::new(Plan::Join(Join {
+                            variables: vec![age],
+                            left_plan: Box::new(Plan::MatchA(e, ":age".to_string(), age)),
+                            right_plan: Box::new(Plan::parameters("aged", vec![age])),
+                        })),
+                    }),
+                }],
+                publish: vec!["aged".to_string()],
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":age",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            assert!(server.interest_prepared("aged", &[], scope).is_err());
+            assert!(server
+                .interest_prepared("unknown", &[Number(25)], scope)
+                .is_err());
+
+            // Asking for the same parameters twice must not bind them
+            // twice.
+            for param in &[25, 40, 25] {
+                let send_results = send_results.clone();
+                server
+                    .interest_prepared("aged", &[Number(*param)], scope)
+                    .unwrap()
+                    .inner
+                    .sink(Pipeline, "Results", move |input| {
+                        input.for_each(|_time, data| {
+                            for (tuple, _time, diff) in data.iter() {
+                                send_results.send((*param, tuple.clone(), *diff)).unwrap();
+                            }
+                        });
+                    });
+            }
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":age", Number(12)),
+                    TxData::add(2, ":age", Number(25)),
+                    TxData::add(3, ":age", Number(40)),
+                    TxData::add(4, ":age", Number(25)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let mut results: Vec<(i64, Vec<Value>, isize)> = results.try_iter().collect();
+        results.sort();
+
+        assert_eq!(
+            results,
+            vec![
+                (25, vec![Eid(2)], 1),
+                (25, vec![Eid(2)], 1),
+                (25, vec![Eid(4)], 1),
+                (25, vec![Eid(4)], 1),
+                (40, vec![Eid(3)], 1),
+            ]
+        );
+    });
+}
+
+#[test]
+fn match_rule() {
+    use timely::dataflow::channels::pact::Pipeline;
+    use timely::dataflow::operators::Operator;
+
+    use declarative_dataflow::plan::Project;
+    use declarative_dataflow::server::Register;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+        let (x, y, z) = (0, 1, 2);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":parent",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+        });
+
+        server
+            .register(Register {
+                rules: vec![
+                    Rule {
+                        name: "grandparent".to_string(),
+                        plan: Plan::Project(Project {
+                            variables: vec![x, z],
+                            plan: Box::new(Plan::Join(Join {
+                                variables: vec![y],
+                                left_plan: Box::new(Plan::MatchA(x, ":parent".to_string(), y)),
+                                right_plan: Box::new(Plan::MatchA(y, ":parent".to_string(), z)),
+                            })),
+                        }),
+                    },
+                    Rule {
+                        name: "parent-of-1".to_string(),
+                        plan: Plan::MatchEA(1, ":parent".to_string(), x),
+                    },
+                    Rule {
+                        name: "unary".to_string(),
+                        plan: Plan::MatchA(x, "parent-of-1".to_string(), y),
+                    },
+                ],
+                publish: vec![],
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let patterns = vec![
+                ("all", Plan::MatchA(x, "grandparent".to_string(), z)),
+                ("of-1", Plan::MatchEA(1, "grandparent".to_string(), z)),
+                ("to-3", Plan::MatchAV(x, "grandparent".to_string(), Eid(3))),
+            ];
+
+            for (name, plan) in patterns.into_iter() {
+                let send_results = send_results.clone();
+
+                server
+                    .test_single(
+                        scope,
+                        Rule {
+                            name: name.to_string(),
+                            plan,
+                        },
+                    )
+                    .inner
+                    .sink(Pipeline, "Results", move |input| {
+                        input.for_each(|_time, data| {
+                            for (tuple, _time, diff) in data.iter() {
+                                send_results.send((name, tuple.clone(), *diff)).unwrap()
+                            }
+                        });
+                    });
+            }
+
+            // Only binary rules can be matched against.
+            assert_eq!(
+                server.interest("unary", scope).unwrap_err().category,
+                "df.error.category/incorrect"
+            );
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":parent", Eid(2)),
+                    TxData::add(2, ":parent", Eid(3)),
+                    TxData::add(4, ":parent", Eid(2)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let mut actual: Vec<(&str, Vec<Value>, isize)> = results.try_iter().collect();
+        actual.sort();
+
+        assert_eq!(
+            actual,
+            vec![
+                ("all", vec![Eid(1), Eid(3)], 1),
+                ("all", vec![Eid(4), Eid(3)], 1),
+                ("of-1", vec![Eid(3)], 1),
+                ("to-3", vec![Eid(1)], 1),
+                ("to-3", vec![Eid(4)], 1),
+            ]
+        );
+    });
+}
+
+#[test]
+fn allowed_attributes() {
+    use declarative_dataflow::server::Register;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (e, n, s) = (0, 1, 2);
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            for aid in [":name", ":secret"].iter() {
+                server
+                    .context
+                    .internal
+                    .create_transactable_attribute(
+                        aid,
+                        AttributeConfig::tx_time(InputSemantics::Raw),
+                        scope,
+                    )
+                    .unwrap();
+            }
+        });
+
+        server.context.allowed_attributes = Some(vec![":name".to_string()].into_iter().collect());
+
+        server
+            .register(Register {
+                rules: vec![
+                    Rule {
+                        name: "names".to_string(),
+                        plan: Plan::MatchA(e, ":name".to_string(), n),
+                    },
+                    Rule {
+                        name: "secrets".to_string(),
+                        plan: Plan::MatchA(e, ":secret".to_string(), s),
+                    },
+                    Rule {
+                        name: "names-and-secrets".to_string(),
+                        plan: Plan::Join(Join {
+                            variables: vec![e],
+                            left_plan: Box::new(Plan::NameExpr(vec![e, n], "names".to_string())),
+                            right_plan: Box::new(Plan::NameExpr(vec![e, s], "secrets".to_string())),
+                        }),
+                    },
+                ],
+                publish: vec![],
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            assert!(server.interest("names", scope).is_ok());
+
+            // Access is checked transitively, through all rules a
+            // query depends on.
+            for name in ["secrets", "names-and-secrets"].iter() {
+                assert_eq!(
+                    server.interest(name, scope).unwrap_err().category,
+                    "df.error.category/forbidden"
+                );
+            }
+        });
+    });
+}
+
+#[test]
+fn rename_attribute() {
+    use declarative_dataflow::plan::ImplContext;
+    use declarative_dataflow::server::Register;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":name",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+        });
+
+        server
+            .transact(
+                vec![TxData::add(1, ":name", String("Dipper".to_string()))],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        server
+            .context
+            .rename_attribute(":name", ":person/name")
+            .unwrap();
+
+        assert!(!server.context.has_attribute(":name"));
+        assert!(server.context.has_attribute(":person/name"));
+        assert_eq!(
+            server.context.renamed_attribute(":name"),
+            Some(":person/name".to_string())
+        );
+
+        // Unknown sources and existing targets are rejected.
+        assert_eq!(
+            server
+                .context
+                .rename_attribute(":name", ":alias")
+                .unwrap_err()
+                .category,
+            "df.error.category/not-found"
+        );
+        assert_eq!(
+            server
+                .context
+                .rename_attribute(":person/name", ":person/name")
+                .unwrap_err()
+                .category,
+            "df.error.category/conflict"
+        );
+
+        // Plans referring to the former name fail.
+        server
+            .register(Register {
+                rules: vec![Rule {
+                    name: "stale".to_string(),
+                    plan: Plan::MatchA(0, ":name".to_string(), 1),
+                }],
+                publish: vec![],
+            })
+            .unwrap();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let error = server.interest("stale", scope).unwrap_err();
+            assert_eq!(error.category, "df.error.category/not-found");
+            assert!(error.message.contains(":person/name"));
+        });
+
+        // Whereas the new name resolves to the existing data, and
+        // continues to accept transactions.
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "renamed".to_string(),
+                        plan: Plan::MatchA(0, ":person/name".to_string(), 1),
+                    },
+                )
+                .inspect(move |x| {
+                    send_results.send((x.0.clone(), x.2)).unwrap();
+                });
+        });
+
+        server
+            .transact(
+                vec![TxData::add(2, ":person/name", String("Mabel".to_string()))],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 2).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        assert_eq!(
+            results.recv().unwrap(),
+            (vec![Eid(1), String("Dipper".to_string())], 1)
+        );
+        assert_eq!(
+            results.recv().unwrap(),
+            (vec![Eid(2), String("Mabel".to_string())], 1)
+        );
+    });
+}
+
+#[test]
+fn interest_arranged() {
+    use differential_dataflow::trace::{Cursor, TraceReader};
+
+    use declarative_dataflow::server::Register;
+    use Value::Number;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+
+        let (e, age) = (0, 1);
+        server
+            .register(Register {
+                rules: vec![Rule {
+                    name: "by_age".to_string(),
+                    plan: Plan::MatchA(e, ":age".to_string(), age),
+                }],
+                publish: vec!["by_age".to_string()],
+            })
+            .unwrap();
+
+        let mut trace = worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":age",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            assert!(server.interest_arranged("by_age", &[2], scope).is_err());
+
+            server.interest_arranged("by_age", &[age], scope).unwrap()
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":age", Number(12)),
+                    TxData::add(2, ":age", Number(12)),
+                    TxData::add(3, ":age", Number(60)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let (mut cursor, storage) = trace.cursor();
+        let key = vec![Number(12)];
+        let mut entities = Vec::new();
+
+        cursor.seek_key(&storage, &key);
+        assert_eq!(cursor.get_key(&storage), Some(&key));
+
+        while let Some(tuple) = cursor.get_val(&storage) {
+            let mut count = 0;
+            cursor.map_times(&storage, |_time, diff| count += diff);
+            if count > 0 {
+                entities.push(tuple.clone());
+            }
+            cursor.step_val(&storage);
+        }
+
+        assert_eq!(entities, vec![vec![Eid(1)], vec![Eid(2)]]);
+    });
+}
@@ -0,0 +1,43 @@
+use differential_dataflow::input::Input;
+use differential_dataflow::operators::arrange::ArrangeBySelf;
+
+use declarative_dataflow::ShutdownHandle;
+
+#[test]
+fn tagged_buttons() {
+    timely::execute_directly(|worker| {
+        let mut trace = worker.dataflow::<u64, _, _>(|scope| {
+            let (_input, collection) = scope.new_collection::<u64, isize>();
+            collection.arrange_by_self().trace
+        });
+
+        let mut handle = ShutdownHandle::empty();
+        assert!(handle.is_empty());
+
+        let mut other = ShutdownHandle::empty();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            let (_arranged, button) = trace.import_core(scope, "a");
+            handle.add_tagged_button("a", button);
+
+            let (_arranged, button) = trace.import_core(scope, "untagged");
+            handle.add_button(button);
+
+            let (_arranged, button) = trace.import_core(scope, "b");
+            other.add_tagged_button("b", button);
+        });
+
+        // Tags survive merging.
+        handle.merge_with(other);
+
+        assert_eq!(handle.len(), 3);
+        assert_eq!(handle.tags(), vec!["a", "b"]);
+
+        // Only the tagged button is pressed.
+        assert_eq!(handle.shutdown_tagged("a"), 1);
+        assert_eq!(handle.shutdown_tagged("a"), 0);
+
+        assert_eq!(handle.len(), 2);
+        assert_eq!(handle.tags(), vec!["b"]);
+    });
+}
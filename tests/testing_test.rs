@@ -0,0 +1,42 @@
+#[cfg(feature = "test-util")]
+#[test]
+fn run_plan() {
+    use declarative_dataflow::plan::Join;
+    use declarative_dataflow::testing::run_plan;
+    use declarative_dataflow::{Plan, Value};
+    use Value::{Eid, Number, String};
+
+    let facts = vec![
+        (":name".to_string(), 1, String("Dipper".to_string())),
+        (":name".to_string(), 2, String("Mabel".to_string())),
+        (":age".to_string(), 1, Number(12)),
+        (":age".to_string(), 2, Number(12)),
+        (":age".to_string(), 3, Number(40)),
+    ];
+
+    let (e, name, age) = (0, 1, 2);
+    let plan = Plan::Join(Join {
+        variables: vec![e],
+        left_plan: Box::new(Plan::MatchA(e, ":name".to_string(), name)),
+        right_plan: Box::new(Plan::MatchA(e, ":age".to_string(), age)),
+    });
+
+    assert_eq!(
+        run_plan(facts.clone(), &plan),
+        vec![
+            vec![Eid(1), String("Dipper".to_string()), Number(12)],
+            vec![Eid(2), String("Mabel".to_string()), Number(12)],
+        ]
+    );
+
+    assert_eq!(
+        run_plan(
+            facts.clone(),
+            &Plan::MatchAV(e, ":age".to_string(), Number(12))
+        ),
+        vec![vec![Eid(1)], vec![Eid(2)]]
+    );
+
+    // Attributes only mentioned by the plan exist, but are empty.
+    assert!(run_plan(facts, &Plan::MatchA(e, ":missing".to_string(), name)).is_empty());
+}
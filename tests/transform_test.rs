@@ -7,11 +7,13 @@ use timely::dataflow::channels::pact::Pipeline;
 use timely::dataflow::operators::Operator;
 
 use declarative_dataflow::binding::Binding;
-use declarative_dataflow::plan::{Function, Implementable, Transform};
+use declarative_dataflow::plan::{
+    DatePart, Function, FunctionFn, Implementable, Join, Project, Transform, TransformFn,
+};
 use declarative_dataflow::server::Server;
 use declarative_dataflow::{Aid, Value};
 use declarative_dataflow::{AttributeConfig, InputSemantics, Plan, Rule, TxData};
-use Value::{Eid, Instant};
+use Value::{Eid, Instant, UNumber};
 
 struct Case {
     description: &'static str,
@@ -32,47 +34,291 @@ fn dependencies(case: &Case) -> HashSet<Aid> {
     deps
 }
 
+#[test]
+fn date_part() {
+    let t = Instant(1_540_048_515_500);
+    let extract = |part| Function::DatePart(part).apply(&[&t], &[]);
+
+    assert_eq!(extract(DatePart::Year), Value::Number(2018));
+    assert_eq!(extract(DatePart::Month), Value::Number(10));
+    assert_eq!(extract(DatePart::Day), Value::Number(20));
+    assert_eq!(extract(DatePart::Hour), Value::Number(15));
+    assert_eq!(extract(DatePart::Minute), Value::Number(15));
+    assert_eq!(extract(DatePart::Weekday), Value::Number(6));
+
+    let epoch = Instant(0);
+    assert_eq!(
+        Function::DatePart(DatePart::Weekday).apply(&[&epoch], &[]),
+        Value::Number(4)
+    );
+}
+
+#[test]
+fn checked_arithmetic() {
+    use Value::{Number, UNumber};
+
+    let big = UNumber(std::u64::MAX - 1);
+
+    assert_eq!(
+        Function::ADD.apply(&[&big, &UNumber(1)], &[]),
+        UNumber(std::u64::MAX)
+    );
+    assert_eq!(
+        Function::SUBTRACT.apply(&[&Number(10), &Number(3)], &[]),
+        Number(7)
+    );
+    assert_eq!(
+        Function::SUBTRACT.apply(&[&Number(3)], &[Some(Number(10))]),
+        Number(7)
+    );
+
+    // Overflows yield no result.
+    assert_eq!(
+        Function::ADD.apply(&[&UNumber(std::u64::MAX), &UNumber(1)], &[]),
+        Value::Null
+    );
+    assert_eq!(
+        Function::SUBTRACT.apply(&[&Number(std::i64::MIN), &Number(1)], &[]),
+        Value::Null
+    );
+    assert!(Function::SUBTRACT
+        .apply_many(&[&UNumber(0), &UNumber(1)], &[])
+        .is_empty());
+
+    let mixed = std::panic::catch_unwind(|| Function::ADD.apply(&[&Number(1), &UNumber(1)], &[]));
+    assert!(mixed.is_err());
+}
+
+#[test]
+fn coalesce() {
+    use Value::{Null, Number};
+
+    assert_eq!(
+        Function::COALESCE.apply(&[&Null, &Number(2), &Number(3)], &[]),
+        Number(2)
+    );
+    assert_eq!(
+        Function::COALESCE.apply(&[&Null, &Null], &[Some(Number(0))]),
+        Number(0)
+    );
+    assert_eq!(Function::COALESCE.apply(&[&Null, &Null], &[]), Null);
+}
+
+#[test]
+fn split() {
+    use Value::String;
+
+    let split = |s: &str, constants: &[Option<Value>]| {
+        Function::SPLIT.apply_many(&[&String(s.to_string())], constants)
+    };
+
+    assert_eq!(
+        split("a,b,,c", &[]),
+        vec![
+            String("a".to_string()),
+            String("b".to_string()),
+            String("c".to_string())
+        ]
+    );
+    assert_eq!(
+        split("a; b", &[None, Some(String("; ".to_string()))]),
+        vec![String("a".to_string()), String("b".to_string())]
+    );
+    assert!(split("", &[]).is_empty());
+
+    // Single-valued functions produce exactly one result.
+    assert_eq!(
+        Function::ADD.apply_many(&[&Value::Number(1), &Value::Number(2)], &[]),
+        vec![Value::Number(3)]
+    );
+}
+
+#[test]
+fn cast() {
+    use Value::{Number, UNumber};
+
+    let cast = |v: Value, exemplar: Value| Function::CAST.apply(&[&v], &[None, Some(exemplar)]);
+
+    assert_eq!(cast(Eid(5), Number(0)), Number(5));
+    assert_eq!(cast(Number(5), Eid(0)), Eid(5));
+    assert_eq!(cast(UNumber(5), Eid(0)), Eid(5));
+    assert_eq!(cast(Eid(5), UNumber(0)), UNumber(5));
+
+    assert_eq!(cast(Number(-1), Eid(0)), Value::Null);
+    assert_eq!(cast(Eid(std::u64::MAX), Number(0)), Value::Null);
+    assert_eq!(
+        Function::CAST.apply_many(&[&Number(-1)], &[None, Some(Eid(0))]),
+        vec![]
+    );
+}
+
 #[test]
 fn run_transform_cases() {
-    let mut cases = vec![Case {
-        description: "[:find ?h :where [?e :timestamp ?t] [(interval ?t) ?h]]",
+    let mut cases = vec![
+        Case {
+            description: "[:find ?h :where [?e :timestamp ?t] [(interval ?t) ?h]]",
+            plan: {
+                let (e, t, h) = (1, 2, 3);
+                let constants = vec![None, None];
+                // let constants = vec![None, Some(Value::String(String::from("hour")))];
+                Plan::Transform(Transform {
+                    variables: vec![t],
+                    result_variable: h,
+                    plan: Box::new(Plan::MatchA(e, ":timestamp".to_string(), t)),
+                    function: Function::TRUNCATE,
+                    constants,
+                })
+            },
+            transactions: vec![vec![
+                TxData::add(1, ":timestamp", Instant(1_540_048_515_500)),
+                TxData::add(2, ":timestamp", Instant(1_540_048_515_616)),
+            ]],
+            expectations: vec![vec![
+                (
+                    vec![
+                        Eid(1),
+                        Instant(1_540_048_515_500),
+                        Instant(1_540_047_600_000),
+                    ],
+                    0,
+                    1,
+                ),
+                (
+                    vec![
+                        Eid(2),
+                        Instant(1_540_048_515_616),
+                        Instant(1_540_047_600_000),
+                    ],
+                    0,
+                    1,
+                ),
+            ]],
+        },
+        Case {
+            description: "[:find ?tag :where [?e :tags ?tags] [(split ?tags) ?tag]]",
+            plan: {
+                let (e, tags, tag) = (1, 2, 3);
+                Plan::Transform(Transform {
+                    variables: vec![tags],
+                    result_variable: tag,
+                    plan: Box::new(Plan::MatchA(e, ":tags".to_string(), tags)),
+                    function: Function::SPLIT,
+                    constants: vec![],
+                })
+            },
+            transactions: vec![vec![
+                TxData::add(1, ":tags", Value::String("red,green".to_string())),
+                TxData::add(2, ":tags", Value::String("".to_string())),
+            ]],
+            expectations: vec![vec![
+                (
+                    vec![
+                        Eid(1),
+                        Value::String("red,green".to_string()),
+                        Value::String("red".to_string()),
+                    ],
+                    0,
+                    1,
+                ),
+                (
+                    vec![
+                        Eid(1),
+                        Value::String("red,green".to_string()),
+                        Value::String("green".to_string()),
+                    ],
+                    0,
+                    1,
+                ),
+            ]],
+        },
+    ];
+
+    cases.push(Case {
+        description: "[:find ?e ?n ?m :where [?e :count ?n] [(+ ?n 1) ?m]]",
         plan: {
-            let (e, t, h) = (1, 2, 3);
-            let constants = vec![None, None];
-            // let constants = vec![None, Some(Value::String(String::from("hour")))];
+            let (e, n, m) = (1, 2, 3);
             Plan::Transform(Transform {
-                variables: vec![t],
-                result_variable: h,
-                plan: Box::new(Plan::MatchA(e, ":timestamp".to_string(), t)),
-                function: Function::TRUNCATE,
-                constants,
+                variables: vec![n],
+                result_variable: m,
+                plan: Box::new(Plan::MatchA(e, ":count".to_string(), n)),
+                function: Function::ADD,
+                constants: vec![None, Some(UNumber(1))],
             })
         },
         transactions: vec![vec![
-            TxData::add(1, ":timestamp", Instant(1_540_048_515_500)),
-            TxData::add(2, ":timestamp", Instant(1_540_048_515_616)),
+            TxData::add(1, ":count", UNumber(41)),
+            TxData::add(2, ":count", UNumber(std::u64::MAX)),
         ]],
-        expectations: vec![vec![
-            (
-                vec![
-                    Eid(1),
-                    Instant(1_540_048_515_500),
-                    Instant(1_540_047_600_000),
-                ],
+        // Tuples overflowing are dropped.
+        expectations: vec![vec![(vec![Eid(1), UNumber(41), UNumber(42)], 0, 1)]],
+    });
+
+    cases.push(Case {
+        description: "[:find ?e ?name ?t :where [?e :name ?name] [(now) ?t]]",
+        plan: {
+            let (e, name, t) = (1, 2, 3);
+            Plan::Transform(Transform {
+                variables: vec![],
+                result_variable: t,
+                plan: Box::new(Plan::MatchA(e, ":name".to_string(), name)),
+                function: Function::NOW,
+                constants: vec![],
+            })
+        },
+        transactions: vec![
+            vec![TxData::add(1, ":name", Value::String("Dipper".to_string()))],
+            vec![TxData::add(2, ":name", Value::String("Mabel".to_string()))],
+        ],
+        // NOW is meant for append-only inputs, where each tuple is
+        // stamped with the time it was added at.
+        expectations: vec![
+            vec![(
+                vec![Eid(1), Value::String("Dipper".to_string()), UNumber(0)],
                 0,
                 1,
-            ),
-            (
-                vec![
-                    Eid(2),
-                    Instant(1_540_048_515_616),
-                    Instant(1_540_047_600_000),
-                ],
-                0,
+            )],
+            vec![(
+                vec![Eid(2), Value::String("Mabel".to_string()), UNumber(1)],
                 1,
-            ),
+                1,
+            )],
+        ],
+    });
+
+    cases.push(Case {
+        description:
+            "[:find ?o ?name :where [?o :order/customer ?id] [(cast ?id 0) ?c] [?c :name ?name]]",
+        plan: {
+            let (o, id, c, name) = (1, 2, 3, 4);
+            Plan::Project(Project {
+                variables: vec![o, name],
+                plan: Box::new(Plan::Join(Join {
+                    variables: vec![c],
+                    left_plan: Box::new(Plan::Transform(Transform {
+                        variables: vec![id],
+                        result_variable: c,
+                        plan: Box::new(Plan::MatchA(o, ":order/customer".to_string(), id)),
+                        function: Function::CAST,
+                        constants: vec![None, Some(Eid(0))],
+                    })),
+                    right_plan: Box::new(Plan::MatchA(c, ":name".to_string(), name)),
+                })),
+            })
+        },
+        transactions: vec![vec![
+            TxData::add(10, ":order/customer", Value::Number(1)),
+            TxData::add(11, ":order/customer", Value::Number(3)),
+            // Ids that can't be cast are dropped.
+            TxData::add(12, ":order/customer", Value::Number(-1)),
+            TxData::add(1, ":name", Value::String("Dipper".to_string())),
+            TxData::add(2, ":name", Value::String("Mabel".to_string())),
         ]],
-    }];
+        expectations: vec![vec![(
+            vec![Eid(10), Value::String("Dipper".to_string())],
+            0,
+            1,
+        )]],
+    });
 
     for case in cases.drain(..) {
         timely::execute_directly(move |worker| {
@@ -152,3 +398,77 @@ fn run_transform_cases() {
         });
     }
 }
+
+#[test]
+fn transform_fn() {
+    use Value::Number;
+
+    timely::execute_directly(move |worker| {
+        let mut server = Server::<u64, u64>::new(Default::default());
+        let (send_results, results) = channel();
+
+        worker.dataflow::<u64, _, _>(|scope| {
+            server
+                .context
+                .internal
+                .create_transactable_attribute(
+                    ":age",
+                    AttributeConfig::tx_time(InputSemantics::Raw),
+                    scope,
+                )
+                .unwrap();
+
+            let (e, age, half) = (0, 1, 2);
+            let plan = Plan::TransformFn(TransformFn {
+                variables: vec![age],
+                result_variable: half,
+                plan: Box::new(Plan::MatchA(e, ":age".to_string(), age)),
+                function: FunctionFn::new(|args| match args[0] {
+                    Number(age) if age % 2 == 0 => Some(Number(age / 2)),
+                    _ => None,
+                }),
+            });
+
+            server
+                .test_single(
+                    scope,
+                    Rule {
+                        name: "half_ages".to_string(),
+                        plan,
+                    },
+                )
+                .inner
+                .sink(Pipeline, "Results", move |input| {
+                    input.for_each(|_time, data| {
+                        for datum in data.iter() {
+                            send_results.send(datum.clone()).unwrap()
+                        }
+                    });
+                });
+        });
+
+        server
+            .transact(
+                vec![
+                    TxData::add(1, ":age", Number(10)),
+                    TxData::add(2, ":age", Number(11)),
+                    TxData::add(3, ":age", Number(12)),
+                ],
+                0,
+                0,
+            )
+            .unwrap();
+
+        server.advance_domain(None, 1).unwrap();
+        worker.step_while(|| server.is_any_outdated());
+
+        let expected: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(vec![
+            (vec![Eid(1), Number(10), Number(5)], 0, 1),
+            (vec![Eid(3), Number(12), Number(6)], 0, 1),
+        ]);
+
+        let actual: HashSet<(Vec<Value>, u64, isize)> = HashSet::from_iter(results.try_iter());
+
+        assert_eq!(actual, expected);
+    });
+}